@@ -0,0 +1,126 @@
+extern crate client;
+#[macro_use] extern crate clap;
+extern crate event_loop;
+extern crate gamenet;
+#[macro_use] extern crate log;
+extern crate logger;
+extern crate snapshot;
+
+use clap::App;
+use clap::Arg;
+use clap::Error;
+use clap::ErrorKind;
+use client::Action;
+use client::Client;
+use client::Config;
+use client::Controller;
+use event_loop::Addr;
+use event_loop::SocketLoop;
+use gamenet::snap_obj::PlayerInput;
+use snapshot::View;
+use std::path::PathBuf;
+
+/// Runs back and forth, jumping whenever it stops making progress.
+struct Walker {
+    direction: i32,
+    last_x: Option<i32>,
+    stuck_ticks: u32,
+}
+
+impl Walker {
+    fn new(direction: i32) -> Walker {
+        Walker {
+            direction: direction,
+            last_x: None,
+            stuck_ticks: 0,
+        }
+    }
+}
+
+impl Controller for Walker {
+    fn on_enter_game(&mut self) {
+        info!("entered game");
+    }
+    fn tick(&mut self, tick: i32, snap: &View) -> Action {
+        let mut input: PlayerInput = Default::default();
+        let x = snap.local_player()
+            .and_then(|(id, _)| snap.character(id))
+            .map(|c| c.character_core.x);
+        if let (Some(x), Some(last_x)) = (x, self.last_x) {
+            if x == last_x {
+                self.stuck_ticks += 1;
+            } else {
+                self.stuck_ticks = 0;
+            }
+        }
+        self.last_x = x;
+        if self.stuck_ticks == 25 {
+            self.direction = -self.direction;
+        }
+        input.direction = self.direction;
+        input.target_x = self.direction * 100;
+        input.jump = (self.stuck_ticks >= 5 && tick % 10 < 5) as i32;
+        Action::new(input)
+    }
+}
+
+fn main() {
+    logger::init();
+
+    let matches = App::new("Teeworlds bots")
+        .about("Connects bots to a Teeworlds server that walk back and forth.")
+        .arg(Arg::with_name("name")
+            .help("Sets the name prefix of the bots")
+            .long("name")
+            .takes_value(true)
+            .value_name("NAME")
+            .default_value("bot")
+        )
+        .arg(Arg::with_name("num")
+            .help("Sets the number of bots")
+            .long("num")
+            .short("n")
+            .takes_value(true)
+            .value_name("NUM")
+            .default_value("1")
+        )
+        .arg(Arg::with_name("password")
+            .help("Sets the password sent to the server")
+            .long("password")
+            .takes_value(true)
+            .value_name("PASSWORD")
+        )
+        .arg(Arg::with_name("maps")
+            .help("Sets the directory to cache maps in")
+            .long("maps")
+            .takes_value(true)
+            .value_name("DIR")
+        )
+        .arg(Arg::with_name("server")
+            .help("Server to connect to")
+            .required(true)
+            .value_name("SERVER")
+        )
+        .get_matches();
+
+    let addr = value_t!(matches, "server", Addr).unwrap_or_else(|e| e.exit());
+    let num = value_t!(matches, "num", u32).unwrap_or_else(|e| e.exit());
+    let name = matches.value_of("name").unwrap();
+
+    if name.len() + num.to_string().len() >= 15 {
+        Error::with_description("Names can have at most 15 bytes", ErrorKind::ValueValidation).exit();
+    }
+
+    let mut client = Client::new();
+    for i in 0..num {
+        let config = Config {
+            name: format!("{}{}", name, i).into_bytes(),
+            password: matches.value_of("password").map(|p| p.as_bytes().to_vec()),
+            map_dir: matches.value_of("maps").map(PathBuf::from),
+            ..Default::default()
+        };
+        let direction = if i % 2 == 0 { 1 } else { -1 };
+        client.add_bot(addr, config, Walker::new(direction));
+    }
+    client.run::<SocketLoop>();
+}
@@ -0,0 +1,430 @@
+use Action;
+use Controller;
+use common::num::Cast;
+use common::pretty;
+use download::Download;
+use download::Status;
+use event_loop::Addr;
+use event_loop::Application;
+use event_loop::Chunk;
+use event_loop::ConnlessChunk;
+use event_loop::Loop;
+use event_loop::LoopExt;
+use event_loop::PeerId;
+use event_loop::Timeout;
+use event_loop::Timestamp;
+use event_loop::collections::PeerMap;
+use gamenet::VERSION;
+use gamenet::enums::Team;
+use gamenet::msg::Game;
+use gamenet::msg::System;
+use gamenet::msg::SystemOrGame;
+use gamenet::msg::game::ClSay;
+use gamenet::msg::game::ClSetTeam;
+use gamenet::msg::game::ClStartInfo;
+use gamenet::msg::system::EnterGame;
+use gamenet::msg::system::Info;
+use gamenet::msg::system::Input;
+use gamenet::msg::system::Ready;
+use gamenet::msg::system::RequestMapData;
+use gamenet::snap_obj::PlayerInput;
+use gamenet::snap_obj::obj_size;
+use hexdump::hexdump_iter;
+use itertools::Itertools;
+use log::LogLevel;
+use packer::Unpacker;
+use snapshot::View;
+use snapshot;
+use std::cmp;
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use warn::Log;
+use warn;
+
+const TICKS_PER_SECOND: u32 = 50;
+
+/// How many ticks ahead of the estimated server tick inputs are sent, to
+/// make them arrive in time despite the latency.
+const INPUT_AHEAD_TICKS: i32 = 5;
+
+fn hexdump(level: LogLevel, data: &[u8]) {
+    if log_enabled!(level) {
+        hexdump_iter(data).foreach(|s| log!(level, "{}", s));
+    }
+}
+
+struct Warn<'a>(&'a [u8]);
+
+impl<'a, W: fmt::Debug> warn::Warn<W> for Warn<'a> {
+    fn warn(&mut self, w: W) {
+        warn!("{:?}", w);
+        hexdump(LogLevel::Warn, self.0);
+    }
+}
+
+fn tick_duration(ticks: u32) -> Duration {
+    Duration::from_millis((1000 * ticks / TICKS_PER_SECOND).u64())
+}
+
+/// The player settings and connection options of a bot.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub name: Vec<u8>,
+    pub clan: Vec<u8>,
+    pub country: i32,
+    pub skin: Vec<u8>,
+    pub use_custom_color: bool,
+    pub color_body: i32,
+    pub color_feet: i32,
+    /// The team joined after entering the game.
+    pub team: Team,
+    pub password: Option<Vec<u8>>,
+    /// Directory to look up maps before downloading them and to store
+    /// downloaded maps in, as `<name>_<crc>.map`.
+    pub map_dir: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            name: b"nameless tee".to_vec(),
+            clan: Vec::new(),
+            country: -1,
+            skin: b"default".to_vec(),
+            use_custom_color: false,
+            color_body: 0,
+            color_feet: 0,
+            team: Team::Red,
+            password: None,
+            map_dir: None,
+        }
+    }
+}
+
+impl Config {
+    fn map_path(&self, name: &str, crc: i32) -> Option<PathBuf> {
+        self.map_dir.as_ref().map(|dir| dir.join(format!("{}_{:08x}.map", name, crc)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Connection,
+    MapChange,
+    MapData,
+    ConReady,
+    ReadyToEnter,
+    Ingame,
+}
+
+struct Bot<C> {
+    controller: C,
+    config: Config,
+    state: State,
+    download: Option<Download>,
+    snaps: snapshot::Manager,
+    view: View,
+    // Tick of the latest snapshot and the time it was received.
+    snap_tick: Option<(i32, Timestamp)>,
+    input_tick: i32,
+}
+
+impl<C: Controller> Bot<C> {
+    fn new(config: Config, controller: C) -> Bot<C> {
+        Bot {
+            controller: controller,
+            config: config,
+            state: State::Connection,
+            download: None,
+            snaps: snapshot::Manager::new(),
+            view: View::new(),
+            snap_tick: None,
+            input_tick: -1,
+        }
+    }
+    fn needs_tick(&self) -> Timeout {
+        if self.state != State::Ingame {
+            return Timeout::inactive();
+        }
+        match self.snap_tick {
+            Some((tick, time)) => {
+                let ticks = cmp::max(self.input_tick + 1 - tick, 0);
+                Timeout::active(time + tick_duration(ticks.assert_u32()))
+            },
+            None => Timeout::inactive(),
+        }
+    }
+    fn estimated_tick(&self, now: Timestamp) -> Option<i32> {
+        self.snap_tick.map(|(tick, time)| {
+            let elapsed = Timeout::active(now).time_from(time).unwrap();
+            let elapsed_ms = elapsed.as_secs() * 1000 + elapsed.subsec_nanos().u64() / 1_000_000;
+            tick + (elapsed_ms * TICKS_PER_SECOND.u64() / 1000).assert_i32()
+        })
+    }
+    fn tick<L: Loop>(&mut self, pid: PeerId, loop_: &mut L) {
+        if self.state != State::Ingame {
+            return;
+        }
+        let tick = match self.estimated_tick(loop_.time()) {
+            Some(t) => t,
+            None => return,
+        };
+        if tick <= self.input_tick {
+            return;
+        }
+        self.input_tick = tick;
+        let Action { input, chat } = self.controller.tick(tick, &self.view);
+        for message in &chat {
+            loop_.sendg(pid, ClSay {
+                team: false,
+                message: message,
+            });
+        }
+        loop_.sends_nonvital(pid, Input {
+            ack_snapshot: self.snaps.ack_tick().unwrap_or(-1),
+            intended_tick: tick + INPUT_AHEAD_TICKS,
+            input_size: mem::size_of::<PlayerInput>().assert_i32(),
+            input: input,
+        });
+        loop_.flush(pid);
+    }
+    fn load_map(&mut self, name: &[u8], crc: i32) -> bool {
+        let path = unwrap_or_return!(self.config.map_path(&String::from_utf8_lossy(name), crc), false);
+        match fs::read(&path) {
+            Ok(data) => {
+                info!("using map from {:?}", path);
+                self.controller.on_map(name, crc, &data);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+    fn finish_download(&mut self) {
+        let download = self.download.take().unwrap();
+        if let Some(path) = self.config.map_path(&String::from_utf8_lossy(download.name()), download.crc()) {
+            if let Err(e) = fs::write(&path, download.data()) {
+                error!("error writing map to {:?}: {:?}", path, e);
+            }
+        }
+        self.controller.on_map(download.name(), download.crc(), download.data());
+    }
+    fn on_ready<L: Loop>(&mut self, pid: PeerId, loop_: &mut L) {
+        self.state = State::MapChange;
+        loop_.sends(pid, Info {
+            version: VERSION,
+            password: Some(self.config.password.as_ref().map(|p| &p[..]).unwrap_or(b"")),
+        });
+        loop_.flush(pid);
+    }
+    fn on_packet<L: Loop>(&mut self, pid: PeerId, data: &[u8], loop_: &mut L) {
+        let msg = match SystemOrGame::decode(&mut Warn(data), &mut Unpacker::new(data)) {
+            Ok(m) => m,
+            Err(err) => {
+                warn!("decode error {:?}:", err);
+                hexdump(LogLevel::Warn, data);
+                return;
+            },
+        };
+        debug!("{:?}", msg);
+        match msg {
+            SystemOrGame::System(System::MapChange(change)) => {
+                let download = match Download::new(change) {
+                    Ok(d) => d,
+                    Err(err) => {
+                        error!("invalid map change {:?}", err);
+                        loop_.disconnect(pid, b"");
+                        return;
+                    },
+                };
+                info!("map change: {}", pretty::AlmostString::new(change.name));
+                self.snaps.reset();
+                self.snap_tick = None;
+                self.input_tick = -1;
+                self.download = None;
+                if self.load_map(change.name, change.crc) {
+                    self.state = State::ConReady;
+                    loop_.sends(pid, Ready);
+                } else {
+                    self.download = Some(download);
+                    self.state = State::MapData;
+                    loop_.sends(pid, RequestMapData { chunk: 0 });
+                }
+            },
+            SystemOrGame::System(System::MapData(data)) => {
+                let status = match (self.state, self.download.as_mut()) {
+                    (State::MapData, Some(download)) => download.on_data(data),
+                    _ => Ok(Status::Unsolicited),
+                };
+                match status {
+                    Ok(Status::Unsolicited) => {
+                        warn!("unsolicited map data crc={:08x} chunk={}", data.crc, data.chunk);
+                    },
+                    Ok(Status::Continue(chunk)) => {
+                        loop_.sends(pid, RequestMapData { chunk: chunk });
+                    },
+                    Ok(Status::Finished) => {
+                        self.finish_download();
+                        self.state = State::ConReady;
+                        loop_.sends(pid, Ready);
+                    },
+                    Err(err) => {
+                        error!("map download error {:?}", err);
+                        loop_.disconnect(pid, b"");
+                        return;
+                    },
+                }
+            },
+            SystemOrGame::System(System::ConReady(..)) => {
+                if self.state != State::ConReady {
+                    warn!("con ready in state {:?}", self.state);
+                }
+                loop_.sendg(pid, ClStartInfo {
+                    name: &self.config.name,
+                    clan: &self.config.clan,
+                    country: self.config.country,
+                    skin: &self.config.skin,
+                    use_custom_color: self.config.use_custom_color,
+                    color_body: self.config.color_body,
+                    color_feet: self.config.color_feet,
+                });
+                self.state = State::ReadyToEnter;
+            },
+            SystemOrGame::Game(Game::SvReadyToEnter(..)) => {
+                if self.state != State::ReadyToEnter {
+                    warn!("ready to enter in state {:?}", self.state);
+                }
+                loop_.sends(pid, EnterGame);
+                loop_.sendg(pid, ClSetTeam { team: self.config.team });
+                self.state = State::Ingame;
+                self.controller.on_enter_game();
+            },
+            SystemOrGame::System(System::Snap(_))
+                | SystemOrGame::System(System::SnapEmpty(_))
+                | SystemOrGame::System(System::SnapSingle(_))
+            => {
+                let res = match msg {
+                    SystemOrGame::System(System::Snap(s)) =>
+                        self.snaps.snap(&mut Log, obj_size, s),
+                    SystemOrGame::System(System::SnapEmpty(s)) =>
+                        self.snaps.snap_empty(&mut Log, obj_size, s),
+                    SystemOrGame::System(System::SnapSingle(s)) =>
+                        self.snaps.snap_single(&mut Log, obj_size, s),
+                    _ => unreachable!(),
+                };
+                match res {
+                    Ok(Some(snap)) => {
+                        self.view.decode_from(&mut Log, snap);
+                        let tick = self.snaps.ack_tick().unwrap();
+                        self.snap_tick = Some((tick, loop_.time()));
+                    },
+                    Ok(None) => {},
+                    Err(err) => warn!("snapshot error {:?}", err),
+                }
+            },
+            SystemOrGame::Game(Game::SvChat(chat)) => {
+                self.controller.on_chat(chat.client_id, chat.team, chat.message);
+            },
+            _ => {},
+        }
+        loop_.flush(pid);
+    }
+}
+
+/// Runs a number of bots, each connected to a server.
+pub struct Client<C> {
+    pending: Vec<(Addr, Config, C)>,
+    bots: PeerMap<Bot<C>>,
+}
+
+impl<C> Default for Client<C> {
+    fn default() -> Client<C> {
+        Client {
+            pending: Vec::new(),
+            bots: PeerMap::new(),
+        }
+    }
+}
+
+impl<C: Controller> Client<C> {
+    pub fn new() -> Client<C> {
+        Default::default()
+    }
+    /// Adds a bot that connects to `addr` once the client runs.
+    pub fn add_bot(&mut self, addr: Addr, config: Config, controller: C) {
+        self.pending.push((addr, config, controller));
+    }
+    /// Connects all bots and runs until all of them are disconnected.
+    ///
+    /// A socket can only hold one connection per server, so bots connecting
+    /// to the same server are distributed over several sockets, each served
+    /// by its own thread.
+    pub fn run<L: Loop+'static>(self) where C: Send+'static {
+        let mut groups: Vec<Client<C>> = Vec::new();
+        for (addr, config, controller) in self.pending {
+            let free = groups.iter().position(|g| g.pending.iter().all(|&(a, _, _)| a != addr));
+            let i = match free {
+                Some(i) => i,
+                None => {
+                    groups.push(Client::new());
+                    groups.len() - 1
+                },
+            };
+            groups[i].add_bot(addr, config, controller);
+        }
+        let threads: Vec<_> = groups.drain(1..).map(|g| {
+            thread::spawn(move || g.run_loop::<L>())
+        }).collect();
+        if let Some(g) = groups.pop() {
+            g.run_loop::<L>();
+        }
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+    fn run_loop<L: Loop>(mut self) {
+        let mut loop_ = L::client();
+        for (addr, config, controller) in self.pending.drain(..) {
+            let pid = loop_.connect(addr);
+            self.bots.insert(pid, Bot::new(config, controller));
+        }
+        loop_.run(self);
+    }
+}
+
+impl<C: Controller, L: Loop> Application<L> for Client<C> {
+    fn needs_tick(&mut self) -> Timeout {
+        self.bots.values().map(|b| b.needs_tick()).min().unwrap_or_default()
+    }
+    fn on_tick(&mut self, loop_: &mut L) {
+        for (pid, bot) in self.bots.iter_mut() {
+            bot.tick(pid, loop_);
+        }
+    }
+    fn on_packet(&mut self, loop_: &mut L, chunk: Chunk) {
+        if let Some(bot) = self.bots.get_mut(chunk.pid) {
+            bot.on_packet(chunk.pid, chunk.data, loop_);
+        }
+    }
+    fn on_connless_packet(&mut self, _: &mut L, chunk: ConnlessChunk) {
+        warn!("connless packet {} {:?}", chunk.addr, pretty::Bytes::new(chunk.data));
+    }
+    fn on_connect(&mut self, _: &mut L, _: PeerId) {
+        unreachable!();
+    }
+    fn on_ready(&mut self, loop_: &mut L, pid: PeerId) {
+        self.bots[pid].on_ready(pid, loop_);
+    }
+    fn on_disconnect(&mut self, _: &mut L, pid: PeerId, remote: bool, reason: &[u8]) {
+        if remote {
+            error!("disconnected pid={:?} error={}", pid, pretty::AlmostString::new(reason));
+        }
+        if let Some(bot) = self.bots.get_mut(pid) {
+            bot.controller.on_disconnect(remote, reason);
+        }
+        self.bots.remove(pid);
+    }
+}
@@ -0,0 +1,209 @@
+//! Map download.
+//!
+//! After a `MapChange`, the client requests the map chunk by chunk using
+//! `RequestMapData`, the server answers each request with a `MapData`
+//! message. Everything in these messages is controlled by the server, so
+//! `Download` validates it before the map is stored or used.
+
+use common::num::Cast;
+use crc::crc32;
+use gamenet::msg::system::MapChange;
+use gamenet::msg::system::MapData;
+
+/// The largest map size accepted from a server.
+pub const MAX_MAP_SIZE: usize = 32 * 1024 * 1024;
+
+/// Protocol errors while downloading a map.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The map name contains path separators.
+    InvalidName,
+    /// The announced map size is negative or larger than `MAX_MAP_SIZE`.
+    InvalidSize,
+    /// The server sent more data than announced.
+    TooMuchData,
+    /// The server sent the last chunk before the announced size was reached.
+    TooLittleData,
+    /// The server sent more chunks than can be requested.
+    TooManyChunks,
+    /// The downloaded map doesn't have the announced CRC.
+    CrcMismatch,
+}
+
+/// Result of a `MapData` message for a `Download`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The message doesn't belong to the current chunk of this download.
+    Unsolicited,
+    /// The chunk was added, the given chunk should be requested next.
+    Continue(i32),
+    /// The map is complete and its CRC has been checked.
+    Finished,
+}
+
+/// Checks that a map name can be used in a file name.
+pub fn check_name(name: &[u8]) -> Result<(), Error> {
+    if name.iter().any(|&b| b == b'/' || b == b'\\') {
+        return Err(Error::InvalidName);
+    }
+    Ok(())
+}
+
+/// A map being downloaded.
+pub struct Download {
+    name: Vec<u8>,
+    crc: i32,
+    size: usize,
+    chunk: i32,
+    data: Vec<u8>,
+}
+
+impl Download {
+    /// Validates the `MapChange` message and prepares the download of the
+    /// announced map. The first chunk to request is `0`.
+    pub fn new(change: MapChange) -> Result<Download, Error> {
+        try!(check_name(change.name));
+        let size = try!(change.size.try_usize().ok_or(Error::InvalidSize));
+        if size > MAX_MAP_SIZE {
+            return Err(Error::InvalidSize);
+        }
+        Ok(Download {
+            name: change.name.to_owned(),
+            crc: change.crc,
+            size: size,
+            chunk: 0,
+            data: Vec::new(),
+        })
+    }
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+    pub fn crc(&self) -> i32 {
+        self.crc
+    }
+    /// Returns the map data. Only complete once `on_data` returned
+    /// `Status::Finished`.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    /// Adds the data of a `MapData` message.
+    pub fn on_data(&mut self, msg: MapData) -> Result<Status, Error> {
+        if msg.crc != self.crc || msg.chunk != self.chunk {
+            return Ok(Status::Unsolicited);
+        }
+        if msg.data.len() > self.size - self.data.len() {
+            return Err(Error::TooMuchData);
+        }
+        self.data.extend_from_slice(msg.data);
+        if msg.last != 0 {
+            if self.data.len() != self.size {
+                return Err(Error::TooLittleData);
+            }
+            if crc32::checksum_ieee(&self.data) != self.crc as u32 {
+                return Err(Error::CrcMismatch);
+            }
+            return Ok(Status::Finished);
+        }
+        self.chunk = try!(self.chunk.checked_add(1).ok_or(Error::TooManyChunks));
+        Ok(Status::Continue(self.chunk))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gamenet::msg::system::MapChange;
+    use gamenet::msg::system::MapData;
+    use std::fs;
+    use std::i32;
+    use super::Download;
+    use super::Error;
+    use super::MAX_MAP_SIZE;
+    use super::Status;
+
+    const CRC: i32 = 0xf2159e6e_u32 as i32;
+
+    fn map() -> Vec<u8> {
+        fs::read("../server/dm1.map").unwrap()
+    }
+
+    fn change(name: &[u8], crc: i32, size: usize) -> MapChange {
+        MapChange {
+            name: name,
+            crc: crc,
+            size: size as i32,
+        }
+    }
+
+    fn chunk(crc: i32, chunk: i32, last: bool, data: &[u8]) -> MapData {
+        MapData {
+            last: last as i32,
+            crc: crc,
+            chunk: chunk,
+            data: data,
+        }
+    }
+
+    fn download(crc: i32, size: usize, data: &[u8], chunk_size: usize) -> Result<Status, Error> {
+        let mut download = Download::new(change(b"dm1", crc, size)).unwrap();
+        let chunks: Vec<_> = data.chunks(chunk_size).collect();
+        for (i, c) in chunks.iter().enumerate() {
+            let last = i == chunks.len() - 1;
+            let status = try!(download.on_data(chunk(crc, i as i32, last, c)));
+            if last {
+                if status == Status::Finished {
+                    assert_eq!(download.data(), data);
+                }
+                return Ok(status);
+            }
+            assert_eq!(status, Status::Continue(i as i32 + 1));
+        }
+        unreachable!();
+    }
+
+    #[test]
+    fn complete() {
+        let map = map();
+        assert_eq!(download(CRC, map.len(), &map, 1024 - 128), Ok(Status::Finished));
+    }
+
+    #[test]
+    fn crc_mismatch() {
+        let map = map();
+        assert_eq!(download(CRC ^ 1, map.len(), &map, 1024 - 128), Err(Error::CrcMismatch));
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let map = map();
+        assert_eq!(download(CRC, map.len() - 1, &map, 1024 - 128), Err(Error::TooMuchData));
+        assert_eq!(download(CRC, map.len() + 1, &map, 1024 - 128), Err(Error::TooLittleData));
+    }
+
+    #[test]
+    fn invalid_change() {
+        assert_eq!(Download::new(change(b"../dm1", CRC, 0)).err(), Some(Error::InvalidName));
+        assert_eq!(Download::new(change(b"dm1\\x", CRC, 0)).err(), Some(Error::InvalidName));
+        let invalid = MapChange { name: b"dm1", crc: CRC, size: -1 };
+        assert_eq!(Download::new(invalid).err(), Some(Error::InvalidSize));
+        assert_eq!(Download::new(change(b"dm1", CRC, MAX_MAP_SIZE + 1)).err(), Some(Error::InvalidSize));
+        assert!(Download::new(change(b"dm1", CRC, MAX_MAP_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn unsolicited() {
+        let map = map();
+        let mut download = Download::new(change(b"dm1", CRC, map.len())).unwrap();
+        assert_eq!(download.on_data(chunk(CRC ^ 1, 0, false, &map[..10])), Ok(Status::Unsolicited));
+        assert_eq!(download.on_data(chunk(CRC, 1, false, &map[..10])), Ok(Status::Unsolicited));
+        assert_eq!(download.on_data(chunk(CRC, 0, false, &map[..10])), Ok(Status::Continue(1)));
+        assert_eq!(download.on_data(chunk(CRC, 0, false, &map[..10])), Ok(Status::Unsolicited));
+        assert_eq!(download.data(), &map[..10]);
+    }
+
+    #[test]
+    fn too_many_chunks() {
+        let mut download = Download::new(change(b"dm1", 0, 0)).unwrap();
+        download.chunk = i32::MAX;
+        assert_eq!(download.on_data(chunk(0, i32::MAX, false, b"")), Err(Error::TooManyChunks));
+    }
+}
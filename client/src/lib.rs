@@ -0,0 +1,71 @@
+//! A headless Teeworlds client for bots.
+//!
+//! `Client` connects any number of bots to servers, doing the join handshake,
+//! map download and snapshot tracking. Each bot is driven by a `Controller`,
+//! which is asked for an input every tick once the bot is in the game.
+
+extern crate arrayvec;
+#[macro_use] extern crate common;
+extern crate crc;
+extern crate event_loop;
+extern crate gamenet;
+extern crate hexdump;
+extern crate itertools;
+#[macro_use] extern crate log;
+extern crate packer;
+extern crate snapshot;
+extern crate warn;
+
+use gamenet::snap_obj::PlayerInput;
+use snapshot::View;
+
+pub use client::Client;
+pub use client::Config;
+pub use download::Download;
+
+mod client;
+pub mod download;
+
+/// The actions a bot takes in one tick.
+#[derive(Clone, Default)]
+pub struct Action {
+    /// The input sent to the server for this tick.
+    pub input: PlayerInput,
+    /// Chat messages to send to everyone.
+    pub chat: Vec<Vec<u8>>,
+}
+
+impl Action {
+    pub fn new(input: PlayerInput) -> Action {
+        Action {
+            input: input,
+            chat: Vec::new(),
+        }
+    }
+}
+
+/// Decides what a bot does.
+///
+/// All methods except `tick` have empty default implementations.
+pub trait Controller {
+    /// Called when a map was loaded, either downloaded or from the map
+    /// directory.
+    fn on_map(&mut self, name: &[u8], crc: i32, data: &[u8]) {
+        let _ = (name, crc, data);
+    }
+    /// Called when the bot entered the game.
+    fn on_enter_game(&mut self) {
+    }
+    /// Called for each received chat message, `client_id` is `-1` for
+    /// server messages.
+    fn on_chat(&mut self, client_id: i32, team: bool, message: &[u8]) {
+        let _ = (client_id, team, message);
+    }
+    /// Called when the bot was disconnected from the server.
+    fn on_disconnect(&mut self, remote: bool, reason: &[u8]) {
+        let _ = (remote, reason);
+    }
+    /// Called every tick while in game, with the estimated current server
+    /// tick and the latest snapshot.
+    fn tick(&mut self, tick: i32, snap: &View) -> Action;
+}
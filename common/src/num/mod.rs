@@ -1,3 +1,4 @@
+use std::cmp;
 use std::fmt;
 
 mod cast;
@@ -55,6 +56,13 @@ pub struct BeU16([u8; 2]);
 #[derive(Clone, Copy)]
 pub struct LeU16([u8; 2]);
 
+/// Little-endian signed 16-bit integer
+///
+/// Is internally represented as `[u8; 2]`.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct LeI16([u8; 2]);
+
 // ======================
 // BOILERPLATE CODE BELOW
 // ======================
@@ -145,9 +153,38 @@ impl fmt::Debug for LeU16 {
     }
 }
 
+impl LeI16 {
+    pub fn from_i16(value: i16) -> LeI16 {
+        LeI16([value as u8, (value >> 8) as u8])
+    }
+    pub fn to_i16(self) -> i16 {
+        let LeI16(v) = self;
+        ((v[1] as u16) << 8 | v[0] as u16) as i16
+    }
+}
+
+impl fmt::Debug for LeI16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.to_i16().fmt(f)
+    }
+}
+
+impl Ord for LeI16 {
+    fn cmp(&self, other: &LeI16) -> cmp::Ordering {
+        self.to_i16().cmp(&other.to_i16())
+    }
+}
+
+impl PartialOrd for LeI16 {
+    fn partial_cmp(&self, other: &LeI16) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 unsafe_boilerplate_packed!(BeI32, 4, test_size_bei32, test_align_bei32);
 unsafe_boilerplate_packed!(BeU16, 2, test_size_beu16, test_align_beu16);
 unsafe_boilerplate_packed!(BeU32, 4, test_size_beu32, test_align_beu32);
+unsafe_boilerplate_packed!(LeI16, 2, test_size_lei16, test_align_lei16);
 unsafe_boilerplate_packed!(LeI32, 4, test_size_lei32, test_align_lei32);
 unsafe_boilerplate_packed!(LeU16, 2, test_size_leu16, test_align_leu16);
 
@@ -156,6 +193,7 @@ mod test {
     use super::BeI32;
     use super::BeU16;
     use super::BeU32;
+    use super::LeI16;
     use super::LeI32;
     use super::LeU16;
 
@@ -163,6 +201,7 @@ mod test {
         fn bei32_roundtrip(val: i32) -> bool { BeI32::from_i32(val).to_i32() == val }
         fn beu16_roundtrip(val: u16) -> bool { BeU16::from_u16(val).to_u16() == val }
         fn beu32_roundtrip(val: u32) -> bool { BeU32::from_u32(val).to_u32() == val }
+        fn lei16_roundtrip(val: i16) -> bool { LeI16::from_i16(val).to_i16() == val }
         fn lei32_roundtrip(val: i32) -> bool { LeI32::from_i32(val).to_i32() == val }
         fn leu16_roundtrip(val: u16) -> bool { LeU16::from_u16(val).to_u16() == val }
 
@@ -178,6 +217,10 @@ mod test {
             let bytes = &[v.0, v.1, v.2, v.3];
             BeU32::from_u32(BeU32::from_bytes(bytes).to_u32()).as_bytes() == bytes
         }
+        fn lei16_unpack(v: (u8, u8)) -> bool {
+            let bytes = &[v.0, v.1];
+            LeI16::from_i16(LeI16::from_bytes(bytes).to_i16()).as_bytes() == bytes
+        }
         fn lei32_unpack(v: (u8, u8, u8, u8)) -> bool {
             let bytes = &[v.0, v.1, v.2, v.3];
             LeI32::from_i32(LeI32::from_bytes(bytes).to_i32()).as_bytes() == bytes
@@ -21,4 +21,4 @@ pub mod buffer;
 mod file;
 pub mod raw;
 pub mod format;
-mod writer;
+pub mod writer;
@@ -1,52 +1,97 @@
-/*
-pub fn write_datafile<T:Datafile,W:Writer>(df: &T, writer: &mut W) -> Result<IoResult<()>,()> {
-    let compressed_data: Vec<Vec<u8>> = try!(result::collect(df.data_iter().map(|maybe_x| maybe_x.map(|x| {
-        zlib::compress_vec(x).unwrap()
-        (item_type.start, item_type.num)
+use common::num::Cast;
+use std::io::Write;
+use std::io;
+use std::mem;
+use zlib;
+
+use buffer::Buffer;
+use file::Error;
+use format::ItemHeader;
+use format;
+
+fn write_i32s<W: Write>(writer: &mut W, values: &[i32]) -> io::Result<()> {
+    for &v in values {
+        writer.write_all(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])?;
     }
+    Ok(())
+}
+
+fn i32_len(len: usize) -> Result<i32, Error> {
+    len.try_i32().ok_or(Error::Df(format::Error::Malformed))
 }
 
-/*
-pub fn write_datafile<T:Datafile,W:Writer>(df: &T, writer: &mut W) -> Result<IoResult<()>,()> {
-    let compressed_data: Vec<Vec<u8>> = try!(result::collect(df.data_iter().map(|maybe_x| maybe_x.map(|x| {
-        zlib::compress_vec(x).unwrap()
-    }))));
-
-    let size_items = df.items().fold(0, |s, i| {
-        s + i.data.len() * mem::size_of::<i32>() + mem::size_of::<DatafileItemHeader>()
-    });
-
-    let size_data = compressed_data.iter().fold(0, |s, d| s + d.len());
-
-    try!(DatafileHeaderVersion {
-        magic: DATAFILE_MAGIC,
-        version: 4,
-    }.write(writer));
-
-    try!(DatafileHeader {
-        _size: unimplemented!(),
-        _swaplen: unimplemented!(),
-        num_item_types: df.item_types().len(),
-        num_items: df.items().len(),
-        num_data: df.data_iter().len(),
-        size_items: size_items,
-        size_data: size_data,
-    }.write(writer));
-
-    for &type_id in df.item_types() {
-        let (start, num) = df.item_type_indexes_start_num(type_id);
-        try!(DatafileItemType {
-            type_id: type_id.as_i32().unwrap(),
-            start: start.as_i32().unwrap(),
-            num: num.as_i32().unwrap(),
-        }.write(writer));
+/// Writes the contents of `buffer` as a version 4 datafile.
+///
+/// The data is compressed with the given zlib compression `level`, see
+/// `zlib_minimal::BEST_COMPRESSION`.
+pub fn write<W: Write>(writer: &mut W, buffer: &Buffer, level: i32) -> Result<(), Error> {
+    let mut compressed_data = Vec::with_capacity(buffer.num_data());
+    for data in buffer.data_iter() {
+        let compressed = zlib::compress_vec_level(data, level)
+            .map_err(|e| Error::Df(format::Error::CompressionError(e)))?;
+        compressed_data.push(compressed);
+    }
+
+    let mut item_types = Vec::new();
+    for type_id in buffer.item_types() {
+        let indices = buffer.item_type_indices(type_id);
+        item_types.extend_from_slice(&[
+            type_id.i32(),
+            i32_len(indices.start)?,
+            i32_len(indices.len())?,
+        ]);
     }
 
-    for DatafileItem { type_id, id, data } in df.items() {
-        try!(DatafileItemHeader::new(type_id, id, data.len()).write(writer));
+    let mut item_offsets = Vec::with_capacity(buffer.num_items());
+    let mut items = Vec::new();
+    for item in buffer.items() {
+        item_offsets.push(i32_len(items.len() * mem::size_of::<i32>())?);
+        let header = ItemHeader::new(item.type_id, item.id, i32_len(item.data.len() * mem::size_of::<i32>())?);
+        items.extend_from_slice(&[header.type_id_and_id, header.size]);
+        items.extend_from_slice(item.data);
+    }
+
+    let mut data_offsets = Vec::with_capacity(compressed_data.len());
+    let mut data_sizes = Vec::with_capacity(compressed_data.len());
+    let mut size_data = 0;
+    for (compressed, data) in compressed_data.iter().zip(buffer.data_iter()) {
+        data_offsets.push(i32_len(size_data)?);
+        data_sizes.push(i32_len(data.len())?);
+        size_data += compressed.len();
+    }
+
+    let size_items = items.len() * mem::size_of::<i32>();
+    let num_data = compressed_data.len();
+    let total_size = mem::size_of::<format::Header>()
+        + item_types.len() * mem::size_of::<i32>()
+        + item_offsets.len() * mem::size_of::<i32>()
+        + data_offsets.len() * mem::size_of::<i32>()
+        + data_sizes.len() * mem::size_of::<i32>()
+        + size_items
+        + size_data;
+    // The first four i32 fields (magic, version, size, swaplen) are not
+    // accounted for in the size field.
+    let size = i32_len(total_size - 4 * mem::size_of::<i32>())?;
+    let swaplen = size - i32_len(size_data)?;
+
+    writer.write_all(&format::MAGIC)?;
+    write_i32s(writer, &[
+        format::VERSION4,
+        size,
+        swaplen,
+        i32_len(item_types.len() / 3)?,
+        i32_len(item_offsets.len())?,
+        i32_len(num_data)?,
+        i32_len(size_items)?,
+        i32_len(size_data)?,
+    ])?;
+    write_i32s(writer, &item_types)?;
+    write_i32s(writer, &item_offsets)?;
+    write_i32s(writer, &data_offsets)?;
+    write_i32s(writer, &data_sizes)?;
+    write_i32s(writer, &items)?;
+    for compressed in &compressed_data {
+        writer.write_all(compressed)?;
     }
-    unimplemented!();
-    Ok(Ok(()))
+    Ok(())
 }
-*/
-*/
@@ -0,0 +1,32 @@
+extern crate datafile;
+
+use datafile::buffer::Buffer;
+use datafile::writer;
+use std::env;
+use std::fs::File;
+use std::fs;
+use std::process;
+
+#[test]
+fn roundtrip() {
+    let mut buffer = Buffer::new();
+    buffer.add_item(3, 1, &[7, 8, 9]).unwrap();
+    buffer.add_item(1, 0, &[1, -1]).unwrap();
+    buffer.add_item(3, 0, &[]).unwrap();
+    let first = buffer.add_data(b"hello world".to_vec());
+    let second = buffer.add_data(vec![0; 1000]);
+
+    let path = env::temp_dir().join(format!("datafile-roundtrip-{}.dat", process::id()));
+    writer::write(&mut File::create(&path).unwrap(), &buffer, 9).unwrap();
+    let mut reader = datafile::Reader::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(reader.version(), datafile::Version::V4);
+    let expected: Vec<_> = buffer.items().collect();
+    let items: Vec<_> = reader.items().collect();
+    assert_eq!(items, expected);
+    assert_eq!(reader.item_type_indices(3), 1..3);
+    assert_eq!(reader.num_data(), 2);
+    assert_eq!(reader.read_data(first).unwrap(), b"hello world");
+    assert_eq!(reader.read_data(second).unwrap(), vec![0; 1000]);
+}
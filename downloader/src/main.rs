@@ -1,5 +1,5 @@
-extern crate arrayvec;
 #[macro_use] extern crate clap;
+extern crate client;
 extern crate common;
 extern crate event_loop;
 extern crate gamenet;
@@ -13,11 +13,12 @@ extern crate snapshot;
 extern crate tempfile;
 extern crate warn;
 
-use arrayvec::ArrayVec;
 use clap::App;
 use clap::Arg;
 use clap::Error;
 use clap::ErrorKind;
+use client::download::Download;
+use client::download::Status;
 use common::num::Cast;
 use common::pretty;
 use event_loop::Addr;
@@ -25,6 +26,7 @@ use event_loop::Application;
 use event_loop::Chunk;
 use event_loop::ConnlessChunk;
 use event_loop::Loop;
+use event_loop::LoopExt;
 use event_loop::PeerId;
 use event_loop::SocketLoop;
 use event_loop::Timeout;
@@ -46,7 +48,6 @@ use gamenet::msg::system::EnterGame;
 use gamenet::msg::system::Info;
 use gamenet::msg::system::Input;
 use gamenet::msg::system::MapChange;
-use gamenet::msg::system::MapData;
 use gamenet::msg::system::Ready;
 use gamenet::msg::system::RequestMapData;
 use gamenet::snap_obj::obj_size;
@@ -56,7 +57,6 @@ use itertools::Itertools;
 use log::LogLevel;
 use packer::IntUnpacker;
 use packer::Unpacker;
-use packer::with_packer;
 use snapshot::Snap;
 use snapshot::format::Item as SnapItem;
 use std::borrow::Cow;
@@ -111,12 +111,6 @@ fn check_dummy_map(name: &[u8], crc: u32, size: i32) -> bool {
     true
 }
 
-struct Download {
-    file: NamedTempFile,
-    crc: i32,
-    name: String,
-}
-
 struct Peer {
     visited_votes: HashSet<Vec<u8>>,
     current_votes: HashSet<Vec<u8>>,
@@ -228,27 +222,19 @@ impl Peer {
     fn progress<L: Loop>(&mut self, loop_: &mut L) {
         self.progress_timeout = loop_.time() + Duration::from_secs(120);
     }
-    fn open_file(&mut self, crc: i32, name: String) -> Result<(), io::Error> {
-        self.download = Some(Download {
-            file: try!(NamedTempFileOptions::new()
-                .prefix(&format!("{}_{:08x}_", name, crc))
-                .suffix(".map")
-                .create_in("downloading")
-            ),
-            crc: crc,
-            name: name,
-        });
-        Ok(())
-    }
-    fn write_file(&mut self, data: &[u8]) -> Result<(), io::Error> {
-        self.download.as_mut().unwrap().file.write_all(data)
-    }
     fn finish_file(&mut self) -> Result<(), io::Error> {
         let download = self.download.take().unwrap();
+        let name = String::from_utf8_lossy(download.name());
+        let mut file: NamedTempFile = try!(NamedTempFileOptions::new()
+            .prefix(&format!("{}_{:08x}_", name, download.crc()))
+            .suffix(".map")
+            .create_in("downloading")
+        );
+        try!(file.write_all(download.data()));
         let mut path = PathBuf::new();
         path.push("maps");
-        path.push(format!("{}_{:08x}.map", &download.name, download.crc));
-        download.file.persist(&path).map(|_| ()).map_err(|e| e.error)
+        path.push(format!("{}_{:08x}.map", name, download.crc()));
+        file.persist(&path).map(|_| ()).map_err(|e| e.error)
     }
 }
 
@@ -256,8 +242,7 @@ impl Peer {
 enum PeerState {
     Connection,
     MapChange,
-    // MapData(crc, chunk)
-    MapData(i32, i32),
+    MapData,
     ConReady,
     ReadyToEnter,
     // VoteSet(timeout)
@@ -282,34 +267,6 @@ impl PeerState {
     }
 }
 
-trait LoopExt: Loop {
-    fn sends<'a, S: Into<System<'a>>>(&mut self, pid: PeerId, msg: S) {
-        fn inner<L: Loop+?Sized>(msg: System, pid: PeerId, loop_: &mut L) {
-            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
-            with_packer(&mut buf, |p| msg.encode(p).unwrap());
-            loop_.send(Chunk {
-                pid: pid,
-                vital: true,
-                data: &buf,
-            })
-        }
-        inner(msg.into(), pid, self)
-    }
-    fn sendg<'a, G: Into<Game<'a>>>(&mut self, pid: PeerId, msg: G) {
-        fn inner<L: Loop+?Sized>(msg: Game, pid: PeerId, loop_: &mut L) {
-            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
-            with_packer(&mut buf, |p| msg.encode(p).unwrap());
-            loop_.send(Chunk {
-                pid: pid,
-                vital: true,
-                data: &buf,
-            })
-        }
-        inner(msg.into(), pid, self)
-    }
-}
-impl<L: Loop> LoopExt for L { }
-
 fn num_players(snap: &Snap) -> u32 {
     let mut num_players = 0;
     for item in snap.items() {
@@ -327,6 +284,7 @@ struct Config {
     clan: String,
     timeout: String,
     error: String,
+    current_map_only: bool,
 }
 
 struct Main {
@@ -433,50 +391,46 @@ impl<'a, L: Loop> MainLoop<'a, L> {
             let peer = &mut self.peers[pid];
             match msg {
                 SystemOrGame::System(ref msg) => match *msg {
-                    System::MapChange(MapChange { crc, size, name }) => {
-                        if let Some(_) = size.try_usize() {
-                            if name.iter().any(|&b| b == b'/' || b == b'\\') {
-                                error!("invalid map name");
+                    System::MapChange(change) => {
+                        let MapChange { crc, size, name } = change;
+                        let download = match Download::new(change) {
+                            Ok(d) => d,
+                            Err(err) => {
+                                error!("invalid map change {:?}", err);
                                 self.loop_.disconnect(pid, self.config.error.as_bytes());
                                 return;
-                            }
-                            match peer.state {
-                                PeerState::MapChange => {},
-                                PeerState::VoteResult(..) => {},
-                                PeerState::ReadyToEnter if peer.dummy_map => {},
-                                _ => warn!("map change from state {:?}", peer.state),
-                            }
-                            peer.dummy_map = check_dummy_map(name, crc as u32, size);
-                            peer.current_votes.clear();
-                            peer.num_snaps_since_reset = 0;
-                            peer.snaps.reset();
-                            info!("map change: {}", pretty::AlmostString::new(name));
-                            let name = String::from_utf8_lossy(name);
-                            if let Cow::Owned(..) = name {
-                                warn!("weird characters in map name");
-                            }
-                            let mut start_download = false;
-                            if need_file(crc, &name) {
-                                if let Err(e) = peer.open_file(crc, name.into_owned()) {
-                                    error!("error opening file {:?}", e);
-                                } else {
-                                    start_download = true;
-                                }
-                            }
-                            if start_download {
-                                info!("download starting");
-                                self.loop_.sends(pid, RequestMapData { chunk: 0, });
-                                peer.state = PeerState::MapData(crc, 0);
-                            } else {
-                                peer.state = PeerState::ConReady;
-                                self.loop_.sends(pid, Ready);
-                            }
-                            progress = true;
-                        } else {
-                            error!("invalid map size");
-                            self.loop_.disconnect(pid, self.config.error.as_bytes());
+                            },
+                        };
+                        match peer.state {
+                            PeerState::MapChange => {},
+                            PeerState::VoteResult(..) => {},
+                            PeerState::ReadyToEnter if peer.dummy_map => {},
+                            _ => warn!("map change from state {:?}", peer.state),
+                        }
+                        peer.dummy_map = check_dummy_map(name, crc as u32, size);
+                        peer.current_votes.clear();
+                        peer.num_snaps_since_reset = 0;
+                        peer.snaps.reset();
+                        info!("map change: {}", pretty::AlmostString::new(name));
+                        let name = String::from_utf8_lossy(name);
+                        if let Cow::Owned(..) = name {
+                            warn!("weird characters in map name");
+                        }
+                        peer.download = None;
+                        if need_file(crc, &name) {
+                            info!("download starting");
+                            peer.download = Some(download);
+                            self.loop_.sends(pid, RequestMapData { chunk: 0, });
+                            peer.state = PeerState::MapData;
+                        } else if self.config.current_map_only {
+                            info!("map already downloaded");
+                            self.loop_.disconnect(pid, self.config.nick.as_bytes());
                             return;
+                        } else {
+                            peer.state = PeerState::ConReady;
+                            self.loop_.sends(pid, Ready);
                         }
+                        progress = true;
                     },
                     System::Snap(_) | System::SnapEmpty(_) | System::SnapSingle(_)
                     => {
@@ -512,7 +466,7 @@ impl<'a, L: Loop> MainLoop<'a, L> {
                                 self.loop_.force_flush(pid);
                             }
                             let tick = peer.snaps.ack_tick().unwrap_or(-1);
-                            self.loop_.sends(pid, Input {
+                            self.loop_.sends_nonvital(pid, Input {
                                 ack_snapshot: tick,
                                 intended_tick: tick,
                                 input_size: mem::size_of_val(&snap_obj::PLAYER_INPUT_EMPTY).assert_i32(),
@@ -552,35 +506,35 @@ impl<'a, L: Loop> MainLoop<'a, L> {
             match peer.state {
                 PeerState::Connection => unreachable!(),
                 PeerState::MapChange => {}, // Handled above.
-                PeerState::MapData(cur_crc, cur_chunk) => match msg {
-                    SystemOrGame::System(System::MapData(MapData { last, crc, chunk, data })) => {
-                        if cur_crc == crc && cur_chunk == chunk {
-                            let res = peer.write_file(data);
-                            if let Err(ref err) = res {
-                                error!("error writing file {:?}", err);
-                            }
-                            if last != 0 || res.is_err() {
-                                if !res.is_err() {
-                                    if let Err(err) = peer.finish_file() {
-                                        error!("error finishing file {:?}", err);
-                                    }
-                                    if last != 1 {
-                                        warn!("weird map data packet");
-                                    }
+                PeerState::MapData => match msg {
+                    SystemOrGame::System(System::MapData(data)) => {
+                        match peer.download.as_mut().unwrap().on_data(data) {
+                            Ok(Status::Unsolicited) => {
+                                warn!("unsolicited map data crc={:08x} chunk={}", data.crc, data.chunk);
+                            },
+                            Ok(Status::Continue(chunk)) => {
+                                self.loop_.sends(pid, RequestMapData { chunk: chunk });
+                            },
+                            Ok(Status::Finished) => {
+                                if let Err(err) = peer.finish_file() {
+                                    error!("error finishing file {:?}", err);
+                                }
+                                if data.last != 1 {
+                                    warn!("weird map data packet");
+                                }
+                                info!("download finished");
+                                if self.config.current_map_only {
+                                    self.loop_.disconnect(pid, self.config.nick.as_bytes());
+                                    return;
                                 }
                                 peer.state = PeerState::ConReady;
                                 self.loop_.sends(pid, Ready);
-                                info!("download finished");
-                            } else {
-                                let cur_chunk = cur_chunk.checked_add(1).unwrap();
-                                peer.state = PeerState::MapData(cur_crc, cur_chunk);
-                                self.loop_.sends(pid, RequestMapData { chunk: cur_chunk });
-                            }
-                        } else {
-                            if cur_crc != crc || cur_chunk < chunk {
-                                warn!("unsolicited map data crc={:08x} chunk={}", crc, chunk);
-                                warn!("want crc={:08x} chunk={}", cur_crc, cur_chunk);
-                            }
+                            },
+                            Err(err) => {
+                                error!("map download error {:?}", err);
+                                self.loop_.disconnect(pid, self.config.error.as_bytes());
+                                return;
+                            },
                         }
                         progress = true;
                     }
@@ -693,6 +647,10 @@ fn main() {
             .value_name("CLAN")
             .default_value("")
         )
+        .arg(Arg::with_name("current-map")
+            .help("Only downloads the current map of each server, without voting")
+            .long("current-map")
+        )
         .arg(Arg::with_name("server")
             .help("Server to scrape")
             .multiple(true)
@@ -717,6 +675,7 @@ fn main() {
         clan: clan.to_owned(),
         timeout: format!("{} (timeout)", nick),
         error: format!("{} (error", nick),
+        current_map_only: matches.is_present("current-map"),
     };
 
     Main::run::<SocketLoop>(&addresses, config);
@@ -0,0 +1,436 @@
+//! A futures-based driver for `net::Net`, for use with tokio.
+//!
+//! `AsyncNet` is a `Stream` of received chunks and connection events and a
+//! `Sink` for outgoing chunks. Timers needed by the protocol (resends,
+//! timeouts) are driven internally, based on `Net::needs_tick`.
+
+use futures::Async;
+use futures::AsyncSink;
+use futures::Future;
+use futures::Poll;
+use futures::Sink;
+use futures::StartSend;
+use futures::Stream;
+use libc;
+use net2::UdpBuilder;
+use net::Net;
+use net::collections::PeerSet;
+use net::connection;
+use net::net::Callback;
+use net::net::Chunk;
+use net::net::ChunkOrEvent;
+use net::net::PeerId;
+use socket::Addr;
+use std::collections::VecDeque;
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::UdpSocket as StdUdpSocket;
+use std::time::Instant;
+use tokio::net::UdpSocket;
+use tokio::reactor::Handle;
+use tokio::timer::Delay;
+use Timestamp;
+use Warn;
+
+/// An owned version of `net::net::ChunkOrEvent`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    Chunk { pid: PeerId, vital: bool, data: Vec<u8> },
+    Connless { addr: Addr, pid: Option<PeerId>, data: Vec<u8> },
+    Connect(PeerId),
+    Ready(PeerId),
+    Disconnect { pid: PeerId, remote: bool, reason: Vec<u8> },
+}
+
+impl Event {
+    fn from_chunk_or_event(chunk: ChunkOrEvent<Addr>) -> Event {
+        match chunk {
+            ChunkOrEvent::Chunk(c) => Event::Chunk {
+                pid: c.pid,
+                vital: c.vital,
+                data: c.data.to_owned(),
+            },
+            ChunkOrEvent::Connless(c) => Event::Connless {
+                addr: c.addr,
+                pid: c.pid,
+                data: c.data.to_owned(),
+            },
+            ChunkOrEvent::Connect(pid) => Event::Connect(pid),
+            ChunkOrEvent::Ready(pid) => Event::Ready(pid),
+            ChunkOrEvent::Disconnect(pid, reason) => Event::Disconnect {
+                pid: pid,
+                remote: true,
+                reason: reason.to_owned(),
+            },
+        }
+    }
+}
+
+/// Something to be sent via `AsyncNet`'s `Sink` implementation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outgoing {
+    /// Queues a chunk for a peer, it's sent on the next flush.
+    Chunk { pid: PeerId, vital: bool, data: Vec<u8> },
+    Connless { addr: Addr, data: Vec<u8> },
+    /// Flushes the peer's queued chunks on the next `poll_complete`.
+    Flush(PeerId),
+}
+
+struct Sender {
+    start: Instant,
+    time_cached: Timestamp,
+    v4: Option<StdUdpSocket>,
+    v6: Option<StdUdpSocket>,
+}
+
+impl Sender {
+    fn update_time_cached(&mut self) {
+        self.time_cached = Timestamp::from_secs_since_epoch(0) + self.start.elapsed();
+    }
+}
+
+impl Callback<Addr> for Sender {
+    type Error = io::Error;
+    fn send(&mut self, addr: Addr, data: &[u8]) -> Result<(), io::Error> {
+        let socket = match addr.ip {
+            IpAddr::V4(..) => self.v4.as_ref(),
+            IpAddr::V6(..) => self.v6.as_ref(),
+        };
+        let socket = socket.ok_or_else(|| io::Error::new(io::ErrorKind::Other,
+            "destination address family (IPv4 or IPv6) not supported on this system"))?;
+        match socket.send_to(data, SocketAddr::new(addr.ip, addr.port)) {
+            Ok(written) if written == data.len() => Ok(()),
+            Ok(written) => Err(io::Error::new(io::ErrorKind::Other,
+                format!("datagram truncated, {} of {} bytes sent", written, data.len()))),
+            // The socket is nonblocking. UDP doesn't guarantee delivery
+            // anyway, so treat a full send buffer like a lost datagram.
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                debug!("dropping datagram to {}, send buffer full", addr);
+                Ok(())
+            },
+            Err(e) => Err(e),
+        }
+    }
+    fn time(&mut self) -> Timestamp {
+        self.time_cached
+    }
+}
+
+fn connection_error(e: connection::Error<io::Error>) -> io::Error {
+    match e {
+        connection::Error::TooLongData =>
+            io::Error::new(io::ErrorKind::InvalidInput, "too long data"),
+        connection::Error::Callback(e) => e,
+    }
+}
+
+fn udp_socket(bindaddr: &SocketAddr) -> io::Result<Option<StdUdpSocket>> {
+    let builder = match *bindaddr {
+        SocketAddr::V4(..) => UdpBuilder::new_v4(),
+        SocketAddr::V6(..) => UdpBuilder::new_v6(),
+    };
+    let builder = match builder {
+        Err(ref e) if e.raw_os_error() == Some(libc::EAFNOSUPPORT) =>
+            return Ok(None), // Address family not supported.
+        b => b?,
+    };
+    if let SocketAddr::V6(..) = *bindaddr {
+        builder.only_v6(true)?;
+    }
+    let socket = builder.bind(bindaddr)?;
+    socket.set_nonblocking(true)?;
+    Ok(Some(socket))
+}
+
+pub struct AsyncNet {
+    net: Net<Addr>,
+    sender: Sender,
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+    delay: Option<Delay>,
+    events: VecDeque<Event>,
+    want_to_flush: PeerSet,
+    buf1: [u8; 4096],
+    buf2: Vec<u8>,
+}
+
+impl AsyncNet {
+    /// Creates a server accepting connections on the given port.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn accept_connections_on_port(port: u16) -> io::Result<AsyncNet> {
+        assert!(port != 0);
+        AsyncNet::construct(port, Net::server())
+    }
+    /// Creates a client, connections can be made using `AsyncNet::connect`.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn client() -> io::Result<AsyncNet> {
+        AsyncNet::construct(0, Net::client())
+    }
+    fn construct(port: u16, net: Net<Addr>) -> io::Result<AsyncNet> {
+        let v4 = udp_socket(&SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port))?;
+        let v6 = udp_socket(&SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), port))?;
+        if v4.is_none() && v6.is_none() {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                "neither IPv4 nor IPv6 supported on this system"));
+        }
+        fn register(s: &Option<StdUdpSocket>) -> io::Result<Option<UdpSocket>> {
+            match *s {
+                Some(ref s) => Ok(Some(UdpSocket::from_std(s.try_clone()?, &Handle::default())?)),
+                None => Ok(None),
+            }
+        }
+        let mut sender = Sender {
+            start: Instant::now(),
+            time_cached: Timestamp::from_secs_since_epoch(0),
+            v4: None,
+            v6: None,
+        };
+        sender.update_time_cached();
+        let recv_v4 = register(&v4)?;
+        let recv_v6 = register(&v6)?;
+        sender.v4 = v4;
+        sender.v6 = v6;
+        Ok(AsyncNet {
+            net: net,
+            sender: sender,
+            v4: recv_v4,
+            v6: recv_v6,
+            delay: None,
+            events: VecDeque::new(),
+            want_to_flush: PeerSet::new(),
+            buf1: [0; 4096],
+            buf2: Vec::with_capacity(4096),
+        })
+    }
+    pub fn time(&mut self) -> Timestamp {
+        self.sender.time()
+    }
+    pub fn connect(&mut self, addr: Addr) -> io::Result<PeerId> {
+        let (pid, res) = self.net.connect(&mut self.sender, addr);
+        res.map(|()| pid)
+    }
+    pub fn disconnect(&mut self, pid: PeerId, reason: &[u8]) -> io::Result<()> {
+        if self.want_to_flush.contains(pid) {
+            self.want_to_flush.remove(pid);
+            self.net.flush(&mut self.sender, pid)?;
+        }
+        self.events.push_back(Event::Disconnect {
+            pid: pid,
+            remote: false,
+            reason: reason.to_owned(),
+        });
+        self.net.disconnect(&mut self.sender, pid, reason)
+    }
+    pub fn ignore(&mut self, pid: PeerId) {
+        self.net.ignore(pid);
+    }
+    pub fn accept(&mut self, pid: PeerId) -> io::Result<()> {
+        self.net.accept(&mut self.sender, pid)
+    }
+    pub fn reject(&mut self, pid: PeerId, reason: &[u8]) -> io::Result<()> {
+        self.net.reject(&mut self.sender, pid, reason)
+    }
+    fn flush_wanted(&mut self) -> io::Result<()> {
+        for pid in self.want_to_flush.drain() {
+            self.net.flush(&mut self.sender, pid)?;
+        }
+        Ok(())
+    }
+    fn receive(&mut self) -> io::Result<()> {
+        for &v6 in &[false, true] {
+            loop {
+                let socket = if v6 { self.v6.as_mut() } else { self.v4.as_mut() };
+                let socket = match socket {
+                    Some(s) => s,
+                    None => break,
+                };
+                let (len, from) = match socket.poll_recv_from(&mut self.buf1)? {
+                    Async::Ready(r) => r,
+                    Async::NotReady => break,
+                };
+                self.sender.update_time_cached();
+                let addr = Addr::from(from);
+                let data = &self.buf1[..len];
+                self.buf2.clear();
+                let (iter, res) = self.net.feed(&mut self.sender, &mut Warn(addr, data), addr, data, &mut self.buf2);
+                res?;
+                for mut chunk in iter {
+                    if !self.net.is_receive_chunk_still_valid(&mut chunk) {
+                        continue;
+                    }
+                    self.events.push_back(Event::from_chunk_or_event(chunk));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stream for AsyncNet {
+    type Item = Event;
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<Option<Event>, io::Error> {
+        loop {
+            self.sender.update_time_cached();
+            if let Some(e) = self.net.tick(&mut self.sender).next() {
+                return Err(e);
+            }
+            self.flush_wanted()?;
+            self.receive()?;
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Async::Ready(Some(event)));
+            }
+
+            self.sender.update_time_cached();
+            let now = self.sender.time();
+            let duration = match self.net.needs_tick().time_from(now) {
+                Some(d) => d,
+                None => return Ok(Async::NotReady),
+            };
+            let deadline = Instant::now() + duration;
+            match self.delay {
+                Some(ref mut d) => d.reset(deadline),
+                None => self.delay = Some(Delay::new(deadline)),
+            }
+            match self.delay.as_mut().unwrap().poll() {
+                Ok(Async::Ready(())) => continue,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+            }
+        }
+    }
+}
+
+impl Sink for AsyncNet {
+    type SinkItem = Outgoing;
+    type SinkError = io::Error;
+    fn start_send(&mut self, item: Outgoing) -> StartSend<Outgoing, io::Error> {
+        self.sender.update_time_cached();
+        match item {
+            Outgoing::Chunk { pid, vital, ref data } => {
+                self.net.send(&mut self.sender, Chunk {
+                    pid: pid,
+                    vital: vital,
+                    data: data,
+                }).map_err(connection_error)?;
+            },
+            Outgoing::Connless { addr, ref data } => {
+                self.net.send_connless(&mut self.sender, addr, data)
+                    .map_err(connection_error)?;
+            },
+            Outgoing::Flush(pid) => {
+                self.want_to_flush.insert(pid);
+            },
+        }
+        Ok(AsyncSink::Ready)
+    }
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        self.sender.update_time_cached();
+        self.flush_wanted()?;
+        Ok(Async::Ready(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::Async;
+    use futures::Poll;
+    use futures::Sink;
+    use futures::Stream;
+    use futures::future;
+    use net::net::PeerId;
+    use socket::Addr;
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+    use super::AsyncNet;
+    use super::Event;
+    use super::Outgoing;
+    use tokio::runtime::current_thread;
+    use tokio::timer::Timeout;
+
+    fn free_port() -> u16 {
+        UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    /// Connects a client to a server over loopback and returns the events
+    /// the server received, once it got a vital chunk.
+    fn loopback(port: u16) -> Vec<Event> {
+        let server_addr = Addr {
+            ip: "127.0.0.1".parse().unwrap(),
+            port: port,
+        };
+        let run = future::lazy(move || {
+            let mut server = AsyncNet::accept_connections_on_port(port).unwrap();
+            let mut client = AsyncNet::client().unwrap();
+            client.connect(server_addr).unwrap();
+            let mut events = Vec::new();
+            future::poll_fn(move || -> Poll<Vec<Event>, io::Error> {
+                loop {
+                    let mut progress = false;
+                    while let Async::Ready(Some(event)) = server.poll()? {
+                        progress = true;
+                        if let Event::Connect(pid) = event {
+                            server.accept(pid)?;
+                        }
+                        let done = matches!(event, Event::Chunk { .. });
+                        events.push(event);
+                        if done {
+                            return Ok(Async::Ready(events.split_off(0)));
+                        }
+                    }
+                    while let Async::Ready(Some(event)) = client.poll()? {
+                        progress = true;
+                        if let Event::Ready(pid) = event {
+                            send(&mut client, server_addr, pid)?;
+                        }
+                    }
+                    if !progress {
+                        return Ok(Async::NotReady);
+                    }
+                }
+            })
+        });
+        let run = Timeout::new(run, Duration::from_secs(5));
+        current_thread::block_on_all(run).unwrap()
+    }
+
+    fn send(client: &mut AsyncNet, server_addr: Addr, pid: PeerId) -> io::Result<()> {
+        client.start_send(Outgoing::Connless {
+            addr: server_addr,
+            data: b"connless".to_vec(),
+        })?;
+        client.start_send(Outgoing::Chunk {
+            pid: pid,
+            vital: true,
+            data: b"vital".to_vec(),
+        })?;
+        client.start_send(Outgoing::Flush(pid))?;
+        client.poll_complete()?;
+        Ok(())
+    }
+
+    #[test]
+    fn connect_and_send() {
+        let events = loopback(free_port());
+        let pid = match events[0] {
+            Event::Connect(pid) => pid,
+            ref e => panic!("unexpected first event {:?}", e),
+        };
+        let connless: Vec<_> = events.iter().filter_map(|e| match *e {
+            Event::Connless { ref data, .. } => Some(&data[..]),
+            _ => None,
+        }).collect();
+        assert_eq!(connless, [&b"connless"[..]]);
+        assert_eq!(events.last(), Some(&Event::Chunk {
+            pid: pid,
+            vital: true,
+            data: b"vital".to_vec(),
+        }));
+    }
+}
@@ -0,0 +1,54 @@
+//! Helpers for sending game, system and connless messages.
+
+use Addr;
+use Chunk;
+use Loop;
+use PeerId;
+use arrayvec::ArrayVec;
+use gamenet::msg::Connless;
+use gamenet::msg::Game;
+use gamenet::msg::System;
+use packer::with_packer;
+
+fn sends_impl<L: Loop+?Sized>(msg: System, pid: PeerId, vital: bool, loop_: &mut L) {
+    let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
+    with_packer(&mut buf, |p| msg.encode(p).unwrap());
+    loop_.send(Chunk {
+        pid: pid,
+        vital: vital,
+        data: &buf,
+    })
+}
+
+/// Helpers for sending game, system and connless messages.
+pub trait LoopExt: Loop {
+    fn sends<'a, S: Into<System<'a>>>(&mut self, pid: PeerId, msg: S) {
+        sends_impl(msg.into(), pid, true, self)
+    }
+    /// Sends a system message that isn't resent if it gets lost, like
+    /// `Input` or snapshots.
+    fn sends_nonvital<'a, S: Into<System<'a>>>(&mut self, pid: PeerId, msg: S) {
+        sends_impl(msg.into(), pid, false, self)
+    }
+    fn sendg<'a, G: Into<Game<'a>>>(&mut self, pid: PeerId, msg: G) {
+        fn inner<L: Loop+?Sized>(msg: Game, pid: PeerId, loop_: &mut L) {
+            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
+            with_packer(&mut buf, |p| msg.encode(p).unwrap());
+            loop_.send(Chunk {
+                pid: pid,
+                vital: true,
+                data: &buf,
+            })
+        }
+        inner(msg.into(), pid, self)
+    }
+    fn sendc<'a, C: Into<Connless<'a>>>(&mut self, addr: Addr, msg: C) {
+        fn inner<L: Loop+?Sized>(msg: Connless, addr: Addr, loop_: &mut L) {
+            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
+            with_packer(&mut buf, |p| msg.encode(p).unwrap());
+            loop_.send_connless(addr, &buf)
+        }
+        inner(msg.into(), addr, self)
+    }
+}
+impl<L: Loop> LoopExt for L { }
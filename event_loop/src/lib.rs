@@ -1,11 +1,17 @@
 extern crate arrayvec;
 extern crate common;
+#[cfg(feature = "async")] extern crate futures;
+extern crate gamenet;
 extern crate hexdump;
 extern crate itertools;
+#[cfg(feature = "async")] extern crate libc;
 #[macro_use] extern crate log;
 extern crate logger;
 extern crate net;
+#[cfg(feature = "async")] extern crate net2;
+extern crate packer;
 extern crate socket;
+#[cfg(feature = "async")] extern crate tokio;
 extern crate warn;
 
 use arrayvec::ArrayVec;
@@ -21,6 +27,11 @@ use socket::Socket;
 use std::cmp;
 use std::fmt;
 
+#[cfg(feature = "async")] pub mod async_net;
+mod ext;
+
+pub use ext::LoopExt;
+
 pub use net::Timeout;
 pub use net::Timestamp;
 pub use net::collections;
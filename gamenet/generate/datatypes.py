@@ -406,6 +406,28 @@ def emit_enum_from(name, structs):
         print("    }")
         print("}")
 
+def emit_enum_fields(name, structs):
+    import_(
+        "fields::Fields",
+        "fields::Visitor",
+    )
+    lifetime = "<'a>" if any(s.lifetime() for s in structs) else ""
+    print()
+    print("impl{l} Fields for {}{l} {{".format(title(name), l=lifetime))
+    print("    fn type_name(&self) -> &'static str {")
+    print("        match *self {")
+    for s in structs:
+        print("            {}::{}(ref i) => i.type_name(),".format(title(name), title(s.name)))
+    print("        }")
+    print("    }")
+    print("    fn visit_fields(&self, v: &mut Visitor) {")
+    print("        match *self {")
+    for s in structs:
+        print("            {}::{}(ref i) => i.visit_fields(v),".format(title(name), title(s.name)))
+    print("        }")
+    print("    }")
+    print("}")
+
 def emit_enum_msg(name, structs):
     import_(
         "buffer::CapacityError",
@@ -450,6 +472,7 @@ def emit_enum_msg(name, structs):
     print("        }")
     print("    }")
     print("}")
+    emit_enum_fields(name, structs)
     emit_enum_from(name, structs)
 
 def emit_enum_msg_module(name, structs):
@@ -463,6 +486,7 @@ def emit_enum_msg_module(name, structs):
     for s in structs:
         s.emit_impl_encode_decode()
         s.emit_impl_debug()
+        s.emit_impl_fields()
         print()
 
 def emit_enum_obj(name, structs):
@@ -507,6 +531,7 @@ def emit_enum_obj(name, structs):
     print("        }")
     print("    }")
     print("}")
+    emit_enum_fields(name, structs)
     emit_enum_from(name, structs)
 
 def emit_enum_obj_module(name, structs, flags):
@@ -523,6 +548,7 @@ def emit_enum_obj_module(name, structs, flags):
         print()
     for s in structs:
         s.emit_impl_debug()
+        s.emit_impl_fields()
         s.emit_impl_encode_decode_int()
         print()
     emit_snap_obj_sizes(structs)
@@ -569,6 +595,7 @@ def emit_enum_connless(name, structs):
     print("        }")
     print("    }")
     print("}")
+    emit_enum_fields(name, structs)
     emit_enum_from(name, structs)
 
 def emit_enum_connless_module(name, structs):
@@ -582,6 +609,7 @@ def emit_enum_connless_module(name, structs):
     for s in structs:
         s.emit_impl_encode_decode()
         s.emit_impl_debug()
+        s.emit_impl_fields()
         print()
 
 def emit_snap_obj_sizes(objects):
@@ -760,6 +788,24 @@ class Struct(NameValues):
         print("            .finish()")
         print("    }")
         print("}")
+    def emit_impl_fields(self):
+        import_(
+            "fields::Fields",
+            "fields::Visitor",
+        )
+        print("impl{l} Fields for {}{l} {{".format(title(self.name), l=self.lifetime()))
+        print("    fn type_name(&self) -> &'static str {")
+        print("        \"{}\"".format(title(self.name)))
+        print("    }")
+        print("    fn visit_fields(&self, _v: &mut Visitor) {")
+        if self.super:
+            super = self.structs[self.super]
+            print("        _v.nested(\"{n}\", &self.{n});".format(n=snake(super.name)))
+        with indent(2):
+            for m in self.values:
+                m.emit_field()
+        print("    }")
+        print("}")
 
 class NetObject(Struct):
     const_type = "u16"
@@ -847,6 +893,8 @@ class Member:
         print("try!({});".format(self.encode_expr("self.{}".format(snake(self.name)))))
     def emit_debug(self):
         print(".field(\"{}\", &{})".format(snake(self.name), self.debug_expr("self.{}".format(snake(self.name)))))
+    def emit_field(self):
+        print("_v.field(\"{}\", &{});".format(snake(self.name), self.debug_expr("self.{}".format(snake(self.name)))))
     def validate_expr(self, self_expr):
         pass
     def assert_expr(self, self_expr):
@@ -1044,6 +1092,8 @@ class NetStruct(Member):
     def encode_expr(self, self_expr):
         import_("packer::with_packer")
         return "with_packer(&mut _p, |p| {}.encode_msg(p))".format(self_expr)
+    def emit_field(self):
+        print("_v.nested(\"{n}\", &self.{n});".format(n=snake(self.name)))
 
 class NetAddrs(Member):
     type_ = "&'a [AddrPacked]"
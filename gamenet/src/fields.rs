@@ -0,0 +1,24 @@
+//! Field-wise access to messages and snapshot objects.
+//!
+//! Used by tools that display the decoded data in a structured way, like
+//! the Wireshark dissector. The implementations are generated along with the
+//! message and object definitions.
+
+use std::fmt;
+
+/// Receives the fields of a message or snapshot object, see
+/// `Fields::visit_fields`.
+pub trait Visitor {
+    /// Called for each field with its name and value.
+    fn field(&mut self, name: &'static str, value: &fmt::Debug);
+    /// Called for fields that have fields themselves.
+    fn nested(&mut self, name: &'static str, value: &Fields);
+}
+
+/// A message or snapshot object.
+pub trait Fields {
+    /// Returns the name of the type, e.g. `"MapChange"`.
+    fn type_name(&self) -> &'static str;
+    /// Calls the visitor for each field, in the order of the definition.
+    fn visit_fields(&self, visitor: &mut Visitor);
+}
@@ -9,6 +9,7 @@ extern crate warn;
 
 pub mod enums;
 pub mod error;
+pub mod fields;
 pub mod msg;
 pub mod snap_obj;
 
@@ -2,6 +2,8 @@ use buffer::CapacityError;
 use common::num::BeU16;
 use common::pretty;
 use error::Error;
+use fields::Fields;
+use fields::Visitor;
 use packer::Packer;
 use packer::Unpacker;
 use packer::Warning;
@@ -159,6 +161,39 @@ impl<'a> fmt::Debug for Connless<'a> {
     }
 }
 
+impl<'a> Fields for Connless<'a> {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Connless::RequestList(ref i) => i.type_name(),
+            Connless::List(ref i) => i.type_name(),
+            Connless::RequestCount(ref i) => i.type_name(),
+            Connless::Count(ref i) => i.type_name(),
+            Connless::RequestInfo(ref i) => i.type_name(),
+            Connless::Info(ref i) => i.type_name(),
+            Connless::Heartbeat(ref i) => i.type_name(),
+            Connless::ForwardCheck(ref i) => i.type_name(),
+            Connless::ForwardResponse(ref i) => i.type_name(),
+            Connless::ForwardOk(ref i) => i.type_name(),
+            Connless::ForwardError(ref i) => i.type_name(),
+        }
+    }
+    fn visit_fields(&self, v: &mut Visitor) {
+        match *self {
+            Connless::RequestList(ref i) => i.visit_fields(v),
+            Connless::List(ref i) => i.visit_fields(v),
+            Connless::RequestCount(ref i) => i.visit_fields(v),
+            Connless::Count(ref i) => i.visit_fields(v),
+            Connless::RequestInfo(ref i) => i.visit_fields(v),
+            Connless::Info(ref i) => i.visit_fields(v),
+            Connless::Heartbeat(ref i) => i.visit_fields(v),
+            Connless::ForwardCheck(ref i) => i.visit_fields(v),
+            Connless::ForwardResponse(ref i) => i.visit_fields(v),
+            Connless::ForwardOk(ref i) => i.visit_fields(v),
+            Connless::ForwardError(ref i) => i.visit_fields(v),
+        }
+    }
+}
+
 impl<'a> From<RequestList> for Connless<'a> {
     fn from(i: RequestList) -> Connless<'a> {
         Connless::RequestList(i)
@@ -293,6 +328,13 @@ impl fmt::Debug for RequestList {
             .finish()
     }
 }
+impl Fields for RequestList {
+    fn type_name(&self) -> &'static str {
+        "RequestList"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl<'a> List<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<List<'a>, Error> {
@@ -314,6 +356,14 @@ impl<'a> fmt::Debug for List<'a> {
             .finish()
     }
 }
+impl<'a> Fields for List<'a> {
+    fn type_name(&self) -> &'static str {
+        "List"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("servers", &self.servers);
+    }
+}
 
 impl RequestCount {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<RequestCount, Error> {
@@ -331,6 +381,13 @@ impl fmt::Debug for RequestCount {
             .finish()
     }
 }
+impl Fields for RequestCount {
+    fn type_name(&self) -> &'static str {
+        "RequestCount"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl Count {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<Count, Error> {
@@ -352,6 +409,14 @@ impl fmt::Debug for Count {
             .finish()
     }
 }
+impl Fields for Count {
+    fn type_name(&self) -> &'static str {
+        "Count"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("count", &self.count);
+    }
+}
 
 impl RequestInfo {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<RequestInfo, Error> {
@@ -373,6 +438,14 @@ impl fmt::Debug for RequestInfo {
             .finish()
     }
 }
+impl Fields for RequestInfo {
+    fn type_name(&self) -> &'static str {
+        "RequestInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("token", &self.token);
+    }
+}
 
 impl<'a> Info<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<Info<'a>, Error> {
@@ -424,6 +497,24 @@ impl<'a> fmt::Debug for Info<'a> {
             .finish()
     }
 }
+impl<'a> Fields for Info<'a> {
+    fn type_name(&self) -> &'static str {
+        "Info"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("token", &self.token);
+        _v.field("version", &pretty::Bytes::new(&self.version));
+        _v.field("name", &pretty::Bytes::new(&self.name));
+        _v.field("map", &pretty::Bytes::new(&self.map));
+        _v.field("game_type", &pretty::Bytes::new(&self.game_type));
+        _v.field("flags", &self.flags);
+        _v.field("num_players", &self.num_players);
+        _v.field("max_players", &self.max_players);
+        _v.field("num_clients", &self.num_clients);
+        _v.field("max_clients", &self.max_clients);
+        _v.field("clients", &self.clients);
+    }
+}
 
 impl Heartbeat {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<Heartbeat, Error> {
@@ -445,6 +536,14 @@ impl fmt::Debug for Heartbeat {
             .finish()
     }
 }
+impl Fields for Heartbeat {
+    fn type_name(&self) -> &'static str {
+        "Heartbeat"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("alt_port", &self.alt_port);
+    }
+}
 
 impl ForwardCheck {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ForwardCheck, Error> {
@@ -462,6 +561,13 @@ impl fmt::Debug for ForwardCheck {
             .finish()
     }
 }
+impl Fields for ForwardCheck {
+    fn type_name(&self) -> &'static str {
+        "ForwardCheck"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl ForwardResponse {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ForwardResponse, Error> {
@@ -479,6 +585,13 @@ impl fmt::Debug for ForwardResponse {
             .finish()
     }
 }
+impl Fields for ForwardResponse {
+    fn type_name(&self) -> &'static str {
+        "ForwardResponse"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl ForwardOk {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ForwardOk, Error> {
@@ -496,6 +609,13 @@ impl fmt::Debug for ForwardOk {
             .finish()
     }
 }
+impl Fields for ForwardOk {
+    fn type_name(&self) -> &'static str {
+        "ForwardOk"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl ForwardError {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ForwardError, Error> {
@@ -513,4 +633,11 @@ impl fmt::Debug for ForwardError {
             .finish()
     }
 }
+impl Fields for ForwardError {
+    fn type_name(&self) -> &'static str {
+        "ForwardError"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
@@ -8,6 +8,8 @@ use enums::Sound;
 use enums::Team;
 use enums::Weapon;
 use error::Error;
+use fields::Fields;
+use fields::Visitor;
 use packer::Packer;
 use packer::Unpacker;
 use packer::Warning;
@@ -271,6 +273,67 @@ impl<'a> fmt::Debug for Game<'a> {
     }
 }
 
+impl<'a> Fields for Game<'a> {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Game::SvMotd(ref i) => i.type_name(),
+            Game::SvBroadcast(ref i) => i.type_name(),
+            Game::SvChat(ref i) => i.type_name(),
+            Game::SvKillMsg(ref i) => i.type_name(),
+            Game::SvSoundGlobal(ref i) => i.type_name(),
+            Game::SvTuneParams(ref i) => i.type_name(),
+            Game::SvExtraProjectile(ref i) => i.type_name(),
+            Game::SvReadyToEnter(ref i) => i.type_name(),
+            Game::SvWeaponPickup(ref i) => i.type_name(),
+            Game::SvEmoticon(ref i) => i.type_name(),
+            Game::SvVoteClearOptions(ref i) => i.type_name(),
+            Game::SvVoteOptionListAdd(ref i) => i.type_name(),
+            Game::SvVoteOptionAdd(ref i) => i.type_name(),
+            Game::SvVoteOptionRemove(ref i) => i.type_name(),
+            Game::SvVoteSet(ref i) => i.type_name(),
+            Game::SvVoteStatus(ref i) => i.type_name(),
+            Game::ClSay(ref i) => i.type_name(),
+            Game::ClSetTeam(ref i) => i.type_name(),
+            Game::ClSetSpectatorMode(ref i) => i.type_name(),
+            Game::ClStartInfo(ref i) => i.type_name(),
+            Game::ClChangeInfo(ref i) => i.type_name(),
+            Game::ClKill(ref i) => i.type_name(),
+            Game::ClEmoticon(ref i) => i.type_name(),
+            Game::ClVote(ref i) => i.type_name(),
+            Game::ClCallVote(ref i) => i.type_name(),
+        }
+    }
+    fn visit_fields(&self, v: &mut Visitor) {
+        match *self {
+            Game::SvMotd(ref i) => i.visit_fields(v),
+            Game::SvBroadcast(ref i) => i.visit_fields(v),
+            Game::SvChat(ref i) => i.visit_fields(v),
+            Game::SvKillMsg(ref i) => i.visit_fields(v),
+            Game::SvSoundGlobal(ref i) => i.visit_fields(v),
+            Game::SvTuneParams(ref i) => i.visit_fields(v),
+            Game::SvExtraProjectile(ref i) => i.visit_fields(v),
+            Game::SvReadyToEnter(ref i) => i.visit_fields(v),
+            Game::SvWeaponPickup(ref i) => i.visit_fields(v),
+            Game::SvEmoticon(ref i) => i.visit_fields(v),
+            Game::SvVoteClearOptions(ref i) => i.visit_fields(v),
+            Game::SvVoteOptionListAdd(ref i) => i.visit_fields(v),
+            Game::SvVoteOptionAdd(ref i) => i.visit_fields(v),
+            Game::SvVoteOptionRemove(ref i) => i.visit_fields(v),
+            Game::SvVoteSet(ref i) => i.visit_fields(v),
+            Game::SvVoteStatus(ref i) => i.visit_fields(v),
+            Game::ClSay(ref i) => i.visit_fields(v),
+            Game::ClSetTeam(ref i) => i.visit_fields(v),
+            Game::ClSetSpectatorMode(ref i) => i.visit_fields(v),
+            Game::ClStartInfo(ref i) => i.visit_fields(v),
+            Game::ClChangeInfo(ref i) => i.visit_fields(v),
+            Game::ClKill(ref i) => i.visit_fields(v),
+            Game::ClEmoticon(ref i) => i.visit_fields(v),
+            Game::ClVote(ref i) => i.visit_fields(v),
+            Game::ClCallVote(ref i) => i.visit_fields(v),
+        }
+    }
+}
+
 impl<'a> From<SvMotd<'a>> for Game<'a> {
     fn from(i: SvMotd<'a>) -> Game<'a> {
         Game::SvMotd(i)
@@ -618,6 +681,14 @@ impl<'a> fmt::Debug for SvMotd<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvMotd<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvMotd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("message", &pretty::Bytes::new(&self.message));
+    }
+}
 
 impl<'a> SvBroadcast<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvBroadcast<'a>, Error> {
@@ -639,6 +710,14 @@ impl<'a> fmt::Debug for SvBroadcast<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvBroadcast<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvBroadcast"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("message", &pretty::Bytes::new(&self.message));
+    }
+}
 
 impl<'a> SvChat<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvChat<'a>, Error> {
@@ -668,6 +747,16 @@ impl<'a> fmt::Debug for SvChat<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvChat<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvChat"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("team", &self.team);
+        _v.field("client_id", &self.client_id);
+        _v.field("message", &pretty::Bytes::new(&self.message));
+    }
+}
 
 impl SvKillMsg {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvKillMsg, Error> {
@@ -701,6 +790,17 @@ impl fmt::Debug for SvKillMsg {
             .finish()
     }
 }
+impl Fields for SvKillMsg {
+    fn type_name(&self) -> &'static str {
+        "SvKillMsg"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("killer", &self.killer);
+        _v.field("victim", &self.victim);
+        _v.field("weapon", &self.weapon);
+        _v.field("mode_special", &self.mode_special);
+    }
+}
 
 impl SvSoundGlobal {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvSoundGlobal, Error> {
@@ -722,6 +822,14 @@ impl fmt::Debug for SvSoundGlobal {
             .finish()
     }
 }
+impl Fields for SvSoundGlobal {
+    fn type_name(&self) -> &'static str {
+        "SvSoundGlobal"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("sound_id", &self.sound_id);
+    }
+}
 
 impl SvTuneParams {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvTuneParams, Error> {
@@ -839,6 +947,46 @@ impl fmt::Debug for SvTuneParams {
             .finish()
     }
 }
+impl Fields for SvTuneParams {
+    fn type_name(&self) -> &'static str {
+        "SvTuneParams"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("ground_control_speed", &self.ground_control_speed);
+        _v.field("ground_control_accel", &self.ground_control_accel);
+        _v.field("ground_friction", &self.ground_friction);
+        _v.field("ground_jump_impulse", &self.ground_jump_impulse);
+        _v.field("air_jump_impulse", &self.air_jump_impulse);
+        _v.field("air_control_speed", &self.air_control_speed);
+        _v.field("air_control_accel", &self.air_control_accel);
+        _v.field("air_friction", &self.air_friction);
+        _v.field("hook_length", &self.hook_length);
+        _v.field("hook_fire_speed", &self.hook_fire_speed);
+        _v.field("hook_drag_accel", &self.hook_drag_accel);
+        _v.field("hook_drag_speed", &self.hook_drag_speed);
+        _v.field("gravity", &self.gravity);
+        _v.field("velramp_start", &self.velramp_start);
+        _v.field("velramp_range", &self.velramp_range);
+        _v.field("velramp_curvature", &self.velramp_curvature);
+        _v.field("gun_curvature", &self.gun_curvature);
+        _v.field("gun_speed", &self.gun_speed);
+        _v.field("gun_lifetime", &self.gun_lifetime);
+        _v.field("shotgun_curvature", &self.shotgun_curvature);
+        _v.field("shotgun_speed", &self.shotgun_speed);
+        _v.field("shotgun_speeddiff", &self.shotgun_speeddiff);
+        _v.field("shotgun_lifetime", &self.shotgun_lifetime);
+        _v.field("grenade_curvature", &self.grenade_curvature);
+        _v.field("grenade_speed", &self.grenade_speed);
+        _v.field("grenade_lifetime", &self.grenade_lifetime);
+        _v.field("laser_reach", &self.laser_reach);
+        _v.field("laser_bounce_delay", &self.laser_bounce_delay);
+        _v.field("laser_bounce_num", &self.laser_bounce_num);
+        _v.field("laser_bounce_cost", &self.laser_bounce_cost);
+        _v.field("laser_damage", &self.laser_damage);
+        _v.field("player_collision", &self.player_collision);
+        _v.field("player_hooking", &self.player_hooking);
+    }
+}
 
 impl SvExtraProjectile {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvExtraProjectile, Error> {
@@ -860,6 +1008,14 @@ impl fmt::Debug for SvExtraProjectile {
             .finish()
     }
 }
+impl Fields for SvExtraProjectile {
+    fn type_name(&self) -> &'static str {
+        "SvExtraProjectile"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("projectile", &self.projectile);
+    }
+}
 
 impl SvReadyToEnter {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvReadyToEnter, Error> {
@@ -877,6 +1033,13 @@ impl fmt::Debug for SvReadyToEnter {
             .finish()
     }
 }
+impl Fields for SvReadyToEnter {
+    fn type_name(&self) -> &'static str {
+        "SvReadyToEnter"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl SvWeaponPickup {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvWeaponPickup, Error> {
@@ -898,6 +1061,14 @@ impl fmt::Debug for SvWeaponPickup {
             .finish()
     }
 }
+impl Fields for SvWeaponPickup {
+    fn type_name(&self) -> &'static str {
+        "SvWeaponPickup"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("weapon", &self.weapon);
+    }
+}
 
 impl SvEmoticon {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvEmoticon, Error> {
@@ -923,6 +1094,15 @@ impl fmt::Debug for SvEmoticon {
             .finish()
     }
 }
+impl Fields for SvEmoticon {
+    fn type_name(&self) -> &'static str {
+        "SvEmoticon"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("client_id", &self.client_id);
+        _v.field("emoticon", &self.emoticon);
+    }
+}
 
 impl SvVoteClearOptions {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvVoteClearOptions, Error> {
@@ -940,6 +1120,13 @@ impl fmt::Debug for SvVoteClearOptions {
             .finish()
     }
 }
+impl Fields for SvVoteClearOptions {
+    fn type_name(&self) -> &'static str {
+        "SvVoteClearOptions"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl<'a> SvVoteOptionListAdd<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvVoteOptionListAdd<'a>, Error> {
@@ -986,6 +1173,15 @@ impl<'a> fmt::Debug for SvVoteOptionListAdd<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvVoteOptionListAdd<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvVoteOptionListAdd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("num_options", &self.num_options);
+        _v.field("description", &DebugSlice::new(&self.description, |e| pretty::Bytes::new(&e)));
+    }
+}
 
 impl<'a> SvVoteOptionAdd<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvVoteOptionAdd<'a>, Error> {
@@ -1008,6 +1204,14 @@ impl<'a> fmt::Debug for SvVoteOptionAdd<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvVoteOptionAdd<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvVoteOptionAdd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("description", &pretty::Bytes::new(&self.description));
+    }
+}
 
 impl<'a> SvVoteOptionRemove<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvVoteOptionRemove<'a>, Error> {
@@ -1030,6 +1234,14 @@ impl<'a> fmt::Debug for SvVoteOptionRemove<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvVoteOptionRemove<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvVoteOptionRemove"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("description", &pretty::Bytes::new(&self.description));
+    }
+}
 
 impl<'a> SvVoteSet<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SvVoteSet<'a>, Error> {
@@ -1060,6 +1272,16 @@ impl<'a> fmt::Debug for SvVoteSet<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SvVoteSet<'a> {
+    fn type_name(&self) -> &'static str {
+        "SvVoteSet"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("timeout", &self.timeout);
+        _v.field("description", &pretty::Bytes::new(&self.description));
+        _v.field("reason", &pretty::Bytes::new(&self.reason));
+    }
+}
 
 impl SvVoteStatus {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SvVoteStatus, Error> {
@@ -1094,6 +1316,17 @@ impl fmt::Debug for SvVoteStatus {
             .finish()
     }
 }
+impl Fields for SvVoteStatus {
+    fn type_name(&self) -> &'static str {
+        "SvVoteStatus"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("yes", &self.yes);
+        _v.field("no", &self.no);
+        _v.field("pass", &self.pass);
+        _v.field("total", &self.total);
+    }
+}
 
 impl<'a> ClSay<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<ClSay<'a>, Error> {
@@ -1119,6 +1352,15 @@ impl<'a> fmt::Debug for ClSay<'a> {
             .finish()
     }
 }
+impl<'a> Fields for ClSay<'a> {
+    fn type_name(&self) -> &'static str {
+        "ClSay"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("team", &self.team);
+        _v.field("message", &pretty::Bytes::new(&self.message));
+    }
+}
 
 impl ClSetTeam {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ClSetTeam, Error> {
@@ -1140,6 +1382,14 @@ impl fmt::Debug for ClSetTeam {
             .finish()
     }
 }
+impl Fields for ClSetTeam {
+    fn type_name(&self) -> &'static str {
+        "ClSetTeam"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("team", &self.team);
+    }
+}
 
 impl ClSetSpectatorMode {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ClSetSpectatorMode, Error> {
@@ -1162,6 +1412,14 @@ impl fmt::Debug for ClSetSpectatorMode {
             .finish()
     }
 }
+impl Fields for ClSetSpectatorMode {
+    fn type_name(&self) -> &'static str {
+        "ClSetSpectatorMode"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("spectator_id", &self.spectator_id);
+    }
+}
 
 impl<'a> ClStartInfo<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<ClStartInfo<'a>, Error> {
@@ -1204,6 +1462,20 @@ impl<'a> fmt::Debug for ClStartInfo<'a> {
             .finish()
     }
 }
+impl<'a> Fields for ClStartInfo<'a> {
+    fn type_name(&self) -> &'static str {
+        "ClStartInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &pretty::Bytes::new(&self.name));
+        _v.field("clan", &pretty::Bytes::new(&self.clan));
+        _v.field("country", &self.country);
+        _v.field("skin", &pretty::Bytes::new(&self.skin));
+        _v.field("use_custom_color", &self.use_custom_color);
+        _v.field("color_body", &self.color_body);
+        _v.field("color_feet", &self.color_feet);
+    }
+}
 
 impl<'a> ClChangeInfo<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<ClChangeInfo<'a>, Error> {
@@ -1246,6 +1518,20 @@ impl<'a> fmt::Debug for ClChangeInfo<'a> {
             .finish()
     }
 }
+impl<'a> Fields for ClChangeInfo<'a> {
+    fn type_name(&self) -> &'static str {
+        "ClChangeInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &pretty::Bytes::new(&self.name));
+        _v.field("clan", &pretty::Bytes::new(&self.clan));
+        _v.field("country", &self.country);
+        _v.field("skin", &pretty::Bytes::new(&self.skin));
+        _v.field("use_custom_color", &self.use_custom_color);
+        _v.field("color_body", &self.color_body);
+        _v.field("color_feet", &self.color_feet);
+    }
+}
 
 impl ClKill {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ClKill, Error> {
@@ -1263,6 +1549,13 @@ impl fmt::Debug for ClKill {
             .finish()
     }
 }
+impl Fields for ClKill {
+    fn type_name(&self) -> &'static str {
+        "ClKill"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl ClEmoticon {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ClEmoticon, Error> {
@@ -1284,6 +1577,14 @@ impl fmt::Debug for ClEmoticon {
             .finish()
     }
 }
+impl Fields for ClEmoticon {
+    fn type_name(&self) -> &'static str {
+        "ClEmoticon"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("emoticon", &self.emoticon);
+    }
+}
 
 impl ClVote {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ClVote, Error> {
@@ -1306,6 +1607,14 @@ impl fmt::Debug for ClVote {
             .finish()
     }
 }
+impl Fields for ClVote {
+    fn type_name(&self) -> &'static str {
+        "ClVote"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("vote", &self.vote);
+    }
+}
 
 impl<'a> ClCallVote<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<ClCallVote<'a>, Error> {
@@ -1336,4 +1645,14 @@ impl<'a> fmt::Debug for ClCallVote<'a> {
             .finish()
     }
 }
+impl<'a> Fields for ClCallVote<'a> {
+    fn type_name(&self) -> &'static str {
+        "ClCallVote"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("type_", &pretty::Bytes::new(&self.type_));
+        _v.field("value", &pretty::Bytes::new(&self.value));
+        _v.field("reason", &pretty::Bytes::new(&self.reason));
+    }
+}
 
@@ -1,6 +1,8 @@
 use buffer::CapacityError;
 use common::pretty;
 use error::Error;
+use fields::Fields;
+use fields::Visitor;
 use packer::Packer;
 use packer::Unpacker;
 use packer::Warning;
@@ -178,6 +180,57 @@ impl<'a> fmt::Debug for System<'a> {
     }
 }
 
+impl<'a> Fields for System<'a> {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            System::Info(ref i) => i.type_name(),
+            System::MapChange(ref i) => i.type_name(),
+            System::MapData(ref i) => i.type_name(),
+            System::ConReady(ref i) => i.type_name(),
+            System::Snap(ref i) => i.type_name(),
+            System::SnapEmpty(ref i) => i.type_name(),
+            System::SnapSingle(ref i) => i.type_name(),
+            System::InputTiming(ref i) => i.type_name(),
+            System::RconAuthStatus(ref i) => i.type_name(),
+            System::RconLine(ref i) => i.type_name(),
+            System::Ready(ref i) => i.type_name(),
+            System::EnterGame(ref i) => i.type_name(),
+            System::Input(ref i) => i.type_name(),
+            System::RconCmd(ref i) => i.type_name(),
+            System::RconAuth(ref i) => i.type_name(),
+            System::RequestMapData(ref i) => i.type_name(),
+            System::Ping(ref i) => i.type_name(),
+            System::PingReply(ref i) => i.type_name(),
+            System::RconCmdAdd(ref i) => i.type_name(),
+            System::RconCmdRemove(ref i) => i.type_name(),
+        }
+    }
+    fn visit_fields(&self, v: &mut Visitor) {
+        match *self {
+            System::Info(ref i) => i.visit_fields(v),
+            System::MapChange(ref i) => i.visit_fields(v),
+            System::MapData(ref i) => i.visit_fields(v),
+            System::ConReady(ref i) => i.visit_fields(v),
+            System::Snap(ref i) => i.visit_fields(v),
+            System::SnapEmpty(ref i) => i.visit_fields(v),
+            System::SnapSingle(ref i) => i.visit_fields(v),
+            System::InputTiming(ref i) => i.visit_fields(v),
+            System::RconAuthStatus(ref i) => i.visit_fields(v),
+            System::RconLine(ref i) => i.visit_fields(v),
+            System::Ready(ref i) => i.visit_fields(v),
+            System::EnterGame(ref i) => i.visit_fields(v),
+            System::Input(ref i) => i.visit_fields(v),
+            System::RconCmd(ref i) => i.visit_fields(v),
+            System::RconAuth(ref i) => i.visit_fields(v),
+            System::RequestMapData(ref i) => i.visit_fields(v),
+            System::Ping(ref i) => i.visit_fields(v),
+            System::PingReply(ref i) => i.visit_fields(v),
+            System::RconCmdAdd(ref i) => i.visit_fields(v),
+            System::RconCmdRemove(ref i) => i.visit_fields(v),
+        }
+    }
+}
+
 impl<'a> From<Info<'a>> for System<'a> {
     fn from(i: Info<'a>) -> System<'a> {
         System::Info(i)
@@ -435,6 +488,15 @@ impl<'a> fmt::Debug for Info<'a> {
             .finish()
     }
 }
+impl<'a> Fields for Info<'a> {
+    fn type_name(&self) -> &'static str {
+        "Info"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("version", &pretty::Bytes::new(&self.version));
+        _v.field("password", &self.password.as_ref().map(|v| pretty::Bytes::new(&v)));
+    }
+}
 
 impl<'a> MapChange<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<MapChange<'a>, Error> {
@@ -462,6 +524,16 @@ impl<'a> fmt::Debug for MapChange<'a> {
             .finish()
     }
 }
+impl<'a> Fields for MapChange<'a> {
+    fn type_name(&self) -> &'static str {
+        "MapChange"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &pretty::Bytes::new(&self.name));
+        _v.field("crc", &self.crc);
+        _v.field("size", &self.size);
+    }
+}
 
 impl<'a> MapData<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<MapData<'a>, Error> {
@@ -492,6 +564,17 @@ impl<'a> fmt::Debug for MapData<'a> {
             .finish()
     }
 }
+impl<'a> Fields for MapData<'a> {
+    fn type_name(&self) -> &'static str {
+        "MapData"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("last", &self.last);
+        _v.field("crc", &self.crc);
+        _v.field("chunk", &self.chunk);
+        _v.field("data", &pretty::Bytes::new(&self.data));
+    }
+}
 
 impl ConReady {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<ConReady, Error> {
@@ -509,6 +592,13 @@ impl fmt::Debug for ConReady {
             .finish()
     }
 }
+impl Fields for ConReady {
+    fn type_name(&self) -> &'static str {
+        "ConReady"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl<'a> Snap<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<Snap<'a>, Error> {
@@ -545,6 +635,19 @@ impl<'a> fmt::Debug for Snap<'a> {
             .finish()
     }
 }
+impl<'a> Fields for Snap<'a> {
+    fn type_name(&self) -> &'static str {
+        "Snap"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("tick", &self.tick);
+        _v.field("delta_tick", &self.delta_tick);
+        _v.field("num_parts", &self.num_parts);
+        _v.field("part", &self.part);
+        _v.field("crc", &self.crc);
+        _v.field("data", &pretty::Bytes::new(&self.data));
+    }
+}
 
 impl SnapEmpty {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<SnapEmpty, Error> {
@@ -569,6 +672,15 @@ impl fmt::Debug for SnapEmpty {
             .finish()
     }
 }
+impl Fields for SnapEmpty {
+    fn type_name(&self) -> &'static str {
+        "SnapEmpty"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("tick", &self.tick);
+        _v.field("delta_tick", &self.delta_tick);
+    }
+}
 
 impl<'a> SnapSingle<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<SnapSingle<'a>, Error> {
@@ -599,6 +711,17 @@ impl<'a> fmt::Debug for SnapSingle<'a> {
             .finish()
     }
 }
+impl<'a> Fields for SnapSingle<'a> {
+    fn type_name(&self) -> &'static str {
+        "SnapSingle"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("tick", &self.tick);
+        _v.field("delta_tick", &self.delta_tick);
+        _v.field("crc", &self.crc);
+        _v.field("data", &pretty::Bytes::new(&self.data));
+    }
+}
 
 impl InputTiming {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<InputTiming, Error> {
@@ -623,6 +746,15 @@ impl fmt::Debug for InputTiming {
             .finish()
     }
 }
+impl Fields for InputTiming {
+    fn type_name(&self) -> &'static str {
+        "InputTiming"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("input_pred_tick", &self.input_pred_tick);
+        _v.field("time_left", &self.time_left);
+    }
+}
 
 impl RconAuthStatus {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<RconAuthStatus, Error> {
@@ -649,6 +781,15 @@ impl fmt::Debug for RconAuthStatus {
             .finish()
     }
 }
+impl Fields for RconAuthStatus {
+    fn type_name(&self) -> &'static str {
+        "RconAuthStatus"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("auth_level", &self.auth_level.as_ref().map(|v| v));
+        _v.field("receive_commands", &self.receive_commands.as_ref().map(|v| v));
+    }
+}
 
 impl<'a> RconLine<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<RconLine<'a>, Error> {
@@ -670,6 +811,14 @@ impl<'a> fmt::Debug for RconLine<'a> {
             .finish()
     }
 }
+impl<'a> Fields for RconLine<'a> {
+    fn type_name(&self) -> &'static str {
+        "RconLine"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("line", &pretty::Bytes::new(&self.line));
+    }
+}
 
 impl Ready {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<Ready, Error> {
@@ -687,6 +836,13 @@ impl fmt::Debug for Ready {
             .finish()
     }
 }
+impl Fields for Ready {
+    fn type_name(&self) -> &'static str {
+        "Ready"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl EnterGame {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<EnterGame, Error> {
@@ -704,6 +860,13 @@ impl fmt::Debug for EnterGame {
             .finish()
     }
 }
+impl Fields for EnterGame {
+    fn type_name(&self) -> &'static str {
+        "EnterGame"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl Input {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<Input, Error> {
@@ -734,6 +897,17 @@ impl fmt::Debug for Input {
             .finish()
     }
 }
+impl Fields for Input {
+    fn type_name(&self) -> &'static str {
+        "Input"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("ack_snapshot", &self.ack_snapshot);
+        _v.field("intended_tick", &self.intended_tick);
+        _v.field("input_size", &self.input_size);
+        _v.nested("input", &self.input);
+    }
+}
 
 impl<'a> RconCmd<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<RconCmd<'a>, Error> {
@@ -755,6 +929,14 @@ impl<'a> fmt::Debug for RconCmd<'a> {
             .finish()
     }
 }
+impl<'a> Fields for RconCmd<'a> {
+    fn type_name(&self) -> &'static str {
+        "RconCmd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("cmd", &pretty::Bytes::new(&self.cmd));
+    }
+}
 
 impl<'a> RconAuth<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<RconAuth<'a>, Error> {
@@ -783,6 +965,16 @@ impl<'a> fmt::Debug for RconAuth<'a> {
             .finish()
     }
 }
+impl<'a> Fields for RconAuth<'a> {
+    fn type_name(&self) -> &'static str {
+        "RconAuth"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("_unused", &pretty::Bytes::new(&self._unused));
+        _v.field("password", &pretty::Bytes::new(&self.password));
+        _v.field("request_commands", &self.request_commands.as_ref().map(|v| v));
+    }
+}
 
 impl RequestMapData {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<RequestMapData, Error> {
@@ -804,6 +996,14 @@ impl fmt::Debug for RequestMapData {
             .finish()
     }
 }
+impl Fields for RequestMapData {
+    fn type_name(&self) -> &'static str {
+        "RequestMapData"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("chunk", &self.chunk);
+    }
+}
 
 impl Ping {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<Ping, Error> {
@@ -821,6 +1021,13 @@ impl fmt::Debug for Ping {
             .finish()
     }
 }
+impl Fields for Ping {
+    fn type_name(&self) -> &'static str {
+        "Ping"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl PingReply {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker) -> Result<PingReply, Error> {
@@ -838,6 +1045,13 @@ impl fmt::Debug for PingReply {
             .finish()
     }
 }
+impl Fields for PingReply {
+    fn type_name(&self) -> &'static str {
+        "PingReply"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+    }
+}
 
 impl<'a> RconCmdAdd<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<RconCmdAdd<'a>, Error> {
@@ -865,6 +1079,16 @@ impl<'a> fmt::Debug for RconCmdAdd<'a> {
             .finish()
     }
 }
+impl<'a> Fields for RconCmdAdd<'a> {
+    fn type_name(&self) -> &'static str {
+        "RconCmdAdd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &pretty::Bytes::new(&self.name));
+        _v.field("help", &pretty::Bytes::new(&self.help));
+        _v.field("params", &pretty::Bytes::new(&self.params));
+    }
+}
 
 impl<'a> RconCmdRemove<'a> {
     pub fn decode<W: Warn<Warning>>(warn: &mut W, _p: &mut Unpacker<'a>) -> Result<RconCmdRemove<'a>, Error> {
@@ -886,4 +1110,12 @@ impl<'a> fmt::Debug for RconCmdRemove<'a> {
             .finish()
     }
 }
+impl<'a> Fields for RconCmdRemove<'a> {
+    fn type_name(&self) -> &'static str {
+        "RconCmdRemove"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &pretty::Bytes::new(&self.name));
+    }
+}
 
@@ -11,6 +11,8 @@ use enums::TEAM_RED;
 use enums::Team;
 use enums::Weapon;
 use error::Error;
+use fields::Fields;
+use fields::Visitor;
 use packer::ExcessData;
 use packer::IntUnpacker;
 use packer::Packer;
@@ -259,6 +261,57 @@ impl fmt::Debug for SnapObj {
     }
 }
 
+impl Fields for SnapObj {
+    fn type_name(&self) -> &'static str {
+        match *self {
+            SnapObj::PlayerInput(ref i) => i.type_name(),
+            SnapObj::Projectile(ref i) => i.type_name(),
+            SnapObj::Laser(ref i) => i.type_name(),
+            SnapObj::Pickup(ref i) => i.type_name(),
+            SnapObj::Flag(ref i) => i.type_name(),
+            SnapObj::GameInfo(ref i) => i.type_name(),
+            SnapObj::GameData(ref i) => i.type_name(),
+            SnapObj::CharacterCore(ref i) => i.type_name(),
+            SnapObj::Character(ref i) => i.type_name(),
+            SnapObj::PlayerInfo(ref i) => i.type_name(),
+            SnapObj::ClientInfo(ref i) => i.type_name(),
+            SnapObj::SpectatorInfo(ref i) => i.type_name(),
+            SnapObj::Common(ref i) => i.type_name(),
+            SnapObj::Explosion(ref i) => i.type_name(),
+            SnapObj::Spawn(ref i) => i.type_name(),
+            SnapObj::HammerHit(ref i) => i.type_name(),
+            SnapObj::Death(ref i) => i.type_name(),
+            SnapObj::SoundGlobal(ref i) => i.type_name(),
+            SnapObj::SoundWorld(ref i) => i.type_name(),
+            SnapObj::DamageInd(ref i) => i.type_name(),
+        }
+    }
+    fn visit_fields(&self, v: &mut Visitor) {
+        match *self {
+            SnapObj::PlayerInput(ref i) => i.visit_fields(v),
+            SnapObj::Projectile(ref i) => i.visit_fields(v),
+            SnapObj::Laser(ref i) => i.visit_fields(v),
+            SnapObj::Pickup(ref i) => i.visit_fields(v),
+            SnapObj::Flag(ref i) => i.visit_fields(v),
+            SnapObj::GameInfo(ref i) => i.visit_fields(v),
+            SnapObj::GameData(ref i) => i.visit_fields(v),
+            SnapObj::CharacterCore(ref i) => i.visit_fields(v),
+            SnapObj::Character(ref i) => i.visit_fields(v),
+            SnapObj::PlayerInfo(ref i) => i.visit_fields(v),
+            SnapObj::ClientInfo(ref i) => i.visit_fields(v),
+            SnapObj::SpectatorInfo(ref i) => i.visit_fields(v),
+            SnapObj::Common(ref i) => i.visit_fields(v),
+            SnapObj::Explosion(ref i) => i.visit_fields(v),
+            SnapObj::Spawn(ref i) => i.visit_fields(v),
+            SnapObj::HammerHit(ref i) => i.visit_fields(v),
+            SnapObj::Death(ref i) => i.visit_fields(v),
+            SnapObj::SoundGlobal(ref i) => i.visit_fields(v),
+            SnapObj::SoundWorld(ref i) => i.visit_fields(v),
+            SnapObj::DamageInd(ref i) => i.visit_fields(v),
+        }
+    }
+}
+
 impl From<PlayerInput> for SnapObj {
     fn from(i: PlayerInput) -> SnapObj {
         SnapObj::PlayerInput(i)
@@ -586,6 +639,23 @@ impl fmt::Debug for PlayerInput {
             .finish()
     }
 }
+impl Fields for PlayerInput {
+    fn type_name(&self) -> &'static str {
+        "PlayerInput"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("direction", &self.direction);
+        _v.field("target_x", &self.target_x);
+        _v.field("target_y", &self.target_y);
+        _v.field("jump", &self.jump);
+        _v.field("fire", &self.fire);
+        _v.field("hook", &self.hook);
+        _v.field("player_flags", &self.player_flags);
+        _v.field("wanted_weapon", &self.wanted_weapon);
+        _v.field("next_weapon", &self.next_weapon);
+        _v.field("prev_weapon", &self.prev_weapon);
+    }
+}
 impl PlayerInput {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<PlayerInput, Error> {
         let result = try!(Self::decode_inner(p));
@@ -623,6 +693,19 @@ impl fmt::Debug for Projectile {
             .finish()
     }
 }
+impl Fields for Projectile {
+    fn type_name(&self) -> &'static str {
+        "Projectile"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+        _v.field("vel_x", &self.vel_x);
+        _v.field("vel_y", &self.vel_y);
+        _v.field("type_", &self.type_);
+        _v.field("start_tick", &self.start_tick);
+    }
+}
 impl Projectile {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Projectile, Error> {
         let result = try!(Self::decode_inner(p));
@@ -655,6 +738,18 @@ impl fmt::Debug for Laser {
             .finish()
     }
 }
+impl Fields for Laser {
+    fn type_name(&self) -> &'static str {
+        "Laser"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+        _v.field("from_x", &self.from_x);
+        _v.field("from_y", &self.from_y);
+        _v.field("start_tick", &self.start_tick);
+    }
+}
 impl Laser {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Laser, Error> {
         let result = try!(Self::decode_inner(p));
@@ -685,6 +780,17 @@ impl fmt::Debug for Pickup {
             .finish()
     }
 }
+impl Fields for Pickup {
+    fn type_name(&self) -> &'static str {
+        "Pickup"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+        _v.field("type_", &self.type_);
+        _v.field("subtype", &self.subtype);
+    }
+}
 impl Pickup {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Pickup, Error> {
         let result = try!(Self::decode_inner(p));
@@ -715,6 +821,16 @@ impl fmt::Debug for Flag {
             .finish()
     }
 }
+impl Fields for Flag {
+    fn type_name(&self) -> &'static str {
+        "Flag"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+        _v.field("team", &self.team);
+    }
+}
 impl Flag {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Flag, Error> {
         let result = try!(Self::decode_inner(p));
@@ -748,6 +864,21 @@ impl fmt::Debug for GameInfo {
             .finish()
     }
 }
+impl Fields for GameInfo {
+    fn type_name(&self) -> &'static str {
+        "GameInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("game_flags", &self.game_flags);
+        _v.field("game_state_flags", &self.game_state_flags);
+        _v.field("round_start_tick", &self.round_start_tick);
+        _v.field("warmup_timer", &self.warmup_timer);
+        _v.field("score_limit", &self.score_limit);
+        _v.field("time_limit", &self.time_limit);
+        _v.field("round_num", &self.round_num);
+        _v.field("round_current", &self.round_current);
+    }
+}
 impl GameInfo {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<GameInfo, Error> {
         let result = try!(Self::decode_inner(p));
@@ -788,6 +919,17 @@ impl fmt::Debug for GameData {
             .finish()
     }
 }
+impl Fields for GameData {
+    fn type_name(&self) -> &'static str {
+        "GameData"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("teamscore_red", &self.teamscore_red);
+        _v.field("teamscore_blue", &self.teamscore_blue);
+        _v.field("flag_carrier_red", &self.flag_carrier_red);
+        _v.field("flag_carrier_blue", &self.flag_carrier_blue);
+    }
+}
 impl GameData {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<GameData, Error> {
         let result = try!(Self::decode_inner(p));
@@ -830,6 +972,28 @@ impl fmt::Debug for CharacterCore {
             .finish()
     }
 }
+impl Fields for CharacterCore {
+    fn type_name(&self) -> &'static str {
+        "CharacterCore"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("tick", &self.tick);
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+        _v.field("vel_x", &self.vel_x);
+        _v.field("vel_y", &self.vel_y);
+        _v.field("angle", &self.angle);
+        _v.field("direction", &self.direction);
+        _v.field("jumped", &self.jumped);
+        _v.field("hooked_player", &self.hooked_player);
+        _v.field("hook_state", &self.hook_state);
+        _v.field("hook_tick", &self.hook_tick);
+        _v.field("hook_x", &self.hook_x);
+        _v.field("hook_y", &self.hook_y);
+        _v.field("hook_dx", &self.hook_dx);
+        _v.field("hook_dy", &self.hook_dy);
+    }
+}
 impl CharacterCore {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<CharacterCore, Error> {
         let result = try!(Self::decode_inner(p));
@@ -878,6 +1042,21 @@ impl fmt::Debug for Character {
             .finish()
     }
 }
+impl Fields for Character {
+    fn type_name(&self) -> &'static str {
+        "Character"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("character_core", &self.character_core);
+        _v.field("player_flags", &self.player_flags);
+        _v.field("health", &self.health);
+        _v.field("armor", &self.armor);
+        _v.field("ammo_count", &self.ammo_count);
+        _v.field("weapon", &self.weapon);
+        _v.field("emote", &self.emote);
+        _v.field("attack_tick", &self.attack_tick);
+    }
+}
 impl Character {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Character, Error> {
         let result = try!(Self::decode_inner(p));
@@ -918,6 +1097,18 @@ impl fmt::Debug for PlayerInfo {
             .finish()
     }
 }
+impl Fields for PlayerInfo {
+    fn type_name(&self) -> &'static str {
+        "PlayerInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("local", &self.local);
+        _v.field("client_id", &self.client_id);
+        _v.field("team", &self.team);
+        _v.field("score", &self.score);
+        _v.field("latency", &self.latency);
+    }
+}
 impl PlayerInfo {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<PlayerInfo, Error> {
         let result = try!(Self::decode_inner(p));
@@ -953,6 +1144,20 @@ impl fmt::Debug for ClientInfo {
             .finish()
     }
 }
+impl Fields for ClientInfo {
+    fn type_name(&self) -> &'static str {
+        "ClientInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("name", &DebugSlice::new(&self.name, |e| e));
+        _v.field("clan", &DebugSlice::new(&self.clan, |e| e));
+        _v.field("country", &self.country);
+        _v.field("skin", &DebugSlice::new(&self.skin, |e| e));
+        _v.field("use_custom_color", &self.use_custom_color);
+        _v.field("color_body", &self.color_body);
+        _v.field("color_feet", &self.color_feet);
+    }
+}
 impl ClientInfo {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<ClientInfo, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1001,6 +1206,16 @@ impl fmt::Debug for SpectatorInfo {
             .finish()
     }
 }
+impl Fields for SpectatorInfo {
+    fn type_name(&self) -> &'static str {
+        "SpectatorInfo"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("spectator_id", &self.spectator_id);
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+    }
+}
 impl SpectatorInfo {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<SpectatorInfo, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1028,6 +1243,15 @@ impl fmt::Debug for Common {
             .finish()
     }
 }
+impl Fields for Common {
+    fn type_name(&self) -> &'static str {
+        "Common"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.field("x", &self.x);
+        _v.field("y", &self.y);
+    }
+}
 impl Common {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Common, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1052,6 +1276,14 @@ impl fmt::Debug for Explosion {
             .finish()
     }
 }
+impl Fields for Explosion {
+    fn type_name(&self) -> &'static str {
+        "Explosion"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+    }
+}
 impl Explosion {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Explosion, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1076,6 +1308,14 @@ impl fmt::Debug for Spawn {
             .finish()
     }
 }
+impl Fields for Spawn {
+    fn type_name(&self) -> &'static str {
+        "Spawn"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+    }
+}
 impl Spawn {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Spawn, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1100,6 +1340,14 @@ impl fmt::Debug for HammerHit {
             .finish()
     }
 }
+impl Fields for HammerHit {
+    fn type_name(&self) -> &'static str {
+        "HammerHit"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+    }
+}
 impl HammerHit {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<HammerHit, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1125,6 +1373,15 @@ impl fmt::Debug for Death {
             .finish()
     }
 }
+impl Fields for Death {
+    fn type_name(&self) -> &'static str {
+        "Death"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+        _v.field("client_id", &self.client_id);
+    }
+}
 impl Death {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<Death, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1152,6 +1409,15 @@ impl fmt::Debug for SoundGlobal {
             .finish()
     }
 }
+impl Fields for SoundGlobal {
+    fn type_name(&self) -> &'static str {
+        "SoundGlobal"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+        _v.field("sound_id", &self.sound_id);
+    }
+}
 impl SoundGlobal {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<SoundGlobal, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1178,6 +1444,15 @@ impl fmt::Debug for SoundWorld {
             .finish()
     }
 }
+impl Fields for SoundWorld {
+    fn type_name(&self) -> &'static str {
+        "SoundWorld"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+        _v.field("sound_id", &self.sound_id);
+    }
+}
 impl SoundWorld {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<SoundWorld, Error> {
         let result = try!(Self::decode_inner(p));
@@ -1204,6 +1479,15 @@ impl fmt::Debug for DamageInd {
             .finish()
     }
 }
+impl Fields for DamageInd {
+    fn type_name(&self) -> &'static str {
+        "DamageInd"
+    }
+    fn visit_fields(&self, _v: &mut Visitor) {
+        _v.nested("common", &self.common);
+        _v.field("angle", &self.angle);
+    }
+}
 impl DamageInd {
     pub fn decode<W: Warn<ExcessData>>(warn: &mut W, p: &mut IntUnpacker) -> Result<DamageInd, Error> {
         let result = try!(Self::decode_inner(p));
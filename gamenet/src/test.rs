@@ -1,7 +1,12 @@
+use fields::Fields;
+use fields::Visitor;
 use hexdump::hexdump;
 use msg::System;
 use msg::system::Info;
+use msg::system::Input;
 use packer::with_packer;
+use snap_obj::PlayerInput;
+use std::fmt;
 
 #[test]
 fn encode_info() {
@@ -15,3 +20,41 @@ fn encode_info() {
     hexdump(result);
     assert!(result == b"\x03abc\0ok\0");
 }
+
+struct Collect(Vec<String>);
+
+impl Visitor for Collect {
+    fn field(&mut self, name: &'static str, value: &fmt::Debug) {
+        self.0.push(format!("{}: {:?}", name, value));
+    }
+    fn nested(&mut self, name: &'static str, value: &Fields) {
+        self.0.push(format!("{}: {} {{", name, value.type_name()));
+        value.visit_fields(self);
+        self.0.push("}".to_owned());
+    }
+}
+
+fn fields(value: &Fields) -> Vec<String> {
+    let mut collect = Collect(vec![value.type_name().to_owned()]);
+    value.visit_fields(&mut collect);
+    collect.0
+}
+
+#[test]
+fn visit_fields() {
+    let info = System::Info(Info {
+        version: b"abc",
+        password: None,
+    });
+    assert_eq!(fields(&info), ["Info", "version: b\"abc\"", "password: None"]);
+    let input = System::Input(Input {
+        ack_snapshot: 1,
+        intended_tick: 2,
+        input_size: 40,
+        input: PlayerInput { direction: -1, ..Default::default() },
+    });
+    let input = fields(&input);
+    assert_eq!(&input[..5], ["Input", "ack_snapshot: 1", "intended_tick: 2", "input_size: 40", "input: PlayerInput {"]);
+    assert_eq!(input[5], "direction: -1");
+    assert_eq!(input.last().unwrap(), "}");
+}
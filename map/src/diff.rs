@@ -0,0 +1,667 @@
+//! Semantic comparison of two maps.
+//!
+//! Groups, layers, images and envelopes are matched by their position in the
+//! map, so inserting a group in the middle shows up as changes to all
+//! following groups.
+
+use common::num::Cast;
+use format::MapItemExt;
+use format;
+use ndarray::Array2;
+use reader::Error;
+use reader::LayerTilemap;
+use reader::LayerTilemapType;
+use reader::LayerType;
+use reader::Reader;
+use std::cmp;
+use std::fmt;
+use std::mem;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    // GroupAdded(group, name)
+    GroupAdded(usize, String),
+    // GroupRemoved(group, name)
+    GroupRemoved(usize, String),
+    // GroupChanged(group, property)
+    GroupChanged(usize, &'static str),
+    // LayerAdded(group, layer, name)
+    LayerAdded(usize, usize, String),
+    // LayerRemoved(group, layer, name)
+    LayerRemoved(usize, usize, String),
+    // LayerChanged(group, layer, property)
+    LayerChanged(usize, usize, &'static str),
+    // TilesChanged(group, layer, rects)
+    TilesChanged(usize, usize, Vec<Rect>),
+    // ImageAdded(image, name)
+    ImageAdded(usize, String),
+    // ImageRemoved(image, name)
+    ImageRemoved(usize, String),
+    // ImageChanged(image, name, property)
+    ImageChanged(usize, String, &'static str),
+    // EnvelopeAdded(envelope, name)
+    EnvelopeAdded(usize, String),
+    // EnvelopeRemoved(envelope, name)
+    EnvelopeRemoved(usize, String),
+    // EnvelopeChanged(envelope, name, property)
+    EnvelopeChanged(usize, String, &'static str),
+    // InfoChanged(field, old, new)
+    InfoChanged(&'static str, Option<String>, Option<String>),
+    SettingAdded(String),
+    SettingRemoved(String),
+}
+
+struct Opt<'a>(&'a Option<String>);
+
+impl<'a> fmt::Display for Opt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Some(ref s) => write!(f, "{:?}", s),
+            None => write!(f, "(none)"),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Change::*;
+        match *self {
+            GroupAdded(g, ref n) => write!(f, "group #{} {:?} added", g, n),
+            GroupRemoved(g, ref n) => write!(f, "group #{} {:?} removed", g, n),
+            GroupChanged(g, p) => write!(f, "group #{}: {} changed", g, p),
+            LayerAdded(g, l, ref n) => write!(f, "group #{} layer #{} {:?} added", g, l, n),
+            LayerRemoved(g, l, ref n) => write!(f, "group #{} layer #{} {:?} removed", g, l, n),
+            LayerChanged(g, l, p) => write!(f, "group #{} layer #{}: {} changed", g, l, p),
+            TilesChanged(g, l, ref rects) => {
+                write!(f, "group #{} layer #{}: tiles changed at", g, l)?;
+                for (i, r) in rects.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(f, "{}({}, {}) {}x{}", sep, r.x, r.y, r.width, r.height)?;
+                }
+                Ok(())
+            },
+            ImageAdded(i, ref n) => write!(f, "image #{} {:?} added", i, n),
+            ImageRemoved(i, ref n) => write!(f, "image #{} {:?} removed", i, n),
+            ImageChanged(i, ref n, p) => write!(f, "image #{} {:?}: {} changed", i, n, p),
+            EnvelopeAdded(e, ref n) => write!(f, "envelope #{} {:?} added", e, n),
+            EnvelopeRemoved(e, ref n) => write!(f, "envelope #{} {:?} removed", e, n),
+            EnvelopeChanged(e, ref n, p) => write!(f, "envelope #{} {:?}: {} changed", e, n, p),
+            InfoChanged(field, ref old, ref new) =>
+                write!(f, "info {}: {} -> {}", field, Opt(old), Opt(new)),
+            SettingAdded(ref s) => write!(f, "setting added: {}", s),
+            SettingRemoved(ref s) => write!(f, "setting removed: {}", s),
+        }
+    }
+}
+
+fn name(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+/// Returns the rectangles covering all tiles that differ between `old` and
+/// `new`.
+///
+/// Tiles that only exist in one of the layers count as changed.
+pub fn changed_rects<T: PartialEq>(old: &Array2<T>, new: &Array2<T>) -> Vec<Rect> {
+    let height = cmp::max(old.dim().0, new.dim().0);
+    let width = cmp::max(old.dim().1, new.dim().1);
+    let changed = |y, x| old.get((y, x)) != new.get((y, x));
+
+    // Merge runs of changed tiles with runs of the same extent in the
+    // previous row.
+    let mut result = Vec::new();
+    let mut open: Vec<Rect> = Vec::new();
+    for y in 0..height {
+        let mut runs = Vec::new();
+        let mut x = 0;
+        while x < width {
+            if !changed(y, x) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < width && changed(y, x) {
+                x += 1;
+            }
+            runs.push((start.assert_u32(), x.assert_u32()));
+        }
+        let mut next = Vec::new();
+        for rect in open.drain(..) {
+            let run = runs.iter().position(|&(s, e)| s == rect.x && e == rect.x + rect.width);
+            match run {
+                Some(i) => {
+                    runs.remove(i);
+                    next.push(Rect { height: rect.height + 1, ..rect });
+                },
+                None => result.push(rect),
+            }
+        }
+        for (start, end) in runs {
+            next.push(Rect {
+                x: start,
+                y: y.assert_u32(),
+                width: end - start,
+                height: 1,
+            });
+        }
+        open = next;
+    }
+    result.extend(open);
+    result.sort_by_key(|r| (r.y, r.x));
+    result
+}
+
+fn tilemap_kind(type_: &LayerTilemapType) -> &'static str {
+    match *type_ {
+        LayerTilemapType::Normal(_) => "tiles",
+        LayerTilemapType::Game(_) => "game",
+        LayerTilemapType::RaceTeleport(..) => "tele",
+        LayerTilemapType::RaceSpeedup(..) => "speedup",
+        LayerTilemapType::DdraceFront(..) => "front",
+        LayerTilemapType::DdraceSwitch(..) => "switch",
+        LayerTilemapType::DdraceTune(..) => "tune",
+    }
+}
+
+fn layer_kind(t: &LayerType) -> &'static str {
+    match *t {
+        LayerType::Quads(_) => "quads",
+        LayerType::Tilemap(ref t) => tilemap_kind(&t.type_),
+        LayerType::DdraceSounds(_) => "sounds",
+    }
+}
+
+fn layer_name(t: &LayerType) -> String {
+    match *t {
+        LayerType::Quads(ref q) => name(&q.name),
+        LayerType::Tilemap(ref t) => name(&t.name),
+        LayerType::DdraceSounds(ref s) => name(&s.name),
+    }
+}
+
+fn image_name(map: &mut Reader, image: Option<usize>) -> Result<Option<String>, Error> {
+    let image = match image {
+        Some(i) => map.image(i)?,
+        None => return Ok(None),
+    };
+    Ok(Some(String::from_utf8_lossy(&map.string(image.name)?).into_owned()))
+}
+
+fn data_eq(old: &mut Reader, old_index: usize, new: &mut Reader, new_index: usize)
+    -> Result<bool, Error>
+{
+    Ok(old.reader.read_data(old_index)? == new.reader.read_data(new_index)?)
+}
+
+fn tile_rects(old: &mut Reader, o: &LayerTilemap, new: &mut Reader, n: &LayerTilemap)
+    -> Result<Option<Vec<Rect>>, Error>
+{
+    use reader::LayerTilemapType::*;
+
+    Ok(Some(match (o.type_, n.type_) {
+        (Normal(a), Normal(b)) => changed_rects(
+            &old.layer_tiles(o.tiles(a.data))?,
+            &new.layer_tiles(n.tiles(b.data))?,
+        ),
+        (Game(a), Game(b)) | (DdraceFront(a, _), DdraceFront(b, _)) => changed_rects(
+            &old.layer_tiles(o.tiles(a))?,
+            &new.layer_tiles(n.tiles(b))?,
+        ),
+        (RaceTeleport(a, _), RaceTeleport(b, _)) => changed_rects(
+            &old.tele_layer_tiles(o.tiles(a))?,
+            &new.tele_layer_tiles(n.tiles(b))?,
+        ),
+        (RaceSpeedup(a, _), RaceSpeedup(b, _)) => changed_rects(
+            &old.speedup_layer_tiles(o.tiles(a))?,
+            &new.speedup_layer_tiles(n.tiles(b))?,
+        ),
+        (DdraceSwitch(a, _), DdraceSwitch(b, _)) => changed_rects(
+            &old.switch_layer_tiles(o.tiles(a))?,
+            &new.switch_layer_tiles(n.tiles(b))?,
+        ),
+        (DdraceTune(a, _), DdraceTune(b, _)) => changed_rects(
+            &old.tune_layer_tiles(o.tiles(a))?,
+            &new.tune_layer_tiles(n.tiles(b))?,
+        ),
+        _ => return Ok(None),
+    }))
+}
+
+struct Differ<'a> {
+    old: &'a mut Reader,
+    new: &'a mut Reader,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn layer(&mut self, g: usize, l: usize, old_index: usize, new_index: usize)
+        -> Result<(), Error>
+    {
+        let o = self.old.layer(old_index)?;
+        let n = self.new.layer(new_index)?;
+        if layer_kind(&o.t) != layer_kind(&n.t) {
+            self.changes.push(Change::LayerChanged(g, l, "type"));
+            return Ok(());
+        }
+        let mut changed = Vec::new();
+        let mut rects = Vec::new();
+        if layer_name(&o.t) != layer_name(&n.t) {
+            changed.push("name");
+        }
+        if o.detail != n.detail {
+            changed.push("detail flag");
+        }
+        let old_envelopes = self.old.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE).start;
+        let new_envelopes = self.new.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE).start;
+        match (o.t, n.t) {
+            (LayerType::Tilemap(ot), LayerType::Tilemap(nt)) => {
+                if (ot.width, ot.height) != (nt.width, nt.height) {
+                    changed.push("size");
+                }
+                if let (Some(on), Some(nn)) = (ot.type_.to_normal(), nt.type_.to_normal()) {
+                    if on.color != nn.color {
+                        changed.push("color");
+                    }
+                    let oe = on.color_env_and_offset.map(|(e, o)| (e - old_envelopes, o));
+                    let ne = nn.color_env_and_offset.map(|(e, o)| (e - new_envelopes, o));
+                    if oe != ne {
+                        changed.push("color envelope");
+                    }
+                    if image_name(self.old, on.image)? != image_name(self.new, nn.image)? {
+                        changed.push("image");
+                    }
+                }
+                rects = tile_rects(self.old, &ot, self.new, &nt)?.unwrap();
+            },
+            (LayerType::Quads(oq), LayerType::Quads(nq)) => {
+                if image_name(self.old, oq.image)? != image_name(self.new, nq.image)? {
+                    changed.push("image");
+                }
+                if !data_eq(self.old, oq.data, self.new, nq.data)? {
+                    changed.push("quads");
+                }
+            },
+            (LayerType::DdraceSounds(os), LayerType::DdraceSounds(ns)) => {
+                if !data_eq(self.old, os.data, self.new, ns.data)? {
+                    changed.push("sound sources");
+                }
+            },
+            _ => unreachable!(),
+        }
+        self.changes.extend(changed.into_iter().map(|p| Change::LayerChanged(g, l, p)));
+        if !rects.is_empty() {
+            self.changes.push(Change::TilesChanged(g, l, rects));
+        }
+        Ok(())
+    }
+    fn groups(&mut self) -> Result<(), Error> {
+        let old_groups: Vec<_> = self.old.group_indices().collect();
+        let new_groups: Vec<_> = self.new.group_indices().collect();
+        for g in 0..cmp::max(old_groups.len(), new_groups.len()) {
+            let (o, n) = match (old_groups.get(g), new_groups.get(g)) {
+                (Some(&o), Some(&n)) => (self.old.group(o)?, self.new.group(n)?),
+                (Some(&o), None) => {
+                    let o = self.old.group(o)?;
+                    self.changes.push(Change::GroupRemoved(g, name(&o.name)));
+                    continue;
+                },
+                (None, Some(&n)) => {
+                    let n = self.new.group(n)?;
+                    self.changes.push(Change::GroupAdded(g, name(&n.name)));
+                    continue;
+                },
+                (None, None) => unreachable!(),
+            };
+            if name(&o.name) != name(&n.name) {
+                self.changes.push(Change::GroupChanged(g, "name"));
+            }
+            if (o.offset_x, o.offset_y) != (n.offset_x, n.offset_y) {
+                self.changes.push(Change::GroupChanged(g, "offset"));
+            }
+            if (o.parallax_x, o.parallax_y) != (n.parallax_x, n.parallax_y) {
+                self.changes.push(Change::GroupChanged(g, "parallax"));
+            }
+            if o.clipping != n.clipping {
+                self.changes.push(Change::GroupChanged(g, "clipping"));
+            }
+            let old_layers: Vec<_> = o.layer_indices.collect();
+            let new_layers: Vec<_> = n.layer_indices.collect();
+            for l in 0..cmp::max(old_layers.len(), new_layers.len()) {
+                match (old_layers.get(l), new_layers.get(l)) {
+                    (Some(&o), Some(&n)) => self.layer(g, l, o, n)?,
+                    (Some(&o), None) => {
+                        let o = self.old.layer(o)?;
+                        self.changes.push(Change::LayerRemoved(g, l, layer_name(&o.t)));
+                    },
+                    (None, Some(&n)) => {
+                        let n = self.new.layer(n)?;
+                        self.changes.push(Change::LayerAdded(g, l, layer_name(&n.t)));
+                    },
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        Ok(())
+    }
+    fn images(&mut self) -> Result<(), Error> {
+        let old_images: Vec<_> = self.old.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE).collect();
+        let new_images: Vec<_> = self.new.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE).collect();
+        for i in 0..cmp::max(old_images.len(), new_images.len()) {
+            let old_image = old_images.get(i).cloned();
+            let new_image = new_images.get(i).cloned();
+            let old_name = image_name(self.old, old_image)?;
+            let new_name = image_name(self.new, new_image)?;
+            let (o, n) = match (old_name.clone(), new_name.clone()) {
+                (Some(_), Some(n)) => (self.old.image(old_image.unwrap())?, n),
+                (Some(o), None) => {
+                    self.changes.push(Change::ImageRemoved(i, o));
+                    continue;
+                },
+                (None, Some(n)) => {
+                    self.changes.push(Change::ImageAdded(i, n));
+                    continue;
+                },
+                (None, None) => unreachable!(),
+            };
+            let new_image = self.new.image(new_image.unwrap())?;
+            let mut changed = Vec::new();
+            if old_name != new_name {
+                changed.push("name");
+            }
+            if (o.width, o.height) != (new_image.width, new_image.height) {
+                changed.push("size");
+            }
+            match (o.data, new_image.data) {
+                (Some(od), Some(nd)) => if !data_eq(self.old, od, self.new, nd)? {
+                    changed.push("data");
+                },
+                (None, None) => {},
+                _ => changed.push("embedding"),
+            }
+            self.changes.extend(changed.into_iter().map(|p| Change::ImageChanged(i, n.clone(), p)));
+        }
+        Ok(())
+    }
+    fn envelopes(&mut self) {
+        let old_envelopes = envelopes(self.old);
+        let new_envelopes = envelopes(self.new);
+        for e in 0..cmp::max(old_envelopes.len(), new_envelopes.len()) {
+            let (o, n) = match (old_envelopes.get(e), new_envelopes.get(e)) {
+                (Some(o), Some(n)) => (o, n),
+                (Some(o), None) => {
+                    self.changes.push(Change::EnvelopeRemoved(e, o.name.clone()));
+                    continue;
+                },
+                (None, Some(n)) => {
+                    self.changes.push(Change::EnvelopeAdded(e, n.name.clone()));
+                    continue;
+                },
+                (None, None) => unreachable!(),
+            };
+            let mut changed = Vec::new();
+            if o.name != n.name {
+                changed.push("name");
+            }
+            if o.channels != n.channels {
+                changed.push("channels");
+            }
+            if o.synchronized != n.synchronized {
+                changed.push("synchronized flag");
+            }
+            if o.points != n.points {
+                changed.push("points");
+            }
+            let name = &n.name;
+            self.changes.extend(changed.into_iter().map(|p| Change::EnvelopeChanged(e, name.clone(), p)));
+        }
+    }
+    fn info(&mut self) -> Result<(), Error> {
+        let old_info = info(self.old)?;
+        let new_info = info(self.new)?;
+        for (&(field, ref o), (_, n)) in old_info.fields.iter().zip(new_info.fields.iter()) {
+            if o != n {
+                self.changes.push(Change::InfoChanged(field, o.clone(), n.clone()));
+            }
+        }
+        for s in &old_info.settings {
+            if !new_info.settings.contains(s) {
+                self.changes.push(Change::SettingRemoved(s.clone()));
+            }
+        }
+        for s in &new_info.settings {
+            if !old_info.settings.contains(s) {
+                self.changes.push(Change::SettingAdded(s.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Envelope {
+    name: String,
+    channels: i32,
+    synchronized: Option<i32>,
+    points: Vec<i32>,
+}
+
+fn envelopes(map: &Reader) -> Vec<Envelope> {
+    let points = map.reader.find_item(format::MAP_ITEMTYPE_ENVPOINTS, 0)
+        .map(|i| i.data)
+        .unwrap_or(&[]);
+    map.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE).map(|i| {
+        let raw = map.reader.item(i).data;
+        let v1 = match format::MapItemEnvelopeV1::from_slice(raw) {
+            Ok(Some(v1)) => v1,
+            // Compare malformed envelopes by their raw data.
+            _ => return Envelope {
+                name: String::new(),
+                channels: 0,
+                synchronized: None,
+                points: raw.to_vec(),
+            },
+        };
+        let v2 = format::MapItemEnvelopeV2::from_slice(raw).ok().and_then(|x| x);
+        let point_len = if raw[0] >= 3 {
+            mem::size_of::<format::MapItemEnvpointV2>()
+        } else {
+            mem::size_of::<format::MapItemEnvpointV1>()
+        } / mem::size_of::<i32>();
+        let start = v1.start_points.try_usize().unwrap_or(0) * point_len;
+        let end = start + v1.num_points.try_usize().unwrap_or(0) * point_len;
+        Envelope {
+            name: name(&v1.name_get()),
+            channels: v1.channels,
+            synchronized: v2.map(|v2| v2.synchronized),
+            points: points.get(start..end).unwrap_or(&[]).to_vec(),
+        }
+    }).collect()
+}
+
+struct Info {
+    fields: Vec<(&'static str, Option<String>)>,
+    settings: Vec<String>,
+}
+
+fn info(map: &mut Reader) -> Result<Info, Error> {
+    let info = match map.info() {
+        Ok(i) => i,
+        Err(format::Error::MissingInfo) => return Ok(Info {
+            fields: vec![("author", None), ("version", None), ("credits", None), ("license", None)],
+            settings: vec![],
+        }),
+        Err(e) => return Err(e.into()),
+    };
+    let mut fields = vec![];
+    for &(field, index) in &[
+        ("author", info.author),
+        ("version", info.version),
+        ("credits", info.credits),
+        ("license", info.license),
+    ] {
+        let value = match index {
+            Some(i) => Some(String::from_utf8_lossy(&map.string(i)?).into_owned()),
+            None => None,
+        };
+        fields.push((field, value));
+    }
+    let settings = match info.settings {
+        Some(i) => map.settings(i)?.iter()
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect(),
+        None => vec![],
+    };
+    Ok(Info {
+        fields: fields,
+        settings: settings,
+    })
+}
+
+/// Lists the changes from `old` to `new`.
+pub fn diff(old: &mut Reader, new: &mut Reader) -> Result<Vec<Change>, Error> {
+    let mut differ = Differ {
+        old: old,
+        new: new,
+        changes: Vec::new(),
+    };
+    differ.info()?;
+    differ.groups()?;
+    differ.images()?;
+    differ.envelopes();
+    Ok(differ.changes)
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::Array2;
+    use super::Change::*;
+    use super::Change;
+    use super::Rect;
+    use super::changed_rects;
+    use testmap::TestMap;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect { x: x, y: y, width: width, height: height }
+    }
+
+    fn tiles(rows: &[&[u8]]) -> Array2<u8> {
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        let data = rows.iter().flat_map(|r| r.iter().cloned()).collect();
+        Array2::from_shape_vec((rows.len(), width), data).unwrap()
+    }
+
+    #[test]
+    fn rects_equal() {
+        let a = tiles(&[b"ab", b"cd"]);
+        assert_eq!(changed_rects(&a, &a.clone()), vec![]);
+    }
+
+    #[test]
+    fn rects_merged() {
+        let old = tiles(&[
+            b"......",
+            b"......",
+            b"......",
+            b"......",
+        ]);
+        let new = tiles(&[
+            b".xx...",
+            b".xx..x",
+            b".xxx.x",
+            b"......",
+        ]);
+        assert_eq!(changed_rects(&old, &new), vec![
+            rect(1, 0, 2, 2),
+            rect(5, 1, 1, 2),
+            rect(1, 2, 3, 1),
+        ]);
+    }
+
+    #[test]
+    fn rects_resized() {
+        let old = tiles(&[b"..", b".."]);
+        let new = tiles(&[b"...", b"...", b"..."]);
+        assert_eq!(changed_rects(&old, &new), vec![
+            rect(2, 0, 1, 2),
+            rect(0, 2, 3, 1),
+        ]);
+        assert_eq!(changed_rects(&new, &old), changed_rects(&old, &new));
+    }
+
+    /// Returns a map with a game group, a group with a tile and a quad
+    /// layer, an image and an envelope.
+    fn map(tiles: &[u8], author: &[u8], settings: &[&[u8]]) -> TestMap {
+        let mut map = TestMap::new();
+        map.info(Some(author), settings);
+        map.group();
+        map.game_layer(3, 2);
+        let image = map.image(b"grass", 1, 1, Some(vec![0; 4]));
+        map.envelope(1, 0, 2);
+        map.envpoints(2);
+        map.group();
+        map.tile_layer(0, 3, 2, image, tiles);
+        map.quad_layer(image, &[(0, -1)]);
+        map
+    }
+
+    fn diff(old: &TestMap, new: &TestMap) -> Vec<Change> {
+        super::diff(&mut old.open(), &mut new.open()).unwrap()
+    }
+
+    #[test]
+    fn same() {
+        let map = map(&[0, 1, 2, 3, 4, 5], b"me", &[b"sv_gametype dm"]);
+        assert_eq!(diff(&map, &map), vec![]);
+    }
+
+    #[test]
+    fn tiles_changed() {
+        let old = map(&[0, 1, 2, 3, 4, 5], b"me", &[]);
+        let new = map(&[0, 1, 9, 3, 9, 9], b"me", &[]);
+        assert_eq!(diff(&old, &new), vec![
+            TilesChanged(1, 0, vec![rect(2, 0, 1, 1), rect(1, 1, 2, 1)]),
+        ]);
+    }
+
+    #[test]
+    fn info_changed() {
+        let old = map(&[0; 6], b"me", &[b"sv_gametype dm", b"sv_scorelimit 20"]);
+        let new = map(&[0; 6], b"you", &[b"sv_scorelimit 20", b"sv_timelimit 10"]);
+        assert_eq!(diff(&old, &new), vec![
+            InfoChanged("author", Some("me".into()), Some("you".into())),
+            SettingRemoved("sv_gametype dm".into()),
+            SettingAdded("sv_timelimit 10".into()),
+        ]);
+    }
+
+    #[test]
+    fn added_and_removed() {
+        let old = map(&[0; 6], b"me", &[]);
+        let mut new = TestMap::new();
+        new.info(Some(b"me"), &[]);
+        new.group();
+        new.game_layer(3, 2);
+        new.envelope(1, 0, 2);
+        new.envelope(2, 0, 1);
+        new.envpoints(2);
+        new.group();
+        new.tile_layer(0, 3, 2, -1, &[0; 6]);
+        new.group();
+        new.quad_layer(-1, &[]);
+        assert_eq!(diff(&old, &new), vec![
+            LayerChanged(1, 0, "image"),
+            LayerRemoved(1, 1, "".into()),
+            GroupAdded(2, "".into()),
+            ImageRemoved(0, "grass".into()),
+            EnvelopeAdded(1, "".into()),
+        ]);
+    }
+}
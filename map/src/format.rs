@@ -1,3 +1,5 @@
+use common::num::LeI16;
+use common::num::LeI32;
 use common;
 use datafile::OnlyI32;
 use std::fmt;
@@ -84,6 +86,25 @@ pub fn bytes_to_string(bytes: &[u8]) -> &[u8] {
     bytes
 }
 
+/// Encodes `string` like the reference implementation's `StrToInts`.
+///
+/// The result is always null-terminated, truncating `string` if necessary.
+pub fn string_to_i32s(result: &mut [i32], string: &[u8]) {
+    let mut bytes = vec![0; result.len() * mem::size_of::<i32>()];
+    for (b, &s) in bytes.iter_mut().zip(string) {
+        *b = s;
+    }
+    for (output, input) in result.iter_mut().zip(bytes.chunks(mem::size_of::<i32>())) {
+        *output = ((input[0] as i32 + 0x80) & 0xff) << 24
+            | ((input[1] as i32 + 0x80) & 0xff) << 16
+            | ((input[2] as i32 + 0x80) & 0xff) << 8
+            | ((input[3] as i32 + 0x80) & 0xff) << 0;
+    }
+    if let Some(last) = result.last_mut() {
+        *last &= !0xff;
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 struct Fixed22_10 {
@@ -290,6 +311,37 @@ pub struct TuneTile {
     pub index: u8,
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct SpeedupTile {
+    pub force: u8,
+    pub max_speed: u8,
+    pub type_: u8,
+    pub padding: u8,
+    pub angle: LeI16,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct SwitchTile {
+    pub number: u8,
+    pub type_: u8,
+    pub flags: u8,
+    pub delay: u8,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Quad {
+    pub points: [[LeI32; 2]; 5],
+    pub colors: [[LeI32; 4]; 4],
+    pub texcoords: [[LeI32; 2]; 4],
+    pub pos_env: LeI32,
+    pub pos_env_offset: LeI32,
+    pub color_env: LeI32,
+    pub color_env_offset: LeI32,
+}
+
 pub const TILEFLAG_VFLIP: u8 = 1 << 0;
 pub const TILEFLAG_HFLIP: u8 = 1 << 1;
 pub const TILEFLAG_OPAQUE: u8 = 1 << 2;
@@ -406,6 +458,7 @@ pub enum ImageError {
     InvalidWidth(i32),
     InvalidHeight(i32),
     InvalidNameIndex(i32),
+    InvalidDataLength(usize),
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -436,14 +489,24 @@ pub enum Error {
     InvalidTilesLength(usize),
     InvalidTeleTilesLength(usize),
     InvalidTuneTilesLength(usize),
+    InvalidSpeedupTilesLength(usize),
+    InvalidSwitchTilesLength(usize),
+    InvalidQuadsLength(usize),
     InvalidVersion(i32),
+    MalformedEnvelope(usize),
     MalformedImageName(usize),
+    NotAnImage(usize),
+    MalformedSound(usize),
     // InvalidTilesDimensions(length, width, height)
     InvalidTilesDimensions(usize, u32, u32),
     // InvalidTeleTilesDimensions(length, width, height)
     InvalidTeleTilesDimensions(usize, u32, u32),
     // InvalidTuneTilesDimensions(length, width, height)
     InvalidTuneTilesDimensions(usize, u32, u32),
+    // InvalidSpeedupTilesDimensions(length, width, height)
+    InvalidSpeedupTilesDimensions(usize, u32, u32),
+    // InvalidSwitchTilesDimensions(length, width, height)
+    InvalidSwitchTilesDimensions(usize, u32, u32),
     EmptyVersion,
     MissingVersion,
     MissingInfo,
@@ -455,8 +518,6 @@ pub enum Error {
     TooManyGameLayers,
 }
 
-pub const MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY: i32 = 9;
-
 pub const MAP_ITEMTYPE_VERSION: u16 = 0;
 pub const MAP_ITEMTYPE_INFO: u16 = 1;
 pub const MAP_ITEMTYPE_IMAGE: u16 = 2;
@@ -698,6 +759,7 @@ impl fmt::Debug for MapItemDdraceSoundV1 {
 }
 pub const MAP_ITEMTYPE_LAYER_V1_TILEMAP: i32 = 2;
 pub const MAP_ITEMTYPE_LAYER_V1_QUADS: i32 = 3;
+pub const MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY: i32 = 9;
 pub const MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS: i32 = 10;
 
 #[derive(Clone, Copy)]
@@ -740,6 +802,15 @@ pub struct MapItemLayerV1QuadsV2 {
     pub name: [i32; 3],
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct MapItemLayerV1DdraceSoundsLegacyV1 {
+    pub num_sources: i32,
+    pub data: i32,
+    pub sound: i32,
+    pub name: [i32; 3],
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct MapItemLayerV1DdraceSoundsV1 {
@@ -758,6 +829,7 @@ unsafe impl OnlyI32 for MapItemLayerV1TilemapV2 { }
 unsafe impl OnlyI32 for MapItemLayerV1TilemapV3 { }
 unsafe impl OnlyI32 for MapItemLayerV1QuadsV1 { }
 unsafe impl OnlyI32 for MapItemLayerV1QuadsV2 { }
+unsafe impl OnlyI32 for MapItemLayerV1DdraceSoundsLegacyV1 { }
 unsafe impl OnlyI32 for MapItemLayerV1DdraceSoundsV1 { }
 unsafe impl OnlyI32 for MapItemLayerV1DdraceSoundsV2 { }
 
@@ -766,6 +838,7 @@ impl MapItem for MapItemLayerV1TilemapV2 { fn version() -> i32 { 2 } fn offset()
 impl MapItem for MapItemLayerV1TilemapV3 { fn version() -> i32 { 3 } fn offset() -> usize { 12 } fn ignore_version() -> bool { false } }
 impl MapItem for MapItemLayerV1QuadsV1 { fn version() -> i32 { 1 } fn offset() -> usize { 1 } fn ignore_version() -> bool { false } }
 impl MapItem for MapItemLayerV1QuadsV2 { fn version() -> i32 { 2 } fn offset() -> usize { 4 } fn ignore_version() -> bool { false } }
+impl MapItem for MapItemLayerV1DdraceSoundsLegacyV1 { fn version() -> i32 { 1 } fn offset() -> usize { 1 } fn ignore_version() -> bool { false } }
 impl MapItem for MapItemLayerV1DdraceSoundsV1 { fn version() -> i32 { 1 } fn offset() -> usize { 1 } fn ignore_version() -> bool { false } }
 impl MapItem for MapItemLayerV1DdraceSoundsV2 { fn version() -> i32 { 2 } fn offset() -> usize { 7 } fn ignore_version() -> bool { false } }
 
@@ -785,6 +858,14 @@ impl MapItemLayerV1QuadsV2 {
         result
     }
 }
+impl MapItemLayerV1DdraceSoundsLegacyV1 {
+    pub fn name_get(&self) -> [u8; 12] {
+        let mut result: [u8; 12] = unsafe { mem::uninitialized() };
+        i32s_to_bytes(&mut result, &self.name);
+        result[12-1] = 0;
+        result
+    }
+}
 impl MapItemLayerV1DdraceSoundsV1 {
     pub fn name_get(&self) -> [u8; 12] {
         let mut result: [u8; 12] = unsafe { mem::uninitialized() };
@@ -835,6 +916,15 @@ impl fmt::Debug for MapItemLayerV1QuadsV2 {
         Ok(())
     }
 }
+impl fmt::Debug for MapItemLayerV1DdraceSoundsLegacyV1 {
+    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(_f, "num_sources={:?}", self.num_sources));
+        try!(write!(_f, " data={:?}", self.data));
+        try!(write!(_f, " sound={:?}", self.sound));
+        try!(write!(_f, " name={:?}", String::from_utf8_lossy(bytes_to_string(&self.name_get()))));
+        Ok(())
+    }
+}
 impl fmt::Debug for MapItemLayerV1DdraceSoundsV1 {
     fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
         try!(write!(_f, "num_sources={:?}", self.num_sources));
@@ -1,4 +1,5 @@
 import re
+import sys
 
 ITEMS = [
     (0, "version", [
@@ -43,6 +44,9 @@ LAYER_V1_ITEMS = [
         ["num_quads", "data", "image"],
         ["name[3s]"],
     ]),
+    (9, "ddrace_sounds_legacy", [
+        ["num_sources", "data", "sound", "name[3s]"],
+    ]),
     (10, "ddrace_sounds", [
         ["num_sources", "data", "sound", "name[3s]"],
         [],
@@ -50,6 +54,8 @@ LAYER_V1_ITEMS = [
 ]
 
 header = """\
+use common::num::LeI16;
+use common::num::LeI32;
 use common;
 use datafile::OnlyI32;
 use std::fmt;
@@ -136,6 +142,25 @@ pub fn bytes_to_string(bytes: &[u8]) -> &[u8] {
     bytes
 }
 
+/// Encodes `string` like the reference implementation's `StrToInts`.
+///
+/// The result is always null-terminated, truncating `string` if necessary.
+pub fn string_to_i32s(result: &mut [i32], string: &[u8]) {
+    let mut bytes = vec![0; result.len() * mem::size_of::<i32>()];
+    for (b, &s) in bytes.iter_mut().zip(string) {
+        *b = s;
+    }
+    for (output, input) in result.iter_mut().zip(bytes.chunks(mem::size_of::<i32>())) {
+        *output = ((input[0] as i32 + 0x80) & 0xff) << 24
+            | ((input[1] as i32 + 0x80) & 0xff) << 16
+            | ((input[2] as i32 + 0x80) & 0xff) << 8
+            | ((input[3] as i32 + 0x80) & 0xff) << 0;
+    }
+    if let Some(last) = result.last_mut() {
+        *last &= !0xff;
+    }
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 struct Fixed22_10 {
@@ -342,6 +367,37 @@ pub struct TuneTile {
     pub index: u8,
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct SpeedupTile {
+    pub force: u8,
+    pub max_speed: u8,
+    pub type_: u8,
+    pub padding: u8,
+    pub angle: LeI16,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(C)]
+pub struct SwitchTile {
+    pub number: u8,
+    pub type_: u8,
+    pub flags: u8,
+    pub delay: u8,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Quad {
+    pub points: [[LeI32; 2]; 5],
+    pub colors: [[LeI32; 4]; 4],
+    pub texcoords: [[LeI32; 2]; 4],
+    pub pos_env: LeI32,
+    pub pos_env_offset: LeI32,
+    pub color_env: LeI32,
+    pub color_env_offset: LeI32,
+}
+
 pub const TILEFLAG_VFLIP: u8 = 1 << 0;
 pub const TILEFLAG_HFLIP: u8 = 1 << 1;
 pub const TILEFLAG_OPAQUE: u8 = 1 << 2;
@@ -458,6 +514,7 @@ pub enum ImageError {
     InvalidWidth(i32),
     InvalidHeight(i32),
     InvalidNameIndex(i32),
+    InvalidDataLength(usize),
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -488,14 +545,24 @@ pub enum Error {
     InvalidTilesLength(usize),
     InvalidTeleTilesLength(usize),
     InvalidTuneTilesLength(usize),
+    InvalidSpeedupTilesLength(usize),
+    InvalidSwitchTilesLength(usize),
+    InvalidQuadsLength(usize),
     InvalidVersion(i32),
+    MalformedEnvelope(usize),
     MalformedImageName(usize),
+    NotAnImage(usize),
+    MalformedSound(usize),
     // InvalidTilesDimensions(length, width, height)
     InvalidTilesDimensions(usize, u32, u32),
     // InvalidTeleTilesDimensions(length, width, height)
     InvalidTeleTilesDimensions(usize, u32, u32),
     // InvalidTuneTilesDimensions(length, width, height)
     InvalidTuneTilesDimensions(usize, u32, u32),
+    // InvalidSpeedupTilesDimensions(length, width, height)
+    InvalidSpeedupTilesDimensions(usize, u32, u32),
+    // InvalidSwitchTilesDimensions(length, width, height)
+    InvalidSwitchTilesDimensions(usize, u32, u32),
     EmptyVersion,
     MissingVersion,
     MissingInfo,
@@ -506,8 +573,6 @@ pub enum Error {
     TooManyGameGroups,
     TooManyGameLayers,
 }
-
-pub const MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY: i32 = 9;
 """
 
 def make_items(items):
@@ -652,11 +717,39 @@ def generate_impl_debug(items):
             result.append("}")
     return "\n".join(result)
 
+# The info item's `version` is renamed to not collide with the item version.
+TEXT_RENAMES = {("info", "version"): "map_version"}
+
+def generate_text_defs(name, items):
+    result = ["const {}: &'static [Def] = &[".format(name)]
+    for (type_id, item_name, versions) in items:
+        has_version = "true" if versions else "false"
+        ignore_version = "true" if item_name == "layer" else "false"
+        result.append("    Def {{ type_id: {}, name: \"{}\", has_version: {}, ignore_version: {}, versions: &[".format(type_id, item_name, has_version, ignore_version))
+        for version in versions:
+            fields = []
+            for (member, size, _) in version:
+                member = TEXT_RENAMES.get((item_name, member), member)
+                if size is not None:
+                    member = "{}[{}]".format(member, size)
+                fields.append("\"{}\"".format(member))
+            result.append("        &[{}],".format(", ".join(fields)))
+        result.append("    ] },")
+    result.append("];")
+    result.append("")
+    return "\n".join(result)
+
 def preprocess_layer_v1_items(items):
     return [(id, "layer_v1_" + name, versions) for (id, name, versions) in items]
 
 def main():
     items = make_items(ITEMS)
+    if sys.argv[1:] == ["--text-defs"]:
+        print("// Generated by generate_format.py --text-defs, included by text.rs.")
+        print()
+        print(generate_text_defs("ITEMS", items))
+        print(generate_text_defs("LAYERS", make_items(LAYER_V1_ITEMS)))
+        return
     layer_v1_items = make_items(preprocess_layer_v1_items(LAYER_V1_ITEMS))
     steps = [
         generate_constants,
@@ -673,5 +766,4 @@ def main():
             print(g(i))
 
 if __name__ == '__main__':
-    import sys
     sys.exit(main())
@@ -0,0 +1,262 @@
+//! Export and replacement of map images.
+//!
+//! Image data is passed around as raw RGBA, four bytes per pixel, row by row,
+//! just like it is stored in the map.
+
+use common::num::Cast;
+use datafile::buffer::Buffer;
+use format::Error as MapError;
+use format::ImageError;
+use format;
+use reader::Error;
+use reader::Reader;
+use sanitize_file_name;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// Indices into the raw image items.
+const IMAGE_WIDTH: usize = 1;
+const IMAGE_HEIGHT: usize = 2;
+const IMAGE_EXTERNAL: usize = 3;
+const IMAGE_DATA: usize = 5;
+const IMAGE_FORMAT: usize = 6;
+
+// Value of `MapItemImageV2::format` for RGBA images.
+const FORMAT_RGBA: i32 = 1;
+
+fn check_len(index: usize, width: u32, height: u32, len: usize) -> Result<(), MapError> {
+    if width.usize() * height.usize() * 4 != len {
+        return Err(MapError::Image(index, ImageError::InvalidDataLength(len)));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageInfo {
+    /// Item index, as used by `Reader::image`.
+    pub index: usize,
+    pub name: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub external: bool,
+}
+
+/// Lists all images of the map, both embedded and external ones.
+pub fn list(map: &mut Reader) -> Result<Vec<ImageInfo>, Error> {
+    let mut result = Vec::new();
+    for i in map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE) {
+        let image = map.image(i)?;
+        result.push(ImageInfo {
+            index: i,
+            name: map.image_name(image.name)?,
+            width: image.width,
+            height: image.height,
+            external: image.data.is_none(),
+        });
+    }
+    Ok(result)
+}
+
+/// Returns file names for exporting the images, without extension.
+///
+/// The names only contain ASCII letters, digits, `-` and `_`, other
+/// characters are replaced by `_`. Names that would collide with an earlier
+/// one, ignoring case, get a numeric suffix.
+pub fn file_names(images: &[ImageInfo]) -> Vec<String> {
+    let mut used = HashSet::new();
+    images.iter().map(|image| {
+        let mut name = sanitize_file_name(&image.name);
+        if name.is_empty() {
+            name.push_str("image");
+        }
+        let mut result = name.clone();
+        let mut suffix = 1;
+        while !used.insert(result.to_ascii_lowercase()) {
+            result = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        result
+    }).collect()
+}
+
+/// Returns the RGBA data of an embedded image, or `None` for an external
+/// image.
+pub fn data(map: &mut Reader, index: usize) -> Result<Option<Vec<u8>>, Error> {
+    let image = map.image(index)?;
+    let data = match image.data {
+        Some(d) => map.image_data(d)?,
+        None => return Ok(None),
+    };
+    check_len(index, image.width, image.height, data.len())?;
+    Ok(Some(data))
+}
+
+#[derive(Clone, Debug)]
+pub struct Replacement {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA data, `width * height * 4` bytes.
+    pub data: Vec<u8>,
+}
+
+/// Creates a copy of the map with some of its images replaced.
+///
+/// `replacements` maps item indices, as used by `Reader::image`, to the new
+/// image contents. External images are embedded when replaced. Names stay
+/// the same.
+pub fn replace(map: &mut Reader, replacements: &HashMap<usize, Replacement>)
+    -> Result<Buffer, Error>
+{
+    let image_indices = map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE);
+    let mut old_data = HashMap::new();
+    for i in image_indices.clone() {
+        let image = map.image(i)?;
+        if let Some(d) = image.data {
+            *old_data.entry(d).or_insert(0) += 1;
+        }
+    }
+    for (&i, r) in replacements {
+        if !(image_indices.start <= i && i < image_indices.end) {
+            return Err(MapError::NotAnImage(i).into());
+        }
+        check_len(i, r.width, r.height, r.data.len())?;
+    }
+
+    let mut data = Vec::with_capacity(map.reader.num_data());
+    for d in 0..map.reader.num_data() {
+        data.push(map.reader.read_data(d)?);
+    }
+    let mut items = Vec::with_capacity(map.reader.num_items());
+    for i in 0..map.reader.num_items() {
+        let item = map.reader.item(i);
+        let mut raw = item.data.to_vec();
+        if let Some(r) = replacements.get(&i) {
+            let d = match map.image(i)?.data {
+                // Only overwrite the data in place if no other image uses it.
+                Some(d) if old_data[&d] == 1 => {
+                    data[d] = r.data.clone();
+                    d
+                },
+                _ => {
+                    data.push(r.data.clone());
+                    data.len() - 1
+                },
+            };
+            raw[IMAGE_WIDTH] = r.width.assert_i32();
+            raw[IMAGE_HEIGHT] = r.height.assert_i32();
+            raw[IMAGE_EXTERNAL] = 0;
+            raw[IMAGE_DATA] = d.assert_i32();
+            if raw.len() > IMAGE_FORMAT {
+                raw[IMAGE_FORMAT] = FORMAT_RGBA;
+            }
+        }
+        items.push((item.type_id, item.id, raw));
+    }
+
+    let mut buffer = Buffer::new();
+    for d in data {
+        buffer.add_data(d);
+    }
+    for (type_id, id, raw) in items {
+        buffer.add_item(type_id, id, &raw).expect("item ids are unique");
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use format::Error as MapError;
+    use format;
+    use reader::Error;
+    use std::collections::HashMap;
+    use super::ImageInfo;
+    use super::Replacement;
+    use super::data;
+    use super::file_names;
+    use super::list;
+    use super::replace;
+    use testmap::TestMap;
+    use testmap;
+
+    fn info(name: &[u8]) -> ImageInfo {
+        ImageInfo {
+            index: 0,
+            name: name.to_vec(),
+            width: 1,
+            height: 1,
+            external: false,
+        }
+    }
+
+    #[test]
+    fn names() {
+        let images: Vec<_> = [
+            &b"grass_main"[..], b"..", b"", b"C:", b"grass main", b"Grass_Main", b"grass_main_1",
+        ].iter().map(|n| info(n)).collect();
+        assert_eq!(file_names(&images), [
+            "grass_main", "__", "image", "C_", "grass_main_1", "Grass_Main_2", "grass_main_1_1",
+        ]);
+    }
+
+    fn replacement(width: u32, height: u32, byte: u8) -> Replacement {
+        Replacement {
+            width: width,
+            height: height,
+            data: vec![byte; (width * height * 4) as usize],
+        }
+    }
+
+    #[test]
+    fn replace_images() {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(1, 1);
+        map.image(b"external", 64, 64, None);
+        map.image(b"embedded", 1, 1, Some(vec![1; 4]));
+        map.image(b"untouched", 1, 1, Some(vec![2; 4]));
+        let mut map = map.open();
+        let images = list(&mut map).unwrap();
+        let mut replacements = HashMap::new();
+        replacements.insert(images[0].index, replacement(2, 1, 3));
+        replacements.insert(images[1].index, replacement(1, 2, 4));
+        let mut result = testmap::open(&replace(&mut map, &replacements).unwrap());
+
+        let new_images = list(&mut result).unwrap();
+        let summary: Vec<_> = new_images.iter()
+            .map(|i| (&i.name[..], i.width, i.height, i.external))
+            .collect();
+        assert_eq!(summary, [
+            (&b"external"[..], 2, 1, false),
+            (&b"embedded"[..], 1, 2, false),
+            (&b"untouched"[..], 1, 1, false),
+        ]);
+        let data: Vec<_> = new_images.iter().map(|i| data(&mut result, i.index).unwrap()).collect();
+        assert_eq!(data, [Some(vec![3; 8]), Some(vec![4; 8]), Some(vec![2; 4])]);
+    }
+
+    #[test]
+    fn replace_invalid() {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(1, 1);
+        map.image(b"embedded", 1, 1, Some(vec![1; 4]));
+        let mut map = map.open();
+        let image = list(&mut map).unwrap()[0].index;
+
+        let mut replacements = HashMap::new();
+        replacements.insert(0, replacement(1, 1, 0));
+        match replace(&mut map, &replacements) {
+            Err(Error::Map(MapError::NotAnImage(0))) => {},
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+
+        let mut replacements = HashMap::new();
+        replacements.insert(image, Replacement { width: 2, height: 2, data: vec![0; 4] });
+        match replace(&mut map, &replacements) {
+            Err(Error::Map(MapError::Image(i, format::ImageError::InvalidDataLength(4)))) => {
+                assert_eq!(i, image);
+            },
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Editing of the map info and the server settings stored in the map.
+//!
+//! `MapInfo::read` extracts the strings, which can then be changed and
+//! written into a copy of the map with `write`.
+
+use common::num::Cast;
+use datafile::buffer::Buffer;
+use format::Error as MapError;
+use format;
+use reader::Error;
+use reader::Reader;
+use std::collections::HashSet;
+
+// Indices into the raw info item.
+const INFO_AUTHOR: usize = 1;
+const INFO_VERSION: usize = 2;
+const INFO_CREDITS: usize = 3;
+const INFO_LICENSE: usize = 4;
+const INFO_SETTINGS: usize = 5;
+const IMAGE_NAME: usize = 4;
+const IMAGE_DATA: usize = 5;
+const SOUND_NAME: usize = 2;
+const SOUND_DATA: usize = 3;
+// For layers, relative to the type-specific part starting at `LAYER_REST`.
+const LAYER_REST: usize = 3;
+const LAYER_DATA: usize = 2;
+const TILEMAP_DATA: usize = 11;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MapInfo {
+    pub author: Option<Vec<u8>>,
+    pub version: Option<Vec<u8>>,
+    pub credits: Option<Vec<u8>>,
+    pub license: Option<Vec<u8>>,
+    /// Console commands executed by the server when loading the map, e.g.
+    /// `sv_deepfly 0`.
+    pub settings: Vec<Vec<u8>>,
+}
+
+fn command(setting: &[u8]) -> &[u8] {
+    setting.split(|&b| b == b' ').next().unwrap()
+}
+
+impl MapInfo {
+    /// Reads the info item of the map, or returns an empty `MapInfo` if the
+    /// map has none.
+    pub fn read(map: &mut Reader) -> Result<MapInfo, Error> {
+        let info = match map.info() {
+            Ok(i) => i,
+            Err(MapError::MissingInfo) => return Ok(MapInfo::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut string = |index: Option<usize>| -> Result<Option<Vec<u8>>, Error> {
+            index.map(|i| map.string(i)).map_or(Ok(None), |r| r.map(Some))
+        };
+        let author = string(info.author)?;
+        let version = string(info.version)?;
+        let credits = string(info.credits)?;
+        let license = string(info.license)?;
+        let settings = match info.settings {
+            Some(s) => map.settings(s)?.iter().map(|s| s.to_vec()).collect(),
+            None => Vec::new(),
+        };
+        Ok(MapInfo {
+            author: author,
+            version: version,
+            credits: credits,
+            license: license,
+            settings: settings,
+        })
+    }
+    /// Sets a setting, replacing the settings with the same command.
+    ///
+    /// `set_setting(b"sv_deepfly 1")` replaces `sv_deepfly 0`.
+    pub fn set_setting(&mut self, setting: &[u8]) {
+        let cmd = command(setting);
+        let i = match self.settings.iter().position(|s| command(s) == cmd) {
+            Some(i) => i,
+            None => {
+                self.settings.push(setting.to_vec());
+                return;
+            },
+        };
+        self.settings[i] = setting.to_vec();
+        let mut j = i + 1;
+        while j < self.settings.len() {
+            if command(&self.settings[j]) == cmd {
+                self.settings.remove(j);
+            } else {
+                j += 1;
+            }
+        }
+    }
+    /// Removes all settings with the given command.
+    pub fn remove_setting(&mut self, cmd: &[u8]) {
+        self.settings.retain(|s| command(s) != cmd);
+    }
+}
+
+fn string_data(string: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(string.len() + 1);
+    data.extend_from_slice(string);
+    data.push(0);
+    data
+}
+
+/// Returns the data indices the items other than the info item might
+/// reference. For items of unknown types, all values count.
+fn referenced_data(map: &Reader) -> HashSet<i32> {
+    let mut result = HashSet::new();
+    for item in map.reader.items() {
+        let raw = item.data;
+        let rest = raw.get(LAYER_REST..).unwrap_or(&[]);
+        let references = match item.type_id {
+            format::MAP_ITEMTYPE_VERSION
+                | format::MAP_ITEMTYPE_ENVELOPE
+                | format::MAP_ITEMTYPE_GROUP
+                | format::MAP_ITEMTYPE_ENVPOINTS
+            => &[][..],
+            format::MAP_ITEMTYPE_INFO if item.id == 0 => &[][..],
+            format::MAP_ITEMTYPE_IMAGE => raw.get(IMAGE_NAME..IMAGE_DATA + 1).unwrap_or(raw),
+            format::MAP_ITEMTYPE_DDRACE_SOUND => raw.get(SOUND_NAME..SOUND_DATA + 1).unwrap_or(raw),
+            format::MAP_ITEMTYPE_LAYER => match raw.get(1).cloned() {
+                // The tile data and the data of the DDNet game layers.
+                Some(format::MAP_ITEMTYPE_LAYER_V1_TILEMAP) =>
+                    rest.get(TILEMAP_DATA..).unwrap_or(raw),
+                Some(format::MAP_ITEMTYPE_LAYER_V1_QUADS)
+                    | Some(format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS)
+                    | Some(format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY)
+                => rest.get(LAYER_DATA..LAYER_DATA + 1).unwrap_or(raw),
+                _ => raw,
+            },
+            _ => raw,
+        };
+        result.extend(references.iter().cloned());
+    }
+    result
+}
+
+/// Creates a copy of the map with the info item replaced by `info`.
+///
+/// All other items are copied unchanged. The new strings are stored in the
+/// data of the old info item where no other item uses it, so that the old
+/// strings don't stay in the file; data that isn't needed anymore is
+/// emptied.
+///
+/// Fails if any of the strings contains a null byte.
+pub fn write(map: &mut Reader, info: &MapInfo) -> Result<Buffer, Error> {
+    for s in info.settings.iter().chain(&info.author).chain(&info.version)
+        .chain(&info.credits).chain(&info.license)
+    {
+        if s.contains(&0) {
+            return Err(MapError::InvalidStringNullTermination.into());
+        }
+    }
+
+    let mut data = Vec::with_capacity(map.reader.num_data());
+    for d in 0..map.reader.num_data() {
+        data.push(map.reader.read_data(d)?);
+    }
+    let mut buffer = Buffer::new();
+    let mut old_info = None;
+    for item in map.reader.items() {
+        if (item.type_id, item.id) == (format::MAP_ITEMTYPE_INFO, 0) {
+            old_info = Some(item.data.to_vec());
+            continue;
+        }
+        buffer.add_item(item.type_id, item.id, item.data).expect("item ids are unique");
+    }
+
+    let mut raw = old_info.unwrap_or_else(|| vec![1]);
+    let referenced = referenced_data(map);
+    let mut free = Vec::new();
+    for &d in raw.get(INFO_AUTHOR..INFO_SETTINGS + 1).unwrap_or(&[]) {
+        if d.try_usize().map(|d| d < data.len()).unwrap_or(false)
+            && !referenced.contains(&d) && !free.contains(&d)
+        {
+            free.push(d);
+        }
+    }
+    free.reverse();
+
+    let len = if info.settings.is_empty() { INFO_SETTINGS } else { INFO_SETTINGS + 1 };
+    if raw.len() < len {
+        raw.resize(len, -1);
+    }
+    {
+        let mut add = |new: Option<Vec<u8>>| match new {
+            Some(d) => match free.pop() {
+                Some(i) => {
+                    data[i.assert_usize()] = d;
+                    i
+                },
+                None => {
+                    data.push(d);
+                    (data.len() - 1).assert_i32()
+                },
+            },
+            None => -1,
+        };
+        raw[INFO_AUTHOR] = add(info.author.as_ref().map(|s| string_data(s)));
+        raw[INFO_VERSION] = add(info.version.as_ref().map(|s| string_data(s)));
+        raw[INFO_CREDITS] = add(info.credits.as_ref().map(|s| string_data(s)));
+        raw[INFO_LICENSE] = add(info.license.as_ref().map(|s| string_data(s)));
+        if raw.len() > INFO_SETTINGS {
+            let settings: Vec<u8> = info.settings.iter().flat_map(|s| string_data(s)).collect();
+            raw[INFO_SETTINGS] = add(if settings.is_empty() { None } else { Some(settings) });
+        }
+    }
+    for i in free {
+        data[i.assert_usize()] = Vec::new();
+    }
+    for d in data {
+        buffer.add_data(d);
+    }
+    buffer.add_item(format::MAP_ITEMTYPE_INFO, 0, &raw).expect("item ids are unique");
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use format::Error as MapError;
+    use format;
+    use reader::Error;
+    use super::MapInfo;
+    use super::write;
+    use testmap::TestMap;
+    use testmap;
+
+    fn settings(settings: &[&[u8]]) -> MapInfo {
+        MapInfo {
+            settings: settings.iter().map(|s| s.to_vec()).collect(),
+            ..MapInfo::default()
+        }
+    }
+
+    #[test]
+    fn set_setting() {
+        let mut info = settings(&[b"sv_deepfly 0", b"sv_gametype dm", b"sv_deepfly 1"]);
+        info.set_setting(b"sv_deepfly 2");
+        assert_eq!(info, settings(&[b"sv_deepfly 2", b"sv_gametype dm"]));
+        info.set_setting(b"sv_scorelimit 20");
+        assert_eq!(info, settings(&[b"sv_deepfly 2", b"sv_gametype dm", b"sv_scorelimit 20"]));
+        // Only whole commands match.
+        info.set_setting(b"sv_deep 1");
+        assert_eq!(info.settings.len(), 4);
+        info.set_setting(b"sv_gametype");
+        assert_eq!(info.settings[1], b"sv_gametype");
+    }
+
+    #[test]
+    fn remove_setting() {
+        let mut info = settings(&[b"sv_deepfly 0", b"sv_gametype dm", b"sv_deepfly 1"]);
+        info.remove_setting(b"sv_deep");
+        assert_eq!(info.settings.len(), 3);
+        info.remove_setting(b"sv_deepfly");
+        assert_eq!(info, settings(&[b"sv_gametype dm"]));
+        info.remove_setting(b"sv_gametype");
+        assert_eq!(info, settings(&[]));
+    }
+
+    fn map() -> TestMap {
+        let mut map = TestMap::new();
+        map.info(Some(b"old author"), &[b"sv_deepfly 0"]);
+        map.group();
+        map.game_layer(1, 1);
+        map
+    }
+
+    #[test]
+    fn roundtrip() {
+        let map = map();
+        let mut old = map.open();
+        assert_eq!(MapInfo::read(&mut old).unwrap(), MapInfo {
+            author: Some(b"old author".to_vec()),
+            ..settings(&[b"sv_deepfly 0"])
+        });
+        let info = MapInfo {
+            author: Some(b"author".to_vec()),
+            license: Some(b"CC0".to_vec()),
+            ..settings(&[b"sv_deepfly 1", b"sv_gametype race"])
+        };
+        let buffer = write(&mut old, &info).unwrap();
+        assert_eq!(MapInfo::read(&mut testmap::open(&buffer)).unwrap(), info);
+
+        // The old strings are gone, only the additional string needed more
+        // data.
+        assert_eq!(buffer.num_data(), map.buffer.num_data() + 1);
+        for d in buffer.data_iter() {
+            assert!(!d.starts_with(b"old author") && !d.starts_with(b"sv_deepfly 0"));
+        }
+
+        // Removing strings empties their data.
+        let buffer = write(&mut testmap::open(&buffer), &MapInfo::default()).unwrap();
+        assert_eq!(MapInfo::read(&mut testmap::open(&buffer)).unwrap(), MapInfo::default());
+        let sizes: Vec<_> = buffer.data_iter().map(|d| d.len()).collect();
+        assert_eq!(sizes.iter().filter(|&&s| s == 0).count(), 3);
+    }
+
+    #[test]
+    fn shared_data() {
+        let mut map = TestMap::new();
+        let name = map.string(b"grass");
+        map.item(format::MAP_ITEMTYPE_INFO, &[1, name, -1, -1, -1]);
+        map.item(format::MAP_ITEMTYPE_IMAGE, &[1, 64, 64, 1, name, -1]);
+        map.group();
+        map.game_layer(1, 1);
+        let info = MapInfo {
+            author: Some(b"author".to_vec()),
+            ..MapInfo::default()
+        };
+        let mut result = testmap::open(&write(&mut map.open(), &info).unwrap());
+        assert_eq!(MapInfo::read(&mut result).unwrap(), info);
+        let image = result.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE).start;
+        let name = result.image(image).unwrap().name;
+        assert_eq!(result.image_name(name).unwrap(), b"grass");
+    }
+
+    #[test]
+    fn null_bytes() {
+        let info = MapInfo {
+            credits: Some(b"a\0b".to_vec()),
+            ..MapInfo::default()
+        };
+        match write(&mut map().open(), &info) {
+            Err(Error::Map(MapError::InvalidStringNullTermination)) => {},
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+        match write(&mut map().open(), &settings(&[b"sv_deepfly 0\0"])) {
+            Err(Error::Map(MapError::InvalidStringNullTermination)) => {},
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -1,10 +1,33 @@
 #[macro_use]
 extern crate common;
 extern crate datafile;
+extern crate image;
 extern crate ndarray;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
+extern crate serde_json;
 
 pub use reader::Reader;
 pub use reader::Error;
 
+pub mod diff;
 pub mod format;
+pub mod images;
+pub mod info;
+pub mod lint;
+pub mod optimize;
 pub mod reader;
+pub mod text;
+
+#[cfg(test)]
+mod testmap;
+
+/// Replaces all characters except ASCII letters, digits, `-` and `_` by `_`,
+/// for use in file names.
+fn sanitize_file_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,653 @@
+//! Consistency checks for map files.
+//!
+//! Unlike `Reader`, which stops at the first malformed item, the checks in
+//! this module look at the raw items and collect everything they find.
+
+use common::num::Cast;
+use format::MapItemExt;
+use format;
+use reader::Error;
+use reader::Reader;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::mem;
+use std::ops;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Check {
+    Layers,
+    Groups,
+    Images,
+    ImageIndices,
+    Envelopes,
+    GameLayers,
+    Teleporters,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Layers => "layers",
+            Check::Groups => "groups",
+            Check::Images => "images",
+            Check::ImageIndices => "image_indices",
+            Check::Envelopes => "envelopes",
+            Check::GameLayers => "game_layers",
+            Check::Teleporters => "teleporters",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Diagnostic {
+    // TruncatedItem(type_id, index)
+    TruncatedItem(u16, usize),
+    // UnknownLayerType(layer, type_)
+    UnknownLayerType(usize, i32),
+    // UnknownLayerVersion(layer, type_, version)
+    UnknownLayerVersion(usize, i32, i32),
+    // MalformedLayerData(layer)
+    MalformedLayerData(usize),
+    // EmptyGroup(group)
+    EmptyGroup(usize),
+    // InvalidGroupLayers(group, start_layer, num_layers)
+    InvalidGroupLayers(usize, i32, i32),
+    // UnusedImage(image)
+    UnusedImage(usize),
+    // MissingImageData(image)
+    MissingImageData(usize),
+    // InvalidImageIndex(layer, image)
+    InvalidImageIndex(usize, i32),
+    // InvalidEnvelopeIndex(layer, envelope)
+    InvalidEnvelopeIndex(usize, i32),
+    // InvalidQuadEnvelopeIndex(layer, quad, envelope)
+    InvalidQuadEnvelopeIndex(usize, usize, i32),
+    // InvalidEnvelopePoints(envelope, start_points, num_points)
+    InvalidEnvelopePoints(usize, i32, i32),
+    MissingGameLayer,
+    // DuplicateGameLayer(layer, flags)
+    DuplicateGameLayer(usize, u32),
+    // GameLayerSizeMismatch(layer, flags, width, height, game_width, game_height)
+    GameLayerSizeMismatch(usize, u32, i32, i32, i32, i32),
+    // TeleporterWithoutNumber(x, y)
+    TeleporterWithoutNumber(u32, u32),
+    // UnpairedTeleporterIn(number, x, y)
+    UnpairedTeleporterIn(u8, u32, u32),
+    // UnpairedTeleporterOut(number, x, y)
+    UnpairedTeleporterOut(u8, u32, u32),
+}
+
+impl Diagnostic {
+    pub fn check(&self) -> Check {
+        use self::Diagnostic::*;
+        match *self {
+            TruncatedItem(format::MAP_ITEMTYPE_GROUP, _) => Check::Groups,
+            TruncatedItem(format::MAP_ITEMTYPE_IMAGE, _) => Check::Images,
+            TruncatedItem(format::MAP_ITEMTYPE_ENVELOPE, _) => Check::Envelopes,
+            TruncatedItem(..) => Check::Layers,
+            UnknownLayerType(..) => Check::Layers,
+            UnknownLayerVersion(..) => Check::Layers,
+            MalformedLayerData(..) => Check::Layers,
+            EmptyGroup(..) => Check::Groups,
+            InvalidGroupLayers(..) => Check::Groups,
+            UnusedImage(..) => Check::Images,
+            MissingImageData(..) => Check::Images,
+            InvalidImageIndex(..) => Check::ImageIndices,
+            InvalidEnvelopeIndex(..) => Check::Envelopes,
+            InvalidQuadEnvelopeIndex(..) => Check::Envelopes,
+            InvalidEnvelopePoints(..) => Check::Envelopes,
+            MissingGameLayer => Check::GameLayers,
+            DuplicateGameLayer(..) => Check::GameLayers,
+            GameLayerSizeMismatch(..) => Check::GameLayers,
+            TeleporterWithoutNumber(..) => Check::Teleporters,
+            UnpairedTeleporterIn(..) => Check::Teleporters,
+            UnpairedTeleporterOut(..) => Check::Teleporters,
+        }
+    }
+    pub fn severity(&self) -> Severity {
+        use self::Diagnostic::*;
+        match *self {
+            EmptyGroup(..) => Severity::Warning,
+            UnusedImage(..) => Severity::Warning,
+            TeleporterWithoutNumber(..) => Severity::Warning,
+            UnpairedTeleporterIn(..) => Severity::Warning,
+            UnpairedTeleporterOut(..) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+fn item_type_name(type_id: u16) -> &'static str {
+    match type_id {
+        format::MAP_ITEMTYPE_VERSION => "version",
+        format::MAP_ITEMTYPE_INFO => "info",
+        format::MAP_ITEMTYPE_IMAGE => "image",
+        format::MAP_ITEMTYPE_ENVELOPE => "envelope",
+        format::MAP_ITEMTYPE_GROUP => "group",
+        format::MAP_ITEMTYPE_LAYER => "layer",
+        format::MAP_ITEMTYPE_ENVPOINTS => "envpoints",
+        format::MAP_ITEMTYPE_DDRACE_SOUND => "sound",
+        _ => "item",
+    }
+}
+
+fn layer_type_name(type_: i32) -> &'static str {
+    match type_ {
+        format::MAP_ITEMTYPE_LAYER_V1_TILEMAP => "tile",
+        format::MAP_ITEMTYPE_LAYER_V1_QUADS => "quad",
+        format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS => "sound",
+        format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY => "sound",
+        _ => "unknown",
+    }
+}
+
+fn game_layer_name(flags: u32) -> &'static str {
+    match flags {
+        format::TILELAYERFLAG_GAME => "game",
+        format::TILELAYERFLAG_TELEPORT => "tele",
+        format::TILELAYERFLAG_SPEEDUP => "speedup",
+        format::TILELAYERFLAG_FRONT => "front",
+        format::TILELAYERFLAG_SWITCH => "switch",
+        format::TILELAYERFLAG_TUNE => "tune",
+        _ => "unknown",
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Diagnostic::*;
+        match *self {
+            TruncatedItem(t, i) =>
+                write!(f, "{} #{} is truncated", item_type_name(t), i),
+            UnknownLayerType(l, t) =>
+                write!(f, "layer #{} has unknown type {}", l, t),
+            UnknownLayerVersion(l, t, v) =>
+                write!(f, "{} layer #{} has unsupported version {}", layer_type_name(t), l, v),
+            MalformedLayerData(l) =>
+                write!(f, "layer #{} has malformed data", l),
+            EmptyGroup(g) =>
+                write!(f, "group #{} contains no layers", g),
+            InvalidGroupLayers(g, s, n) =>
+                write!(f, "group #{} references {} layers starting at #{}, which don't exist", g, n, s),
+            UnusedImage(i) =>
+                write!(f, "image #{} is not used by any layer", i),
+            MissingImageData(i) =>
+                write!(f, "embedded image #{} has no image data", i),
+            InvalidImageIndex(l, i) =>
+                write!(f, "layer #{} references image #{}, which doesn't exist", l, i),
+            InvalidEnvelopeIndex(l, e) =>
+                write!(f, "layer #{} references envelope #{}, which doesn't exist", l, e),
+            InvalidQuadEnvelopeIndex(l, q, e) =>
+                write!(f, "quad #{} of layer #{} references envelope #{}, which doesn't exist", q, l, e),
+            InvalidEnvelopePoints(e, s, n) =>
+                write!(f, "envelope #{} references {} points starting at #{}, which don't exist", e, n, s),
+            MissingGameLayer =>
+                write!(f, "map has no game layer"),
+            DuplicateGameLayer(l, fl) =>
+                write!(f, "layer #{} is a second {} layer", l, game_layer_name(fl)),
+            GameLayerSizeMismatch(l, fl, w, h, gw, gh) =>
+                write!(f, "{} layer #{} is {}x{}, but the game layer is {}x{}",
+                    game_layer_name(fl), l, w, h, gw, gh),
+            TeleporterWithoutNumber(x, y) =>
+                write!(f, "teleporter at ({}, {}) has no number", x, y),
+            UnpairedTeleporterIn(n, x, y) =>
+                write!(f, "teleporter {} at ({}, {}) has no exit", n, x, y),
+            UnpairedTeleporterOut(n, x, y) =>
+                write!(f, "teleporter exit {} at ({}, {}) has no entrance", n, x, y),
+        }
+    }
+}
+
+const TELE_IN: &'static [u8] = &[10, 14, 15, 26];
+const TELE_OUT: u8 = 27;
+// Only the checkpoint teleporters and their exits are numbered, the
+// `TELECHECKIN` variants (31 and 63) go to the last checkpoint instead.
+const TELE_CHECKPOINT: &'static [u8] = &[29, 30];
+
+fn in_range(index: i32, indices: &ops::Range<usize>) -> bool {
+    index.try_usize().map(|i| i < indices.len()).unwrap_or(false)
+}
+
+struct Linter<'a> {
+    map: &'a mut Reader,
+    diagnostics: Vec<Diagnostic>,
+    used_images: HashSet<usize>,
+    // (layer, flags, width, height)
+    game_layers: Vec<(usize, u32, i32, i32)>,
+}
+
+impl<'a> Linter<'a> {
+    fn add(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+    fn check_env(&mut self, layer: usize, quad: Option<usize>, env: i32) {
+        let envelopes = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE);
+        if env != -1 && !in_range(env, &envelopes) {
+            self.add(match quad {
+                None => Diagnostic::InvalidEnvelopeIndex(layer, env),
+                Some(q) => Diagnostic::InvalidQuadEnvelopeIndex(layer, q, env),
+            });
+        }
+    }
+    fn check_image(&mut self, layer: usize, image: i32) {
+        let images = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE);
+        if image == -1 {
+            return;
+        }
+        if in_range(image, &images) {
+            self.used_images.insert(image.assert_usize());
+        } else {
+            self.add(Diagnostic::InvalidImageIndex(layer, image));
+        }
+    }
+    fn groups(&mut self) {
+        let num_layers = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_LAYER).len();
+        let indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_GROUP);
+        for (g, i) in indices.enumerate() {
+            let (start, num) = match format::MapItemGroupV1::from_slice(self.map.reader.item(i).data) {
+                Ok(Some(v1)) => (v1.start_layer, v1.num_layers),
+                _ => {
+                    self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_GROUP, g));
+                    continue;
+                },
+            };
+            let valid = start.try_usize().and_then(|s| num.try_usize().map(|n| s + n))
+                .map(|end| end <= num_layers)
+                .unwrap_or(false);
+            if !valid {
+                self.add(Diagnostic::InvalidGroupLayers(g, start, num));
+            } else if num == 0 {
+                self.add(Diagnostic::EmptyGroup(g));
+            }
+        }
+    }
+    fn tilemap(&mut self, l: usize, raw: &[i32]) {
+        let v2 = match format::MapItemLayerV1TilemapV2::from_slice(raw) {
+            Ok(Some(v2)) => *v2,
+            Ok(None) => {
+                let t = format::MAP_ITEMTYPE_LAYER_V1_TILEMAP;
+                self.add(Diagnostic::UnknownLayerVersion(l, t, raw[0]));
+                return;
+            },
+            Err(format::TooShort) => {
+                self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_LAYER, l));
+                return;
+            },
+        };
+        if raw[0] > 3 {
+            let t = format::MAP_ITEMTYPE_LAYER_V1_TILEMAP;
+            self.add(Diagnostic::UnknownLayerVersion(l, t, raw[0]));
+        }
+        let flags = v2.flags as u32;
+        if flags == 0 {
+            self.check_image(l, v2.image);
+            self.check_env(l, None, v2.color_env);
+        } else {
+            self.game_layers.push((l, flags, v2.width, v2.height));
+        }
+    }
+    fn quads(&mut self, l: usize, raw: &[i32]) -> Result<(), Error> {
+        let v1 = match format::MapItemLayerV1QuadsV1::from_slice(raw) {
+            Ok(Some(v1)) => *v1,
+            Ok(None) => {
+                let t = format::MAP_ITEMTYPE_LAYER_V1_QUADS;
+                self.add(Diagnostic::UnknownLayerVersion(l, t, raw[0]));
+                return Ok(());
+            },
+            Err(format::TooShort) => {
+                self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_LAYER, l));
+                return Ok(());
+            },
+        };
+        if raw[0] > 2 {
+            let t = format::MAP_ITEMTYPE_LAYER_V1_QUADS;
+            self.add(Diagnostic::UnknownLayerVersion(l, t, raw[0]));
+        }
+        self.check_image(l, v1.image);
+        let data = 0..self.map.reader.num_data();
+        if !in_range(v1.data, &data) {
+            self.add(Diagnostic::MalformedLayerData(l));
+            return Ok(());
+        }
+        let quads = match self.map.quads(v1.data.assert_usize()) {
+            Ok(q) => q,
+            Err(Error::Map(_)) => {
+                self.add(Diagnostic::MalformedLayerData(l));
+                return Ok(());
+            },
+            Err(e) => return Err(e),
+        };
+        for (q, quad) in quads.iter().enumerate() {
+            self.check_env(l, Some(q), quad.pos_env.to_i32());
+            self.check_env(l, Some(q), quad.color_env.to_i32());
+        }
+        Ok(())
+    }
+    fn layers(&mut self) -> Result<(), Error> {
+        let indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_LAYER);
+        for (l, i) in indices.enumerate() {
+            let raw = self.map.reader.item(i).data.to_vec();
+            let (type_, rest) = match format::MapItemLayerV1::from_slice_rest(&raw) {
+                Ok(Some((v1, rest))) if !rest.is_empty() => (v1.type_, rest),
+                _ => {
+                    self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_LAYER, l));
+                    continue;
+                },
+            };
+            match type_ {
+                format::MAP_ITEMTYPE_LAYER_V1_TILEMAP => self.tilemap(l, rest),
+                format::MAP_ITEMTYPE_LAYER_V1_QUADS => self.quads(l, rest)?,
+                format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS
+                    | format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY
+                => {
+                    if !(1 <= rest[0] && rest[0] <= 2) {
+                        self.add(Diagnostic::UnknownLayerVersion(l, type_, rest[0]));
+                    }
+                },
+                _ => self.add(Diagnostic::UnknownLayerType(l, type_)),
+            }
+        }
+        Ok(())
+    }
+    fn images(&mut self) {
+        let num_data = self.map.reader.num_data();
+        let indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE);
+        for (m, i) in indices.enumerate() {
+            match format::MapItemImageV1::from_slice(self.map.reader.item(i).data) {
+                Ok(Some(v1)) => {
+                    if v1.external == 0 && !in_range(v1.data, &(0..num_data)) {
+                        self.add(Diagnostic::MissingImageData(m));
+                    }
+                },
+                _ => {
+                    self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_IMAGE, m));
+                    continue;
+                },
+            }
+            if !self.used_images.contains(&m) {
+                self.add(Diagnostic::UnusedImage(m));
+            }
+        }
+    }
+    fn envelopes(&mut self) {
+        let indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE);
+        let envpoints_len = self.map.reader.find_item(format::MAP_ITEMTYPE_ENVPOINTS, 0)
+            .map(|i| i.data.len())
+            .unwrap_or(0);
+        for (e, i) in indices.enumerate() {
+            let raw = self.map.reader.item(i).data;
+            let v1 = match format::MapItemEnvelopeV1::from_slice(raw) {
+                Ok(Some(v1)) => *v1,
+                _ => {
+                    self.add(Diagnostic::TruncatedItem(format::MAP_ITEMTYPE_ENVELOPE, e));
+                    continue;
+                },
+            };
+            let point_size = if raw[0] >= 3 {
+                mem::size_of::<format::MapItemEnvpointV2>()
+            } else {
+                mem::size_of::<format::MapItemEnvpointV1>()
+            };
+            let num_points = envpoints_len * mem::size_of::<i32>() / point_size;
+            let (start, num) = (v1.start_points, v1.num_points);
+            let valid = start.try_usize().and_then(|s| num.try_usize().map(|n| s + n))
+                .map(|end| end <= num_points)
+                .unwrap_or(false);
+            if !valid {
+                self.diagnostics.push(Diagnostic::InvalidEnvelopePoints(e, start, num));
+            }
+        }
+    }
+    fn game_layers(&mut self) {
+        let game = self.game_layers.iter()
+            .find(|&&(_, flags, _, _)| flags == format::TILELAYERFLAG_GAME)
+            .cloned();
+        let (_, _, width, height) = match game {
+            Some(g) => g,
+            None => {
+                self.add(Diagnostic::MissingGameLayer);
+                return;
+            },
+        };
+        let mut seen = HashSet::new();
+        for &(l, flags, w, h) in &self.game_layers {
+            if !seen.insert(flags) {
+                self.diagnostics.push(Diagnostic::DuplicateGameLayer(l, flags));
+            } else if w != width || h != height {
+                self.diagnostics.push(
+                    Diagnostic::GameLayerSizeMismatch(l, flags, w, h, width, height)
+                );
+            }
+        }
+    }
+    fn teleporters(&mut self) -> Result<(), Error> {
+        let tele = match self.map.game_layers().ok().and_then(|g| g.teleport()) {
+            Some(t) => t,
+            // Problems with the game layers are reported separately.
+            None => return Ok(()),
+        };
+        let tiles = match self.map.tele_layer_tiles(tele) {
+            Ok(t) => t,
+            Err(Error::Map(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut ins = BTreeMap::new();
+        let mut outs = BTreeMap::new();
+        for ((y, x), t) in tiles.indexed_iter() {
+            let (x, y) = (x.assert_u32(), y.assert_u32());
+            let is_in = TELE_IN.contains(&t.index);
+            if t.number == 0 {
+                if is_in || t.index == TELE_OUT || TELE_CHECKPOINT.contains(&t.index) {
+                    self.add(Diagnostic::TeleporterWithoutNumber(x, y));
+                }
+                continue;
+            }
+            if is_in {
+                ins.entry(t.number).or_insert((x, y));
+            } else if t.index == TELE_OUT {
+                outs.entry(t.number).or_insert((x, y));
+            }
+        }
+        for (&n, &(x, y)) in &ins {
+            if !outs.contains_key(&n) {
+                self.add(Diagnostic::UnpairedTeleporterIn(n, x, y));
+            }
+        }
+        for (&n, &(x, y)) in &outs {
+            if !ins.contains_key(&n) {
+                self.add(Diagnostic::UnpairedTeleporterOut(n, x, y));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs all checks on the map.
+///
+/// Only fails if the map's data can't be read, problems with the map's
+/// contents are reported as diagnostics.
+pub fn lint(map: &mut Reader) -> Result<Vec<Diagnostic>, Error> {
+    let mut linter = Linter {
+        map: map,
+        diagnostics: Vec::new(),
+        used_images: HashSet::new(),
+        game_layers: Vec::new(),
+    };
+    linter.layers()?;
+    linter.groups();
+    linter.images();
+    linter.envelopes();
+    linter.game_layers();
+    linter.teleporters()?;
+    Ok(linter.diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use format;
+    use super::Diagnostic::*;
+    use super::Diagnostic;
+    use testmap::TestMap;
+
+    fn lint(map: &TestMap) -> Vec<Diagnostic> {
+        super::lint(&mut map.open()).unwrap()
+    }
+
+    /// Returns a map with a game layer that passes all checks.
+    fn map() -> TestMap {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(4, 2);
+        map
+    }
+
+    #[test]
+    fn clean() {
+        let mut map = map();
+        let image = map.image(b"grass", 1, 1, Some(vec![0; 4]));
+        map.envelope(1, 0, 2);
+        map.envpoints(2);
+        map.group();
+        map.tile_layer(0, 2, 1, image, &[1, 2]);
+        map.quad_layer(image, &[(0, -1), (-1, 0)]);
+        assert_eq!(lint(&map), vec![]);
+    }
+
+    #[test]
+    fn groups() {
+        let mut map = map();
+        map.group();
+        map.item(format::MAP_ITEMTYPE_GROUP, &[1, 0, 0, 100, 100, 1, 1]);
+        map.item(format::MAP_ITEMTYPE_GROUP, &[1, 0, 0, 100, 100, -1, 1]);
+        map.item(format::MAP_ITEMTYPE_GROUP, &[1, 0, 0]);
+        assert_eq!(lint(&map), vec![
+            EmptyGroup(1),
+            InvalidGroupLayers(2, 1, 1),
+            InvalidGroupLayers(3, -1, 1),
+            TruncatedItem(format::MAP_ITEMTYPE_GROUP, 4),
+        ]);
+    }
+
+    #[test]
+    fn layers() {
+        let mut map = map();
+        map.layer(42, &[1]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_TILEMAP, &[3, 1]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_TILEMAP, &[4, 1, 1, 0, 255, 255, 255, 255, -1, 0, -1, 0]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_QUADS, &[2, 1, 100, -1]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS, &[3]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS_LEGACY, &[1]);
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_TILEMAP, &[]);
+        assert_eq!(lint(&map), vec![
+            UnknownLayerType(1, 42),
+            TruncatedItem(format::MAP_ITEMTYPE_LAYER, 2),
+            UnknownLayerVersion(3, format::MAP_ITEMTYPE_LAYER_V1_TILEMAP, 4),
+            MalformedLayerData(4),
+            UnknownLayerVersion(5, format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS, 3),
+            TruncatedItem(format::MAP_ITEMTYPE_LAYER, 7),
+        ]);
+    }
+
+    #[test]
+    fn images() {
+        let mut map = map();
+        map.image(b"external", 64, 64, None);
+        map.image(b"embedded", 1, 1, Some(vec![0; 4]));
+        let name = map.string(b"missing");
+        map.item(format::MAP_ITEMTYPE_IMAGE, &[1, 1, 1, 0, name, -1]);
+        map.item(format::MAP_ITEMTYPE_IMAGE, &[1, 1]);
+        map.tile_layer(0, 1, 1, 1, &[1]);
+        assert_eq!(lint(&map), vec![
+            UnusedImage(0),
+            MissingImageData(2),
+            UnusedImage(2),
+            TruncatedItem(format::MAP_ITEMTYPE_IMAGE, 3),
+        ]);
+    }
+
+    #[test]
+    fn image_indices() {
+        let mut map = map();
+        let image = map.image(b"external", 64, 64, None);
+        map.tile_layer(0, 1, 1, image, &[1]);
+        map.tile_layer(0, 1, 1, 1, &[1]);
+        map.quad_layer(-2, &[]);
+        assert_eq!(lint(&map), vec![
+            InvalidImageIndex(2, 1),
+            InvalidImageIndex(3, -2),
+        ]);
+    }
+
+    #[test]
+    fn envelopes() {
+        let mut map = map();
+        map.envelope(1, 0, 2);
+        map.envelope(2, 3, 2);
+        // Version 3 envelopes have larger points, only one fits.
+        map.envelope(3, 0, 2);
+        map.envelope(3, 0, 1);
+        map.envelope(1, -1, 1);
+        map.item(format::MAP_ITEMTYPE_ENVELOPE, &[1, 4, 0]);
+        map.envpoints(4);
+        let data = map.data(vec![0; 4]);
+        map.tiles(0, 1, 1, -1, 6, data, None);
+        map.quad_layer(-1, &[(0, 4), (7, -1)]);
+        assert_eq!(lint(&map), vec![
+            InvalidEnvelopeIndex(1, 6),
+            InvalidQuadEnvelopeIndex(2, 1, 7),
+            InvalidEnvelopePoints(1, 3, 2),
+            InvalidEnvelopePoints(2, 0, 2),
+            InvalidEnvelopePoints(4, -1, 1),
+            TruncatedItem(format::MAP_ITEMTYPE_ENVELOPE, 5),
+        ]);
+    }
+
+    #[test]
+    fn game_layers() {
+        let mut map = TestMap::new();
+        map.group();
+        map.tile_layer(0, 1, 1, -1, &[1]);
+        assert_eq!(lint(&map), vec![MissingGameLayer]);
+
+        let mut map = self::map();
+        map.game_layer(4, 2);
+        map.race_layer(format::TILELAYERFLAG_TELEPORT, 4, 3, vec![0; 24]);
+        map.race_layer(format::TILELAYERFLAG_SWITCH, 4, 2, vec![0; 32]);
+        assert_eq!(lint(&map), vec![
+            DuplicateGameLayer(1, format::TILELAYERFLAG_GAME),
+            GameLayerSizeMismatch(2, format::TILELAYERFLAG_TELEPORT, 4, 3, 4, 2),
+        ]);
+    }
+
+    #[test]
+    fn teleporters() {
+        let mut map = map();
+        map.race_layer(format::TILELAYERFLAG_TELEPORT, 4, 2, vec![
+            // (number, index)
+            1, 10,  1, 27,  2, 26,  0, 27,
+            0, 30,  3, 27,  0, 63,  0, 31,
+        ]);
+        assert_eq!(lint(&map), vec![
+            TeleporterWithoutNumber(3, 0),
+            TeleporterWithoutNumber(0, 1),
+            UnpairedTeleporterIn(2, 2, 0),
+            UnpairedTeleporterOut(3, 1, 1),
+        ]);
+    }
+}
@@ -0,0 +1,566 @@
+//! Size optimization for map files.
+//!
+//! `optimize` rebuilds a map into a fresh datafile buffer, leaving out
+//! everything the game doesn't need and upgrading old item versions on the
+//! way. Write the result with `datafile::writer::write`.
+
+use common::num::Cast;
+use common::num::LeI32;
+use common::vec;
+use datafile::buffer::Buffer;
+use format::MapItemExt;
+use format;
+use reader::Error;
+use reader::LayerTilemapType;
+use reader::LayerType;
+use reader::Reader;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops;
+
+// Sizes of the envelope points in `i32`s, see `format::MapItemEnvpointV1`
+// and `format::MapItemEnvpointV2`.
+const ENVPOINT_V1_LEN: usize = 6;
+const ENVPOINT_V2_LEN: usize = 22;
+
+// Indices into the raw items. For layers, they're relative to the
+// type-specific part starting at `LAYER_REST`.
+const LAYER_REST: usize = 3;
+const TILEMAP_COLOR_ENV: usize = 8;
+const TILEMAP_IMAGE: usize = 10;
+const TILEMAP_DATA: usize = 11;
+const QUADS_NUM: usize = 1;
+const QUADS_DATA: usize = 2;
+const QUADS_IMAGE: usize = 3;
+const SOUNDS_DATA: usize = 2;
+const SOUNDS_SOUND: usize = 3;
+const GROUP_START_LAYER: usize = 5;
+const GROUP_NUM_LAYERS: usize = 6;
+const IMAGE_NAME: usize = 4;
+const IMAGE_DATA: usize = 5;
+const SOUND_NAME: usize = 2;
+const SOUND_DATA: usize = 3;
+const INFO_DATA: ops::Range<usize> = 1..6;
+
+struct Envelope {
+    version: i32,
+    channels: i32,
+    start_points: usize,
+    num_points: usize,
+    name: [i32; 8],
+    synchronized: i32,
+}
+
+impl Envelope {
+    fn from_raw(raw: &[i32], envelope: usize) -> Result<Envelope, format::Error> {
+        let malformed = || format::Error::MalformedEnvelope(envelope);
+        let (version, channels, start, num, name, synchronized);
+        if let Ok(Some(v1)) = format::MapItemEnvelopeV1::from_slice(raw) {
+            version = raw[0];
+            channels = v1.channels;
+            start = v1.start_points;
+            num = v1.num_points;
+            name = v1.name;
+            synchronized = match format::MapItemEnvelopeV2::from_slice(raw) {
+                Ok(Some(v2)) => v2.synchronized,
+                Ok(None) => 1,
+                Err(format::TooShort) => return Err(malformed()),
+            };
+        } else if let Ok(Some(legacy)) = format::MapItemEnvelopeV1Legacy::from_slice(raw) {
+            version = raw[0];
+            channels = legacy.channels;
+            start = legacy.start_points;
+            num = legacy.num_points;
+            name = [0; 8];
+            synchronized = 1;
+        } else {
+            return Err(malformed());
+        }
+        Ok(Envelope {
+            version: version,
+            channels: channels,
+            start_points: start.try_usize().ok_or_else(malformed)?,
+            num_points: num.try_usize().ok_or_else(malformed)?,
+            name: name,
+            synchronized: synchronized,
+        })
+    }
+    fn point_len(&self) -> usize {
+        if self.version >= 3 { ENVPOINT_V2_LEN } else { ENVPOINT_V1_LEN }
+    }
+}
+
+// Maps the kept items of one type to their new indices.
+fn remap(kept: &[bool]) -> Vec<i32> {
+    let mut next = 0;
+    kept.iter().map(|&k| {
+        if !k {
+            return -1;
+        }
+        next += 1;
+        next - 1
+    }).collect()
+}
+
+fn empty_name() -> [i32; 3] {
+    let mut name = [0; 3];
+    format::string_to_i32s(&mut name, b"");
+    name
+}
+
+fn lookup(map: &[i32], index: i32) -> i32 {
+    index.try_usize().and_then(|i| map.get(i).cloned()).unwrap_or(-1)
+}
+
+struct Optimizer<'a> {
+    map: &'a mut Reader,
+    buffer: Buffer,
+    // Maps old data indices to new ones.
+    data: HashMap<usize, i32>,
+    // Maps data contents to new data indices, for deduplication.
+    blobs: HashMap<Vec<u8>, i32>,
+}
+
+impl<'a> Optimizer<'a> {
+    fn add_item(&mut self, type_id: u16, id: usize, data: &[i32]) {
+        self.buffer.add_item(type_id, id.assert_u16(), data)
+            .expect("item ids are unique");
+    }
+    fn add_data(&mut self, blob: Vec<u8>) -> i32 {
+        if let Some(&index) = self.blobs.get(&blob) {
+            return index;
+        }
+        let index = self.buffer.add_data(blob.clone()).assert_i32();
+        self.blobs.insert(blob, index);
+        index
+    }
+    fn data(&mut self, index: i32) -> Result<i32, Error> {
+        let index = match index.try_usize() {
+            Some(i) if i < self.map.reader.num_data() => i,
+            // References to missing data are dropped.
+            _ => return Ok(-1),
+        };
+        if let Some(&new) = self.data.get(&index) {
+            return Ok(new);
+        }
+        let blob = self.map.reader.read_data(index)?;
+        let new = self.add_data(blob);
+        self.data.insert(index, new);
+        Ok(new)
+    }
+    fn remap_data(&mut self, raw: &mut [i32], indices: ops::Range<usize>) -> Result<(), Error> {
+        for i in indices {
+            if i < raw.len() {
+                raw[i] = self.data(raw[i])?;
+            }
+        }
+        Ok(())
+    }
+    fn is_empty_tilemap(&mut self, data: usize) -> Result<bool, Error> {
+        Ok(self.map.layer_tiles_raw(data)?.iter().all(|t| t.index == 0))
+    }
+    fn optimize(&mut self) -> Result<(), Error> {
+        let group_indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_GROUP);
+        let layer_indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_LAYER);
+        let image_indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE);
+        let envelope_indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE);
+        let sound_indices = self.map.reader.item_type_indices(format::MAP_ITEMTYPE_DDRACE_SOUND);
+
+        // Parse everything first, so that malformed maps are rejected instead
+        // of being written out with dangling references.
+        for i in group_indices.clone() {
+            self.map.group(i)?;
+        }
+        for i in image_indices.clone() {
+            self.map.image(i)?;
+        }
+        let mut envelopes = Vec::new();
+        for (e, i) in envelope_indices.clone().enumerate() {
+            envelopes.push(Envelope::from_raw(self.map.reader.item(i).data, e)?);
+        }
+        for (s, i) in sound_indices.clone().enumerate() {
+            let raw = self.map.reader.item(i).data;
+            if let Ok(Some(_)) = format::MapItemDdraceSoundV1::from_slice(raw) {
+                // ok
+            } else {
+                return Err(format::Error::MalformedSound(s).into());
+            }
+        }
+
+        let mut kept_layers = Vec::new();
+        let mut used_images = HashSet::new();
+        let mut used_envelopes = HashSet::new();
+        let mut used_sounds = HashSet::new();
+        let mut all_envelopes = false;
+        for i in layer_indices.clone() {
+            let keep = match self.map.layer(i)?.t {
+                LayerType::Tilemap(t) => match t.type_ {
+                    LayerTilemapType::Normal(n) => {
+                        let keep = !self.is_empty_tilemap(n.data)?;
+                        if keep {
+                            used_images.extend(n.image);
+                            used_envelopes.extend(n.color_env_and_offset.map(|(e, _)| e));
+                        }
+                        keep
+                    },
+                    _ => true,
+                },
+                LayerType::Quads(q) => {
+                    let keep = q.num_quads != 0;
+                    if keep {
+                        used_images.extend(q.image);
+                        for quad in self.map.quads(q.data)? {
+                            for &env in &[quad.pos_env.to_i32(), quad.color_env.to_i32()] {
+                                if let Some(e) = env.try_usize() {
+                                    used_envelopes.insert(envelope_indices.start + e);
+                                }
+                            }
+                        }
+                    }
+                    keep
+                },
+                LayerType::DdraceSounds(s) => {
+                    let keep = s.num_sources != 0;
+                    if keep {
+                        used_sounds.extend(s.sound);
+                        // Sound sources reference envelopes, too. Their
+                        // format isn't parsed here, so keep all envelopes.
+                        all_envelopes = true;
+                    }
+                    keep
+                },
+            };
+            kept_layers.push(keep);
+        }
+
+        let kept = |indices: ops::Range<usize>, used: &HashSet<usize>| -> Vec<bool> {
+            indices.map(|i| used.contains(&i)).collect()
+        };
+        let kept_images = kept(image_indices.clone(), &used_images);
+        let kept_envelopes = if all_envelopes {
+            vec![true; envelope_indices.len()]
+        } else {
+            kept(envelope_indices.clone(), &used_envelopes)
+        };
+        let kept_sounds = kept(sound_indices.clone(), &used_sounds);
+        let image_map = remap(&kept_images);
+        let envelope_map = remap(&kept_envelopes);
+        let sound_map = remap(&kept_sounds);
+
+        // Items of other types are dropped, they might reference the
+        // removed items or data.
+        if let Some(version) = self.map.reader.find_item(format::MAP_ITEMTYPE_VERSION, 0) {
+            let data = version.data.to_vec();
+            self.add_item(format::MAP_ITEMTYPE_VERSION, 0, &data);
+        }
+
+        if let Some(info) = self.map.reader.find_item(format::MAP_ITEMTYPE_INFO, 0) {
+            self.map.info()?;
+            let mut raw = info.data.to_vec();
+            self.remap_data(&mut raw, INFO_DATA)?;
+            self.add_item(format::MAP_ITEMTYPE_INFO, 0, &raw);
+        }
+
+        let mut new_image = 0;
+        for (m, i) in image_indices.enumerate() {
+            if !kept_images[m] {
+                continue;
+            }
+            let external = self.map.image(i)?.data.is_none();
+            let mut raw = self.map.reader.item(i).data.to_vec();
+            if external {
+                raw[IMAGE_DATA] = -1;
+            }
+            self.remap_data(&mut raw, IMAGE_NAME..IMAGE_DATA + 1)?;
+            self.add_item(format::MAP_ITEMTYPE_IMAGE, new_image, &raw);
+            new_image += 1;
+        }
+
+        let old_envpoints = self.map.reader.find_item(format::MAP_ITEMTYPE_ENVPOINTS, 0)
+            .map(|i| i.data.to_vec())
+            .unwrap_or_default();
+        // All envelopes share the envpoints item and refer to their points by
+        // index, so all points need to have the same size. Points are only
+        // upgraded to the larger format if an envelope already uses it.
+        let any_v3 = envelopes.iter().enumerate()
+            .any(|(e, envelope)| kept_envelopes[e] && envelope.version >= 3);
+        let (version, new_len) = if any_v3 { (3, ENVPOINT_V2_LEN) } else { (2, ENVPOINT_V1_LEN) };
+        let mut envpoints = Vec::new();
+        let mut num_envpoints = 0;
+        let mut new_envelope = 0;
+        for (e, envelope) in envelopes.iter().enumerate() {
+            if !kept_envelopes[e] {
+                continue;
+            }
+            let len = envelope.point_len();
+            let start = envelope.start_points * len;
+            let end = start + envelope.num_points * len;
+            if end > old_envpoints.len() {
+                return Err(format::Error::MalformedEnvelope(e).into());
+            }
+            for point in old_envpoints[start..end].chunks(len) {
+                // Missing bezier tangents are zero.
+                envpoints.extend_from_slice(point);
+                envpoints.resize(envpoints.len() + new_len - len, 0);
+            }
+            let mut raw = vec![
+                version,
+                envelope.channels,
+                num_envpoints.assert_i32(),
+                envelope.num_points.assert_i32(),
+            ];
+            num_envpoints += envelope.num_points;
+            raw.extend_from_slice(&envelope.name);
+            raw.push(envelope.synchronized);
+            self.add_item(format::MAP_ITEMTYPE_ENVELOPE, new_envelope, &raw);
+            new_envelope += 1;
+        }
+        if !envpoints.is_empty() {
+            self.add_item(format::MAP_ITEMTYPE_ENVPOINTS, 0, &envpoints);
+        }
+
+        // New index of each old layer, counting only the kept ones.
+        let mut layer_starts = Vec::with_capacity(kept_layers.len() + 1);
+        layer_starts.push(0);
+        for &k in &kept_layers {
+            let last = *layer_starts.last().unwrap();
+            layer_starts.push(if k { last + 1 } else { last });
+        }
+        for (g, i) in group_indices.enumerate() {
+            let group = self.map.group(i)?;
+            let start = group.layer_indices.start - layer_indices.start;
+            let end = group.layer_indices.end - layer_indices.start;
+            let mut raw = self.map.reader.item(i).data.to_vec();
+            // Upgrade to the latest group version, without clipping and
+            // without name if they weren't present.
+            let known_len = match raw[0] {
+                1 => format::MapItemGroupV1::sum_len(),
+                2 => format::MapItemGroupV2::sum_len(),
+                _ => raw.len(),
+            };
+            raw.truncate(known_len);
+            if raw[0] < 3 {
+                raw.resize(format::MapItemGroupV2::sum_len(), 0);
+                raw.extend_from_slice(&empty_name());
+                raw[0] = 3;
+            }
+            raw[GROUP_START_LAYER] = layer_starts[start];
+            raw[GROUP_NUM_LAYERS] = layer_starts[end] - layer_starts[start];
+            self.add_item(format::MAP_ITEMTYPE_GROUP, g, &raw);
+        }
+
+        let mut new_layer = 0;
+        for (l, i) in layer_indices.enumerate() {
+            if !kept_layers[l] {
+                continue;
+            }
+            let mut raw = self.map.reader.item(i).data.to_vec();
+            let mut rest = raw.split_off(LAYER_REST);
+            match raw[1] {
+                format::MAP_ITEMTYPE_LAYER_V1_TILEMAP =>
+                    self.tilemap(&mut rest, &image_map, &envelope_map)?,
+                format::MAP_ITEMTYPE_LAYER_V1_QUADS =>
+                    self.quads(&mut rest, &image_map, &envelope_map)?,
+                _ => {
+                    rest[SOUNDS_DATA] = self.data(rest[SOUNDS_DATA])?;
+                    rest[SOUNDS_SOUND] = lookup(&sound_map, rest[SOUNDS_SOUND]);
+                },
+            }
+            raw.extend(rest);
+            self.add_item(format::MAP_ITEMTYPE_LAYER, new_layer, &raw);
+            new_layer += 1;
+        }
+
+        let mut new_sound = 0;
+        for (s, i) in sound_indices.enumerate() {
+            if !kept_sounds[s] {
+                continue;
+            }
+            let mut raw = self.map.reader.item(i).data.to_vec();
+            self.remap_data(&mut raw, SOUND_NAME..SOUND_DATA + 1)?;
+            self.add_item(format::MAP_ITEMTYPE_DDRACE_SOUND, new_sound, &raw);
+            new_sound += 1;
+        }
+        Ok(())
+    }
+    fn tilemap(&mut self, rest: &mut Vec<i32>, image_map: &[i32], envelope_map: &[i32])
+        -> Result<(), Error>
+    {
+        if rest[0] == 2 {
+            // Upgrade to version 3 by inserting an empty name.
+            let extra = rest.split_off(format::MapItemLayerV1TilemapV2::sum_len());
+            rest.extend_from_slice(&empty_name());
+            rest.extend(extra);
+            rest[0] = 3;
+        }
+        rest[TILEMAP_COLOR_ENV] = lookup(envelope_map, rest[TILEMAP_COLOR_ENV]);
+        rest[TILEMAP_IMAGE] = lookup(image_map, rest[TILEMAP_IMAGE]);
+        rest[TILEMAP_DATA] = self.data(rest[TILEMAP_DATA])?;
+        let extra = format::MapItemLayerV1TilemapV3::sum_len();
+        let len = rest.len();
+        self.remap_data(rest, extra..cmp::max(extra, len))
+    }
+    fn quads(&mut self, rest: &mut Vec<i32>, image_map: &[i32], envelope_map: &[i32])
+        -> Result<(), Error>
+    {
+        if rest[0] == 1 {
+            // Upgrade to version 2 by appending an empty name.
+            rest.truncate(format::MapItemLayerV1QuadsV1::sum_len());
+            rest.extend_from_slice(&empty_name());
+            rest[0] = 2;
+        }
+        let mut quads = self.map.quads(rest[QUADS_DATA].assert_usize())?;
+        for quad in &mut quads {
+            quad.pos_env = LeI32::from_i32(lookup(envelope_map, quad.pos_env.to_i32()));
+            quad.color_env = LeI32::from_i32(lookup(envelope_map, quad.color_env.to_i32()));
+        }
+        rest[QUADS_NUM] = quads.len().assert_i32();
+        rest[QUADS_DATA] = self.add_data(unsafe { vec::transmute(quads) });
+        rest[QUADS_IMAGE] = lookup(image_map, rest[QUADS_IMAGE]);
+        Ok(())
+    }
+}
+
+/// Rebuilds the map into a new datafile buffer.
+///
+/// Images, envelopes and sounds that aren't referenced by any layer are
+/// removed, as are layers that don't contain anything. Identical data blobs
+/// are stored only once. Groups, layers and envelopes are upgraded to their
+/// latest item versions.
+///
+/// Items of unknown types, like DDNet's UUID items, are dropped; they might
+/// reference the removed items or data by their old indices.
+pub fn optimize(map: &mut Reader) -> Result<Buffer, Error> {
+    let mut optimizer = Optimizer {
+        map: map,
+        buffer: Buffer::new(),
+        data: HashMap::new(),
+        blobs: HashMap::new(),
+    };
+    optimizer.optimize()?;
+    Ok(optimizer.buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use common::num::Cast;
+    use format;
+    use lint::lint;
+    use reader::LayerTilemapType;
+    use reader::LayerType;
+    use reader::Reader;
+    use super::ENVPOINT_V1_LEN;
+    use super::ENVPOINT_V2_LEN;
+    use super::optimize;
+    use testmap::TestMap;
+    use testmap;
+
+    fn optimized(map: &TestMap) -> Reader {
+        let mut result = testmap::open(&optimize(&mut map.open()).unwrap());
+        assert_eq!(lint(&mut result).unwrap(), vec![]);
+        result
+    }
+
+    fn items(map: &Reader, type_id: u16) -> Vec<Vec<i32>> {
+        map.reader.item_type_indices(type_id).map(|i| map.reader.item(i).data.to_vec()).collect()
+    }
+
+    #[test]
+    fn remap() {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(2, 1);
+        map.image(b"unused", 64, 64, None);
+        let image = map.image(b"used", 1, 1, Some(vec![0; 4]));
+        map.envelope(1, 0, 1);
+        let envelope = map.envelope(1, 1, 2);
+        map.envpoints(3);
+        map.group();
+        map.tile_layer(0, 2, 1, image, &[0, 0]);
+        let data = map.data(vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        map.tiles(0, 2, 1, image, envelope, data, None);
+        map.quad_layer(-1, &[]);
+        map.quad_layer(image, &[(-1, envelope)]);
+
+        let mut map = optimized(&map);
+        let images: Vec<_> = map.reader.item_type_indices(format::MAP_ITEMTYPE_IMAGE).collect();
+        assert_eq!(images.len(), 1);
+        let name = map.image(images[0]).unwrap().name;
+        assert_eq!(map.string(name).unwrap(), b"used");
+
+        let groups = items(&map, format::MAP_ITEMTYPE_GROUP);
+        assert_eq!(groups.len(), 2);
+        // (start_layer, num_layers)
+        assert_eq!((groups[0][5], groups[0][6]), (0, 1));
+        assert_eq!((groups[1][5], groups[1][6]), (1, 2));
+
+        let layers = map.reader.item_type_indices(format::MAP_ITEMTYPE_LAYER);
+        assert_eq!(layers.len(), 3);
+        match map.layer(layers.start + 1).unwrap().t {
+            LayerType::Tilemap(t) => match t.type_ {
+                LayerTilemapType::Normal(n) => {
+                    assert_eq!(n.image, Some(images[0]));
+                    let envelopes = map.reader.item_type_indices(format::MAP_ITEMTYPE_ENVELOPE);
+                    assert_eq!(n.color_env_and_offset, Some((envelopes.start, 0)));
+                },
+                _ => panic!("not a tile layer"),
+            },
+            _ => panic!("not a tile layer"),
+        }
+        let quads = match map.layer(layers.start + 2).unwrap().t {
+            LayerType::Quads(q) => {
+                assert_eq!(q.image, Some(images[0]));
+                map.quads(q.data).unwrap()
+            },
+            _ => panic!("not a quad layer"),
+        };
+        assert_eq!(quads.len(), 1);
+        assert_eq!((quads[0].pos_env.to_i32(), quads[0].color_env.to_i32()), (-1, 0));
+
+        let envelopes = items(&map, format::MAP_ITEMTYPE_ENVELOPE);
+        assert_eq!(envelopes.len(), 1);
+        // (version, start_points, num_points)
+        assert_eq!((envelopes[0][0], envelopes[0][2], envelopes[0][3]), (2, 0, 2));
+        let envpoints = &items(&map, format::MAP_ITEMTYPE_ENVPOINTS)[0];
+        let times: Vec<_> = envpoints.chunks(ENVPOINT_V1_LEN).map(|p| p[0]).collect();
+        assert_eq!(times, [1, 2]);
+    }
+
+    #[test]
+    fn mixed_envelope_versions() {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(1, 1);
+        let v1 = map.envelope(1, 0, 2);
+        // The start of version 3 envelopes counts the larger points.
+        let v3 = map.envelope(3, 1, 1);
+        let mut envpoints = vec![0; 2 * ENVPOINT_V2_LEN];
+        envpoints[ENVPOINT_V1_LEN] = 1;
+        envpoints[ENVPOINT_V2_LEN] = 100;
+        envpoints[ENVPOINT_V2_LEN + ENVPOINT_V2_LEN - 1] = 101;
+        map.item(format::MAP_ITEMTYPE_ENVPOINTS, &envpoints);
+        map.quad_layer(-1, &[(v1, v3)]);
+
+        let map = optimized(&map);
+        let envelopes = items(&map, format::MAP_ITEMTYPE_ENVELOPE);
+        let envelopes: Vec<_> = envelopes.iter().map(|e| (e[0], e[2], e[3])).collect();
+        assert_eq!(envelopes, [(3, 0, 2), (3, 2, 1)]);
+        let envpoints = &items(&map, format::MAP_ITEMTYPE_ENVPOINTS)[0];
+        assert_eq!(envpoints.len(), 3 * ENVPOINT_V2_LEN);
+        let points: Vec<_> = envpoints.chunks(ENVPOINT_V2_LEN).map(|p| (p[0], p[ENVPOINT_V2_LEN - 1])).collect();
+        assert_eq!(points, [(0, 0), (1, 0), (100, 101)]);
+    }
+
+    #[test]
+    fn unknown_items() {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(1, 1);
+        map.item(0x8000, &[0, 1, 2]);
+        let map = optimized(&map);
+        assert!(map.reader.find_item(0x8000, 0).is_none());
+        assert_eq!(map.version(), Ok(1));
+        assert_eq!(map.reader.num_items().assert_i32(), 3);
+    }
+}
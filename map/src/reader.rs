@@ -36,7 +36,7 @@ impl From<io::Error> for Error {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -44,7 +44,7 @@ pub struct Color {
     pub alpha: u8,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Clipping {
     pub x: i32,
     pub y: i32,
@@ -799,7 +799,7 @@ impl Reader {
         let tiles = self.tune_layer_tiles_raw(data_index)?;
         let len = tiles.len();
         Ok(Array2::from_shape_vec((height.usize(), width.usize()), tiles)
-            .map_err(|_| MapError::InvalidTilesDimensions(len, height, width))?)
+            .map_err(|_| MapError::InvalidTilesDimensions(len, width, height))?)
     }
     pub fn tele_layer_tiles_raw(&mut self, data_index: usize)
         -> Result<Vec<format::TeleTile>, Error>
@@ -818,7 +818,45 @@ impl Reader {
         let tiles = self.tele_layer_tiles_raw(data_index)?;
         let len = tiles.len();
         Ok(Array2::from_shape_vec((height.usize(), width.usize()), tiles)
-            .map_err(|_| MapError::InvalidTilesDimensions(len, height, width))?)
+            .map_err(|_| MapError::InvalidTilesDimensions(len, width, height))?)
+    }
+    pub fn speedup_layer_tiles_raw(&mut self, data_index: usize)
+        -> Result<Vec<format::SpeedupTile>, Error>
+    {
+        let raw = self.reader.read_data(data_index)?;
+        if raw.len() % mem::size_of::<format::SpeedupTile>() != 0 {
+            return Err(Error::Map(MapError::InvalidSpeedupTilesLength(raw.len())));
+        }
+        let tiles: Vec<format::SpeedupTile> = unsafe { vec::transmute(raw) };
+        Ok(tiles)
+    }
+    pub fn speedup_layer_tiles(&mut self, index: LayerTilesIndex)
+        -> Result<Array2<format::SpeedupTile>, Error>
+    {
+        let LayerTilesIndex { data_index, width, height } = index;
+        let tiles = self.speedup_layer_tiles_raw(data_index)?;
+        let len = tiles.len();
+        Ok(Array2::from_shape_vec((height.usize(), width.usize()), tiles)
+            .map_err(|_| MapError::InvalidSpeedupTilesDimensions(len, width, height))?)
+    }
+    pub fn switch_layer_tiles_raw(&mut self, data_index: usize)
+        -> Result<Vec<format::SwitchTile>, Error>
+    {
+        let raw = self.reader.read_data(data_index)?;
+        if raw.len() % mem::size_of::<format::SwitchTile>() != 0 {
+            return Err(Error::Map(MapError::InvalidSwitchTilesLength(raw.len())));
+        }
+        let tiles: Vec<format::SwitchTile> = unsafe { vec::transmute(raw) };
+        Ok(tiles)
+    }
+    pub fn switch_layer_tiles(&mut self, index: LayerTilesIndex)
+        -> Result<Array2<format::SwitchTile>, Error>
+    {
+        let LayerTilesIndex { data_index, width, height } = index;
+        let tiles = self.switch_layer_tiles_raw(data_index)?;
+        let len = tiles.len();
+        Ok(Array2::from_shape_vec((height.usize(), width.usize()), tiles)
+            .map_err(|_| MapError::InvalidSwitchTilesDimensions(len, width, height))?)
     }
     pub fn layer_tiles_raw(&mut self, data_index: usize)
         -> Result<Vec<format::Tile>, Error>
@@ -837,7 +875,17 @@ impl Reader {
         let tiles = self.layer_tiles_raw(data_index)?;
         let len = tiles.len();
         Ok(Array2::from_shape_vec((height.usize(), width.usize()), tiles)
-            .map_err(|_| MapError::InvalidTilesDimensions(len, height, width))?)
+            .map_err(|_| MapError::InvalidTilesDimensions(len, width, height))?)
+    }
+    pub fn quads(&mut self, data_index: usize)
+        -> Result<Vec<format::Quad>, Error>
+    {
+        let raw = self.reader.read_data(data_index)?;
+        if raw.len() % mem::size_of::<format::Quad>() != 0 {
+            return Err(Error::Map(MapError::InvalidQuadsLength(raw.len())));
+        }
+        let quads: Vec<format::Quad> = unsafe { vec::transmute(raw) };
+        Ok(quads)
     }
     pub fn string(&mut self, data_index: usize)
         -> Result<Vec<u8>, Error>
@@ -865,3 +913,89 @@ impl Reader {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use format::Error as MapError;
+    use format;
+    use super::Error;
+    use super::Reader;
+    use testmap::TestMap;
+
+    /// Returns a 2×1 map whose speedup or switch layer has `tiles` as data.
+    fn open(flags: u32, tiles: Vec<u8>) -> Reader {
+        let mut map = TestMap::new();
+        map.group();
+        map.game_layer(2, 1);
+        map.race_layer(flags, 2, 1, tiles);
+        map.open()
+    }
+
+    fn map_error<T>(result: Result<T, Error>) -> MapError {
+        match result {
+            Err(Error::Map(e)) => e,
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("unexpected success"),
+        }
+    }
+
+    #[test]
+    fn speedup_tiles() {
+        let mut reader = open(format::TILELAYERFLAG_SPEEDUP, vec![
+            10, 20, 28, 0, 0x2c, 0x01,
+            5, 0, 28, 0, 0xa6, 0xff,
+        ]);
+        let index = reader.game_layers().unwrap().speedup().unwrap();
+        let tiles = reader.speedup_layer_tiles(index).unwrap();
+        assert_eq!(tiles.dim(), (1, 2));
+        assert_eq!((tiles[(0, 0)].force, tiles[(0, 0)].max_speed), (10, 20));
+        assert_eq!(tiles[(0, 0)].type_, 28);
+        assert_eq!(tiles[(0, 0)].angle.to_i16(), 300);
+        assert_eq!((tiles[(0, 1)].force, tiles[(0, 1)].max_speed), (5, 0));
+        assert_eq!(tiles[(0, 1)].angle.to_i16(), -90);
+    }
+
+    #[test]
+    fn speedup_tiles_invalid() {
+        let mut reader = open(format::TILELAYERFLAG_SPEEDUP, vec![0; 7]);
+        let index = reader.game_layers().unwrap().speedup().unwrap();
+        assert_eq!(map_error(reader.speedup_layer_tiles(index)),
+                   MapError::InvalidSpeedupTilesLength(7));
+
+        let mut reader = open(format::TILELAYERFLAG_SPEEDUP, vec![0; 18]);
+        let index = reader.game_layers().unwrap().speedup().unwrap();
+        assert_eq!(map_error(reader.speedup_layer_tiles(index)),
+                   MapError::InvalidSpeedupTilesDimensions(3, 2, 1));
+    }
+
+    #[test]
+    fn switch_tiles() {
+        let mut reader = open(format::TILELAYERFLAG_SWITCH, vec![
+            1, 22, 0, 0,
+            255, 24, format::TILEFLAG_HFLIP, 10,
+        ]);
+        let index = reader.game_layers().unwrap().switch().unwrap();
+        let tiles = reader.switch_layer_tiles(index).unwrap();
+        assert_eq!(tiles.dim(), (1, 2));
+        assert_eq!(tiles[(0, 0)], format::SwitchTile { number: 1, type_: 22, flags: 0, delay: 0 });
+        assert_eq!(tiles[(0, 1)], format::SwitchTile {
+            number: 255,
+            type_: 24,
+            flags: format::TILEFLAG_HFLIP,
+            delay: 10,
+        });
+    }
+
+    #[test]
+    fn switch_tiles_invalid() {
+        let mut reader = open(format::TILELAYERFLAG_SWITCH, vec![0; 6]);
+        let index = reader.game_layers().unwrap().switch().unwrap();
+        assert_eq!(map_error(reader.switch_layer_tiles(index)),
+                   MapError::InvalidSwitchTilesLength(6));
+
+        let mut reader = open(format::TILELAYERFLAG_SWITCH, vec![0; 4]);
+        let index = reader.game_layers().unwrap().switch().unwrap();
+        assert_eq!(map_error(reader.switch_layer_tiles(index)),
+                   MapError::InvalidSwitchTilesDimensions(1, 2, 1));
+    }
+}
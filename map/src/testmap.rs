@@ -0,0 +1,200 @@
+//! Small hand-built maps for the tests of the other modules.
+
+use common::num::Cast;
+use datafile as df;
+use datafile::buffer::Buffer;
+use format;
+use reader::Reader;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::fs;
+use std::mem;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// Opens the map in `buffer` by writing it to a temporary file.
+pub fn open(buffer: &Buffer) -> Reader {
+    let n = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("map-test-{}-{}.map", process::id(), n));
+    df::writer::write(&mut File::create(&path).unwrap(), buffer, 9).unwrap();
+    let file = File::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    Reader::from_datafile(df::Reader::new(file).unwrap())
+}
+
+/// Encodes a string like the reference implementation's `StrToInts`.
+pub fn name<A: AsMut<[i32]> + Default>(string: &[u8]) -> A {
+    let mut result = A::default();
+    format::string_to_i32s(result.as_mut(), string);
+    result
+}
+
+/// Builds a map from raw items.
+///
+/// Layers are added to the group created last, the group items are written
+/// by `finish`.
+pub struct TestMap {
+    pub buffer: Buffer,
+    next_ids: HashMap<u16, u16>,
+    // (id, start_layer, num_layers)
+    groups: Vec<(u16, i32, i32)>,
+    num_layers: i32,
+}
+
+impl TestMap {
+    /// Returns a map containing only the version item.
+    pub fn new() -> TestMap {
+        let mut result = TestMap {
+            buffer: Buffer::new(),
+            next_ids: HashMap::new(),
+            groups: Vec::new(),
+            num_layers: 0,
+        };
+        result.item(format::MAP_ITEMTYPE_VERSION, &[1]);
+        result
+    }
+    fn next_id(&mut self, type_id: u16) -> u16 {
+        let next = self.next_ids.entry(type_id).or_insert(0);
+        *next += 1;
+        *next - 1
+    }
+    /// Adds an item with the next free ID of its type and returns that ID.
+    pub fn item(&mut self, type_id: u16, data: &[i32]) -> i32 {
+        let id = self.next_id(type_id);
+        self.buffer.add_item(type_id, id, data).unwrap();
+        id.i32()
+    }
+    pub fn data(&mut self, data: Vec<u8>) -> i32 {
+        self.buffer.add_data(data).assert_i32()
+    }
+    pub fn string(&mut self, string: &[u8]) -> i32 {
+        let mut data = string.to_vec();
+        data.push(0);
+        self.data(data)
+    }
+    /// Adds a group, following layers go into it.
+    pub fn group(&mut self) -> i32 {
+        let id = self.next_id(format::MAP_ITEMTYPE_GROUP);
+        self.groups.push((id, self.num_layers, 0));
+        id.i32()
+    }
+    /// Adds a layer item to the current group, `rest` being everything after
+    /// the layer type and flags.
+    pub fn layer(&mut self, type_: i32, rest: &[i32]) -> i32 {
+        let mut data = vec![0, type_, 0];
+        data.extend_from_slice(rest);
+        let id = self.item(format::MAP_ITEMTYPE_LAYER, &data);
+        self.num_layers += 1;
+        self.groups.last_mut().expect("layer outside of group").2 += 1;
+        id
+    }
+    /// Adds a version 3 tile layer with the given flags, `data` being the
+    /// tiles, `extra` the data index of a DDNet game layer.
+    pub fn tiles(&mut self, flags: u32, width: i32, height: i32, image: i32, color_env: i32, data: i32, extra: Option<i32>) -> i32 {
+        let mut rest = vec![
+            3, width, height, flags.assert_i32(), 255, 255, 255, 255, color_env, 0, image, data,
+        ];
+        rest.extend_from_slice(&name::<[i32; 3]>(b"")[..]);
+        if let Some(extra) = extra {
+            let offset = format::MapItemLayerV1TilemapExtraRace::offset(3, flags).unwrap();
+            rest.resize(offset + 5, -1);
+            rest[offset] = extra;
+        }
+        self.layer(format::MAP_ITEMTYPE_LAYER_V1_TILEMAP, &rest)
+    }
+    /// Adds a tile layer, `tiles` being the tile indices.
+    pub fn tile_layer(&mut self, flags: u32, width: i32, height: i32, image: i32, tiles: &[u8]) -> i32 {
+        assert_eq!(tiles.len(), (width * height).assert_usize());
+        let data = self.data(tiles.iter().flat_map(|&t| vec![t, 0, 0, 0]).collect());
+        self.tiles(flags, width, height, image, -1, data, None)
+    }
+    /// Adds a game layer of the given size filled with air.
+    pub fn game_layer(&mut self, width: i32, height: i32) -> i32 {
+        let tiles = vec![0; (width * height).assert_usize()];
+        self.tile_layer(format::TILELAYERFLAG_GAME, width, height, -1, &tiles)
+    }
+    /// Adds a DDNet game layer like tele or switch, `tiles` being the raw
+    /// data of the special tiles.
+    pub fn race_layer(&mut self, flags: u32, width: i32, height: i32, tiles: Vec<u8>) -> i32 {
+        let zeroes = self.data(vec![0; (width * height * 4).assert_usize()]);
+        let data = self.data(tiles);
+        self.tiles(flags, width, height, -1, -1, zeroes, Some(data))
+    }
+    /// Adds a quad layer, `quads` being the position and color envelopes of
+    /// each quad.
+    pub fn quad_layer(&mut self, image: i32, quads: &[(i32, i32)]) -> i32 {
+        let mut data = Vec::new();
+        for &(pos_env, color_env) in quads {
+            let mut quad = [0i32; 38];
+            quad[34] = pos_env;
+            quad[36] = color_env;
+            for v in &quad[..] {
+                data.extend_from_slice(&[*v as u8, (*v >> 8) as u8, (*v >> 16) as u8, (*v >> 24) as u8]);
+            }
+        }
+        assert_eq!(data.len(), quads.len() * mem::size_of::<format::Quad>());
+        let data = self.data(data);
+        let mut rest = vec![2, quads.len().assert_i32(), data, image];
+        rest.extend_from_slice(&name::<[i32; 3]>(b"")[..]);
+        self.layer(format::MAP_ITEMTYPE_LAYER_V1_QUADS, &rest)
+    }
+    /// Adds the info item with the given author and settings.
+    pub fn info(&mut self, author: Option<&[u8]>, settings: &[&[u8]]) {
+        let author = author.map(|a| self.string(a)).unwrap_or(-1);
+        let settings = if settings.is_empty() {
+            -1
+        } else {
+            let mut data = Vec::new();
+            for s in settings {
+                data.extend_from_slice(s);
+                data.push(0);
+            }
+            self.data(data)
+        };
+        self.item(format::MAP_ITEMTYPE_INFO, &[1, author, -1, -1, -1, settings]);
+    }
+    /// Adds an image, embedded if `data` is given.
+    pub fn image(&mut self, name: &[u8], width: i32, height: i32, data: Option<Vec<u8>>) -> i32 {
+        let name = self.string(name);
+        let (external, data) = match data {
+            Some(d) => (0, self.data(d)),
+            None => (1, -1),
+        };
+        self.item(format::MAP_ITEMTYPE_IMAGE, &[1, width, height, external, name, data])
+    }
+    /// Adds an envelope with the given item version.
+    pub fn envelope(&mut self, version: i32, start_points: i32, num_points: i32) -> i32 {
+        let mut data = vec![version, 4, start_points, num_points];
+        data.extend_from_slice(&name::<[i32; 8]>(b"")[..]);
+        if version >= 2 {
+            data.push(0);
+        }
+        self.item(format::MAP_ITEMTYPE_ENVELOPE, &data)
+    }
+    /// Adds the envelope points item with `num` points of version 1
+    /// envelopes, the time of each point being its index.
+    pub fn envpoints(&mut self, num: usize) {
+        let len = mem::size_of::<format::MapItemEnvpointV1>() / mem::size_of::<i32>();
+        let mut data = vec![0; num * len];
+        for i in 0..num {
+            data[i * len] = i.assert_i32();
+        }
+        self.item(format::MAP_ITEMTYPE_ENVPOINTS, &data);
+    }
+    /// Returns the map with the group items.
+    pub fn finish(&self) -> Buffer {
+        let mut buffer = self.buffer.clone();
+        for &(id, start, num) in &self.groups {
+            let data = [1, 0, 0, 100, 100, start, num];
+            buffer.add_item(format::MAP_ITEMTYPE_GROUP, id, &data).unwrap();
+        }
+        buffer
+    }
+    pub fn open(&self) -> Reader {
+        open(&self.finish())
+    }
+}
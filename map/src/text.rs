@@ -0,0 +1,822 @@
+//! Lossless text representation of maps, e.g. for version control.
+//!
+//! `export` converts a map into a `TextMap`, which can be serialized with any
+//! serde format. Items are written with the field names of `format`, data
+//! according to its use: strings, settings, tiles as run-length encoded rows,
+//! quads as rows of integers, and images and sounds as separate files.
+//! `import` converts a `TextMap` back into a datafile with the same items and
+//! data.
+//!
+//! Everything `format` doesn't know about is kept as raw integers or hex
+//! bytes.
+
+use common::num::Cast;
+use datafile::buffer::Buffer;
+use format::MapItemLayerV1TilemapExtraRace;
+use format;
+use image;
+use reader::Reader;
+use reader;
+use sanitize_file_name;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde::de;
+use serde::ser::SerializeMap;
+use serde::ser::SerializeSeq;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    Map(reader::Error),
+    Io(io::Error),
+    Image(image::ImageError),
+    /// The text representation is inconsistent.
+    Invalid(String),
+}
+
+impl From<reader::Error> for Error {
+    fn from(err: reader::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Error {
+        Error::Image(err)
+    }
+}
+
+/// Field layout of an item, see `generate_format.py`.
+struct Def {
+    type_id: i32,
+    name: &'static str,
+    /// Whether the first integer is a version.
+    has_version: bool,
+    /// Whether all versions are present regardless of the version number.
+    ignore_version: bool,
+    /// Fields added by each version, `name[n]` denoting a string of `n`
+    /// integers.
+    versions: &'static [&'static [&'static str]],
+}
+
+impl Def {
+    fn fields(&self, version: i32) -> Vec<&'static str> {
+        let num = if self.ignore_version {
+            self.versions.len()
+        } else {
+            cmp::min(version.try_usize().unwrap_or(0), self.versions.len())
+        };
+        let mut result: Vec<_> = self.versions[..num].iter()
+            .flat_map(|v| v.iter().cloned()).collect();
+        if self.type_id == format::MAP_ITEMTYPE_LAYER_V1_TILEMAP && version >= 2 {
+            result.extend(TILEMAP_EXTRA_RACE.iter().map(|&(n, _)| n));
+        }
+        result
+    }
+}
+
+include!("text_defs.rs");
+
+// Data fields of DDRace tilemaps with their flag and tile size in bytes.
+const TILEMAP_EXTRA_RACE: &'static [(&'static str, (u32, usize))] = &[
+    ("tele", (format::TILELAYERFLAG_TELEPORT, 2)),
+    ("speedup", (format::TILELAYERFLAG_SPEEDUP, 6)),
+    ("front", (format::TILELAYERFLAG_FRONT, 4)),
+    ("switch", (format::TILELAYERFLAG_SWITCH, 4)),
+    ("tune", (format::TILELAYERFLAG_TUNE, 2)),
+];
+
+const TILE_SIZE: usize = 4;
+const QUAD_LEN: usize = 38;
+const ENVPOINT_V1_LEN: usize = 6;
+const ENVPOINT_V2_LEN: usize = 22;
+const HEX_ROW_LEN: usize = 32;
+
+fn item_def(type_id: u16) -> Option<&'static Def> {
+    ITEMS.iter().find(|d| d.type_id == type_id.i32())
+}
+
+fn layer_def(type_: i32) -> Option<&'static Def> {
+    LAYERS.iter().find(|d| d.type_id == type_)
+}
+
+/// Splits `name[n]` into the name and the number of integers, 0 for plain
+/// integer fields.
+fn split_field(field: &str) -> (&str, usize) {
+    match field.find('[') {
+        Some(i) => (&field[..i], field[i+1..field.len()-1].parse().unwrap()),
+        None => (field, 0),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Field {
+    Int(i32),
+    String(String),
+    Ints(Vec<i32>),
+    Rows(Vec<String>),
+    Object(Fields),
+}
+
+/// Item fields, keeping their order.
+#[derive(Clone, Debug, Default)]
+struct Fields(Vec<(String, Field)>);
+
+impl Fields {
+    fn push(&mut self, name: &str, value: Field) {
+        self.0.push((name.to_owned(), value));
+    }
+    fn take(&mut self, name: &str) -> Option<Field> {
+        self.0.iter().position(|&(ref n, _)| n == name).map(|i| self.0.remove(i).1)
+    }
+    fn check_empty(&self) -> Result<(), String> {
+        match self.0.first() {
+            Some(&(ref n, _)) => Err(format!("unknown field {:?}", n)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Serialize for Fields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for &(ref name, ref value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+struct FieldsVisitor;
+
+impl<'de> de::Visitor<'de> for FieldsVisitor {
+    type Value = Fields;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of item fields")
+    }
+    fn visit_map<A: de::MapAccess<'de>>(self, mut access: A) -> Result<Fields, A::Error> {
+        let mut result = Fields::default();
+        while let Some((name, value)) = access.next_entry::<String, Field>()? {
+            result.0.push((name, value));
+        }
+        Ok(result)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+        deserializer.deserialize_map(FieldsVisitor)
+    }
+}
+
+fn ints_row(ints: &[i32]) -> String {
+    let strings: Vec<_> = ints.iter().map(|i| i.to_string()).collect();
+    strings.join(" ")
+}
+
+fn parse_ints_row(row: &str) -> Result<Vec<i32>, String> {
+    row.split_whitespace()
+        .map(|i| i.parse().map_err(|_| format!("invalid integer {:?}", i)))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(string: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid hex string {:?}", string);
+    if string.len() % 2 != 0 || !string.is_ascii() {
+        return Err(invalid());
+    }
+    (0..string.len() / 2)
+        .map(|i| u8::from_str_radix(&string[i*2..i*2+2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// Returns a string field if the integers are exactly the encoding of a
+/// string, the integers otherwise.
+fn name_field(ints: &[i32]) -> Field {
+    let mut bytes = vec![0; ints.len() * 4];
+    format::i32s_to_bytes(&mut bytes, ints);
+    let string = format::bytes_to_string(&bytes);
+    let mut encoded = vec![0; ints.len()];
+    format::string_to_i32s(&mut encoded, string);
+    match String::from_utf8(string.to_vec()) {
+        Ok(s) if encoded == ints => Field::String(s),
+        _ => Field::Ints(ints.to_vec()),
+    }
+}
+
+/// Adds the fields of `raw` described by `def` and returns the remaining
+/// integers.
+fn export_fields<'a>(fields: &mut Fields, raw: &'a [i32], def: &Def) -> &'a [i32] {
+    if !def.has_version || raw.is_empty() {
+        return raw;
+    }
+    let version = raw[0];
+    fields.push("version", Field::Int(version));
+    let mut raw = &raw[1..];
+    for field in def.fields(version) {
+        let (name, len) = split_field(field);
+        if raw.len() < cmp::max(len, 1) {
+            break;
+        }
+        if len == 0 {
+            fields.push(name, Field::Int(raw[0]));
+            raw = &raw[1..];
+        } else {
+            fields.push(name, name_field(&raw[..len]));
+            raw = &raw[len..];
+        }
+    }
+    raw
+}
+
+/// Inverse of `export_fields`, appending the integers to `raw`.
+fn import_fields(fields: &mut Fields, def: &Def, raw: &mut Vec<i32>) -> Result<(), String> {
+    if !def.has_version {
+        return Ok(());
+    }
+    let version = match fields.take("version") {
+        Some(Field::Int(v)) => v,
+        Some(_) => return Err("invalid version".to_owned()),
+        None => return Ok(()),
+    };
+    raw.push(version);
+    for field in def.fields(version) {
+        let (name, len) = split_field(field);
+        match fields.take(name) {
+            None => break,
+            Some(Field::Int(v)) if len == 0 => raw.push(v),
+            Some(Field::String(ref s)) if len != 0 => {
+                let start = raw.len();
+                raw.resize(start + len, 0);
+                format::string_to_i32s(&mut raw[start..], s.as_bytes());
+            },
+            Some(Field::Ints(ref v)) if len != 0 && v.len() == len => raw.extend(v),
+            Some(_) => return Err(format!("invalid value for field {:?}", name)),
+        }
+    }
+    Ok(())
+}
+
+fn export_extra(fields: &mut Fields, raw: &[i32], row_len: usize) {
+    if raw.is_empty() {
+        return;
+    }
+    let rows = if row_len == 0 {
+        vec![ints_row(raw)]
+    } else {
+        raw.chunks(row_len).map(ints_row).collect()
+    };
+    fields.push("extra", Field::Rows(rows));
+}
+
+fn import_extra(fields: &mut Fields, raw: &mut Vec<i32>) -> Result<(), String> {
+    match fields.take("extra") {
+        Some(Field::Rows(rows)) => for row in rows {
+            raw.extend(parse_ints_row(&row)?);
+        },
+        Some(_) => return Err("invalid extra field".to_owned()),
+        None => {},
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Item {
+    pub type_id: u16,
+    pub id: u16,
+    pub data: Vec<i32>,
+}
+
+impl Item {
+    fn to_fields(&self, envpoint_len: usize) -> Fields {
+        let mut fields = Fields::default();
+        let def = item_def(self.type_id);
+        fields.push("type", match def {
+            Some(d) => Field::String(d.name.to_owned()),
+            None => Field::Int(self.type_id.i32()),
+        });
+        fields.push("id", Field::Int(self.id.i32()));
+        let mut rest = &self.data[..];
+        if let Some(def) = def {
+            rest = export_fields(&mut fields, rest, def);
+            let layer = if def.type_id == format::MAP_ITEMTYPE_LAYER.i32() && self.data.len() >= 3 {
+                layer_def(self.data[1])
+            } else {
+                None
+            };
+            if let Some(layer) = layer.filter(|_| !rest.is_empty()) {
+                let mut nested = Fields::default();
+                let nested_rest = export_fields(&mut nested, rest, layer);
+                export_extra(&mut nested, nested_rest, 0);
+                fields.push(layer.name, Field::Object(nested));
+                rest = &[];
+            }
+        }
+        let row_len = if self.type_id == format::MAP_ITEMTYPE_ENVPOINTS { envpoint_len } else { 0 };
+        export_extra(&mut fields, rest, row_len);
+        fields
+    }
+    fn from_fields(mut fields: Fields) -> Result<Item, String> {
+        let type_id = match fields.take("type") {
+            Some(Field::String(ref n)) => match ITEMS.iter().find(|d| d.name == n) {
+                Some(d) => d.type_id.assert_u16(),
+                None => return Err(format!("unknown item type {:?}", n)),
+            },
+            Some(Field::Int(t)) => t.try_u16().ok_or_else(|| format!("invalid item type {}", t))?,
+            _ => return Err("missing or invalid item type".to_owned()),
+        };
+        let id = match fields.take("id") {
+            Some(Field::Int(i)) => i.try_u16().ok_or_else(|| format!("invalid item id {}", i))?,
+            _ => return Err("missing or invalid item id".to_owned()),
+        };
+        let mut data = Vec::new();
+        if let Some(def) = item_def(type_id) {
+            import_fields(&mut fields, def, &mut data)?;
+            let layer = if def.type_id == format::MAP_ITEMTYPE_LAYER.i32() && data.len() >= 3 {
+                layer_def(data[1])
+            } else {
+                None
+            };
+            if let Some(layer) = layer {
+                match fields.take(layer.name) {
+                    Some(Field::Object(mut nested)) => {
+                        import_fields(&mut nested, layer, &mut data)?;
+                        import_extra(&mut nested, &mut data)?;
+                        nested.check_empty()?;
+                    },
+                    Some(_) => return Err(format!("invalid {} field", layer.name)),
+                    None => {},
+                }
+            }
+        }
+        import_extra(&mut fields, &mut data)?;
+        fields.check_empty()?;
+        Ok(Item {
+            type_id: type_id,
+            id: id,
+            data: data,
+        })
+    }
+}
+
+/// Returns the number of integers per envelope point, which depends on the
+/// envelope versions.
+fn envpoint_len(items: &[Item]) -> usize {
+    let v2 = items.iter().any(|i| {
+        i.type_id == format::MAP_ITEMTYPE_ENVELOPE && i.data.first().map_or(false, |&v| v >= 3)
+    });
+    if v2 { ENVPOINT_V2_LEN } else { ENVPOINT_V1_LEN }
+}
+
+fn serialize_items<S: Serializer>(items: &Vec<Item>, serializer: S) -> Result<S::Ok, S::Error> {
+    let envpoint_len = envpoint_len(items);
+    let mut seq = serializer.serialize_seq(Some(items.len()))?;
+    for item in items {
+        seq.serialize_element(&item.to_fields(envpoint_len))?;
+    }
+    seq.end()
+}
+
+fn deserialize_items<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Item>, D::Error> {
+    let fields: Vec<Fields> = Deserialize::deserialize(deserializer)?;
+    fields.into_iter().enumerate()
+        .map(|(i, f)| Item::from_fields(f).map_err(|e| de::Error::custom(format!("item #{}: {}", i, e))))
+        .collect()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Data {
+    /// Null-terminated string.
+    String(String),
+    /// Null-terminated server settings.
+    Settings(Vec<String>),
+    /// Tiles of `size` bytes, one row of `width` tiles per string.
+    ///
+    /// Each row is a space-separated list of tiles in hex, `tile*n` denoting
+    /// `n` repetitions.
+    Tiles { width: u32, size: usize, rows: Vec<String> },
+    /// Quads, one row of integers per quad.
+    Quads(Vec<String>),
+    /// RGBA image stored as a PNG file.
+    Image { file: String, width: u32, height: u32 },
+    /// Data stored in a file as is.
+    File(String),
+    /// Anything else, as rows of hex bytes.
+    Hex(Vec<String>),
+}
+
+/// How a data item is used by the items.
+enum Use {
+    String,
+    Settings,
+    Tiles(u32, u32, usize),
+    Quads,
+    Image(u32, u32, Vec<u8>),
+    Sound(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TextMap {
+    #[serde(serialize_with = "serialize_items", deserialize_with = "deserialize_items")]
+    pub items: Vec<Item>,
+    pub data: Vec<Data>,
+}
+
+fn file_name(index: usize, name: &[u8], extension: &str) -> String {
+    format!("{}_{}.{}", index, sanitize_file_name(name), extension)
+}
+
+fn uses(items: &[Item]) -> HashMap<usize, Use> {
+    let mut result = HashMap::new();
+    {
+        let mut add = |index: i32, use_: Use| {
+            if let Some(i) = index.try_usize() {
+                result.entry(i).or_insert(use_);
+            }
+        };
+        for item in items {
+            let raw = &item.data;
+            match item.type_id {
+                format::MAP_ITEMTYPE_INFO => for (i, &d) in raw.iter().enumerate().skip(1).take(5) {
+                    add(d, if i == 5 { Use::Settings } else { Use::String });
+                },
+                format::MAP_ITEMTYPE_IMAGE if raw.len() >= 6 => {
+                    add(raw[4], Use::String);
+                    if raw[3] == 0 {
+                        let (w, h) = (raw[1].try_u32(), raw[2].try_u32());
+                        if let (Some(w), Some(h)) = (w, h) {
+                            add(raw[5], Use::Image(w, h, Vec::new()));
+                        }
+                    }
+                },
+                format::MAP_ITEMTYPE_DDRACE_SOUND if raw.len() >= 4 => {
+                    add(raw[2], Use::String);
+                    if raw[1] == 0 {
+                        add(raw[3], Use::Sound(Vec::new()));
+                    }
+                },
+                format::MAP_ITEMTYPE_LAYER if raw.len() >= 3 => {
+                    let rest = &raw[3..];
+                    match raw[1] {
+                        format::MAP_ITEMTYPE_LAYER_V1_TILEMAP if rest.len() >= 12 && rest[0] >= 2 => {
+                            let (w, h) = match (rest[1].try_u32(), rest[2].try_u32()) {
+                                (Some(w), Some(h)) => (w, h),
+                                _ => continue,
+                            };
+                            add(rest[11], Use::Tiles(w, h, TILE_SIZE));
+                            for &(_, (flag, size)) in TILEMAP_EXTRA_RACE {
+                                if rest[3] as u32 & flag == 0 {
+                                    continue;
+                                }
+                                let extra = MapItemLayerV1TilemapExtraRace::from_slice(rest, rest[0], flag);
+                                if let Some(e) = extra {
+                                    add(e.data, Use::Tiles(w, h, size));
+                                }
+                            }
+                        },
+                        format::MAP_ITEMTYPE_LAYER_V1_QUADS if rest.len() >= 3 => {
+                            add(rest[2], Use::Quads);
+                        },
+                        _ => {},
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+    result
+}
+
+fn hex_rows(data: &[u8]) -> Data {
+    Data::Hex(data.chunks(HEX_ROW_LEN).map(hex).collect())
+}
+
+fn string(data: &[u8]) -> Option<String> {
+    match data.split_last() {
+        Some((&0, s)) if !s.contains(&0) => String::from_utf8(s.to_vec()).ok(),
+        _ => None,
+    }
+}
+
+fn tile_rows(data: &[u8], width: u32, size: usize) -> Vec<String> {
+    data.chunks(width.usize() * size).map(|row| {
+        let mut tokens = Vec::new();
+        let mut tiles = row.chunks(size).peekable();
+        while let Some(tile) = tiles.next() {
+            let mut count = 1;
+            while tiles.peek() == Some(&tile) {
+                tiles.next();
+                count += 1;
+            }
+            if count == 1 {
+                tokens.push(hex(tile));
+            } else {
+                tokens.push(format!("{}*{}", hex(tile), count));
+            }
+        }
+        tokens.join(" ")
+    }).collect()
+}
+
+fn parse_tile_rows(rows: &[String], width: u32, size: usize) -> Result<Vec<u8>, String> {
+    let mut result = Vec::new();
+    for row in rows {
+        let start = result.len();
+        for token in row.split_whitespace() {
+            let (tile, count) = match token.find('*') {
+                Some(i) => (&token[..i], token[i+1..].parse()
+                    .map_err(|_| format!("invalid tile count {:?}", token))?),
+                None => (token, 1),
+            };
+            let tile = parse_hex(tile)?;
+            if tile.len() != size {
+                return Err(format!("invalid tile size {:?}", token));
+            }
+            for _ in 0..count {
+                result.extend_from_slice(&tile);
+            }
+        }
+        if result.len() - start != width.usize() * size {
+            return Err(format!("invalid tile row length {:?}", row));
+        }
+    }
+    Ok(result)
+}
+
+fn export_data(data: Vec<u8>, index: usize, use_: Option<&Use>, dir: &Path)
+    -> Result<Data, Error>
+{
+    Ok(match use_ {
+        Some(&Use::String) => match string(&data) {
+            Some(s) => Data::String(s),
+            None => hex_rows(&data),
+        },
+        Some(&Use::Settings) => {
+            let settings = data.split_last()
+                .filter(|&(&last, _)| last == 0)
+                .and_then(|(_, s)| s.split(|&b| b == 0)
+                    .map(|s| String::from_utf8(s.to_vec()).ok())
+                    .collect());
+            match settings {
+                Some(s) => Data::Settings(s),
+                None => hex_rows(&data),
+            }
+        },
+        Some(&Use::Tiles(width, height, size))
+            if width != 0 && data.len() == width.usize() * height.usize() * size =>
+        {
+            Data::Tiles { width: width, size: size, rows: tile_rows(&data, width, size) }
+        },
+        Some(&Use::Quads) if data.len() % (QUAD_LEN * 4) == 0 => {
+            let mut ints = vec![0; data.len() / 4];
+            for (i, b) in ints.iter_mut().zip(data.chunks(4)) {
+                *i = b[0] as i32 | (b[1] as i32) << 8 | (b[2] as i32) << 16 | (b[3] as i32) << 24;
+            }
+            Data::Quads(ints.chunks(QUAD_LEN).map(ints_row).collect())
+        },
+        Some(&Use::Image(width, height, ref name))
+            if width != 0 && height != 0 && data.len() == width.usize() * height.usize() * 4 =>
+        {
+            let file = file_name(index, name, "png");
+            image::save_buffer(dir.join(&file), &data, width, height, image::RGBA(8))?;
+            Data::Image { file: file, width: width, height: height }
+        },
+        Some(&Use::Sound(ref name)) => {
+            let file = file_name(index, name, "opus");
+            File::create(dir.join(&file))?.write_all(&data)?;
+            Data::File(file)
+        },
+        _ => hex_rows(&data),
+    })
+}
+
+fn import_data(data: &Data, dir: &Path) -> Result<Vec<u8>, Error> {
+    Ok(match *data {
+        Data::String(ref s) => {
+            let mut result = s.as_bytes().to_vec();
+            result.push(0);
+            result
+        },
+        Data::Settings(ref settings) => {
+            let mut result = Vec::new();
+            for s in settings {
+                result.extend_from_slice(s.as_bytes());
+                result.push(0);
+            }
+            result
+        },
+        Data::Tiles { width, size, ref rows } => {
+            parse_tile_rows(rows, width, size).map_err(Error::Invalid)?
+        },
+        Data::Quads(ref rows) => {
+            let mut result = Vec::new();
+            for row in rows {
+                let ints = parse_ints_row(row).map_err(Error::Invalid)?;
+                if ints.len() != QUAD_LEN {
+                    return Err(Error::Invalid(format!("invalid quad {:?}", row)));
+                }
+                for i in ints {
+                    result.extend_from_slice(&[i as u8, (i >> 8) as u8, (i >> 16) as u8, (i >> 24) as u8]);
+                }
+            }
+            result
+        },
+        Data::Image { ref file, width, height } => {
+            let rgba = image::open(dir.join(file))?.to_rgba();
+            if rgba.dimensions() != (width, height) {
+                return Err(Error::Invalid(format!("image {:?} has the wrong size", file)));
+            }
+            rgba.into_raw()
+        },
+        Data::File(ref file) => {
+            let mut result = Vec::new();
+            File::open(dir.join(file))?.read_to_end(&mut result)?;
+            result
+        },
+        Data::Hex(ref rows) => {
+            let mut result = Vec::new();
+            for row in rows {
+                result.extend(parse_hex(row).map_err(Error::Invalid)?);
+            }
+            result
+        },
+    })
+}
+
+/// Converts the map into its text representation, writing images and sounds
+/// into `dir`.
+pub fn export(map: &mut Reader, dir: &Path) -> Result<TextMap, Error> {
+    let items: Vec<_> = map.reader.items().map(|i| Item {
+        type_id: i.type_id,
+        id: i.id,
+        data: i.data.to_vec(),
+    }).collect();
+    let mut uses = uses(&items);
+    // Name the files after the images and sounds.
+    for item in &items {
+        let (name, data) = match item.type_id {
+            format::MAP_ITEMTYPE_IMAGE if item.data.len() >= 6 => (item.data[4], item.data[5]),
+            format::MAP_ITEMTYPE_DDRACE_SOUND if item.data.len() >= 4 => (item.data[2], item.data[3]),
+            _ => continue,
+        };
+        let name = match name.try_usize().filter(|&n| n < map.reader.num_data()) {
+            Some(n) => map.reader.read_data(n).map_err(reader::Error::from)?,
+            None => continue,
+        };
+        let name = format::bytes_to_string(&name).to_vec();
+        match data.try_usize().and_then(|d| uses.get_mut(&d)) {
+            Some(&mut Use::Image(_, _, ref mut n)) | Some(&mut Use::Sound(ref mut n))
+                if n.is_empty() => *n = name,
+            _ => {},
+        }
+    }
+    let mut data = Vec::with_capacity(map.reader.num_data());
+    for i in 0..map.reader.num_data() {
+        let d = map.reader.read_data(i).map_err(reader::Error::from)?;
+        data.push(export_data(d, i, uses.get(&i), dir)?);
+    }
+    Ok(TextMap {
+        items: items,
+        data: data,
+    })
+}
+
+/// Converts the text representation back into a datafile, reading images and
+/// sounds from `dir`.
+pub fn import(map: &TextMap, dir: &Path) -> Result<Buffer, Error> {
+    let mut buffer = Buffer::new();
+    for d in &map.data {
+        buffer.add_data(import_data(d, dir)?);
+    }
+    for item in &map.items {
+        buffer.add_item(item.type_id, item.id, &item.data).map_err(|()| {
+            Error::Invalid(format!("duplicate item type={} id={}", item.type_id, item.id))
+        })?;
+    }
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use common::num::Cast;
+    use format;
+    use serde_json;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use super::Data;
+    use super::ENVPOINT_V2_LEN;
+    use super::TextMap;
+    use super::export;
+    use super::import;
+    use testmap::TestMap;
+    use testmap::name;
+
+    /// Returns a map with DDNet game layers, a version 3 envelope and a
+    /// sound.
+    fn ddnet_map() -> TestMap {
+        let (w, h) = (3, 2);
+        let mut map = TestMap::new();
+        map.info(Some(b"author"), &[b"sv_deepfly 0", b"tune gravity 0.4"]);
+        map.image(b"grass", 2, 1, Some((0..8).collect()));
+        map.envelope(3, 0, 2);
+        let envpoints: Vec<_> = (0..2 * ENVPOINT_V2_LEN.assert_i32()).collect();
+        map.item(format::MAP_ITEMTYPE_ENVPOINTS, &envpoints);
+        let sound_name = map.string(b"wind");
+        let sound = map.data(b"OggS\0\0sound".to_vec());
+        map.item(format::MAP_ITEMTYPE_DDRACE_SOUND, &[1, 0, sound_name, sound, 11]);
+
+        map.group();
+        map.game_layer(w, h);
+        let n = (w * h).assert_usize();
+        map.race_layer(format::TILELAYERFLAG_TELEPORT, w, h, (0..2 * n as u8).collect());
+        map.race_layer(format::TILELAYERFLAG_SPEEDUP, w, h, (0..6 * n as u8).collect());
+        map.race_layer(format::TILELAYERFLAG_FRONT, w, h, (0..4 * n as u8).collect());
+        map.race_layer(format::TILELAYERFLAG_SWITCH, w, h, (0..4 * n as u8).collect());
+        map.race_layer(format::TILELAYERFLAG_TUNE, w, h, (0..2 * n as u8).collect());
+
+        map.group();
+        map.tile_layer(0, w, h, 0, &[1, 2, 3, 4, 5, 6]);
+        map.quad_layer(0, &[(0, 0)]);
+        let sources = map.data((0..40).collect());
+        let mut sounds = vec![2, 1, sources, 0];
+        sounds.extend_from_slice(&name::<[i32; 3]>(b"ambience"));
+        map.layer(format::MAP_ITEMTYPE_LAYER_V1_DDRACE_SOUNDS, &sounds);
+        map
+    }
+
+    #[test]
+    fn roundtrip_ddnet() {
+        let original = ddnet_map().finish();
+        let dir = env::temp_dir().join(format!("map-text-unit-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = export(&mut ::testmap::open(&original), &dir).unwrap();
+
+        let mut tiles: Vec<_> = text.data.iter().filter_map(|d| match *d {
+            Data::Tiles { width, size, ref rows } => Some((width, size, rows.len())),
+            _ => None,
+        }).collect();
+        tiles.sort();
+        // Tele and tune; game, tiles, front, switch and the tile data of the
+        // five DDNet layers; speedup.
+        let mut expected = vec![(3, 2, 2); 2];
+        expected.extend(vec![(3, 4, 2); 9]);
+        expected.push((3, 6, 2));
+        assert_eq!(tiles, expected);
+        let count = |f: fn(&Data) -> bool| text.data.iter().filter(|d| f(d)).count();
+        assert_eq!(count(|d| match *d { Data::Image { .. } => true, _ => false }), 1);
+        assert_eq!(count(|d| match *d { Data::File(_) => true, _ => false }), 1);
+        assert_eq!(count(|d| match *d { Data::Quads(_) => true, _ => false }), 1);
+        assert_eq!(count(|d| match *d { Data::Settings(_) => true, _ => false }), 1);
+
+        let json = serde_json::to_value(&text).unwrap();
+        let envpoints = json["items"].as_array().unwrap().iter()
+            .find(|i| i["type"] == "envpoints")
+            .unwrap();
+        let rows = envpoints["extra"].as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].as_str().unwrap().split(' ').count(), ENVPOINT_V2_LEN);
+        let layers: Vec<_> = json["items"].as_array().unwrap().iter()
+            .filter(|i| i["type"] == "layer")
+            .map(|i| {
+                let common = ["type", "id", "version", "type_", "flags"];
+                i.as_object().unwrap().keys().find(|k| !common.contains(&&k[..])).unwrap().clone()
+            })
+            .collect();
+        assert_eq!(layers, [
+            "tilemap", "tilemap", "tilemap", "tilemap", "tilemap", "tilemap",
+            "tilemap", "quads", "ddrace_sounds",
+        ]);
+
+        let text: TextMap = serde_json::from_value(json).unwrap();
+        let result = import(&text, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let expected: Vec<_> = original.items().collect();
+        let items: Vec<_> = result.items().collect();
+        assert_eq!(items, expected);
+        let expected: Vec<_> = original.data_iter().collect();
+        let data: Vec<_> = result.data_iter().collect();
+        assert!(data == expected);
+    }
+}
@@ -0,0 +1,52 @@
+// Generated by generate_format.py --text-defs, included by text.rs.
+
+const ITEMS: &'static [Def] = &[
+    Def { type_id: 0, name: "version", has_version: true, ignore_version: false, versions: &[
+        &[],
+    ] },
+    Def { type_id: 1, name: "info", has_version: true, ignore_version: false, versions: &[
+        &["author", "map_version", "credits", "license"],
+        &["settings"],
+    ] },
+    Def { type_id: 2, name: "image", has_version: true, ignore_version: false, versions: &[
+        &["width", "height", "external", "name", "data"],
+        &["format"],
+    ] },
+    Def { type_id: 3, name: "envelope", has_version: true, ignore_version: false, versions: &[
+        &["channels", "start_points", "num_points", "name[8]"],
+        &["synchronized"],
+    ] },
+    Def { type_id: 4, name: "group", has_version: true, ignore_version: false, versions: &[
+        &["offset_x", "offset_y", "parallax_x", "parallax_y", "start_layer", "num_layers"],
+        &["use_clipping", "clip_x", "clip_y", "clip_w", "clip_h"],
+        &["name[3]"],
+    ] },
+    Def { type_id: 5, name: "layer", has_version: true, ignore_version: true, versions: &[
+        &["type_", "flags"],
+    ] },
+    Def { type_id: 6, name: "envpoints", has_version: false, ignore_version: false, versions: &[
+    ] },
+    Def { type_id: 7, name: "ddrace_sound", has_version: true, ignore_version: false, versions: &[
+        &["external", "name", "data", "data_size"],
+    ] },
+];
+
+const LAYERS: &'static [Def] = &[
+    Def { type_id: 2, name: "tilemap", has_version: true, ignore_version: false, versions: &[
+        &[],
+        &["width", "height", "flags", "color_red", "color_green", "color_blue", "color_alpha", "color_env", "color_env_offset", "image", "data"],
+        &["name[3]"],
+    ] },
+    Def { type_id: 3, name: "quads", has_version: true, ignore_version: false, versions: &[
+        &["num_quads", "data", "image"],
+        &["name[3]"],
+    ] },
+    Def { type_id: 9, name: "ddrace_sounds_legacy", has_version: true, ignore_version: false, versions: &[
+        &["num_sources", "data", "sound", "name[3]"],
+    ] },
+    Def { type_id: 10, name: "ddrace_sounds", has_version: true, ignore_version: false, versions: &[
+        &["num_sources", "data", "sound", "name[3]"],
+        &[],
+    ] },
+];
+
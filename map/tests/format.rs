@@ -0,0 +1,29 @@
+use std::fs;
+use std::io;
+use std::process::Command;
+
+/// Checks that the output of `src/generate_format.py` with the given
+/// arguments matches the checked-in `file`.
+fn check_generated(args: &[&str], file: &str) {
+    let output = match Command::new("python3").arg("src/generate_format.py").args(args).output() {
+        Ok(o) => o,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("python3 not found, skipping");
+            return;
+        },
+        Err(e) => panic!("{}", e),
+    };
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let expected = fs::read(file).unwrap();
+    assert!(output.stdout == expected, "{} differs from the output of src/generate_format.py", file);
+}
+
+#[test]
+fn format() {
+    check_generated(&[], "src/format.rs");
+}
+
+#[test]
+fn text_defs() {
+    check_generated(&["--text-defs"], "src/text_defs.rs");
+}
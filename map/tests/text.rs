@@ -0,0 +1,86 @@
+extern crate datafile;
+extern crate map;
+extern crate serde_json;
+
+use datafile::buffer::Buffer;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+static NEXT_PATH: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a path in the temporary directory that differs on every call, as
+/// the tests run in parallel.
+fn temp_path(name: &str) -> PathBuf {
+    let n = NEXT_PATH.fetch_add(1, Ordering::SeqCst);
+    env::temp_dir().join(format!("map-text-{}-{}-{}", process::id(), n, name))
+}
+
+fn write(buffer: &Buffer, path: &Path) {
+    datafile::writer::write(&mut File::create(path).unwrap(), buffer, 9).unwrap();
+}
+
+fn assert_roundtrip(path: &Path) {
+    let dir = temp_path("files");
+    fs::create_dir_all(&dir).unwrap();
+    let mut map = map::Reader::open(path).unwrap();
+    let text = map::text::export(&mut map, &dir).unwrap();
+    let json = serde_json::to_string_pretty(&text).unwrap();
+    let text: map::text::TextMap = serde_json::from_str(&json).unwrap();
+    let buffer = map::text::import(&text, &dir).unwrap();
+    let out_path = temp_path("out.map");
+    write(&buffer, &out_path);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let mut original = datafile::Reader::open(path).unwrap();
+    let mut result = datafile::Reader::open(&out_path).unwrap();
+    fs::remove_file(&out_path).unwrap();
+    let expected: Vec<_> = original.items().collect();
+    let items: Vec<_> = result.items().collect();
+    assert_eq!(items, expected, "{}", path.display());
+    assert_eq!(result.num_data(), original.num_data(), "{}", path.display());
+    for i in 0..original.num_data() {
+        assert!(result.read_data(i).unwrap() == original.read_data(i).unwrap(),
+            "{}: data #{}", path.display(), i);
+    }
+}
+
+#[test]
+fn roundtrip_maps() {
+    let dir = env::var_os("MAPS_DIR").map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../server"));
+    let mut num = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension() == Some(OsStr::new("map")) {
+            assert_roundtrip(&path);
+            num += 1;
+        }
+    }
+    assert!(num != 0, "no maps in {}", dir.display());
+}
+
+#[test]
+fn roundtrip_embedded_image() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../server/dm1.map");
+    let mut map = map::Reader::open(&path).unwrap();
+    let image = map::images::list(&mut map).unwrap()[0].index;
+    let mut replacements = HashMap::new();
+    replacements.insert(image, map::images::Replacement {
+        width: 2,
+        height: 2,
+        data: (0..16).collect(),
+    });
+    let buffer = map::images::replace(&mut map, &replacements).unwrap();
+    let embedded = temp_path("embedded.map");
+    write(&buffer, &embedded);
+    assert_roundtrip(&embedded);
+    fs::remove_file(&embedded).unwrap();
+}
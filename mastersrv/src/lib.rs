@@ -0,0 +1,342 @@
+//! Master server keeping track of the game servers and telling clients about
+//! them.
+//!
+//! Game servers register by sending heartbeats. The master checks whether they
+//! are reachable by sending `fw??` to the announced port, which the server
+//! answers with `fw!!`. It then confirms the registration with `fwok` or, if
+//! the server doesn't answer, reports the failure with `fwer`. Servers that
+//! stop sending heartbeats expire.
+
+#[macro_use] extern crate log;
+extern crate net;
+extern crate serverbrowse;
+extern crate socket;
+
+use net::Timeout;
+use net::Timestamp;
+use net::net::Callback;
+use serverbrowse::protocol::MasterRequest;
+use serverbrowse::protocol;
+use socket::Addr;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::net::IpAddr;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Time after the last successful check after which a server is removed.
+    pub expire_time: Duration,
+    /// Time between two `fw??` packets to a server being checked.
+    pub check_interval: Duration,
+    /// Number of `fw??` packets sent before a check fails.
+    pub check_tries: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            expire_time: Duration::from_secs(90),
+            check_interval: Duration::from_secs(1),
+            check_tries: 10,
+        }
+    }
+}
+
+struct Check {
+    /// Address the heartbeat came from.
+    heartbeat_addr: Addr,
+    tries: u32,
+    next_try: Timestamp,
+}
+
+pub struct Master {
+    config: Config,
+    /// Registered servers and the time they expire.
+    servers: BTreeMap<Addr, Timestamp>,
+    /// Servers being checked, indexed by their announced address.
+    checks: BTreeMap<Addr, Check>,
+}
+
+fn browse_addr(addr: Addr) -> protocol::Addr {
+    protocol::Addr {
+        ip_address: match addr.ip {
+            IpAddr::V4(ip) => protocol::IpAddr::V4(ip),
+            IpAddr::V6(ip) => protocol::IpAddr::V6(ip),
+        },
+        port: addr.port,
+    }
+}
+
+impl Master {
+    pub fn new(config: Config) -> Master {
+        Master {
+            config: config,
+            servers: BTreeMap::new(),
+            checks: BTreeMap::new(),
+        }
+    }
+    /// Returns the addresses of the registered servers.
+    pub fn servers<'a>(&'a self) -> btree_map::Keys<'a, Addr, Timestamp> {
+        self.servers.keys()
+    }
+    pub fn needs_tick(&self) -> Timeout {
+        let expiries = self.servers.values();
+        let tries = self.checks.values().map(|c| &c.next_try);
+        expiries.chain(tries).min()
+            .map(|&t| Timeout::active(t))
+            .unwrap_or_else(Timeout::inactive)
+    }
+    /// Removes expired servers and continues the forward checks.
+    pub fn tick<CB: Callback<Addr>>(&mut self, cb: &mut CB) -> Result<(), CB::Error> {
+        let now = cb.time();
+        let expired: Vec<_> = self.servers.iter()
+            .filter(|&(_, &expire)| expire <= now)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in expired {
+            info!("{} expired", addr);
+            self.servers.remove(&addr);
+        }
+        let due: Vec<_> = self.checks.iter()
+            .filter(|&(_, check)| check.next_try <= now)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in due {
+            if self.checks[&addr].tries < self.config.check_tries {
+                self.try_check(cb, addr)?;
+                continue;
+            }
+            let check = self.checks.remove(&addr).unwrap();
+            info!("{} failed the forward check", addr);
+            cb.send(check.heartbeat_addr, &protocol::forward_error())?;
+        }
+        Ok(())
+    }
+    fn try_check<CB: Callback<Addr>>(&mut self, cb: &mut CB, addr: Addr) -> Result<(), CB::Error> {
+        let now = cb.time();
+        let check = self.checks.get_mut(&addr).unwrap();
+        check.tries += 1;
+        check.next_try = now + self.config.check_interval;
+        cb.send(addr, &protocol::forward_check())
+    }
+    pub fn on_packet<CB>(&mut self, cb: &mut CB, addr: Addr, data: &[u8])
+        -> Result<(), CB::Error>
+        where CB: Callback<Addr>,
+    {
+        let request = match protocol::parse_master_request(data) {
+            Some(r) => r,
+            None => {
+                debug!("{}: unknown packet", addr);
+                return Ok(());
+            },
+        };
+        match request {
+            MasterRequest::Heartbeat { alt_port } => {
+                let announced = Addr { ip: addr.ip, port: alt_port };
+                if let btree_map::Entry::Vacant(e) = self.checks.entry(announced) {
+                    e.insert(Check {
+                        heartbeat_addr: addr,
+                        tries: 0,
+                        next_try: cb.time(),
+                    });
+                } else {
+                    return Ok(());
+                }
+                self.try_check(cb, announced)?;
+            },
+            MasterRequest::ForwardResponse => {
+                // The response can come from either the announced address or
+                // the one the heartbeat came from.
+                let announced = if self.checks.contains_key(&addr) {
+                    Some(addr)
+                } else {
+                    self.checks.iter()
+                        .find(|&(_, c)| c.heartbeat_addr == addr)
+                        .map(|(&a, _)| a)
+                };
+                if let Some(announced) = announced {
+                    let check = self.checks.remove(&announced).unwrap();
+                    let expire = cb.time() + self.config.expire_time;
+                    if self.servers.insert(announced, expire).is_none() {
+                        info!("{} registered", announced);
+                    }
+                    cb.send(check.heartbeat_addr, &protocol::forward_ok())?;
+                }
+            },
+            MasterRequest::List5 => {
+                let servers: Vec<_> = self.servers.keys()
+                    .filter_map(|&a| browse_addr(a).pack5())
+                    .collect();
+                for packet in protocol::list_5(&servers) {
+                    cb.send(addr, &packet)?;
+                }
+            },
+            MasterRequest::List6 => {
+                let servers: Vec<_> = self.servers.keys()
+                    .map(|&a| browse_addr(a).pack6())
+                    .collect();
+                for packet in protocol::list_6(&servers) {
+                    cb.send(addr, &packet)?;
+                }
+            },
+            MasterRequest::Count => {
+                let count = cmp::min(self.servers.len(), u16::max_value() as usize) as u16;
+                cb.send(addr, &protocol::count(count))?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use net::Timestamp;
+    use net::net::Callback;
+    use serverbrowse::protocol::Response;
+    use serverbrowse::protocol;
+    use socket::Addr;
+    use std::time::Duration;
+    use super::Config;
+    use super::Master;
+    use super::browse_addr;
+
+    struct Mock {
+        time: Timestamp,
+        sent: Vec<(Addr, Vec<u8>)>,
+    }
+
+    impl Mock {
+        fn new() -> Mock {
+            Mock {
+                time: Timestamp::from_secs_since_epoch(0),
+                sent: Vec::new(),
+            }
+        }
+        fn advance(&mut self, secs: u64) {
+            self.time = self.time + Duration::from_secs(secs);
+        }
+    }
+
+    impl Callback<Addr> for Mock {
+        type Error = ();
+        fn send(&mut self, addr: Addr, data: &[u8]) -> Result<(), ()> {
+            self.sent.push((addr, data.to_vec()));
+            Ok(())
+        }
+        fn time(&mut self) -> Timestamp {
+            self.time
+        }
+    }
+
+    fn addr(s: &str) -> Addr {
+        s.parse().unwrap()
+    }
+
+    fn register(master: &mut Master, mock: &mut Mock, heartbeat_addr: Addr, port: u16) {
+        let announced = Addr { ip: heartbeat_addr.ip, port: port };
+        master.on_packet(mock, heartbeat_addr, &protocol::heartbeat(port)).unwrap();
+        assert_eq!(mock.sent, [(announced, protocol::forward_check().to_vec())]);
+        mock.sent.clear();
+        master.on_packet(mock, announced, &protocol::forward_response()).unwrap();
+        assert_eq!(mock.sent, [(heartbeat_addr, protocol::forward_ok().to_vec())]);
+        mock.sent.clear();
+    }
+
+    fn list(master: &mut Master, mock: &mut Mock) -> Vec<Vec<Addr>> {
+        let client = addr("10.0.0.1:1234");
+        master.on_packet(mock, client, &protocol::request_list_6()).unwrap();
+        let result = mock.sent.drain(..).map(|(a, packet)| {
+            assert_eq!(a, client);
+            match protocol::parse_response(&packet) {
+                Some(Response::List6(l)) => l.0.iter().map(|s| {
+                    let s = s.unpack();
+                    let ip = match s.ip_address {
+                        protocol::IpAddr::V4(ip) => ip.into(),
+                        protocol::IpAddr::V6(ip) => ip.into(),
+                    };
+                    Addr { ip: ip, port: s.port }
+                }).collect(),
+                _ => panic!("not a list response"),
+            }
+        }).collect();
+        result
+    }
+
+    fn count(master: &mut Master, mock: &mut Mock) -> u16 {
+        master.on_packet(mock, addr("10.0.0.1:1234"), &protocol::request_count()).unwrap();
+        let (_, packet) = mock.sent.pop().unwrap();
+        match protocol::parse_response(&packet) {
+            Some(Response::Count(c)) => c.0,
+            _ => panic!("not a count response"),
+        }
+    }
+
+    #[test]
+    fn register_and_list() {
+        let mut mock = Mock::new();
+        let mut master = Master::new(Config::default());
+        register(&mut master, &mut mock, addr("1.2.3.4:50000"), 8303);
+        register(&mut master, &mut mock, addr("[2001:db8::1]:8303"), 8303);
+        assert_eq!(list(&mut master, &mut mock), [[addr("1.2.3.4:8303"), addr("[2001:db8::1]:8303")]]);
+        assert_eq!(count(&mut master, &mut mock), 2);
+
+        master.on_packet(&mut mock, addr("10.0.0.1:1234"), &protocol::request_list_5()).unwrap();
+        let (_, packet) = mock.sent.pop().unwrap();
+        match protocol::parse_response(&packet) {
+            Some(Response::List5(l)) => {
+                let servers: Vec<_> = l.0.iter().map(|s| s.unpack()).collect();
+                assert_eq!(servers, [browse_addr(addr("1.2.3.4:8303"))]);
+            },
+            _ => panic!("not a list response"),
+        }
+    }
+
+    #[test]
+    fn failed_check() {
+        let mut mock = Mock::new();
+        let config = Config::default();
+        let mut master = Master::new(config);
+        let heartbeat_addr = addr("1.2.3.4:8303");
+        master.on_packet(&mut mock, heartbeat_addr, &protocol::heartbeat(8303)).unwrap();
+        for _ in 0..config.check_tries {
+            assert_eq!(mock.sent, [(heartbeat_addr, protocol::forward_check().to_vec())]);
+            mock.sent.clear();
+            mock.advance(1);
+            master.tick(&mut mock).unwrap();
+        }
+        assert_eq!(mock.sent, [(heartbeat_addr, protocol::forward_error().to_vec())]);
+        assert!(!master.needs_tick().is_active());
+        assert_eq!(count(&mut master, &mut mock), 0);
+    }
+
+    #[test]
+    fn expire() {
+        let mut mock = Mock::new();
+        let mut master = Master::new(Config::default());
+        register(&mut master, &mut mock, addr("1.2.3.4:8303"), 8303);
+        mock.advance(60);
+        master.tick(&mut mock).unwrap();
+        register(&mut master, &mut mock, addr("1.2.3.5:8303"), 8303);
+        mock.advance(60);
+        master.tick(&mut mock).unwrap();
+        assert_eq!(list(&mut master, &mut mock), [[addr("1.2.3.5:8303")]]);
+        mock.advance(60);
+        master.tick(&mut mock).unwrap();
+        assert_eq!(count(&mut master, &mut mock), 0);
+        assert!(mock.sent.is_empty());
+    }
+
+    #[test]
+    fn list_split() {
+        let mut mock = Mock::new();
+        let mut master = Master::new(Config::default());
+        for i in 0..100 {
+            register(&mut master, &mut mock, addr(&format!("1.2.3.{}:8303", i)), 8303);
+        }
+        let lens: Vec<_> = list(&mut master, &mut mock).iter().map(|l| l.len()).collect();
+        assert_eq!(lens, [protocol::MAX_SERVERS_PER_PACKET, 100 - protocol::MAX_SERVERS_PER_PACKET]);
+        assert_eq!(count(&mut master, &mut mock), 100);
+    }
+}
@@ -0,0 +1,73 @@
+#![cfg(not(test))]
+
+extern crate arrayvec;
+#[macro_use] extern crate clap;
+#[macro_use] extern crate log;
+extern crate logger;
+extern crate mastersrv;
+extern crate net;
+extern crate serverbrowse;
+extern crate socket;
+
+use arrayvec::ArrayVec;
+use clap::App;
+use clap::Arg;
+use mastersrv::Config;
+use mastersrv::Master;
+use net::net::Callback;
+use serverbrowse::protocol::MASTERSERVER_PORT;
+use socket::Socket;
+use std::time::Duration;
+
+fn main() {
+    logger::init();
+
+    let default_port = MASTERSERVER_PORT.to_string();
+    let matches = App::new("Master server")
+        .about("Keeps a list of Teeworlds servers and answers server list requests.")
+        .arg(Arg::with_name("port")
+            .help("Sets the UDP port to listen on")
+            .long("port")
+            .takes_value(true)
+            .value_name("PORT")
+            .default_value(&default_port)
+        )
+        .arg(Arg::with_name("expire")
+            .help("Sets the number of seconds after which servers without heartbeat are removed")
+            .long("expire")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .default_value("90")
+        )
+        .get_matches();
+
+    let port = value_t!(matches, "port", u16).unwrap_or_else(|e| e.exit());
+    let expire = value_t!(matches, "expire", u64).unwrap_or_else(|e| e.exit());
+
+    let mut socket = Socket::bound(port).unwrap_or_else(|e| panic!("failed to bind port {}: {}", port, e));
+    let mut master = Master::new(Config {
+        expire_time: Duration::from_secs(expire),
+        ..Config::default()
+    });
+    let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
+    info!("listening on port {}", port);
+    loop {
+        if let Err(e) = master.tick(&mut socket) {
+            warn!("send error: {}", e);
+        }
+        let timeout = master.needs_tick().time_from(socket.time());
+        socket.sleep(timeout).unwrap();
+        while let Some(res) = { buf.clear(); socket.receive(&mut buf) } {
+            let (addr, data) = match res {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("receive error: {}", e);
+                    continue;
+                },
+            };
+            if let Err(e) = master.on_packet(&mut socket, addr, data) {
+                warn!("{}: send error: {}", addr, e);
+            }
+        }
+    }
+}
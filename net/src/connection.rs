@@ -148,9 +148,9 @@ impl<'a> ReceivePacket<'a> {
             type_: ReceivePacketType::Ready(iter::once(())),
         }
     }
-    fn connless(data: &[u8]) -> ReceivePacket {
+    fn connless(header: &'a [u8], data: &'a [u8]) -> ReceivePacket<'a> {
         ReceivePacket {
-            type_: ReceivePacketType::Connless(iter::once(data)),
+            type_: ReceivePacketType::Connless(iter::once((header, data))),
         }
     }
     fn connected<W>(warn: &mut W, online: &mut OnlineState, num_chunks: u8, data: &'a [u8])
@@ -187,7 +187,7 @@ impl<'a> ReceivePacket<'a> {
 #[derive(Clone)]
 enum ReceivePacketType<'a> {
     None,
-    Connless(iter::Once<&'a [u8]>),
+    Connless(iter::Once<(&'a [u8], &'a [u8])>),
     Connected(ReceiveChunks<'a>),
     Ready(iter::Once<()>),
     Close(iter::Once<&'a [u8]>),
@@ -201,7 +201,7 @@ impl<'a> Iterator for ReceivePacket<'a> {
             ReceivePacketType::Ready(ref mut once) =>
                 once.next().map(|()| ReceiveChunk::Ready),
             ReceivePacketType::Connless(ref mut once) =>
-                once.next().map(ReceiveChunk::Connless),
+                once.next().map(|(h, d)| ReceiveChunk::Connless(h, d)),
             ReceivePacketType::Connected(ref mut chunks) => chunks.next(),
             ReceivePacketType::Close(ref mut once) =>
                 once.next().map(ReceiveChunk::Disconnect),
@@ -246,7 +246,8 @@ impl<'a> ExactSizeIterator for ReceiveChunks<'a> { }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum ReceiveChunk<'a> {
-    Connless(&'a [u8]),
+    // Connless(header, data)
+    Connless(&'a [u8], &'a [u8]),
     // Connected(data, vital)
     Connected(&'a [u8], bool),
     Ready,
@@ -624,7 +625,10 @@ impl Connection {
             };
 
             let connected = match packet {
-                Packet::Connless(data) => return (ReceivePacket::connless(data), Ok(())),
+                Packet::Connless(payload) => {
+                    let header = &data[..protocol::CONNLESS_HEADER_SIZE];
+                    return (ReceivePacket::connless(header, payload), Ok(()));
+                }
                 Packet::Connected(c) => c,
             };
             let ConnectedPacket { ack, type_ } = connected;
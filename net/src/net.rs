@@ -170,6 +170,9 @@ pub struct Chunk<'a> {
 pub struct ConnlessChunk<'a, A: Address> {
     pub addr: A,
     pub pid: Option<PeerId>,
+    /// The packet header and padding, `CONNLESS_HEADER_SIZE` bytes that are
+    /// normally all `0xff`. DDNet puts an extra token into them.
+    pub header: &'a [u8],
     pub data: &'a [u8],
 }
 
@@ -212,9 +215,10 @@ impl<'a, A: Address> Iterator for ReceivePacket<'a, A> {
             Connect(ref mut once) => once.next().map(|pid| ChunkOrEvent::Connect(pid)),
             Connected(addr, pid, ref mut receive_packet) => receive_packet.next().map(|chunk| {
                 match chunk {
-                    ReceiveChunk::Connless(d) => ChunkOrEvent::Connless(ConnlessChunk {
+                    ReceiveChunk::Connless(h, d) => ChunkOrEvent::Connless(ConnlessChunk {
                         addr: addr,
                         pid: Some(pid),
+                        header: h,
                         data: d,
                     }),
                     ReceiveChunk::Connected(d, vital) => ChunkOrEvent::Chunk(Chunk {
@@ -226,10 +230,11 @@ impl<'a, A: Address> Iterator for ReceivePacket<'a, A> {
                     ReceiveChunk::Disconnect(r) => ChunkOrEvent::Disconnect(pid, r),
                 }
             }),
-            Connless(addr, ref mut once) => once.next().map(|data| {
+            Connless(addr, ref mut once) => once.next().map(|(header, data)| {
                 ChunkOrEvent::Connless(ConnlessChunk {
                     addr: addr,
                     pid: None,
+                    header: header,
                     data: data,
                 })
             }),
@@ -267,9 +272,9 @@ impl<'a, A: Address> ReceivePacket<'a, A> {
         }
     }
 
-    fn connless(addr: A, data: &'a [u8]) -> ReceivePacket<'a, A> {
+    fn connless(addr: A, header: &'a [u8], data: &'a [u8]) -> ReceivePacket<'a, A> {
         ReceivePacket {
-            type_: ReceivePacketType::Connless(addr, iter::once(data)),
+            type_: ReceivePacketType::Connless(addr, iter::once((header, data))),
         }
     }
 }
@@ -279,7 +284,7 @@ enum ReceivePacketType<'a, A: Address> {
     None,
     Connect(iter::Once<PeerId>),
     Connected(A, PeerId, connection::ReceivePacket<'a>),
-    Connless(A, iter::Once<&'a [u8]>),
+    Connless(A, iter::Once<(&'a [u8], &'a [u8])>),
 }
 
 pub struct Net<A: Address> {
@@ -475,7 +480,8 @@ impl<A: Address> Net<A> {
                 }
             };
             if let Packet::Connless(d) = packet {
-                (ReceivePacket::connless(addr, d), Ok(()))
+                let header = &data[..protocol::CONNLESS_HEADER_SIZE];
+                (ReceivePacket::connless(addr, header, d), Ok(()))
             } else if let Packet::Connected(ConnectedPacket {
                     type_: ConnectedPacketType::Control(ControlPacket::Connect), ..
                 }) = packet
@@ -521,9 +527,11 @@ mod test {
     use std::collections::VecDeque;
     use super::Callback;
     use super::ChunkOrEvent;
+    use super::ConnlessChunk;
     use super::Net;
     use void::ResultVoidExt;
     use void::Void;
+    use warn::Ignore;
     use warn::Panic;
 
     #[test]
@@ -610,4 +618,28 @@ mod test {
                 == &[ChunkOrEvent::Disconnect(s_pid, b"foobar")]);
         assert!(cb.packets.is_empty());
     }
+
+    #[test]
+    fn connless_header() {
+        struct Cb;
+        impl Callback<()> for Cb {
+            type Error = Void;
+            fn send(&mut self, _: (), _: &[u8]) -> Result<(), Void> {
+                unreachable!();
+            }
+            fn time(&mut self) -> Timestamp {
+                Timestamp::from_secs_since_epoch(0)
+            }
+        }
+        let mut buffer = [0; protocol::MAX_PAYLOAD];
+        let mut net = Net::server();
+        let packet = b"xe\x01\x02\0\0\xff\xff\xff\xffgie3\x05";
+        let p = net.feed(&mut Cb, &mut Ignore, (), packet, &mut buffer[..]).0.collect_vec();
+        assert_eq!(p, &[ChunkOrEvent::Connless(ConnlessChunk {
+            addr: (),
+            pid: None,
+            header: b"xe\x01\x02\0\0",
+            data: b"\xff\xff\xff\xffgie3\x05",
+        })]);
+    }
 }
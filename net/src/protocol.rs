@@ -16,6 +16,7 @@ pub const CHUNK_HEADER_SIZE_VITAL: usize = 3;
 pub const HEADER_SIZE: usize = 3;
 pub const MAX_PACKETSIZE: usize = 1400;
 pub const PADDING_SIZE_CONNLESS: usize = 3;
+pub const CONNLESS_HEADER_SIZE: usize = HEADER_SIZE + PADDING_SIZE_CONNLESS;
 
 // For connectionless packets, this is obvious (MAX_PACKETSIZE - HEADER_SIZE -
 // PADDING_SIZE_CONNLESS). For packets sent in a connection context, you also
@@ -1,5 +1,3 @@
-#![cfg(not(test))]
-
 #[macro_use]
 extern crate clap;
 extern crate common;
@@ -10,6 +8,8 @@ extern crate map;
 extern crate ndarray;
 extern crate num_traits;
 
+mod overlay;
+
 use clap::App;
 use clap::Arg;
 use common::num::Cast;
@@ -22,6 +22,8 @@ use map::format;
 use map::reader;
 use ndarray::Array2;
 use num_traits::ToPrimitive;
+use overlay::GameData;
+use overlay::Overlays;
 use std::cmp;
 use std::collections::HashMap;
 use std::collections::hash_map;
@@ -30,6 +32,7 @@ use std::fmt;
 use std::io;
 use std::mem;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process;
 use std::str;
 
@@ -47,6 +50,9 @@ struct Config {
     size: u32,
     render_detail: bool,
     crop: Option<Rect>,
+    overlays: Overlays,
+    legend: bool,
+    diff: Option<PathBuf>,
 }
 
 #[repr(C)]
@@ -309,6 +315,18 @@ fn process<E>(path: &Path, out_path: &Path, mut external: &mut E, config: &Confi
 
     let width = max_x - min_x;
     let height = max_y - min_y;
+    let area = Rect {
+        min_x: min_x,
+        min_y: min_y,
+        max_x: max_x,
+        max_y: max_y,
+    };
+
+    let game_data = if config.overlays.any() {
+        Some(try!(GameData::load(&mut map, &config.overlays)))
+    } else {
+        None
+    };
 
     let mut tile_len = 64;
     while tile_len != 1 && tile_len * tile_len * width * height > 16 * config.size * config.size {
@@ -354,6 +372,10 @@ fn process<E>(path: &Path, out_path: &Path, mut external: &mut E, config: &Confi
         }
     }
 
+    if let Some(ref data) = game_data {
+        overlay::draw_tiles(&mut result, data, &config.overlays, &area, tile_len);
+    }
+
     let image = {
         let raw: &[Color] = result.as_slice().unwrap();
         let raw: &[u8] = unsafe { slice::transmute(raw) };
@@ -371,8 +393,24 @@ fn process<E>(path: &Path, out_path: &Path, mut external: &mut E, config: &Confi
     };
     if new_width == 0 { new_width = 1; }
     if new_height == 0 { new_height = 1; }
-    let resized = imageops::resize(&image, new_width, new_height, imageops::CatmullRom);
+    let mut resized = imageops::resize(&image, new_width, new_height, imageops::CatmullRom);
     mem::drop(image);
+    if let Some(ref data) = game_data {
+        overlay::draw_markers(&mut resized, data, &config.overlays, &area);
+    }
+    if let Some(ref old_path) = config.diff {
+        let mut old = try!(map::Reader::open(old_path));
+        let rects: Vec<_> = try!(map::diff::diff(&mut old, &mut map)).into_iter()
+            .flat_map(|c| match c {
+                map::diff::Change::TilesChanged(_, _, rects) => rects,
+                _ => vec![],
+            })
+            .collect();
+        overlay::draw_changes(&mut resized, &rects, &area);
+    }
+    if config.legend {
+        resized = overlay::add_legend(&resized, &config.overlays);
+    }
     try!(resized.save(out_path));
 
     Ok(())
@@ -547,6 +585,25 @@ fn main() {
             .number_of_values(4)
             .value_name("CROP")
         )
+        .arg(Arg::with_name("overlay")
+            .help("Draws overlays for the game layers on top of the map")
+            .long("overlay")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .possible_values(&["game", "entities", "teleporters", "speedups", "switches"])
+            .value_name("OVERLAY")
+        )
+        .arg(Arg::with_name("legend")
+            .help("Appends a legend for the overlays below the thumbnail")
+            .long("legend")
+        )
+        .arg(Arg::with_name("diff")
+            .help("Highlights the tiles that changed compared to this map")
+            .long("diff")
+            .takes_value(true)
+            .value_name("OLD_MAP")
+        )
         .get_matches();
 
     let crop = if !matches.is_present("crop") {
@@ -573,10 +630,25 @@ fn main() {
         })
     };
 
+    let mut overlays = Overlays::default();
+    for o in matches.values_of("overlay").into_iter().flat_map(|v| v) {
+        match o {
+            "game" => overlays.game = true,
+            "entities" => overlays.entities = true,
+            "teleporters" => overlays.teleporters = true,
+            "speedups" => overlays.speedups = true,
+            "switches" => overlays.switches = true,
+            _ => unreachable!(),
+        }
+    }
+
     let config = Config {
         size: value_t!(matches, "size", u32).unwrap_or_else(|e| e.exit()),
         render_detail: !matches.is_present("no-detail"),
         crop: crop,
+        overlays: overlays,
+        legend: matches.is_present("legend"),
+        diff: matches.value_of_os("diff").map(PathBuf::from),
     };
 
     let args = matches.values_of_os("map").unwrap();
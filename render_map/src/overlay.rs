@@ -0,0 +1,597 @@
+//! Overlays visualizing the game layers on top of the rendered map.
+//!
+//! Game layer tiles are blended into the full-size rendering, everything
+//! else (markers, arrows, numbers and the legend) is drawn onto the resized
+//! thumbnail so that it stays visible at small sizes.
+
+use Color;
+use Error;
+use Rect;
+use common::num::Cast;
+use image::Rgba;
+use image::RgbaImage;
+use map::format;
+use map;
+use ndarray::Array2;
+use num_traits::ToPrimitive;
+use std::cmp;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use swap;
+
+const TILE_SOLID: u8 = 1;
+const TILE_DEATH: u8 = 2;
+const TILE_NOHOOK: u8 = 3;
+
+const ENTITY_SPAWN: u8 = 0xc0;
+const ENTITY_SPAWN_RED: u8 = 0xc1;
+const ENTITY_SPAWN_BLUE: u8 = 0xc2;
+const ENTITY_FLAGSTAND_RED: u8 = 0xc3;
+const ENTITY_FLAGSTAND_BLUE: u8 = 0xc4;
+const ENTITY_ARMOR: u8 = 0xc5;
+const ENTITY_HEALTH: u8 = 0xc6;
+const ENTITY_WEAPON_SHOTGUN: u8 = 0xc7;
+const ENTITY_WEAPON_GRENADE: u8 = 0xc8;
+const ENTITY_POWERUP_NINJA: u8 = 0xc9;
+const ENTITY_WEAPON_LASER: u8 = 0xca;
+
+const TELE_IN_EVIL: u8 = 10;
+const TELE_IN_WEAPON: u8 = 14;
+const TELE_IN_HOOK: u8 = 15;
+const TELE_IN: u8 = 26;
+const TELE_OUT: u8 = 27;
+
+const fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Color {
+    Color {
+        red: red,
+        green: green,
+        blue: blue,
+        alpha: alpha,
+    }
+}
+
+const COLOR_SOLID: Color = rgba(0x30, 0x30, 0x30, 0xb0);
+const COLOR_DEATH: Color = rgba(0xe0, 0x20, 0x20, 0xb0);
+const COLOR_NOHOOK: Color = rgba(0x90, 0x60, 0xd0, 0xb0);
+const COLOR_SPAWN: Color = rgba(0xff, 0xff, 0xff, 0xff);
+const COLOR_SPAWN_RED: Color = rgba(0xff, 0x50, 0x50, 0xff);
+const COLOR_SPAWN_BLUE: Color = rgba(0x50, 0x70, 0xff, 0xff);
+const COLOR_FLAG_RED: Color = rgba(0xa0, 0x00, 0x00, 0xff);
+const COLOR_FLAG_BLUE: Color = rgba(0x00, 0x00, 0xa0, 0xff);
+const COLOR_ARMOR: Color = rgba(0xff, 0xd0, 0x00, 0xff);
+const COLOR_HEALTH: Color = rgba(0xff, 0x60, 0xb0, 0xff);
+const COLOR_WEAPON: Color = rgba(0x20, 0xd0, 0x20, 0xff);
+const COLOR_TELEPORTER: Color = rgba(0x00, 0xd0, 0xff, 0xff);
+const COLOR_SPEEDUP: Color = rgba(0xff, 0x80, 0x00, 0xff);
+const COLOR_SWITCH: Color = rgba(0xff, 0xff, 0x40, 0xff);
+const COLOR_CHANGED: Color = rgba(0xff, 0x00, 0xff, 0xff);
+const COLOR_CHANGED_FILL: Color = rgba(0xff, 0x00, 0xff, 0x40);
+const COLOR_LEGEND_BACKGROUND: Color = rgba(0x20, 0x20, 0x20, 0xff);
+const COLOR_LEGEND_TEXT: Color = rgba(0xff, 0xff, 0xff, 0xff);
+
+/// Which overlays to draw.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Overlays {
+    /// Solid, death and unhookable tiles.
+    pub game: bool,
+    /// Spawns, flags and pickups.
+    pub entities: bool,
+    /// Arrows from teleporter entrances to their exits.
+    pub teleporters: bool,
+    /// Arrows in the direction of speedups.
+    pub speedups: bool,
+    /// Numbers of switch tiles.
+    pub switches: bool,
+}
+
+impl Overlays {
+    pub fn any(&self) -> bool {
+        self.game || self.entities || self.teleporters || self.speedups || self.switches
+    }
+    fn legend(&self) -> Vec<(Color, &'static str)> {
+        let mut result = Vec::new();
+        if self.game {
+            result.push((COLOR_SOLID, "SOLID"));
+            result.push((COLOR_DEATH, "DEATH"));
+            result.push((COLOR_NOHOOK, "UNHOOKABLE"));
+        }
+        if self.entities {
+            result.push((COLOR_SPAWN, "SPAWN"));
+            result.push((COLOR_SPAWN_RED, "RED SPAWN"));
+            result.push((COLOR_SPAWN_BLUE, "BLUE SPAWN"));
+            result.push((COLOR_FLAG_RED, "RED FLAG"));
+            result.push((COLOR_FLAG_BLUE, "BLUE FLAG"));
+            result.push((COLOR_ARMOR, "ARMOR"));
+            result.push((COLOR_HEALTH, "HEALTH"));
+            result.push((COLOR_WEAPON, "WEAPON"));
+        }
+        if self.teleporters {
+            result.push((COLOR_TELEPORTER, "TELEPORTER"));
+        }
+        if self.speedups {
+            result.push((COLOR_SPEEDUP, "SPEEDUP"));
+        }
+        if self.switches {
+            result.push((COLOR_SWITCH, "SWITCH NUMBER"));
+        }
+        result
+    }
+}
+
+fn entity_color(index: u8) -> Option<Color> {
+    Some(match index {
+        ENTITY_SPAWN => COLOR_SPAWN,
+        ENTITY_SPAWN_RED => COLOR_SPAWN_RED,
+        ENTITY_SPAWN_BLUE => COLOR_SPAWN_BLUE,
+        ENTITY_FLAGSTAND_RED => COLOR_FLAG_RED,
+        ENTITY_FLAGSTAND_BLUE => COLOR_FLAG_BLUE,
+        ENTITY_ARMOR => COLOR_ARMOR,
+        ENTITY_HEALTH => COLOR_HEALTH,
+        ENTITY_WEAPON_SHOTGUN
+            | ENTITY_WEAPON_GRENADE
+            | ENTITY_POWERUP_NINJA
+            | ENTITY_WEAPON_LASER
+            => COLOR_WEAPON,
+        _ => return None,
+    })
+}
+
+/// The game layer tiles needed for the requested overlays.
+pub struct GameData {
+    /// The game layer and the DDNet front layer, if present.
+    game: Vec<Array2<format::Tile>>,
+    tele: Option<Array2<format::TeleTile>>,
+    speedup: Option<Array2<format::SpeedupTile>>,
+    switch: Option<Array2<format::SwitchTile>>,
+}
+
+impl GameData {
+    pub fn load(map: &mut map::Reader, overlays: &Overlays) -> Result<GameData, Error> {
+        let layers = try!(map.game_layers());
+        let mut game = Vec::new();
+        if overlays.game || overlays.entities {
+            game.push(try!(map.layer_tiles(layers.game())));
+            if let Some(front) = layers.front() {
+                game.push(try!(map.layer_tiles(front)));
+            }
+        }
+        let tele = if overlays.teleporters {
+            try!(swap(layers.teleport().map(|t| map.tele_layer_tiles(t))))
+        } else {
+            None
+        };
+        let speedup = if overlays.speedups {
+            try!(swap(layers.speedup().map(|s| map.speedup_layer_tiles(s))))
+        } else {
+            None
+        };
+        let switch = if overlays.switches {
+            try!(swap(layers.switch().map(|s| map.switch_layer_tiles(s))))
+        } else {
+            None
+        };
+        Ok(GameData {
+            game: game,
+            tele: tele,
+            speedup: speedup,
+            switch: switch,
+        })
+    }
+}
+
+/// Calls `f` with the coordinates relative to `area` of each tile inside
+/// `area`.
+fn for_each_tile<T, F>(tiles: &Array2<T>, area: &Rect, mut f: F)
+    where F: FnMut(u32, u32, &T),
+{
+    let max_y = cmp::min(area.max_y, tiles.dim().0.assert_u32());
+    let max_x = cmp::min(area.max_x, tiles.dim().1.assert_u32());
+    for y in area.min_y..max_y {
+        for x in area.min_x..max_x {
+            f(x - area.min_x, y - area.min_y, &tiles[(y.usize(), x.usize())]);
+        }
+    }
+}
+
+/// Blends the solid, death and unhookable tiles into the full-size
+/// rendering.
+pub fn draw_tiles(result: &mut Array2<Color>, data: &GameData, overlays: &Overlays, area: &Rect, tile_len: u32) {
+    if !overlays.game {
+        return;
+    }
+    for tiles in &data.game {
+        for_each_tile(tiles, area, |x, y, tile| {
+            let color = match tile.index {
+                TILE_SOLID => COLOR_SOLID,
+                TILE_DEATH => COLOR_DEATH,
+                TILE_NOHOOK => COLOR_NOHOOK,
+                _ => return,
+            };
+            for iy in 0..tile_len {
+                for ix in 0..tile_len {
+                    let p = &mut result[((y * tile_len + iy).usize(), (x * tile_len + ix).usize())];
+                    *p = p.overlay_with(color);
+                }
+            }
+        });
+    }
+}
+
+struct Canvas<'a> {
+    image: &'a mut RgbaImage,
+    tile_width: f32,
+    tile_height: f32,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(image: &'a mut RgbaImage, area: &Rect) -> Canvas<'a> {
+        let (width, height) = image.dimensions();
+        Canvas {
+            tile_width: width.to_f32().unwrap() / (area.max_x - area.min_x).to_f32().unwrap(),
+            tile_height: height.to_f32().unwrap() / (area.max_y - area.min_y).to_f32().unwrap(),
+            image: image,
+        }
+    }
+    fn center(&self, x: u32, y: u32) -> (f32, f32) {
+        ((x.to_f32().unwrap() + 0.5) * self.tile_width, (y.to_f32().unwrap() + 0.5) * self.tile_height)
+    }
+    fn tile_size(&self) -> f32 {
+        self.tile_width.min(self.tile_height)
+    }
+    fn blend(&mut self, x: i64, y: i64, color: Color) {
+        let (width, height) = self.image.dimensions();
+        if x < 0 || y < 0 || x >= width.i64() || y >= height.i64() {
+            return;
+        }
+        let pixel = self.image.get_pixel_mut(x.assert_u32(), y.assert_u32());
+        let d = pixel.data;
+        let c = rgba(d[0], d[1], d[2], d[3]).overlay_with(color);
+        *pixel = Rgba { data: [c.red, c.green, c.blue, c.alpha] };
+    }
+    fn square(&mut self, (cx, cy): (f32, f32), half: f32, color: Color) {
+        let min_x = (cx - half).round().to_i64().unwrap();
+        let min_y = (cy - half).round().to_i64().unwrap();
+        let max_x = (cx + half).round().to_i64().unwrap();
+        let max_y = (cy + half).round().to_i64().unwrap();
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                self.blend(x, y, color);
+            }
+        }
+    }
+    fn line(&mut self, (x0, y0): (f32, f32), (x1, y1): (f32, f32), color: Color) {
+        let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0);
+        let num_steps = steps.to_i64().unwrap();
+        for i in 0..num_steps + 1 {
+            let t = i.to_f32().unwrap() / steps;
+            let x = (x0 + (x1 - x0) * t).round().to_i64().unwrap();
+            let y = (y0 + (y1 - y0) * t).round().to_i64().unwrap();
+            self.blend(x, y, color);
+        }
+    }
+    fn arrow(&mut self, from: (f32, f32), to: (f32, f32), color: Color) {
+        self.line(from, to, color);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            return;
+        }
+        let head = (len * 0.4).min(6.0).max(2.0);
+        let angle = dy.atan2(dx);
+        for &offset in &[PI * 5.0 / 6.0, -PI * 5.0 / 6.0] {
+            let end = (to.0 + head * (angle + offset).cos(), to.1 + head * (angle + offset).sin());
+            self.line(to, end, color);
+        }
+    }
+    /// Draws `text` with its top left corner at `(x, y)`, each glyph being
+    /// `3 * scale` pixels wide and `5 * scale` pixels high.
+    fn text(&mut self, x: i64, y: i64, text: &str, scale: i64, color: Color) {
+        for (i, c) in text.chars().enumerate() {
+            let rows = glyph(c);
+            let left = x + i.assert_i64() * GLYPH_ADVANCE * scale;
+            for (row_y, &row) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if row & (0b100 >> column) == 0 {
+                        continue;
+                    }
+                    for sy in 0..scale {
+                        for sx in 0..scale {
+                            self.blend(left + column * scale + sx, y + row_y.assert_i64() * scale + sy, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: i64 = 3;
+const GLYPH_HEIGHT: i64 = 5;
+const GLYPH_ADVANCE: i64 = GLYPH_WIDTH + 1;
+
+/// A tiny 3x5 pixel font, each row is given as three bits, the most
+/// significant bit being the leftmost pixel.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+/// Draws entity markers, teleporter and speedup arrows and switch numbers
+/// onto the resized image.
+pub fn draw_markers(image: &mut RgbaImage, data: &GameData, overlays: &Overlays, area: &Rect) {
+    let mut canvas = Canvas::new(image, area);
+    let half = (canvas.tile_size() * 0.4).max(1.0);
+
+    if overlays.entities {
+        for tiles in &data.game {
+            for_each_tile(tiles, area, |x, y, tile| {
+                if let Some(color) = entity_color(tile.index) {
+                    let center = canvas.center(x, y);
+                    canvas.square(center, half, color);
+                }
+            });
+        }
+    }
+
+    if let Some(ref tele) = data.tele {
+        // Connect the centers of all entrances and exits of each
+        // teleporter number.
+        let mut entrances: HashMap<u8, (f32, f32, f32)> = HashMap::new();
+        let mut exits: HashMap<u8, (f32, f32, f32)> = HashMap::new();
+        for_each_tile(tele, area, |x, y, tile| {
+            let sums = match tile.index {
+                TELE_IN | TELE_IN_EVIL | TELE_IN_WEAPON | TELE_IN_HOOK => &mut entrances,
+                TELE_OUT => &mut exits,
+                _ => return,
+            };
+            let (cx, cy) = canvas.center(x, y);
+            let sum = sums.entry(tile.number).or_insert((0.0, 0.0, 0.0));
+            sum.0 += cx;
+            sum.1 += cy;
+            sum.2 += 1.0;
+        });
+        let mut numbers: Vec<_> = entrances.keys().cloned().collect();
+        numbers.sort();
+        for number in numbers {
+            let from = entrances[&number];
+            if let Some(to) = exits.get(&number) {
+                canvas.arrow((from.0 / from.2, from.1 / from.2), (to.0 / to.2, to.1 / to.2), COLOR_TELEPORTER);
+            }
+        }
+    }
+
+    if let Some(ref speedup) = data.speedup {
+        for_each_tile(speedup, area, |x, y, tile| {
+            if tile.force == 0 {
+                return;
+            }
+            let angle = f32::from(tile.angle.to_i16()) * PI / 180.0;
+            let (cx, cy) = canvas.center(x, y);
+            let (dx, dy) = (angle.cos() * half, angle.sin() * half);
+            canvas.arrow((cx - dx, cy - dy), (cx + dx, cy + dy), COLOR_SPEEDUP);
+        });
+    }
+
+    if let Some(ref switch) = data.switch {
+        for_each_tile(switch, area, |x, y, tile| {
+            if tile.number == 0 {
+                return;
+            }
+            let number = tile.number.to_string();
+            let (cx, cy) = canvas.center(x, y);
+            let text_width = number.len().assert_i64() * GLYPH_ADVANCE - 1;
+            let left = cx.round().to_i64().unwrap() - text_width / 2;
+            let top = cy.round().to_i64().unwrap() - GLYPH_HEIGHT / 2;
+            canvas.text(left, top, &number, 1, COLOR_SWITCH);
+        });
+    }
+}
+
+const LEGEND_SCALE: i64 = 2;
+const LEGEND_MARGIN: i64 = 4;
+const LEGEND_LINE_HEIGHT: i64 = GLYPH_HEIGHT * LEGEND_SCALE + LEGEND_MARGIN;
+
+/// Highlights the given tile regions, e.g. the ones that changed according
+/// to `map::diff`.
+pub fn draw_changes(image: &mut RgbaImage, rects: &[map::diff::Rect], area: &Rect) {
+    let mut canvas = Canvas::new(image, area);
+    for r in rects {
+        let min_x = cmp::max(r.x, area.min_x);
+        let min_y = cmp::max(r.y, area.min_y);
+        let max_x = cmp::min(r.x + r.width, area.max_x);
+        let max_y = cmp::min(r.y + r.height, area.max_y);
+        if min_x >= max_x || min_y >= max_y {
+            continue;
+        }
+        let left = (min_x - area.min_x).to_f32().unwrap() * canvas.tile_width;
+        let top = (min_y - area.min_y).to_f32().unwrap() * canvas.tile_height;
+        let right = (max_x - area.min_x).to_f32().unwrap() * canvas.tile_width - 1.0;
+        let bottom = (max_y - area.min_y).to_f32().unwrap() * canvas.tile_height - 1.0;
+        for y in top.round().to_i64().unwrap()..bottom.round().to_i64().unwrap() + 1 {
+            for x in left.round().to_i64().unwrap()..right.round().to_i64().unwrap() + 1 {
+                canvas.blend(x, y, COLOR_CHANGED_FILL);
+            }
+        }
+        canvas.line((left, top), (right, top), COLOR_CHANGED);
+        canvas.line((right, top), (right, bottom), COLOR_CHANGED);
+        canvas.line((right, bottom), (left, bottom), COLOR_CHANGED);
+        canvas.line((left, bottom), (left, top), COLOR_CHANGED);
+    }
+}
+
+/// Returns a copy of `image` with a legend for the overlays appended
+/// below.
+pub fn add_legend(image: &RgbaImage, overlays: &Overlays) -> RgbaImage {
+    let entries = overlays.legend();
+    let swatch = GLYPH_HEIGHT * LEGEND_SCALE;
+    let text_left = LEGEND_MARGIN + swatch + LEGEND_MARGIN;
+    let max_len = entries.iter().map(|&(_, l)| l.len().assert_i64()).max().unwrap_or(0);
+    let legend_width = text_left + max_len * GLYPH_ADVANCE * LEGEND_SCALE + LEGEND_MARGIN;
+    let legend_height = LEGEND_MARGIN + entries.len().assert_i64() * LEGEND_LINE_HEIGHT;
+
+    let (width, height) = image.dimensions();
+    let mut result = RgbaImage::new(
+        cmp::max(width, legend_width.assert_u32()),
+        height + legend_height.assert_u32(),
+    );
+    for (x, y, &pixel) in image.enumerate_pixels() {
+        result.put_pixel(x, y, pixel);
+    }
+    let mut canvas = Canvas {
+        image: &mut result,
+        tile_width: 1.0,
+        tile_height: 1.0,
+    };
+    let top = height.i64();
+    for y in top..top + legend_height {
+        for x in 0..legend_width {
+            canvas.blend(x, y, COLOR_LEGEND_BACKGROUND);
+        }
+    }
+    for (i, &(color, label)) in entries.iter().enumerate() {
+        let y = top + LEGEND_MARGIN + i.assert_i64() * LEGEND_LINE_HEIGHT;
+        for sy in 0..swatch {
+            for sx in 0..swatch {
+                canvas.blend(LEGEND_MARGIN + sx, y + sy, color);
+            }
+        }
+        canvas.text(text_left, y, label, LEGEND_SCALE, COLOR_LEGEND_TEXT);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use Color;
+    use Rect;
+    use image::Rgba;
+    use image::RgbaImage;
+    use map::diff;
+    use ndarray::Array2;
+    use super::COLOR_CHANGED;
+    use super::COLOR_CHANGED_FILL;
+    use super::COLOR_LEGEND_BACKGROUND;
+    use super::COLOR_SWITCH;
+    use super::Overlays;
+    use super::add_legend;
+    use super::draw_changes;
+    use super::for_each_tile;
+    use super::rgba;
+
+    const BLACK: Color = rgba(0, 0, 0, 0xff);
+
+    fn black(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba { data: [0, 0, 0, 0xff] })
+    }
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> Color {
+        let d = image.get_pixel(x, y).data;
+        rgba(d[0], d[1], d[2], d[3])
+    }
+
+    fn area(min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> Rect {
+        Rect {
+            min_x: min_x,
+            min_y: min_y,
+            max_x: max_x,
+            max_y: max_y,
+        }
+    }
+
+    #[test]
+    fn tiles_in_area() {
+        let tiles = Array2::from_shape_fn((3, 4), |(y, x)| y * 10 + x);
+        let mut visited = Vec::new();
+        for_each_tile(&tiles, &area(2, 1, 6, 5), |x, y, &t| visited.push((x, y, t)));
+        assert_eq!(visited, [(0, 0, 12), (1, 0, 13), (0, 1, 22), (1, 1, 23)]);
+
+        visited.clear();
+        for_each_tile(&tiles, &area(4, 0, 6, 3), |x, y, &t| visited.push((x, y, t)));
+        assert!(visited.is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        // Two pixels per tile.
+        let mut image = black(8, 8);
+        let rects = [
+            diff::Rect { x: 0, y: 0, width: 3, height: 3 },
+            diff::Rect { x: 4, y: 0, width: 1, height: 1 },
+        ];
+        draw_changes(&mut image, &rects, &area(0, 0, 4, 4));
+        let fill = BLACK.overlay_with(COLOR_CHANGED_FILL);
+        let outline = fill.overlay_with(COLOR_CHANGED);
+        for &(x, y) in &[(0, 0), (5, 0), (5, 5), (0, 5), (3, 5)] {
+            assert_eq!(pixel(&image, x, y), outline);
+        }
+        for &(x, y) in &[(1, 1), (4, 4), (2, 3)] {
+            assert_eq!(pixel(&image, x, y), fill);
+        }
+        for &(x, y) in &[(6, 0), (7, 7), (0, 6)] {
+            assert_eq!(pixel(&image, x, y), BLACK);
+        }
+    }
+
+    #[test]
+    fn legend() {
+        let mut image = black(2, 2);
+        image.put_pixel(1, 1, Rgba { data: [1, 2, 3, 4] });
+
+        let result = add_legend(&image, &Overlays::default());
+        assert_eq!(result.dimensions(), (22, 6));
+        assert_eq!(pixel(&result, 1, 1), rgba(1, 2, 3, 4));
+        assert_eq!(pixel(&result, 0, 2), COLOR_LEGEND_BACKGROUND);
+
+        let overlays = Overlays { switches: true, ..Overlays::default() };
+        let result = add_legend(&image, &overlays);
+        // Margin, swatch, margin, "SWITCH NUMBER" and margin.
+        assert_eq!(result.dimensions(), (4 + 10 + 4 + 13 * 8 + 4, 2 + 4 + 14));
+        assert_eq!(pixel(&result, 0, 0), BLACK);
+        assert_eq!(pixel(&result, 1, 1), rgba(1, 2, 3, 4));
+        assert_eq!(pixel(&result, 2, 0), rgba(0, 0, 0, 0));
+        assert_eq!(pixel(&result, 0, 2), COLOR_LEGEND_BACKGROUND);
+        assert_eq!(pixel(&result, 4, 6), COLOR_SWITCH);
+        assert_eq!(pixel(&result, 13, 15), COLOR_SWITCH);
+        assert_eq!(pixel(&result, 14, 15), COLOR_LEGEND_BACKGROUND);
+        // Top left pixel of the "S".
+        assert_eq!(pixel(&result, 18, 6), COLOR_LEGEND_BACKGROUND);
+        assert_eq!(pixel(&result, 20, 6), rgba(0xff, 0xff, 0xff, 0xff));
+    }
+}
@@ -1,4 +1,5 @@
 extern crate arrayvec;
+extern crate clap;
 #[macro_use] extern crate common;
 extern crate datafile;
 extern crate event_loop;
@@ -11,6 +12,7 @@ extern crate map;
 #[macro_use] extern crate matches;
 extern crate ndarray;
 extern crate packer;
+extern crate serverbrowse;
 extern crate snapshot;
 extern crate socket;
 extern crate warn;
@@ -18,6 +20,8 @@ extern crate world;
 
 use arrayvec::ArrayString;
 use arrayvec::ArrayVec;
+use clap::App;
+use clap::Arg;
 use common::Takeable;
 use common::num::Cast;
 use common::num::CastFloat;
@@ -27,6 +31,7 @@ use event_loop::Application;
 use event_loop::Chunk;
 use event_loop::ConnlessChunk;
 use event_loop::Loop;
+use event_loop::LoopExt;
 use event_loop::PeerId;
 use event_loop::SocketLoop;
 use event_loop::Timeout;
@@ -47,7 +52,6 @@ use gamenet::msg::connless;
 use gamenet::msg::game::SV_TUNE_PARAMS_DEFAULT;
 use gamenet::msg::game;
 use gamenet::msg::system;
-use gamenet::msg;
 use gamenet::snap_obj::Character;
 use gamenet::snap_obj::ClientInfo;
 use gamenet::snap_obj::GameInfo;
@@ -64,8 +68,11 @@ use packer::string_to_ints3;
 use packer::string_to_ints4;
 use packer::string_to_ints6;
 use packer::with_packer;
+use register::Register;
+use serverbrowse::protocol as browse;
 use snapshot::snap;
 use std::cell::Cell;
+use std::cmp;
 use std::fmt::Write;
 use std::fmt;
 use std::fs::File;
@@ -73,9 +80,13 @@ use std::io::Read;
 use std::time::Duration;
 use world::vec2;
 
+mod register;
+
+const PORT: u16 = 8303;
 const TICKS_PER_SECOND: u32 = 50;
 const PLAYER_NAME_LENGTH: usize = 16-1; // -1 for null termination
 const MAPDOWNLOAD_CHUNK_SIZE: u64 = 1024-128;
+const CONNLESS_HEADER_LEN: usize = 6;
 
 fn hexdump(level: LogLevel, data: &[u8]) {
     if log_enabled!(level) {
@@ -92,46 +103,6 @@ impl<'a, T: fmt::Debug, W: fmt::Debug> warn::Warn<W> for Warn<'a, T> {
     }
 }
 
-fn sends_impl<L: Loop+?Sized>(msg: System, pid: PeerId, vital: bool, loop_: &mut L) {
-    let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
-    with_packer(&mut buf, |p| msg.encode(p).unwrap());
-    loop_.send(Chunk {
-        pid: pid,
-        vital: vital,
-        data: &buf,
-    })
-}
-
-trait LoopExt: Loop {
-    fn sends<'a, S: Into<System<'a>>>(&mut self, pid: PeerId, msg: S) {
-        sends_impl(msg.into(), pid, true, self)
-    }
-    fn sends_nonvital<'a, S: Into<System<'a>>>(&mut self, pid: PeerId, msg: S) {
-        sends_impl(msg.into(), pid, false, self)
-    }
-    fn sendg<'a, G: Into<Game<'a>>>(&mut self, pid: PeerId, msg: G) {
-        fn inner<L: Loop+?Sized>(msg: Game, pid: PeerId, loop_: &mut L) {
-            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
-            with_packer(&mut buf, |p| msg.encode(p).unwrap());
-            loop_.send(Chunk {
-                pid: pid,
-                vital: true,
-                data: &buf,
-            })
-        }
-        inner(msg.into(), pid, self)
-    }
-    fn sendc<'a, C: Into<Connless<'a>>>(&mut self, addr: Addr, msg: C) {
-        fn inner<L: Loop+?Sized>(msg: Connless, addr: Addr, loop_: &mut L) {
-            let mut buf: ArrayVec<[u8; 2048]> = ArrayVec::new();
-            with_packer(&mut buf, |p| msg.encode(p).unwrap());
-            loop_.send_connless(addr, &buf)
-        }
-        inner(msg.into(), addr, self)
-    }
-}
-impl<L: Loop> LoopExt for L { }
-
 trait SnapBuilderExt {
     fn add<O: Into<SnapObj>>(&mut self, id: u16, obj: O);
 }
@@ -250,6 +221,7 @@ struct Server {
     game_tick: u32,
     delta_buffer: Vec<u8>,
     map: Map,
+    register: Register,
 
     send_snapshots_peer_set: Takeable<PeerSet>,
 }
@@ -368,13 +340,15 @@ struct ServerLoop<'a, L: Loop+'a> {
 
 impl<L: Loop> Application<L> for Server {
     fn needs_tick(&mut self) -> Timeout {
-        if !self.peers.is_empty() {
+        let game = if !self.peers.is_empty() {
             Timeout::active(self.game_tick_time(self.game_tick + 1))
         } else {
             Timeout::inactive()
-        }
+        };
+        cmp::min(game, self.register.needs_tick())
     }
     fn on_tick(&mut self, loop_: &mut L) {
+        self.register.tick(loop_);
         if !self.peers.is_empty() {
             self.loop_(loop_).tick();
         }
@@ -383,7 +357,7 @@ impl<L: Loop> Application<L> for Server {
         self.loop_(loop_).on_packet(chunk.pid, chunk.vital, chunk.data);
     }
     fn on_connless_packet(&mut self, loop_: &mut L, chunk: ConnlessChunk) {
-        self.loop_(loop_).on_connless_packet(chunk.addr, chunk.data);
+        self.loop_(loop_).on_connless_packet(chunk.addr, chunk.header, chunk.data);
     }
     fn on_connect(&mut self, loop_: &mut L, pid: PeerId) {
         self.loop_(loop_).on_connect(pid);
@@ -397,8 +371,12 @@ impl<L: Loop> Application<L> for Server {
 }
 
 impl Server {
-    fn run<L: Loop>() {
-        L::accept_connections_on_port(8303).run(Server::default());
+    fn run<L: Loop>(masters: &[String]) {
+        let server = Server {
+            register: Register::new(PORT, masters),
+            ..Server::default()
+        };
+        L::accept_connections_on_port(PORT).run(server);
     }
     fn loop_<'a, L: Loop+'a>(&'a mut self, loop_: &'a mut L) -> ServerLoop<'a, L> {
         ServerLoop { server: self, loop_: loop_ }
@@ -544,7 +522,7 @@ impl<'a, L: Loop> ServerLoop<'a, L> {
             warn!("unprocessed message {:?}", msg);
         }
     }
-    fn on_connless_packet(&mut self, addr: Addr, data: &[u8]) {
+    fn on_connless_packet(&mut self, addr: Addr, header: &[u8], data: &[u8]) {
         let msg = match Connless::decode(&mut Warn(addr, data), &mut Unpacker::new(data)) {
             Ok(m) => m,
             Err(err) => {
@@ -553,34 +531,61 @@ impl<'a, L: Loop> ServerLoop<'a, L> {
                 return;
             },
         };
+        if self.server.register.on_connless_packet(self.loop_, addr, &msg) {
+            return;
+        }
         let mut processed = false;
         match msg {
             Connless::RequestInfo(request) => {
                 processed = true;
-                let mut clients_buf: ArrayVec<[u8; 1024]> = Default::default();
-                for (_, peer) in &self.server.peers {
-                    with_packer(&mut clients_buf, |p| connless::Client {
-                        name: peer.state.net_name(),
-                        clan: b"",
-                        country: -1,
-                        score: 0,
-                        is_player: peer.state.net_is_player(),
-                    }.encode(p).unwrap());
-                }
-                // TODO: Send clients. :)
-                self.loop_.sendc(addr, connless::Info {
-                    token: request.token.i32(),
-                    version: VERSION,
-                    name: b"Rust Teeworlds Server",
-                    game_type: b"DM",
-                    map: b"dm1",
+                let clients = self.server.peers.iter().map(|(_, peer)| browse::ClientInfo {
+                    name: peer.state.net_name().iter().cloned().collect(),
+                    clan: ArrayVec::new(),
+                    country: -1,
+                    score: 0,
+                    is_player: peer.state.net_is_player(),
+                }).collect();
+                // DDNet clients ask for the extended info by putting an
+                // extra token into the packet header.
+                let request = browse::parse_info_request(&[header, data].concat())
+                    .unwrap_or(browse::InfoRequest {
+                        info_version: browse::ServerInfoVersion::V6,
+                        token: request.token.i32(),
+                    });
+                let info_version = match request.info_version {
+                    browse::ServerInfoVersion::V6Ex => browse::ServerInfoVersion::V6Ex,
+                    _ => browse::ServerInfoVersion::V6,
+                };
+                let info = browse::ServerInfo {
+                    info_version: info_version,
+                    token: request.token,
+                    version: VERSION.iter().cloned().collect(),
+                    name: b"Rust Teeworlds Server".iter().cloned().collect(),
+                    hostname: None,
+                    map: b"dm1".iter().cloned().collect(),
+                    map_crc: None,
+                    map_size: None,
+                    game_type: b"DM".iter().cloned().collect(),
                     flags: connless::INFO_FLAG_PASSWORD,
+                    progression: None,
+                    skill_level: None,
                     num_players: self.server.players.len().assert_i32(),
                     max_players: MAX_CLIENTS,
                     num_clients: self.server.peers.len().assert_i32(),
                     max_clients: MAX_CLIENTS,
-                    clients: msg::ClientsData::from_bytes(&clients_buf),
-                });
+                    clients: clients,
+                };
+                let packets = match info.encode() {
+                    Ok(p) => p,
+                    Err(e) => {
+                        warn!("couldn't encode server info: {:?}", e);
+                        return;
+                    }
+                };
+                for packet in packets {
+                    // The network layer adds the connless packet header.
+                    self.loop_.send_connless(addr, &packet[CONNLESS_HEADER_LEN..]);
+                }
             },
             _ => {},
         }
@@ -751,5 +756,29 @@ impl<'a, L: Loop> ServerLoop<'a, L> {
 
 fn main() {
     logger::init();
-    Server::run::<SocketLoop>();
+
+    let matches = App::new("Teeworlds server")
+        .arg(Arg::with_name("master")
+            .help("Registers with MASTER instead of the default master servers")
+            .long("master")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("MASTER")
+        )
+        .arg(Arg::with_name("no-register")
+            .help("Doesn't register with any master server")
+            .long("no-register")
+            .conflicts_with("master")
+        )
+        .get_matches();
+
+    let masters: Vec<String> = if matches.is_present("no-register") {
+        Vec::new()
+    } else {
+        matches.values_of("master")
+            .map(|m| m.map(str::to_owned).collect())
+            .unwrap_or_else(|| register::MASTERS.iter().map(|&m| m.to_owned()).collect())
+    };
+    Server::run::<SocketLoop>(&masters);
 }
@@ -0,0 +1,361 @@
+//! Registration of the server with the master servers.
+//!
+//! The server sends heartbeats to each master server. The master answers by
+//! checking whether the server is reachable from the outside: it sends a
+//! forward check, which the server answers, and then confirms the
+//! registration with a forward ok or reports a failure with a forward error.
+//! Heartbeats are repeated periodically so the masters don't forget about the
+//! server.
+
+use event_loop::Addr;
+use event_loop::Loop;
+use event_loop::LoopExt;
+use event_loop::Timeout;
+use event_loop::Timestamp;
+use gamenet::msg::Connless;
+use gamenet::msg::connless;
+use serverbrowse::protocol::MASTERSERVER_PORT;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::TryRecvError;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Master servers the server registers with by default.
+pub const MASTERS: &'static [&'static str] = &[
+    "master1.teeworlds.com",
+    "master2.teeworlds.com",
+    "master3.teeworlds.com",
+    "master4.teeworlds.com",
+];
+
+/// Time between two heartbeats to a master the server is registered with.
+const HEARTBEAT_INTERVAL_SECS: u64 = 30;
+/// Time to wait for the master to confirm or reject the registration.
+const REGISTER_TIMEOUT_SECS: u64 = 10;
+/// Time to wait before retrying after resolving or registering failed.
+const RETRY_SECS: u64 = 60;
+/// Time between two checks whether a master has been resolved.
+const RESOLVE_POLL_MILLIS: u64 = 100;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    /// Nothing sent yet, or the last attempt failed.
+    Start,
+    /// Heartbeat sent, waiting for the forward check to complete.
+    Heartbeat,
+    Registered,
+}
+
+struct Master {
+    domain: String,
+    addr: Option<Addr>,
+    /// Result of the lookup running in the background, if any.
+    resolving: Option<Receiver<Option<Addr>>>,
+    state: State,
+    next: Timestamp,
+}
+
+#[derive(Default)]
+pub struct Register {
+    port: u16,
+    masters: Vec<Master>,
+}
+
+fn lookup_host(domain: &str) -> Option<Addr> {
+    match (domain, MASTERSERVER_PORT).to_socket_addrs() {
+        Ok(mut addrs) => {
+            let result = addrs.next().map(Addr::from);
+            if result.is_none() {
+                warn!("resolved master {}, no address found", domain);
+            }
+            result
+        },
+        Err(e) => {
+            warn!("error while resolving master {}: {}", domain, e);
+            None
+        },
+    }
+}
+
+/// Looks up the master on a background thread, IP addresses are returned
+/// immediately.
+fn resolve(domain: &str) -> Receiver<Option<Addr>> {
+    let (sender, receiver) = mpsc::channel();
+    if let Ok(ip) = domain.parse::<IpAddr>() {
+        sender.send(Some(Addr::from(SocketAddr::new(ip, MASTERSERVER_PORT)))).unwrap();
+        return receiver;
+    }
+    let domain = domain.to_owned();
+    thread::spawn(move || {
+        // The receiver is gone if the server stopped in the meantime.
+        let _ = sender.send(lookup_host(&domain));
+    });
+    receiver
+}
+
+impl Register {
+    /// Creates a registration with the given master servers for a server
+    /// listening on `port`.
+    pub fn new<S: AsRef<str>>(port: u16, masters: &[S]) -> Register {
+        Register {
+            port: port,
+            masters: masters.iter().map(|d| Master {
+                domain: d.as_ref().to_owned(),
+                addr: None,
+                resolving: None,
+                state: State::Start,
+                next: Timestamp::from_secs_since_epoch(0),
+            }).collect(),
+        }
+    }
+    pub fn needs_tick(&self) -> Timeout {
+        self.masters.iter().map(|m| m.next).min()
+            .map(Timeout::active)
+            .unwrap_or_else(Timeout::inactive)
+    }
+    /// Resolves the masters and sends heartbeats when they're due.
+    ///
+    /// The masters are resolved in the background, `tick` checks for the
+    /// result until it's there.
+    pub fn tick<L: Loop>(&mut self, loop_: &mut L) {
+        let now = loop_.time();
+        for master in &mut self.masters {
+            if master.next > now {
+                continue;
+            }
+            if master.state == State::Heartbeat {
+                warn!("no registration response from master {}", master.domain);
+                // Resolve again, the address might have changed.
+                master.addr = None;
+                master.state = State::Start;
+            }
+            if master.addr.is_none() {
+                let resolving = master.resolving.take()
+                    .unwrap_or_else(|| resolve(&master.domain));
+                master.addr = match resolving.try_recv() {
+                    Ok(addr) => addr,
+                    Err(TryRecvError::Empty) => {
+                        master.resolving = Some(resolving);
+                        master.next = now + Duration::from_millis(RESOLVE_POLL_MILLIS);
+                        continue;
+                    },
+                    Err(TryRecvError::Disconnected) => None,
+                };
+                match master.addr {
+                    Some(addr) => info!("resolved master {} to {}", master.domain, addr),
+                    None => {
+                        master.next = now + Duration::from_secs(RETRY_SECS);
+                        continue;
+                    },
+                }
+            }
+            let addr = master.addr.unwrap();
+            debug!("sending heartbeat to master {}", master.domain);
+            loop_.sendc(addr, connless::Heartbeat { alt_port: self.port });
+            if master.state == State::Registered {
+                master.next = now + Duration::from_secs(HEARTBEAT_INTERVAL_SECS);
+            } else {
+                master.state = State::Heartbeat;
+                master.next = now + Duration::from_secs(REGISTER_TIMEOUT_SECS);
+            }
+        }
+    }
+    /// Handles the registration messages from the master servers.
+    ///
+    /// Returns whether the message was processed.
+    pub fn on_connless_packet<L: Loop>(&mut self, loop_: &mut L, addr: Addr, msg: &Connless)
+        -> bool
+    {
+        // Only compare the IP address, masters might check the server from a
+        // different port.
+        let master = match self.masters.iter_mut().find(|m| m.addr.map(|a| a.ip) == Some(addr.ip)) {
+            Some(m) => m,
+            None => return false,
+        };
+        let now = loop_.time();
+        match *msg {
+            Connless::ForwardCheck(connless::ForwardCheck) => {
+                loop_.sendc(addr, connless::ForwardResponse);
+            },
+            Connless::ForwardOk(connless::ForwardOk) => {
+                if master.state != State::Registered {
+                    info!("registered with master {}", master.domain);
+                    master.state = State::Registered;
+                    master.next = now + Duration::from_secs(HEARTBEAT_INTERVAL_SECS);
+                }
+            },
+            Connless::ForwardError(connless::ForwardError) => {
+                warn!("master {} can't reach the server on port {}, \
+                       check your firewall and port forwarding",
+                      master.domain, self.port);
+                master.state = State::Start;
+                master.next = now + Duration::from_secs(RETRY_SECS);
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use event_loop::Addr;
+    use event_loop::Application;
+    use event_loop::Chunk;
+    use event_loop::Loop;
+    use event_loop::PeerId;
+    use event_loop::Timeout;
+    use event_loop::Timestamp;
+    use gamenet::msg::Connless;
+    use gamenet::msg::connless;
+    use packer::Unpacker;
+    use std::time::Duration;
+    use super::Register;
+    use super::State;
+    use warn::Panic;
+
+    /// Records the connless messages sent through it. Registering doesn't
+    /// use connections, so the other methods are never called.
+    struct TestLoop {
+        time: Timestamp,
+        sent: Vec<(Addr, Vec<u8>)>,
+    }
+
+    impl Loop for TestLoop {
+        fn accept_connections_on_port(_: u16) -> TestLoop { unreachable!() }
+        fn client() -> TestLoop { unreachable!() }
+        fn run<A: Application<TestLoop>>(self, _: A) { unreachable!() }
+        fn time(&mut self) -> Timestamp { self.time }
+        fn connect(&mut self, _: Addr) -> PeerId { unreachable!() }
+        fn disconnect(&mut self, _: PeerId, _: &[u8]) { unreachable!() }
+        fn send_connless(&mut self, addr: Addr, data: &[u8]) {
+            self.sent.push((addr, data.to_owned()));
+        }
+        fn send(&mut self, _: Chunk) { unreachable!() }
+        fn force_flush(&mut self, _: PeerId) { unreachable!() }
+        fn flush(&mut self, _: PeerId) { unreachable!() }
+        fn ignore(&mut self, _: PeerId) { unreachable!() }
+        fn accept(&mut self, _: PeerId) { unreachable!() }
+        fn reject(&mut self, _: PeerId, _: &[u8]) { unreachable!() }
+    }
+
+    impl TestLoop {
+        fn new() -> TestLoop {
+            TestLoop {
+                time: Timestamp::from_secs_since_epoch(1000),
+                sent: Vec::new(),
+            }
+        }
+        fn advance(&mut self, secs: u64) {
+            self.time = self.time + Duration::from_secs(secs);
+        }
+        /// Checks that exactly one message was sent to `to` and that it
+        /// satisfies `check`.
+        fn expect<F: FnOnce(Connless) -> bool>(&mut self, to: Addr, check: F) {
+            assert_eq!(self.sent.len(), 1);
+            let (addr, data) = self.sent.pop().unwrap();
+            assert_eq!(addr, to);
+            let msg = Connless::decode(&mut Panic, &mut Unpacker::new(&data)).unwrap();
+            assert!(check(msg));
+        }
+    }
+
+    fn master() -> Addr {
+        "127.0.0.2:8300".parse().unwrap()
+    }
+
+    fn from_master() -> Addr {
+        "127.0.0.2:12345".parse().unwrap()
+    }
+
+    fn heartbeat(msg: Connless) -> bool {
+        matches!(msg, Connless::Heartbeat(connless::Heartbeat { alt_port: 8303 }))
+    }
+
+    fn register(loop_: &mut TestLoop) -> Register {
+        let mut register = Register::new(8303, &["127.0.0.2"]);
+        register.tick(loop_);
+        loop_.expect(master(), heartbeat);
+        assert_eq!(register.masters[0].state, State::Heartbeat);
+        register
+    }
+
+    #[test]
+    fn registration() {
+        let mut loop_ = TestLoop::new();
+        let mut register = register(&mut loop_);
+
+        // The forward check might come from a different port.
+        let msg = Connless::ForwardCheck(connless::ForwardCheck);
+        assert!(register.on_connless_packet(&mut loop_, from_master(), &msg));
+        loop_.expect(from_master(), |m| matches!(m, Connless::ForwardResponse(..)));
+
+        let msg = Connless::ForwardOk(connless::ForwardOk);
+        assert!(register.on_connless_packet(&mut loop_, from_master(), &msg));
+        assert!(loop_.sent.is_empty());
+        assert_eq!(register.masters[0].state, State::Registered);
+        let next = loop_.time + Duration::from_secs(30);
+        assert_eq!(register.needs_tick(), Timeout::active(next));
+
+        // Heartbeats continue once registered.
+        loop_.advance(29);
+        register.tick(&mut loop_);
+        assert!(loop_.sent.is_empty());
+        loop_.advance(1);
+        register.tick(&mut loop_);
+        loop_.expect(master(), heartbeat);
+        assert_eq!(register.masters[0].state, State::Registered);
+    }
+
+    #[test]
+    fn forward_error() {
+        let mut loop_ = TestLoop::new();
+        let mut register = register(&mut loop_);
+
+        let msg = Connless::ForwardError(connless::ForwardError);
+        assert!(register.on_connless_packet(&mut loop_, master(), &msg));
+        assert_eq!(register.masters[0].state, State::Start);
+        let next = loop_.time + Duration::from_secs(60);
+        assert_eq!(register.needs_tick(), Timeout::active(next));
+
+        loop_.advance(60);
+        register.tick(&mut loop_);
+        loop_.expect(master(), heartbeat);
+        assert_eq!(register.masters[0].state, State::Heartbeat);
+    }
+
+    #[test]
+    fn no_response() {
+        let mut loop_ = TestLoop::new();
+        let mut register = register(&mut loop_);
+
+        loop_.advance(9);
+        register.tick(&mut loop_);
+        assert!(loop_.sent.is_empty());
+
+        // The master is resolved again and gets another heartbeat.
+        loop_.advance(1);
+        register.tick(&mut loop_);
+        loop_.expect(master(), heartbeat);
+        assert_eq!(register.masters[0].state, State::Heartbeat);
+    }
+
+    #[test]
+    fn other_senders() {
+        let mut loop_ = TestLoop::new();
+        let mut register = register(&mut loop_);
+
+        let msg = Connless::ForwardOk(connless::ForwardOk);
+        let other = "127.0.0.3:8300".parse().unwrap();
+        assert!(!register.on_connless_packet(&mut loop_, other, &msg));
+        assert_eq!(register.masters[0].state, State::Heartbeat);
+
+        let msg = Connless::RequestList(connless::RequestList);
+        assert!(!register.on_connless_packet(&mut loop_, master(), &msg));
+        assert!(loop_.sent.is_empty());
+    }
+}
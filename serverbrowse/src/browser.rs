@@ -0,0 +1,731 @@
+//! Blocking server browser.
+//!
+//! Queries master servers for their server lists and every listed server for
+//! its info, all at the same time. Servers are first asked with the extended
+//! info request, falling back to the vanilla 0.6 requests if they don't
+//! answer it.
+
+use protocol::CountResponse;
+use protocol::IpAddr;
+use protocol::List6Response;
+use protocol::PartialServerInfo;
+use protocol::PongResponse;
+use protocol::Response;
+use protocol::ServerInfo;
+use protocol::ServerInfoVersion;
+use protocol;
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::net::UdpSocket;
+use std::net;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const BUFSIZE: usize = 2048;
+
+/// Time after which servers that didn't reply to the extended info request
+/// are asked with the vanilla 0.6 info requests.
+pub const FALLBACK_MS: u64 = 500;
+
+/// Interval in which the receiving threads check whether they should shut
+/// down.
+const RECEIVE_TIMEOUT_MS: u64 = 100;
+
+/// Packet received by one of the receiving threads.
+struct Packet {
+    time: Instant,
+    from: SocketAddr,
+    data: Vec<u8>,
+}
+
+fn spawn_receiver(socket: UdpSocket, sender: mpsc::Sender<Packet>, shutdown: Arc<AtomicBool>)
+    -> thread::JoinHandle<()>
+{
+    thread::spawn(move || {
+        let mut buf = [0; BUFSIZE];
+        while !shutdown.load(Ordering::Relaxed) {
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(e) => {
+                    // E.g. ICMP port unreachable messages on Windows.
+                    debug!("error while receiving: {}", e);
+                    continue;
+                },
+            };
+            let packet = Packet {
+                time: Instant::now(),
+                from: from,
+                data: buf[..len].to_vec(),
+            };
+            if sender.send(packet).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+/// UDP sockets for both address families, bound on first use.
+struct Network {
+    v4: Option<Result<UdpSocket, ()>>,
+    v6: Option<Result<UdpSocket, ()>>,
+    sender: mpsc::Sender<Packet>,
+    /// Tells the receiving threads to stop.
+    shutdown: Arc<AtomicBool>,
+    receivers: Vec<thread::JoinHandle<()>>,
+}
+
+impl Network {
+    fn new(sender: mpsc::Sender<Packet>) -> Network {
+        Network {
+            v4: None,
+            v6: None,
+            sender: sender,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            receivers: Vec::new(),
+        }
+    }
+    fn bind(bindaddr: &str, sender: &mpsc::Sender<Packet>, shutdown: &Arc<AtomicBool>)
+        -> io::Result<(UdpSocket, thread::JoinHandle<()>)>
+    {
+        let socket = UdpSocket::bind(bindaddr)?;
+        let receiving = socket.try_clone()?;
+        receiving.set_read_timeout(Some(Duration::from_millis(RECEIVE_TIMEOUT_MS)))?;
+        let receiver = spawn_receiver(receiving, sender.clone(), shutdown.clone());
+        Ok((socket, receiver))
+    }
+    fn send_to(&mut self, data: &[u8], addr: SocketAddr) {
+        let (socket, bindaddr) = match addr {
+            SocketAddr::V4(..) => (&mut self.v4, "0.0.0.0:0"),
+            SocketAddr::V6(..) => (&mut self.v6, "[::]:0"),
+        };
+        if socket.is_none() {
+            let receivers = &mut self.receivers;
+            *socket = Some(Network::bind(bindaddr, &self.sender, &self.shutdown).map(|(s, r)| {
+                receivers.push(r);
+                s
+            }).map_err(|e| {
+                error!("couldn't bind socket to {}: {}", bindaddr, e);
+            }));
+        }
+        if let Some(Ok(ref s)) = *socket {
+            if let Err(e) = s.send_to(data, addr) {
+                debug!("failed to send packet to {}: {}", addr, e);
+            }
+        }
+    }
+}
+
+impl Drop for Network {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        for receiver in self.receivers.drain(..) {
+            if receiver.join().is_err() {
+                error!("receiving thread panicked");
+            }
+        }
+    }
+}
+
+fn to_socket_addr(addr: protocol::Addr) -> SocketAddr {
+    match addr.ip_address {
+        IpAddr::V4(ip) => SocketAddr::new(net::IpAddr::V4(ip), addr.port),
+        IpAddr::V6(ip) => SocketAddr::new(net::IpAddr::V6(ip), addr.port),
+    }
+}
+
+struct Master {
+    addr: SocketAddr,
+    /// Number of servers the master announced, if it replied to the count
+    /// request.
+    count: Option<u16>,
+    num_received: usize,
+    last_activity: Instant,
+}
+
+impl Master {
+    fn is_done(&self) -> bool {
+        self.count.map(|c| self.num_received >= c as usize).unwrap_or(false)
+    }
+}
+
+/// A server queried by the `Browser`.
+pub struct Server {
+    addr: SocketAddr,
+    /// Token of the extended info request, the vanilla requests use its
+    /// lowest byte.
+    token: u32,
+    /// Time the extended info request was sent.
+    sent: Instant,
+    /// Time the vanilla info requests were sent, if they were.
+    fallback_sent: Option<Instant>,
+    /// Whether the server replied to any request.
+    responded: bool,
+    ping: Option<Duration>,
+    /// Vanilla info, lists at most 16 clients.
+    info_6: Option<ServerInfo>,
+    info_6_64: Option<PartialServerInfo>,
+    info_6_ex: Option<PartialServerInfo>,
+    /// The complete server info, once received.
+    info: Option<ServerInfo>,
+}
+
+impl Server {
+    fn new(addr: SocketAddr, token: u32, now: Instant) -> Server {
+        Server {
+            addr: addr,
+            token: token,
+            sent: now,
+            fallback_sent: None,
+            responded: false,
+            ping: None,
+            info_6: None,
+            info_6_64: None,
+            info_6_ex: None,
+            info: None,
+        }
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    /// Returns the time between the first request and its reply, if the
+    /// server replied.
+    pub fn ping(&self) -> Option<Duration> {
+        self.ping
+    }
+    /// Returns the complete server info, if it was received.
+    pub fn info(&self) -> Option<&ServerInfo> {
+        self.info.as_ref()
+    }
+    /// Returns the best info received from the server, which might lack some
+    /// clients if it didn't reply completely.
+    pub fn best_info(&self) -> Option<ServerInfo> {
+        self.info.clone()
+            .or_else(|| self.info_6.clone())
+            .or_else(|| self.info_6_ex.as_ref().map(|p| p.partial_info().clone()))
+            .or_else(|| self.info_6_64.as_ref().map(|p| p.partial_info().clone()))
+    }
+    fn record_ping(&mut self, request_time: Instant, time: Instant) {
+        self.responded = true;
+        if self.ping.is_none() {
+            self.ping = Some(time.duration_since(request_time));
+        }
+    }
+    fn complete_info(&mut self) -> Option<ServerInfo> {
+        if let Some(i) = self.info_6_ex.as_mut().and_then(|p| p.get_info()) {
+            return Some(i.clone());
+        }
+        if let Some(i) = self.info_6_64.as_mut().and_then(|p| p.get_info()) {
+            return Some(i.clone());
+        }
+        match self.info_6 {
+            Some(ref i) if i.is_complete_vanilla() => Some(i.clone()),
+            _ => None,
+        }
+    }
+    fn on_packet(&mut self, time: Instant, data: &[u8]) {
+        if self.info.is_some() {
+            return;
+        }
+        let partial = match protocol::parse_response(data) {
+            Some(Response::Pong(PongResponse(token))) => {
+                if token == self.token as i32 {
+                    let sent = self.sent;
+                    self.record_ping(sent, time);
+                } else {
+                    debug!("ignoring pong with wrong token from {}", self.addr);
+                }
+                return;
+            },
+            Some(Response::Info6(r)) => {
+                match r.parse() {
+                    Some(ref i) if i.token != (self.token & 0xff) as i32 => {
+                        debug!("ignoring info with wrong token from {}", self.addr);
+                    },
+                    Some(i) => {
+                        // Vanilla servers also answer the extended request
+                        // this way.
+                        let sent = self.fallback_sent.unwrap_or(self.sent);
+                        self.record_ping(sent, time);
+                        self.info_6 = Some(i);
+                    },
+                    None => debug!("invalid info from {}", self.addr),
+                }
+                self.info = self.complete_info();
+                return;
+            },
+            Some(Response::Info664(r)) => r.parse(),
+            Some(Response::Info6Ex(r)) => r.parse(),
+            Some(Response::Info6ExMore(r)) => r.parse(),
+            _ => {
+                debug!("ignoring unexpected packet from {}", self.addr);
+                return;
+            },
+        };
+        let partial = match partial {
+            Some(p) => p,
+            None => {
+                debug!("invalid info from {}", self.addr);
+                return;
+            },
+        };
+        let (token, request_time) = match partial.partial_info().info_version {
+            ServerInfoVersion::V6Ex => (self.token as i32, self.sent),
+            _ => ((self.token & 0xff) as i32, self.fallback_sent.unwrap_or(self.sent)),
+        };
+        if partial.partial_info().token != token {
+            debug!("ignoring info with wrong token from {}", self.addr);
+            return;
+        }
+        self.record_ping(request_time, time);
+        let existing = match partial.partial_info().info_version {
+            ServerInfoVersion::V6Ex => &mut self.info_6_ex,
+            _ => &mut self.info_6_64,
+        };
+        match *existing {
+            Some(ref mut e) => if let Err(e) = e.merge(partial) {
+                debug!("failed to merge info from {}: {:?}", self.addr, e);
+            },
+            None => *existing = Some(partial),
+        }
+        self.info = self.complete_info();
+    }
+}
+
+/// Queries master servers for their server lists and every listed server
+/// for its info.
+pub struct Browser {
+    network: Network,
+    receiver: mpsc::Receiver<Packet>,
+    timeout: Duration,
+    next_token: u32,
+    masters: Vec<Master>,
+    master_indices: HashMap<SocketAddr, usize>,
+    servers: Vec<Server>,
+    server_indices: HashMap<SocketAddr, usize>,
+}
+
+impl Browser {
+    /// Creates a browser that waits `timeout` for replies to each request.
+    pub fn new(timeout: Duration) -> Browser {
+        let (sender, receiver) = mpsc::channel();
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        Browser {
+            network: Network::new(sender),
+            receiver: receiver,
+            timeout: timeout,
+            next_token: seed,
+            masters: Vec::new(),
+            master_indices: HashMap::new(),
+            servers: Vec::new(),
+            server_indices: HashMap::new(),
+        }
+    }
+    /// Requests the server list of the master server, the listed servers are
+    /// queried as they arrive.
+    pub fn add_master(&mut self, addr: SocketAddr) {
+        if self.master_indices.contains_key(&addr) {
+            return;
+        }
+        self.network.send_to(&protocol::request_count(), addr);
+        self.network.send_to(&protocol::request_list_6(), addr);
+        self.master_indices.insert(addr, self.masters.len());
+        self.masters.push(Master {
+            addr: addr,
+            count: None,
+            num_received: 0,
+            last_activity: Instant::now(),
+        });
+    }
+    /// Requests the info of the server.
+    pub fn add_server(&mut self, addr: SocketAddr) {
+        if self.server_indices.contains_key(&addr) {
+            return;
+        }
+        let token = self.next_token & 0x00ff_ffff;
+        self.next_token = self.next_token.wrapping_add(1);
+        self.network.send_to(&protocol::request_info_ex(token, true), addr);
+        self.server_indices.insert(addr, self.servers.len());
+        self.servers.push(Server::new(addr, token, Instant::now()));
+    }
+    /// Returns the servers added so far, in the order they were added.
+    pub fn servers(&self) -> &[Server] {
+        &self.servers
+    }
+    fn on_master_packet(&mut self, master: usize, data: &[u8]) {
+        let addr = self.masters[master].addr;
+        match protocol::parse_response(data) {
+            Some(Response::Count(CountResponse(count))) => {
+                self.masters[master].count = Some(count);
+            },
+            Some(Response::List6(List6Response(list))) => {
+                self.masters[master].num_received += list.len();
+                for &server in list {
+                    self.add_server(to_socket_addr(server.unpack()));
+                }
+            },
+            _ => debug!("ignoring unexpected packet from master {}", addr),
+        }
+    }
+    fn on_packet(&mut self, packet: Packet) {
+        if let Some(&i) = self.master_indices.get(&packet.from) {
+            self.masters[i].last_activity = packet.time;
+            self.on_master_packet(i, &packet.data);
+        } else if let Some(&i) = self.server_indices.get(&packet.from) {
+            self.servers[i].on_packet(packet.time, &packet.data);
+        } else {
+            debug!("ignoring packet from unknown address {}", packet.from);
+        }
+    }
+    /// Sends the vanilla requests to servers that need them and returns the
+    /// time until which to wait for further packets, or `None` if all
+    /// masters and servers are done.
+    fn tick(&mut self, now: Instant) -> Option<Instant> {
+        let fallback = Duration::from_millis(FALLBACK_MS);
+        let mut next = None;
+        {
+            let mut wait_until = |time: Instant| {
+                next = Some(next.map(|n| cmp::min(n, time)).unwrap_or(time));
+            };
+            for master in &self.masters {
+                let deadline = master.last_activity + self.timeout;
+                if !master.is_done() && now < deadline {
+                    wait_until(deadline);
+                }
+            }
+            for server in &mut self.servers {
+                if server.info.is_some() {
+                    continue;
+                }
+                if server.fallback_sent.is_none() {
+                    let retry = server.sent + fallback;
+                    // A vanilla info without a complete one means that the
+                    // server doesn't support the extended info and might
+                    // have more than 16 clients, ask for the 64 player info.
+                    let truncated = server.info_6.is_some();
+                    if truncated || !server.responded && now >= retry {
+                        let token = (server.token & 0xff) as u8;
+                        self.network.send_to(&protocol::request_info_6(token), server.addr);
+                        self.network.send_to(&protocol::request_info_6_64(token), server.addr);
+                        server.fallback_sent = Some(now);
+                    } else if !server.responded {
+                        wait_until(retry);
+                        continue;
+                    }
+                }
+                let deadline = server.fallback_sent.unwrap_or(server.sent) + self.timeout;
+                if now < deadline {
+                    wait_until(deadline);
+                }
+            }
+        }
+        next
+    }
+    /// Receives replies until all masters and servers replied completely or
+    /// timed out.
+    pub fn run(&mut self) {
+        loop {
+            let now = Instant::now();
+            let next = match self.tick(now) {
+                Some(n) => n,
+                None => break,
+            };
+            match self.receiver.recv_timeout(next - now) {
+                Ok(packet) => self.on_packet(packet),
+                Err(mpsc::RecvTimeoutError::Timeout) => {},
+                Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Selects servers by their info.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Only match servers whose game type contains this, ignoring case.
+    pub game_type: Option<String>,
+    /// Only match servers whose map name contains this, ignoring case.
+    pub map: Option<String>,
+    /// Only match servers with a client whose name contains this, ignoring
+    /// case.
+    pub player: Option<String>,
+    /// Only match servers with at least one client.
+    pub non_empty: bool,
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    String::from_utf8_lossy(haystack).to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl Filter {
+    pub fn matches(&self, info: &ServerInfo) -> bool {
+        self.game_type.as_ref().map(|g| contains(&info.game_type, g)).unwrap_or(true)
+            && self.map.as_ref().map(|m| contains(&info.map, m)).unwrap_or(true)
+            && self.player.as_ref()
+                .map(|p| info.clients.iter().any(|c| contains(&c.name, p)))
+                .unwrap_or(true)
+            && (!self.non_empty || info.num_clients > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use protocol::ClientInfo;
+    use protocol::PONG;
+    use protocol::ServerInfo;
+    use protocol::ServerInfoVersion;
+    use protocol::parse_info_request;
+    use std::net::UdpSocket;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use std::time::Instant;
+    use super::Browser;
+    use super::FALLBACK_MS;
+    use super::Filter;
+    use super::Server;
+
+    const TOKEN: u32 = 0x12_3456;
+
+    fn info(version: ServerInfoVersion, token: u32, num_clients: usize) -> ServerInfo {
+        let clients: Vec<_> = (0..num_clients).map(|i| ClientInfo {
+            name: format!("long player {:03}", i).bytes().collect(),
+            clan: b"Clan".iter().cloned().collect(),
+            country: -1,
+            score: i as i32,
+            is_player: 1,
+        }).collect();
+        let max_clients = version.max_clients().map(|m| m as i32).unwrap_or(64);
+        let mut info = ServerInfo {
+            info_version: version,
+            token: token as i32,
+            version: b"0.6.4".iter().cloned().collect(),
+            name: b"test server".iter().cloned().collect(),
+            map: b"ctf5".iter().cloned().collect(),
+            game_type: b"CTF".iter().cloned().collect(),
+            num_players: clients.len() as i32,
+            max_players: max_clients,
+            num_clients: clients.len() as i32,
+            max_clients: max_clients,
+            clients: clients,
+            ..Default::default()
+        };
+        if version == ServerInfoVersion::V6Ex {
+            info.map_crc = Some(0x1234_5678);
+            info.map_size = Some(5805);
+        }
+        info.sort_clients();
+        info
+    }
+
+    fn packets(info: &ServerInfo) -> Vec<Vec<u8>> {
+        info.encode().unwrap()
+    }
+
+    fn server(now: Instant) -> Server {
+        Server::new("127.0.0.1:8303".parse().unwrap(), TOKEN, now)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn pong() {
+        let now = Instant::now();
+        let mut server = server(now);
+        server.on_packet(now + ms(10), &[&PONG[..], b"1\0"].concat());
+        assert!(server.ping().is_none());
+        server.on_packet(now + ms(20), &[&PONG[..], TOKEN.to_string().as_bytes(), b"\0"].concat());
+        assert_eq!(server.ping(), Some(ms(20)));
+        // Only the first reply counts.
+        server.on_packet(now + ms(30), &[&PONG[..], TOKEN.to_string().as_bytes(), b"\0"].concat());
+        assert_eq!(server.ping(), Some(ms(20)));
+    }
+
+    #[test]
+    fn wrong_token() {
+        let now = Instant::now();
+        let mut server = server(now);
+        // The extended info must carry the full token, the vanilla ones
+        // its lowest byte.
+        let ex = info(ServerInfoVersion::V6Ex, TOKEN & 0xff, 3);
+        let vanilla = info(ServerInfoVersion::V6, TOKEN, 3);
+        let info_6_64 = info(ServerInfoVersion::V664, TOKEN + 1, 3);
+        for packet in packets(&ex).iter().chain(&packets(&vanilla)).chain(&packets(&info_6_64)) {
+            server.on_packet(now + ms(10), packet);
+        }
+        assert!(server.best_info().is_none());
+        assert!(server.ping().is_none());
+    }
+
+    #[test]
+    fn unexpected_version() {
+        let now = Instant::now();
+        let mut server = server(now);
+        for packet in packets(&info(ServerInfoVersion::V5, TOKEN & 0xff, 3)) {
+            server.on_packet(now + ms(10), &packet);
+        }
+        assert!(server.best_info().is_none());
+        assert!(!server.responded);
+    }
+
+    #[test]
+    fn extended() {
+        let now = Instant::now();
+        let mut server = server(now);
+        let ex = info(ServerInfoVersion::V6Ex, TOKEN, 64);
+        let packets = packets(&ex);
+        assert!(packets.len() > 1);
+        for packet in &packets[..packets.len() - 1] {
+            server.on_packet(now + ms(10), packet);
+        }
+        assert!(server.info().is_none());
+        assert_eq!(server.best_info().unwrap().info_version, ServerInfoVersion::V6Ex);
+        server.on_packet(now + ms(20), packets.last().unwrap());
+        assert!(server.info() == Some(&ex));
+        assert_eq!(server.ping(), Some(ms(10)));
+    }
+
+    #[test]
+    fn complete_info() {
+        let now = Instant::now();
+        let token = TOKEN & 0xff;
+
+        let mut small = server(now);
+        let vanilla = info(ServerInfoVersion::V6, token, 3);
+        small.on_packet(now, &packets(&vanilla)[0]);
+        assert!(small.info() == Some(&vanilla));
+
+        // DDNet servers cap the vanilla info at 15 or 16 clients.
+        let mut big = server(now);
+        big.on_packet(now, &packets(&info(ServerInfoVersion::V6, token, 15))[0]);
+        assert!(big.info().is_none());
+        assert_eq!(big.best_info().unwrap().clients.len(), 15);
+
+        let info_6_64 = info(ServerInfoVersion::V664, token, 40);
+        for packet in packets(&info_6_64) {
+            big.on_packet(now, &packet);
+        }
+        assert!(big.info() == Some(&info_6_64));
+    }
+
+    fn browser(timeout: Duration) -> (Browser, UdpSocket, Instant) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut browser = Browser::new(timeout);
+        browser.add_server(socket.local_addr().unwrap());
+        let sent = browser.servers[0].sent;
+        (browser, socket, sent)
+    }
+
+    fn receive_request(socket: &UdpSocket) -> ServerInfoVersion {
+        let mut buf = [0; 2048];
+        let (len, _) = socket.recv_from(&mut buf).unwrap();
+        parse_info_request(&buf[..len]).unwrap().info_version
+    }
+
+    #[test]
+    fn fallback() {
+        let timeout = ms(2000);
+        let fallback = ms(FALLBACK_MS);
+        let (mut browser, socket, sent) = browser(timeout);
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V6Ex);
+
+        // Wait for the extended info first.
+        assert_eq!(browser.tick(sent), Some(sent + fallback));
+        assert!(browser.servers[0].fallback_sent.is_none());
+
+        // Then ask with the vanilla requests and wait for those.
+        let now = sent + fallback;
+        assert_eq!(browser.tick(now), Some(now + timeout));
+        assert_eq!(browser.servers[0].fallback_sent, Some(now));
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V6);
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V664);
+
+        assert_eq!(browser.tick(now + timeout), None);
+    }
+
+    #[test]
+    fn no_fallback_after_reply() {
+        let timeout = ms(2000);
+        let (mut browser, _socket, sent) = browser(timeout);
+        let pong = [&PONG[..], browser.servers[0].token.to_string().as_bytes(), b"\0"].concat();
+        browser.servers[0].on_packet(sent + ms(10), &pong);
+
+        // The server supports the extended info, wait for it until the
+        // timeout.
+        assert_eq!(browser.tick(sent + ms(FALLBACK_MS)), Some(sent + timeout));
+        assert!(browser.servers[0].fallback_sent.is_none());
+        assert_eq!(browser.tick(sent + timeout), None);
+    }
+
+    #[test]
+    fn truncated_fallback() {
+        let timeout = ms(2000);
+        let (mut browser, socket, sent) = browser(timeout);
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V6Ex);
+        let token = browser.servers[0].token & 0xff;
+        let vanilla = info(ServerInfoVersion::V6, token, 15);
+        browser.servers[0].on_packet(sent + ms(10), &packets(&vanilla)[0]);
+
+        // A possibly truncated vanilla info is followed by the 64 player
+        // request right away.
+        let now = sent + ms(20);
+        assert_eq!(browser.tick(now), Some(now + timeout));
+        assert_eq!(browser.servers[0].fallback_sent, Some(now));
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V6);
+        assert_eq!(receive_request(&socket), ServerInfoVersion::V664);
+    }
+
+    #[test]
+    fn shutdown() {
+        let (browser, _socket, _) = browser(ms(2000));
+        assert_eq!(browser.network.receivers.len(), 1);
+        let shutdown = browser.network.shutdown.clone();
+        let start = Instant::now();
+        // Dropping the browser waits for the receiving thread, which is
+        // blocked without any packets arriving.
+        drop(browser);
+        assert!(shutdown.load(Ordering::Relaxed));
+        assert!(start.elapsed() < ms(1000));
+    }
+
+    #[test]
+    fn filter() {
+        let mut info = info(ServerInfoVersion::V6, 0, 2);
+        assert!(Filter::default().matches(&info));
+
+        let game_type = Filter { game_type: Some("ctf".to_owned()), ..Filter::default() };
+        let map = Filter { map: Some("TF5".to_owned()), ..Filter::default() };
+        let player = Filter { player: Some("Player 001".to_owned()), ..Filter::default() };
+        let non_empty = Filter { non_empty: true, ..Filter::default() };
+        assert!(game_type.matches(&info));
+        assert!(map.matches(&info));
+        assert!(player.matches(&info));
+        assert!(non_empty.matches(&info));
+
+        let all = Filter {
+            game_type: Some("DM".to_owned()),
+            ..player.clone()
+        };
+        assert!(!all.matches(&info));
+
+        info.clients.clear();
+        info.num_clients = 0;
+        assert!(!player.matches(&info));
+        assert!(!non_empty.matches(&info));
+        assert!(game_type.matches(&info));
+    }
+}
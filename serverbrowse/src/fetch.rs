@@ -0,0 +1,441 @@
+//! Sources for the server lists of HTTP master servers.
+//!
+//! `Fetch` abstracts over where the list comes from, so it can be read from a
+//! local file in tests or from an HTTP or HTTPS server. HTTPS needs the
+//! `https` feature.
+
+use json::Server;
+use json::parse_server_list;
+use json;
+#[cfg(feature = "https")] use native_tls::HandshakeError;
+#[cfg(feature = "https")] use native_tls::TlsConnector;
+#[cfg(feature = "https")] use native_tls;
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::io;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::path::PathBuf;
+use std::str;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    #[cfg(feature = "https")]
+    Tls(native_tls::Error),
+    /// HTTPS was requested, but the crate was built without the `https`
+    /// feature.
+    HttpsUnsupported,
+    /// The HTTP server answered with something other than `200 OK`.
+    Http(String),
+    /// The response was longer than `HttpFetch::max_size`.
+    TooLarge,
+    Utf8(str::Utf8Error),
+    List(json::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+#[cfg(feature = "https")]
+impl From<native_tls::Error> for Error {
+    fn from(err: native_tls::Error) -> Error {
+        Error::Tls(err)
+    }
+}
+
+#[cfg(feature = "https")]
+impl From<HandshakeError<TcpStream>> for Error {
+    fn from(err: HandshakeError<TcpStream>) -> Error {
+        match err {
+            HandshakeError::Failure(e) => Error::Tls(e),
+            // The stream is blocking, so this only happens on timeouts.
+            HandshakeError::WouldBlock(_) =>
+                Error::Io(io::Error::new(io::ErrorKind::TimedOut, "TLS handshake timed out")),
+        }
+    }
+}
+
+impl From<str::Utf8Error> for Error {
+    fn from(err: str::Utf8Error) -> Error {
+        Error::Utf8(err)
+    }
+}
+
+impl From<json::Error> for Error {
+    fn from(err: json::Error) -> Error {
+        Error::List(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            #[cfg(feature = "https")]
+            Error::Tls(ref e) => write!(f, "TLS error: {}", e),
+            Error::HttpsUnsupported => write!(f, "built without HTTPS support"),
+            Error::Http(ref status) => write!(f, "HTTP error: {}", status),
+            Error::TooLarge => write!(f, "server list is too large"),
+            Error::Utf8(ref e) => write!(f, "server list isn't UTF-8: {}", e),
+            Error::List(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Source of a server list.
+///
+/// Fetching might take a while, so it's `Send` to allow running it on a
+/// separate thread.
+pub trait Fetch: Send {
+    /// Returns a human-readable description of the source, for logging.
+    fn source(&self) -> String;
+    /// Fetches the raw server list. Might block.
+    fn fetch(&mut self) -> Result<Vec<u8>, Error>;
+    /// Fetches and parses the server list.
+    fn fetch_servers(&mut self) -> Result<Vec<Server>, Error> {
+        let data = try!(self.fetch());
+        Ok(try!(parse_server_list(try!(str::from_utf8(&data)))))
+    }
+}
+
+/// Reads the server list from a file.
+#[derive(Clone, Debug)]
+pub struct FileFetch {
+    pub path: PathBuf,
+}
+
+impl FileFetch {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileFetch {
+        FileFetch {
+            path: path.into(),
+        }
+    }
+}
+
+impl Fetch for FileFetch {
+    fn source(&self) -> String {
+        self.path.display().to_string()
+    }
+    fn fetch(&mut self) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        try!(try!(File::open(&self.path)).read_to_end(&mut result));
+        Ok(result)
+    }
+}
+
+/// Downloads the server list from an HTTP or HTTPS server.
+#[derive(Clone, Debug)]
+pub struct HttpFetch {
+    pub https: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    /// Time to wait for the connection to be established.
+    pub connect_timeout: Duration,
+    /// Time to wait for each read from and write to the server.
+    pub timeout: Duration,
+    /// Time the whole download may take, so that a server trickling data
+    /// can't stall the fetch.
+    pub deadline: Duration,
+    /// Maximum size of the response in bytes, including the headers.
+    pub max_size: u64,
+}
+
+impl HttpFetch {
+    /// Parses a URL like `https://master.example.org:8080/servers.json`.
+    ///
+    /// Returns `None` for URLs that aren't HTTP or HTTPS.
+    pub fn from_url(url: &str) -> Option<HttpFetch> {
+        let (scheme, rest) = match url.find("://") {
+            Some(i) => (&url[..i], &url[i+3..]),
+            None => return None,
+        };
+        let https = match scheme {
+            "http" => false,
+            "https" => true,
+            _ => return None,
+        };
+        let (host_port, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match host_port.rfind(':') {
+            Some(i) if !host_port.ends_with(']') => {
+                let port = match host_port[i+1..].parse() {
+                    Ok(p) => p,
+                    Err(_) => return None,
+                };
+                (&host_port[..i], port)
+            },
+            _ => (host_port, if https { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(HttpFetch {
+            https: https,
+            host: host.to_owned(),
+            port: port,
+            path: path.to_owned(),
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(10),
+            deadline: Duration::from_secs(60),
+            max_size: 16 * 1024 * 1024,
+        })
+    }
+    /// Returns the host without the brackets of IPv6 addresses.
+    fn bare_host(&self) -> &str {
+        if self.host.starts_with('[') && self.host.ends_with(']') {
+            &self.host[1..self.host.len()-1]
+        } else {
+            &self.host[..]
+        }
+    }
+    fn connect(&self) -> Result<TcpStream, Error> {
+        let mut error = None;
+        for addr in try!((self.bare_host(), self.port).to_socket_addrs()) {
+            match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+                Ok(stream) => {
+                    try!(stream.set_read_timeout(Some(self.timeout)));
+                    try!(stream.set_write_timeout(Some(self.timeout)));
+                    return Ok(stream);
+                },
+                Err(e) => error = Some(e),
+            }
+        }
+        Err(Error::Io(error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no address found")
+        })))
+    }
+    /// Sends the request over `stream` and reads the response, `socket`
+    /// being the underlying connection.
+    fn request<S: Read + Write>(&self, mut stream: S, socket: &TcpStream, start: Instant)
+        -> Result<Vec<u8>, Error>
+    {
+        // HTTP/1.0 makes the server close the connection after the response,
+        // so no chunked encoding needs to be handled.
+        try!(write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            self.path, self.host));
+        let mut response = Vec::new();
+        let mut stream = stream.take(self.max_size + 1);
+        let mut buf = [0; 4096];
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= self.deadline {
+                return Err(Error::Io(io::Error::new(io::ErrorKind::TimedOut, "download took too long")));
+            }
+            try!(socket.set_read_timeout(Some(cmp::min(self.timeout, self.deadline - elapsed))));
+            let read = match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            };
+            response.extend_from_slice(&buf[..read]);
+        }
+        if response.len() as u64 > self.max_size {
+            return Err(Error::TooLarge);
+        }
+        Ok(response)
+    }
+    #[cfg(feature = "https")]
+    fn request_https(&self, start: Instant) -> Result<Vec<u8>, Error> {
+        let stream = try!(self.connect());
+        let socket = try!(stream.try_clone());
+        let stream = try!(try!(TlsConnector::new()).connect(self.bare_host(), stream));
+        self.request(stream, &socket, start)
+    }
+    #[cfg(not(feature = "https"))]
+    fn request_https(&self, _: Instant) -> Result<Vec<u8>, Error> {
+        Err(Error::HttpsUnsupported)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+impl Fetch for HttpFetch {
+    fn source(&self) -> String {
+        let scheme = if self.https { "https" } else { "http" };
+        format!("{}://{}:{}{}", scheme, self.host, self.port, self.path)
+    }
+    fn fetch(&mut self) -> Result<Vec<u8>, Error> {
+        let start = Instant::now();
+        let response = if self.https {
+            try!(self.request_https(start))
+        } else {
+            let stream = try!(self.connect());
+            try!(self.request(&stream, &stream, start))
+        };
+
+        let header_end = try!(find(&response, b"\r\n\r\n")
+            .ok_or_else(|| Error::Http("incomplete response".to_owned())));
+        let status = response.split(|&b| b == b'\r').next().unwrap();
+        let status = String::from_utf8_lossy(status).into_owned();
+        if status.split(' ').nth(1) != Some("200") {
+            return Err(Error::Http(status));
+        }
+        Ok(response[header_end+4..].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::fs;
+    use std::io::Read;
+    use std::io::Write;
+    use std::io;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use super::Error;
+    use super::Fetch;
+    use super::FileFetch;
+    use super::HttpFetch;
+
+    const LIST: &'static str = r#"{"servers": [{
+        "addresses": ["tw-0.6+udp://192.0.2.1:8303"],
+        "info": {
+            "max_clients": 16, "max_players": 16, "passworded": false,
+            "game_type": "DM", "name": "test", "map": {"name": "dm1"},
+            "version": "0.6.4", "clients": []
+        }
+    }]}"#;
+
+    fn serve(response: String) -> HttpFetch {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let len = stream.read(&mut request).unwrap();
+            assert!(request[..len].starts_with(b"GET /servers.json HTTP/1.0\r\n"));
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        HttpFetch::from_url(&format!("http://127.0.0.1:{}/servers.json", port)).unwrap()
+    }
+
+    fn check(fetch: &mut Fetch) {
+        let servers = fetch.fetch_servers().unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(&servers[0].info.name[..], b"test");
+    }
+
+    #[test]
+    fn file() {
+        let path = env::temp_dir().join(format!("serverbrowse-fetch-{}.json", ::std::process::id()));
+        File::create(&path).unwrap().write_all(LIST.as_bytes()).unwrap();
+        check(&mut FileFetch::new(&path));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn from_url() {
+        let fetch = HttpFetch::from_url("http://master.example.org:8080/ddnet/servers.json").unwrap();
+        assert_eq!((&fetch.host[..], fetch.port, &fetch.path[..]), ("master.example.org", 8080, "/ddnet/servers.json"));
+        let fetch = HttpFetch::from_url("http://[::1]").unwrap();
+        assert_eq!((&fetch.host[..], fetch.port, &fetch.path[..]), ("[::1]", 80, "/"));
+        let fetch = HttpFetch::from_url("https://master.example.org/servers.json").unwrap();
+        assert_eq!((fetch.https, &fetch.host[..], fetch.port), (true, "master.example.org", 443));
+        assert_eq!(fetch.source(), "https://master.example.org:443/servers.json");
+        assert!(HttpFetch::from_url("ftp://master.example.org/servers.json").is_none());
+        assert!(HttpFetch::from_url("http://master.example.org:x/").is_none());
+    }
+
+    #[test]
+    fn http() {
+        check(&mut serve(format!("HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}", LIST)));
+    }
+
+    #[test]
+    fn http_error() {
+        let mut fetch = serve("HTTP/1.0 404 Not Found\r\n\r\n".to_owned());
+        match fetch.fetch() {
+            Err(Error::Http(status)) => assert_eq!(status, "HTTP/1.0 404 Not Found"),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        let mut fetch = serve(format!("HTTP/1.0 200 OK\r\n\r\n{}", LIST));
+        fetch.max_size = 100;
+        match fetch.fetch() {
+            Err(Error::TooLarge) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Each write comes well within the read timeout.
+            while stream.write_all(b"x").is_ok() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let mut fetch = HttpFetch::from_url(&format!("http://127.0.0.1:{}/servers.json", port)).unwrap();
+        fetch.deadline = Duration::from_millis(100);
+        match fetch.fetch() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock
+                || e.kind() == io::ErrorKind::TimedOut => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "https")]
+    #[test]
+    fn https_handshake_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"HTTP/1.0 400 Bad Request\r\n\r\n").unwrap();
+        });
+        let mut fetch = HttpFetch::from_url(&format!("https://127.0.0.1:{}/servers.json", port)).unwrap();
+        match fetch.fetch() {
+            Err(Error::Tls(_)) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[cfg(not(feature = "https"))]
+    #[test]
+    fn https_unsupported() {
+        let mut fetch = HttpFetch::from_url("https://127.0.0.1:1/servers.json").unwrap();
+        match fetch.fetch() {
+            Err(Error::HttpsUnsupported) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut fetch = HttpFetch::from_url(&format!("http://127.0.0.1:{}/servers.json", port)).unwrap();
+        fetch.timeout = Duration::from_millis(50);
+        // The connection is accepted by the OS, but never answered.
+        match fetch.fetch() {
+            Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock
+                || e.kind() == io::ErrorKind::TimedOut => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+        drop(listener);
+    }
+}
@@ -0,0 +1,264 @@
+//! Server lists in the JSON format published by HTTP master servers.
+//!
+//! The list looks like this:
+//!
+//! ```json
+//! {"servers": [{
+//!     "addresses": ["tw-0.6+udp://192.0.2.1:8303"],
+//!     "location": "eu:de",
+//!     "info": {
+//!         "name": "My server", "game_type": "DM", "version": "0.6.4",
+//!         "passworded": false, "max_clients": 16, "max_players": 16,
+//!         "map": {"name": "dm1", "size": 5805},
+//!         "clients": [{"name": "nameless tee", "clan": "", "country": -1,
+//!                      "score": 0, "is_player": true}]
+//!     }
+//! }]}
+//! ```
+
+use arrayvec::Array;
+use arrayvec::ArrayVec;
+use common::num::Cast;
+use protocol::Addr;
+use protocol::ClientInfo;
+use protocol::IpAddr;
+use protocol::ServerInfo;
+use protocol::ServerInfoVersion;
+use rustc_serialize::json::Json;
+use rustc_serialize::json::ParserError;
+use rustc_serialize::json;
+use std::fmt;
+use std::net::SocketAddr;
+
+#[derive(Debug)]
+pub enum Error {
+    Json(ParserError),
+    /// The JSON doesn't describe a server list. Contains a description of
+    /// the missing or malformed field.
+    Invalid(&'static str),
+}
+
+impl From<ParserError> for Error {
+    fn from(err: ParserError) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Json(ref e) => write!(f, "invalid JSON: {}", e),
+            Error::Invalid(field) => write!(f, "invalid server list: {}", field),
+        }
+    }
+}
+
+/// Network protocol spoken on a server address.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Protocol {
+    /// `tw-0.6+udp`.
+    V6,
+    /// `tw-0.7+udp`.
+    V7,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ServerAddr {
+    pub protocol: Protocol,
+    pub addr: Addr,
+}
+
+/// Parses a server address like `tw-0.6+udp://192.0.2.1:8303`.
+///
+/// Returns `None` for unknown protocols or malformed addresses.
+pub fn parse_addr(addr: &str) -> Option<ServerAddr> {
+    let mut split = addr.splitn(2, "://");
+    let protocol = match split.next() {
+        Some("tw-0.6+udp") => Protocol::V6,
+        Some("tw-0.7+udp") => Protocol::V7,
+        _ => return None,
+    };
+    let socket_addr: SocketAddr = match split.next().map(str::parse) {
+        Some(Ok(a)) => a,
+        _ => return None,
+    };
+    let ip_address = match socket_addr {
+        SocketAddr::V4(a) => IpAddr::V4(*a.ip()),
+        SocketAddr::V6(a) => IpAddr::V6(*a.ip()),
+    };
+    Some(ServerAddr {
+        protocol: protocol,
+        addr: Addr { ip_address: ip_address, port: socket_addr.port() },
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Server {
+    /// Addresses of the server, only the ones with known protocols.
+    pub addresses: Vec<ServerAddr>,
+    /// Location as announced by the master, e.g. `eu:de`.
+    pub location: Option<String>,
+    /// Server info, with `info_version` set to `ServerInfoVersion::V6Ex` as
+    /// the list isn't limited in the number of clients.
+    pub info: ServerInfo,
+}
+
+fn field<'a>(object: &'a Json, key: &str) -> Option<&'a Json> {
+    object.as_object().and_then(|o| o.get(key))
+}
+
+fn string<A: Array<Item=u8>>(object: &Json, key: &'static str) -> Result<ArrayVec<A>, Error> {
+    let s = field(object, key).and_then(Json::as_string).ok_or(Error::Invalid(key))?;
+    // Overlong strings are truncated like the game does.
+    Ok(s.bytes().take(A::capacity()).collect())
+}
+
+fn int(object: &Json, key: &'static str) -> Result<i32, Error> {
+    field(object, key).and_then(Json::as_i64).and_then(|i| i.try_i32())
+        .ok_or(Error::Invalid(key))
+}
+
+fn bool_(object: &Json, key: &'static str) -> Result<bool, Error> {
+    field(object, key).and_then(Json::as_boolean).ok_or(Error::Invalid(key))
+}
+
+fn parse_client(client: &Json) -> Result<ClientInfo, Error> {
+    Ok(ClientInfo {
+        name: string(client, "name")?,
+        clan: string(client, "clan")?,
+        country: int(client, "country")?,
+        score: int(client, "score")?,
+        is_player: bool_(client, "is_player")? as i32,
+    })
+}
+
+fn parse_info(info: &Json) -> Result<ServerInfo, Error> {
+    let map = field(info, "map").ok_or(Error::Invalid("map"))?;
+    let map_size = field(map, "size").and_then(Json::as_u64).and_then(|s| s.try_u32());
+    let clients = field(info, "clients").and_then(Json::as_array)
+        .ok_or(Error::Invalid("clients"))?
+        .iter().map(parse_client).collect::<Result<Vec<_>, _>>()?;
+    let num_players = clients.iter().filter(|c| c.is_player != 0).count();
+    Ok(ServerInfo {
+        info_version: ServerInfoVersion::V6Ex,
+        token: -1,
+        version: string(info, "version")?,
+        name: string(info, "name")?,
+        hostname: None,
+        map: string(map, "name")?,
+        map_crc: None,
+        map_size: map_size,
+        game_type: string(info, "game_type")?,
+        flags: bool_(info, "passworded")? as i32,
+        progression: None,
+        skill_level: None,
+        num_players: num_players.assert_i32(),
+        max_players: int(info, "max_players")?,
+        num_clients: clients.len().assert_i32(),
+        max_clients: int(info, "max_clients")?,
+        clients: clients,
+    })
+}
+
+fn parse_server(server: &Json) -> Result<Server, Error> {
+    let addresses = field(server, "addresses").and_then(Json::as_array)
+        .ok_or(Error::Invalid("addresses"))?;
+    let addresses = addresses.iter().filter_map(|a| {
+        let result = a.as_string().and_then(parse_addr);
+        if result.is_none() {
+            debug!("skipping unknown address {}", a);
+        }
+        result
+    }).collect();
+    let location = field(server, "location").and_then(Json::as_string).map(str::to_owned);
+    let info = field(server, "info").ok_or(Error::Invalid("info"))?;
+    Ok(Server {
+        addresses: addresses,
+        location: location,
+        info: parse_info(info)?,
+    })
+}
+
+/// Parses a JSON server list.
+///
+/// Malformed server entries are skipped with a warning, only a malformed list
+/// is an error.
+pub fn parse_server_list(data: &str) -> Result<Vec<Server>, Error> {
+    let list = Json::from_str(data)?;
+    let servers = field(&list, "servers").and_then(Json::as_array)
+        .ok_or(Error::Invalid("servers"))?;
+    let mut result = Vec::with_capacity(servers.len());
+    for server in servers {
+        match parse_server(server) {
+            Ok(s) => result.push(s),
+            Err(e) => warn!("skipping server {}: {}", json::as_json(server), e),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use protocol::Addr;
+    use protocol::IpAddr;
+    use super::Protocol;
+    use super::ServerAddr;
+    use super::parse_addr;
+    use super::parse_server_list;
+
+    const LIST: &'static str = r#"{"servers": [
+        {
+            "addresses": ["tw-0.6+udp://192.0.2.1:8303", "tw-0.7+udp://192.0.2.1:8303", "tw-0.6+quic://192.0.2.1:8303"],
+            "location": "eu:de",
+            "info": {
+                "max_clients": 64, "max_players": 32, "passworded": true,
+                "game_type": "DDraceNetwork", "name": "My server",
+                "map": {"name": "Kobra 4", "sha256": "00", "size": 1234},
+                "version": "0.6.4, 16.4",
+                "clients": [
+                    {"name": "nameless tee", "clan": "", "country": -1, "score": 10, "is_player": true, "afk": false},
+                    {"name": "spectator", "clan": "clan", "country": 276, "score": -9999, "is_player": false}
+                ]
+            }
+        },
+        {"addresses": ["tw-0.6+udp://[2001:db8::1]:8304"], "info": {"name": "missing fields"}}
+    ]}"#;
+
+    #[test]
+    fn addr() {
+        let v4 = Addr { ip_address: IpAddr::V4("192.0.2.1".parse().unwrap()), port: 8303 };
+        let v6 = Addr { ip_address: IpAddr::V6("2001:db8::1".parse().unwrap()), port: 8304 };
+        assert_eq!(parse_addr("tw-0.6+udp://192.0.2.1:8303"), Some(ServerAddr { protocol: Protocol::V6, addr: v4 }));
+        assert_eq!(parse_addr("tw-0.7+udp://[2001:db8::1]:8304"), Some(ServerAddr { protocol: Protocol::V7, addr: v6 }));
+        assert_eq!(parse_addr("tw-0.6+quic://192.0.2.1:8303"), None);
+        assert_eq!(parse_addr("tw-0.6+udp://192.0.2.1"), None);
+        assert_eq!(parse_addr("192.0.2.1:8303"), None);
+    }
+
+    #[test]
+    fn list() {
+        let servers = parse_server_list(LIST).unwrap();
+        assert_eq!(servers.len(), 1);
+        let server = &servers[0];
+        let protocols: Vec<_> = server.addresses.iter().map(|a| a.protocol).collect();
+        assert_eq!(protocols, [Protocol::V6, Protocol::V7]);
+        assert_eq!(server.location.as_ref().map(|l| &l[..]), Some("eu:de"));
+        let info = &server.info;
+        assert_eq!(&info.name[..], b"My server");
+        assert_eq!(&info.map[..], b"Kobra 4");
+        assert_eq!(info.map_size, Some(1234));
+        assert_eq!(info.flags, 1);
+        assert_eq!((info.num_players, info.max_players), (1, 32));
+        assert_eq!((info.num_clients, info.max_clients), (2, 64));
+        assert_eq!(&info.clients[1].clan[..], b"clan");
+        assert_eq!(info.clients[1].country, 276);
+        assert_eq!(info.clients[1].is_player, 0);
+    }
+
+    #[test]
+    fn invalid_list() {
+        assert!(parse_server_list("{").is_err());
+        assert!(parse_server_list(r#"{"server": []}"#).is_err());
+        assert!(parse_server_list(r#"{"servers": []}"#).unwrap().is_empty());
+    }
+}
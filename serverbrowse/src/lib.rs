@@ -1,8 +1,15 @@
 extern crate arrayvec;
 #[macro_use] extern crate common;
+#[cfg(feature = "async")] extern crate futures;
 #[macro_use] extern crate log;
+#[cfg(feature = "https")] extern crate native_tls;
 extern crate packer;
 extern crate rustc_serialize;
+#[cfg(feature = "async")] extern crate tokio;
 extern crate warn;
 
+pub mod browser;
+pub mod fetch;
+pub mod json;
 pub mod protocol;
+#[cfg(feature = "async")] pub mod query;
@@ -5,6 +5,8 @@ use common::num::LeU16;
 use common::pretty;
 use common;
 use packer::Unpacker;
+use packer::with_packer;
+use std::cmp;
 use std::default::Default;
 use std::fmt;
 use std::mem;
@@ -39,6 +41,20 @@ pub const INFO_6_64:         Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff
 pub const INFO_6_EX:         Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffiext";
 pub const INFO_6_EX_MORE:    Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffiex+";
 pub const PONG:              Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffpong";
+pub const HEARTBEAT:         Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffbea2";
+pub const FORWARD_CHECK:     Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xfffw??";
+pub const FORWARD_RESPONSE:  Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xfffw!!";
+pub const FORWARD_OK:        Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xfffwok";
+pub const FORWARD_ERROR:     Header = b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xfffwer";
+
+/// Maximum number of servers in a list response packet.
+pub const MAX_SERVERS_PER_PACKET: usize = 75;
+
+/// Maximum size of a server info packet created by `ServerInfo::encode`,
+/// header included.
+pub const MAX_INFO_PACKET_SIZE: usize = 1400;
+// `packet_no` of the extended server info must be less than 64.
+const MAX_INFO_6_EX_PACKETS: usize = 64;
 
 pub const PACKETFLAG_CONNLESS: u8 = 1 << 6;
 pub const REQUEST_INFO_6_EX_FLAG_PONG: u16 = 1 << 0;
@@ -73,6 +89,44 @@ pub fn request_info_ex(challenge: u32, request_pong: bool) -> [u8; 15] {
 
 pub fn request_count() -> [u8; 14] { *REQUEST_COUNT }
 
+pub fn heartbeat(alt_port: u16) -> [u8; 16] {
+    let mut heartbeat = [0; HEADER_LEN+2];
+    heartbeat[..HEADER_LEN].copy_from_slice(HEARTBEAT);
+    heartbeat[HEADER_LEN..].copy_from_slice(&[(alt_port >> 8) as u8, alt_port as u8]);
+    heartbeat
+}
+pub fn forward_check() -> [u8; 14] { *FORWARD_CHECK }
+pub fn forward_response() -> [u8; 14] { *FORWARD_RESPONSE }
+pub fn forward_ok() -> [u8; 14] { *FORWARD_OK }
+pub fn forward_error() -> [u8; 14] { *FORWARD_ERROR }
+
+pub fn count(count: u16) -> [u8; 16] {
+    let mut response = [0; HEADER_LEN+2];
+    response[..HEADER_LEN].copy_from_slice(COUNT);
+    response[HEADER_LEN..].copy_from_slice(&[(count >> 8) as u8, count as u8]);
+    response
+}
+
+/// Creates the list responses for the given servers, each containing at most
+/// `MAX_SERVERS_PER_PACKET` servers.
+pub fn list_5(servers: &[Addr5Packed]) -> Vec<Vec<u8>> {
+    list(LIST_5, unsafe { common::slice::transmute(servers) }, mem::size_of::<Addr5Packed>())
+}
+pub fn list_6(servers: &[Addr6Packed]) -> Vec<Vec<u8>> {
+    list(LIST_6, unsafe { common::slice::transmute(servers) }, mem::size_of::<Addr6Packed>())
+}
+
+fn list(header: Header, servers: &[u8], addr_len: usize) -> Vec<Vec<u8>> {
+    if servers.is_empty() {
+        return vec![header.to_vec()];
+    }
+    servers.chunks(MAX_SERVERS_PER_PACKET * addr_len).map(|chunk| {
+        let mut response = header.to_vec();
+        response.extend_from_slice(chunk);
+        response
+    }).collect()
+}
+
 fn request_info(header: Header, challenge: u8) -> [u8; 15] {
     let mut request = [0; HEADER_LEN+1];
     request[..HEADER_LEN].copy_from_slice(header);
@@ -80,6 +134,53 @@ fn request_info(header: Header, challenge: u8) -> [u8; 15] {
     request
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct InfoRequest {
+    pub info_version: ServerInfoVersion,
+    /// Token to send back in the server info, including the extra token of
+    /// extended requests.
+    pub token: i32,
+}
+
+/// Parses a server info request as sent by `request_info_*`.
+///
+/// 0.7 requests can't be distinguished from 0.6 ones, they're returned as
+/// `ServerInfoVersion::V6`.
+pub fn parse_info_request(data: &[u8]) -> Option<InfoRequest> {
+    if data.len() < HEADER_LEN + 1 {
+        return None;
+    }
+    if data.len() > HEADER_LEN + 1 {
+        warn!("parsing overlong info request");
+    }
+    let (header, data) = data.split_at(HEADER_LEN);
+    let token = data[0] as i32;
+    if header[..2] == REQUEST_INFO_6_EX[..2] && header[6..] == REQUEST_INFO_6_EX[6..] {
+        let extra_token = (header[2] as i32) << 8 | header[3] as i32;
+        return Some(InfoRequest {
+            info_version: ServerInfoVersion::V6Ex,
+            token: extra_token << 8 | token,
+        });
+    }
+    if header[0] & PACKETFLAG_CONNLESS == 0 {
+        return None;
+    }
+    let mut header: [u8; HEADER_LEN] = *unsafe { &*(header.as_ptr() as *const [u8; HEADER_LEN]) };
+    for b in &mut header[..6] {
+        *b = 0xff;
+    }
+    let info_version = match &header {
+        REQUEST_INFO_5 => ServerInfoVersion::V5,
+        REQUEST_INFO_6 => ServerInfoVersion::V6,
+        REQUEST_INFO_6_64 => ServerInfoVersion::V664,
+        _ => return None,
+    };
+    Some(InfoRequest {
+        info_version: info_version,
+        token: token,
+    })
+}
+
 
 
 #[derive(Clone, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -178,6 +279,17 @@ impl ServerInfoVersion {
     pub fn has_extra_info(self) -> bool {
         self == ServerInfoVersion::V6Ex
     }
+    /// Returns a short name of the version, stable for use in logs and
+    /// serialized output.
+    pub fn name(self) -> &'static str {
+        match self {
+            ServerInfoVersion::V5       => "5",
+            ServerInfoVersion::V6       => "6",
+            ServerInfoVersion::V664     => "6_64",
+            ServerInfoVersion::V6Ex     => "6_ex",
+            ServerInfoVersion::V7       => "7",
+        }
+    }
 }
 
 impl Default for ServerInfoVersion { fn default() -> ServerInfoVersion { ServerInfoVersion::V5 } }
@@ -207,6 +319,169 @@ impl ServerInfo {
     pub fn sort_clients(&mut self) {
         self.clients.sort();
     }
+    /// Returns whether this vanilla 0.6 info lists all clients of the
+    /// server.
+    ///
+    /// DDNet servers with more than 16 clients report 15 or 16 of them in
+    /// the vanilla info, so such infos might be truncated even if they list
+    /// as many clients as they claim.
+    pub fn is_complete_vanilla(&self) -> bool {
+        self.clients.len() as i32 == self.num_clients && self.num_clients < 15
+    }
+    /// Encodes the server info as response packets of its `info_version`,
+    /// headers included.
+    ///
+    /// The 64 player and extended versions are split across several packets
+    /// if necessary, each at most `MAX_INFO_PACKET_SIZE` bytes long. Clients
+    /// that don't fit into the number of packets supported by the version are
+    /// left out.
+    pub fn encode(&self) -> Result<Vec<Vec<u8>>, EncodeError> {
+        use self::ServerInfoVersion::*;
+
+        let version = self.info_version;
+        let header = match version {
+            V5 => INFO_5,
+            V6 | V7 => INFO_6,
+            V664 => INFO_6_64,
+            V6Ex => return self.encode_6_ex(),
+        };
+        let per_packet = version.clients_per_packet().unwrap().usize();
+        let num_clients = cmp::min(self.clients.len(), version.max_clients().unwrap().usize());
+        let mut result = Vec::new();
+        let mut offset = 0;
+        loop {
+            let mut packer = InfoPacker::new(header, version);
+            self.pack_main(&mut packer)?;
+            if version.has_offset() {
+                packer.int(offset.assert_i32());
+            }
+            let end = cmp::min(offset + per_packet, num_clients);
+            for client in &self.clients[offset..end] {
+                packer.client(client)?;
+            }
+            result.push(packer.packet);
+            offset = end;
+            if offset == num_clients || !version.has_offset() {
+                break;
+            }
+        }
+        Ok(result)
+    }
+    fn encode_6_ex(&self) -> Result<Vec<Vec<u8>>, EncodeError> {
+        let mut result = Vec::new();
+        let mut packer = InfoPacker::new(INFO_6_EX, ServerInfoVersion::V6Ex);
+        self.pack_main(&mut packer)?;
+        for client in &self.clients {
+            let len = packer.packet.len();
+            packer.client(client)?;
+            if packer.packet.len() <= MAX_INFO_PACKET_SIZE {
+                continue;
+            }
+            packer.packet.truncate(len);
+            if result.len() + 1 == MAX_INFO_6_EX_PACKETS {
+                break;
+            }
+            let packet_no = result.len() + 1;
+            let more = InfoPacker::new(INFO_6_EX_MORE, ServerInfoVersion::V6Ex);
+            result.push(mem::replace(&mut packer, more).packet);
+            packer.int(self.token);
+            packer.int(packet_no.assert_i32());
+            packer.str(b"")?;
+            packer.client(client)?;
+        }
+        result.push(packer.packet);
+        Ok(result)
+    }
+    fn pack_main(&self, packer: &mut InfoPacker) -> Result<(), EncodeError> {
+        let version = self.info_version;
+        packer.int(self.token);
+        packer.str(&self.version)?;
+        packer.str(&self.name)?;
+        if version.has_hostname() {
+            packer.str(self.hostname.as_ref().map(|h| &h[..]).unwrap_or(b""))?;
+        }
+        packer.str(&self.map)?;
+        if version.has_extended_map_info() {
+            let map_size = self.map_size.unwrap_or(0);
+            packer.int(self.map_crc.unwrap_or(0) as i32);
+            packer.int(map_size.try_i32().ok_or(EncodeError::MapSizeTooLarge)?);
+        }
+        packer.str(&self.game_type)?;
+        packer.int(self.flags);
+        if version.has_progression() {
+            packer.int(self.progression.unwrap_or(-1));
+        }
+        if version.has_skill_level() {
+            packer.int(self.skill_level.unwrap_or(0));
+        }
+        packer.int(self.num_players);
+        packer.int(self.max_players);
+        if version.has_extended_player_info() {
+            packer.int(self.num_clients);
+            packer.int(self.max_clients);
+        }
+        if version.has_extra_info() {
+            packer.str(b"")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reasons why a `ServerInfo` can't be encoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EncodeError {
+    /// A string contains a null byte.
+    NullByte,
+    /// The map size doesn't fit into a signed 32-bit integer.
+    MapSizeTooLarge,
+}
+
+struct InfoPacker {
+    version: ServerInfoVersion,
+    packet: Vec<u8>,
+}
+
+impl InfoPacker {
+    fn new(header: Header, version: ServerInfoVersion) -> InfoPacker {
+        InfoPacker {
+            version: version,
+            packet: header.to_vec(),
+        }
+    }
+    fn int(&mut self, int: i32) {
+        if self.version == ServerInfoVersion::V7 {
+            let mut buf: ArrayVec<[u8; 8]> = ArrayVec::new();
+            with_packer(&mut buf, |mut p| p.write_int(int).unwrap());
+            self.packet.extend_from_slice(&buf);
+        } else {
+            self.packet.extend_from_slice(int.to_string().as_bytes());
+            self.packet.push(0);
+        }
+    }
+    fn str(&mut self, string: &[u8]) -> Result<(), EncodeError> {
+        if string.contains(&0) {
+            return Err(EncodeError::NullByte);
+        }
+        self.packet.extend_from_slice(string);
+        self.packet.push(0);
+        Ok(())
+    }
+    fn client(&mut self, client: &ClientInfo) -> Result<(), EncodeError> {
+        let version = self.version;
+        self.str(&client.name)?;
+        if version.has_extended_player_info() {
+            self.str(&client.clan)?;
+            self.int(client.country);
+        }
+        self.int(client.score);
+        if version.has_extended_player_info() {
+            self.int(client.is_player);
+        }
+        if version.has_extra_info() {
+            self.str(b"")?;
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Debug for ServerInfo {
@@ -283,6 +558,10 @@ impl PartialServerInfo {
 
         Ok(())
     }
+    /// Returns the info received so far, which might lack some clients.
+    pub fn partial_info(&self) -> &ServerInfo {
+        &self.info
+    }
     pub fn get_info(&mut self) -> Option<&ServerInfo> {
         if self.info.clients.len().assert_i32() != self.info.num_clients {
             return None;
@@ -551,6 +830,15 @@ impl<'a> Info6ExMoreResponse<'a> {
 #[derive(Copy, Clone)] pub struct List6Response<'a>(pub &'a [Addr6Packed]);
 #[derive(Copy, Clone)] pub struct PongResponse(pub i32);
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MasterRequest {
+    List5,
+    List6,
+    Count,
+    Heartbeat { alt_port: u16 },
+    ForwardResponse,
+}
+
 #[derive(Copy, Clone)]
 pub enum Response<'a> {
     List5(List5Response<'a>),
@@ -625,6 +913,39 @@ pub fn parse_response(data: &[u8]) -> Option<Response> {
     }
 }
 
+/// Parses the packets a master server receives from clients and servers.
+pub fn parse_master_request(data: &[u8]) -> Option<MasterRequest> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    if data[0] & PACKETFLAG_CONNLESS == 0 {
+        return None;
+    }
+    let (header, data) = data.split_at(HEADER_LEN);
+    let mut header: [u8; HEADER_LEN] = *unsafe { &*(header.as_ptr() as *const [u8; HEADER_LEN]) };
+    for b in &mut header[..6] {
+        *b = 0xff;
+    }
+    let (request, len) = match &header {
+        REQUEST_LIST_5 => (MasterRequest::List5, 0),
+        REQUEST_LIST_6 => (MasterRequest::List6, 0),
+        REQUEST_COUNT => (MasterRequest::Count, 0),
+        FORWARD_RESPONSE => (MasterRequest::ForwardResponse, 0),
+        HEARTBEAT => {
+            if data.len() < 2 {
+                return None;
+            }
+            let alt_port = ((data[0] as u16) << 8) | (data[1] as u16);
+            (MasterRequest::Heartbeat { alt_port: alt_port }, 2)
+        },
+        _ => return None,
+    };
+    if data.len() > len {
+        warn!("parsing overlong master request");
+    }
+    Some(request)
+}
+
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum IpAddr {
     V4(Ipv4Addr),
@@ -693,6 +1014,36 @@ impl fmt::Display for IpAddr {
 
 #[test] fn check_alignment_addr5_packed() { assert_eq!(mem::align_of::<Addr5Packed>(), 1); }
 
+impl Addr {
+    /// Packs the address for 0.5 list responses, which only support IPv4.
+    pub fn pack5(self) -> Option<Addr5Packed> {
+        match self.ip_address {
+            IpAddr::V4(ip) => Some(Addr5Packed {
+                ip_address: ip.octets(),
+                port: LeU16::from_u16(self.port),
+            }),
+            IpAddr::V6(_) => None,
+        }
+    }
+    /// Packs the address for 0.6 list responses, mapping IPv4 addresses into
+    /// IPv6.
+    pub fn pack6(self) -> Addr6Packed {
+        let ip_address = match self.ip_address {
+            IpAddr::V4(ip) => {
+                let mut result = [0; 16];
+                result[..IPV4_MAPPING.len()].copy_from_slice(&IPV4_MAPPING);
+                result[IPV4_MAPPING.len()..].copy_from_slice(&ip.octets());
+                result
+            },
+            IpAddr::V6(ip) => ip.octets(),
+        };
+        Addr6Packed {
+            ip_address: ip_address,
+            port: BeU16::from_u16(self.port),
+        }
+    }
+}
+
 impl Addr5Packed {
     pub fn unpack(self) -> Addr {
         let Addr5Packed { ip_address, port } = self;
@@ -735,16 +1086,120 @@ impl Addr6Packed {
 mod test {
     use std::iter::FromIterator;
     use super::ClientInfo;
+    use super::EncodeError;
     use super::Info6ExMoreResponse;
     use super::Info6ExResponse;
     use super::Info6Response;
+    use super::InfoRequest;
+    use super::MAX_INFO_PACKET_SIZE;
+    use super::PartialServerInfo;
+    use super::Response;
     use super::ServerInfo;
     use super::ServerInfoVersion;
+    use super::parse_info_request;
+    use super::parse_response;
+    use super::request_info_5;
+    use super::request_info_6;
+    use super::request_info_6_64;
+    use super::request_info_ex;
 
     fn b<FI: FromIterator<u8>>(s: &str) -> FI {
         s.as_bytes().iter().cloned().collect()
     }
 
+    fn parse_packets(packets: &[Vec<u8>]) -> ServerInfo {
+        let mut partial: Option<PartialServerInfo> = None;
+        for packet in packets {
+            assert!(packet.len() <= MAX_INFO_PACKET_SIZE);
+            let part = match parse_response(packet).unwrap() {
+                Response::Info5(r) => return r.parse().unwrap(),
+                Response::Info6(r) => return r.parse().unwrap(),
+                Response::Info664(r) => r.parse().unwrap(),
+                Response::Info6Ex(r) => r.parse().unwrap(),
+                Response::Info6ExMore(r) => r.parse().unwrap(),
+                _ => panic!("not a server info"),
+            };
+            match partial {
+                Some(ref mut p) => p.merge(part).unwrap(),
+                None => partial = Some(part),
+            }
+        }
+        partial.unwrap().get_info().unwrap().clone()
+    }
+
+    fn example_info(version: ServerInfoVersion, num_clients: usize) -> ServerInfo {
+        let extended = version.has_extended_player_info();
+        let mut clients: Vec<_> = (0..num_clients).map(|i| ClientInfo {
+            name: b(&format!("player name {}", i)),
+            clan: if extended { b(&format!("clan {}", i)) } else { b("") },
+            country: if extended { i as i32 } else { -1 },
+            score: -(i as i32),
+            is_player: if extended { (i % 2) as i32 } else { 1 },
+        }).collect();
+        clients.sort();
+        let num_players = clients.iter().filter(|c| c.is_player != 0).count() as i32;
+        ServerInfo {
+            info_version: version,
+            token: if version == ServerInfoVersion::V6Ex { 0x123456 } else { 1000 },
+            version: b("0.6.4"),
+            name: b("server name"),
+            hostname: if version.has_hostname() { Some(b("example.org")) } else { None },
+            map: b("dm1"),
+            map_crc: if version.has_extended_map_info() { Some(0xf2159e6e) } else { None },
+            map_size: if version.has_extended_map_info() { Some(5805) } else { None },
+            game_type: b("DM"),
+            flags: 1,
+            progression: if version.has_progression() { Some(50) } else { None },
+            skill_level: if version.has_skill_level() { Some(2) } else { None },
+            num_players: num_players,
+            max_players: if extended { num_clients as i32 } else { num_players },
+            num_clients: if extended { num_clients as i32 } else { num_players },
+            max_clients: if extended { num_clients as i32 } else { num_players },
+            clients: clients,
+        }
+    }
+
+    #[test]
+    fn encode_parse_info() {
+        let cases = [
+            (ServerInfoVersion::V5, 16, 1),
+            (ServerInfoVersion::V6, 16, 1),
+            (ServerInfoVersion::V7, 16, 1),
+            (ServerInfoVersion::V664, 0, 1),
+            (ServerInfoVersion::V664, 63, 3),
+            (ServerInfoVersion::V6Ex, 0, 1),
+            (ServerInfoVersion::V6Ex, 200, 6),
+        ];
+        for &(version, num_clients, num_packets) in &cases {
+            let info = example_info(version, num_clients);
+            let packets = info.encode().unwrap();
+            assert_eq!(packets.len(), num_packets, "{:?}", version);
+            assert_eq!(parse_packets(&packets), info);
+        }
+    }
+
+    #[test]
+    fn encode_info_invalid() {
+        let mut info = example_info(ServerInfoVersion::V6, 1);
+        info.clients[0].name.push(0);
+        assert_eq!(info.encode(), Err(EncodeError::NullByte));
+        let mut info = example_info(ServerInfoVersion::V6Ex, 1);
+        info.map_size = Some(0x8000_0000);
+        assert_eq!(info.encode(), Err(EncodeError::MapSizeTooLarge));
+    }
+
+    #[test]
+    fn parse_info_request_token() {
+        fn request(version: ServerInfoVersion, token: i32) -> Option<InfoRequest> {
+            Some(InfoRequest { info_version: version, token: token })
+        }
+        assert_eq!(parse_info_request(&request_info_5(12)), request(ServerInfoVersion::V5, 12));
+        assert_eq!(parse_info_request(&request_info_6(34)), request(ServerInfoVersion::V6, 34));
+        assert_eq!(parse_info_request(&request_info_6_64(56)), request(ServerInfoVersion::V664, 56));
+        assert_eq!(parse_info_request(&request_info_ex(0x789abc, true)), request(ServerInfoVersion::V6Ex, 0x789abc));
+        assert_eq!(parse_info_request(b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\xffinf3\x00"), None);
+    }
+
     #[test]
     fn parse_info_v6_real_world() {
         let info_raw = b"0\x000.6.4, 11.2.1\x00DDNet RUS - Moderate [DDraceNetwork] [0/64]\x00Sunreal\x00DDraceNetwork\x000\x000\x0016\x000\x0016\x00";
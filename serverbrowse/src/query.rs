@@ -0,0 +1,435 @@
+//! Asynchronous server info queries, for use with tokio.
+
+use futures::Async;
+use futures::Future;
+use futures::Poll;
+use protocol::Addr;
+use protocol::IpAddr;
+use protocol::PartialServerInfo;
+use protocol::Response;
+use protocol::ServerInfo;
+use protocol::ServerInfoVersion;
+use protocol::parse_response;
+use protocol;
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tokio::net::UdpSocket;
+use tokio::timer::Delay;
+
+fn to_socket_addr(addr: Addr) -> SocketAddr {
+    match addr.ip_address {
+        IpAddr::V4(ip) => SocketAddr::new(net::IpAddr::V4(ip), addr.port),
+        IpAddr::V6(ip) => SocketAddr::new(net::IpAddr::V6(ip), addr.port),
+    }
+}
+
+fn from_socket_addr(addr: SocketAddr) -> Addr {
+    let ip_address = match addr.ip() {
+        net::IpAddr::V4(ip) => IpAddr::V4(ip),
+        net::IpAddr::V6(ip) => match ip.to_ipv4() {
+            // Replies to IPv4 addresses might come in as IPv4-mapped IPv6
+            // addresses on dual-stack sockets.
+            Some(v4) if ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] =>
+                IpAddr::V4(v4),
+            _ => IpAddr::V6(ip),
+        },
+    };
+    Addr {
+        ip_address: ip_address,
+        port: addr.port(),
+    }
+}
+
+struct Server {
+    addr: Addr,
+    /// Token of the extended info request, the vanilla request uses its
+    /// lowest byte.
+    token: u32,
+    /// Number of requests already sent to this server.
+    sent: u32,
+    /// Vanilla info, lists at most 16 clients.
+    info_6: Option<ServerInfo>,
+    partial: Option<PartialServerInfo>,
+    /// The complete server info, once received.
+    info: Option<ServerInfo>,
+}
+
+impl Server {
+    /// Returns whether the info carries the token of the request it
+    /// answers.
+    fn check_token(&self, info: &ServerInfo) -> bool {
+        let expected = match info.info_version {
+            ServerInfoVersion::V6Ex => self.token,
+            _ => self.token & 0xff,
+        };
+        info.token == expected as i32
+    }
+    fn complete_info(&mut self) -> Option<ServerInfo> {
+        if let Some(i) = self.partial.as_mut().and_then(|p| p.get_info()) {
+            return Some(i.clone());
+        }
+        match self.info_6 {
+            Some(ref i) if i.is_complete_vanilla() => Some(i.clone()),
+            _ => None,
+        }
+    }
+    /// Returns the best info received, which might lack some clients if the
+    /// server didn't reply completely.
+    fn best_info(&self) -> Option<ServerInfo> {
+        self.info.clone()
+            .or_else(|| self.info_6.clone())
+            .or_else(|| self.partial.as_ref().map(|p| p.partial_info().clone()))
+    }
+}
+
+/// The requests sent to each server: a vanilla 0.6 info request and an
+/// extended info request for servers with more than 16 clients.
+const NUM_REQUESTS: u32 = 2;
+
+/// A future resolving to the server infos of a list of servers.
+///
+/// Resolves once every server answered completely or the timeout expired,
+/// whichever happens first. Servers that didn't answer in time get `None`,
+/// servers that answered partially get the info received so far.
+pub struct Query {
+    addrs: Vec<Addr>,
+    servers: Vec<Server>,
+    indices: HashMap<Addr, usize>,
+    remaining: usize,
+    next_send: usize,
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+    delay: Delay,
+    buf: Vec<u8>,
+}
+
+impl Query {
+    /// Creates a query for the info of the given servers.
+    ///
+    /// The timeout starts running immediately. Sockets are only bound for
+    /// the address families that occur in `addrs`.
+    pub fn new(addrs: &[Addr], timeout: Duration) -> io::Result<Query> {
+        let mut servers = Vec::new();
+        let mut indices = HashMap::new();
+        let mut next_token = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        for &addr in addrs {
+            indices.entry(addr).or_insert_with(|| {
+                let token = next_token & 0x00ff_ffff;
+                next_token = next_token.wrapping_add(1);
+                servers.push(Server {
+                    addr: addr,
+                    token: token,
+                    sent: 0,
+                    info_6: None,
+                    partial: None,
+                    info: None,
+                });
+                servers.len() - 1
+            });
+        }
+        let mut any_v4 = false;
+        let mut any_v6 = false;
+        for server in &servers {
+            match server.addr.ip_address {
+                IpAddr::V4(..) => any_v4 = true,
+                IpAddr::V6(..) => any_v6 = true,
+            }
+        }
+        let v4 = if any_v4 {
+            let addr = SocketAddr::new(net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+            Some(UdpSocket::bind(&addr)?)
+        } else {
+            None
+        };
+        let v6 = if any_v6 {
+            let addr = SocketAddr::new(net::IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0);
+            Some(UdpSocket::bind(&addr)?)
+        } else {
+            None
+        };
+        Ok(Query {
+            addrs: addrs.to_owned(),
+            remaining: servers.len(),
+            servers: servers,
+            indices: indices,
+            next_send: 0,
+            v4: v4,
+            v6: v6,
+            delay: Delay::new(Instant::now() + timeout),
+            buf: vec![0; 4096],
+        })
+    }
+    fn send(&mut self) -> io::Result<()> {
+        while self.next_send < self.servers.len() {
+            let server = &mut self.servers[self.next_send];
+            let socket = match server.addr.ip_address {
+                IpAddr::V4(..) => self.v4.as_mut().unwrap(),
+                IpAddr::V6(..) => self.v6.as_mut().unwrap(),
+            };
+            let addr = to_socket_addr(server.addr);
+            while server.sent < NUM_REQUESTS {
+                let request = match server.sent {
+                    0 => protocol::request_info_6((server.token & 0xff) as u8),
+                    1 => protocol::request_info_ex(server.token, false),
+                    _ => unreachable!(),
+                };
+                match socket.poll_send_to(&request, &addr) {
+                    Ok(Async::Ready(_)) => {},
+                    Ok(Async::NotReady) => return Ok(()),
+                    Err(e) => {
+                        // Don't fail the whole query because of a single
+                        // unreachable server.
+                        debug!("failed to send info request to {}: {}", server.addr, e);
+                    },
+                }
+                server.sent += 1;
+            }
+            self.next_send += 1;
+        }
+        Ok(())
+    }
+    fn receive(&mut self) -> io::Result<()> {
+        for &v6 in &[false, true] {
+            loop {
+                let socket = if v6 { self.v6.as_mut() } else { self.v4.as_mut() };
+                let socket = match socket {
+                    Some(s) => s,
+                    None => break,
+                };
+                let (len, from) = match socket.poll_recv_from(&mut self.buf)? {
+                    Async::Ready(r) => r,
+                    Async::NotReady => break,
+                };
+                let from = from_socket_addr(from);
+                let server = match self.indices.get(&from) {
+                    Some(&i) => &mut self.servers[i],
+                    None => {
+                        debug!("ignoring packet from unknown address {}", from);
+                        continue;
+                    },
+                };
+                if server.info.is_some() {
+                    continue;
+                }
+                let partial = match parse_response(&self.buf[..len]) {
+                    Some(Response::Info5(r)) => {
+                        server.info = r.parse().filter(|i| server.check_token(i));
+                        None
+                    },
+                    Some(Response::Info6(r)) => {
+                        // Servers with more than 16 clients only list some
+                        // of them here, wait for the extended info then.
+                        if let Some(i) = r.parse().filter(|i| server.check_token(i)) {
+                            server.info_6 = Some(i);
+                        }
+                        None
+                    },
+                    Some(Response::Info664(r)) => r.parse(),
+                    Some(Response::Info6Ex(r)) => r.parse(),
+                    Some(Response::Info6ExMore(r)) => r.parse(),
+                    _ => {
+                        debug!("ignoring unexpected packet from {}", from);
+                        None
+                    },
+                };
+                let partial = partial.filter(|p| server.check_token(p.partial_info()));
+                if let Some(p) = partial {
+                    match server.partial {
+                        Some(ref mut existing) => {
+                            if let Err(e) = existing.merge(p) {
+                                debug!("failed to merge info from {}: {:?}", from, e);
+                            }
+                        },
+                        None => server.partial = Some(p),
+                    }
+                }
+                if server.info.is_none() {
+                    server.info = server.complete_info();
+                }
+                if server.info.is_some() {
+                    self.remaining -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn finish(&self) -> Vec<(Addr, Option<ServerInfo>)> {
+        let servers = &self.servers;
+        let indices = &self.indices;
+        self.addrs.iter().map(|&addr| {
+            let info = servers[indices[&addr]].best_info();
+            (addr, info)
+        }).collect()
+    }
+}
+
+impl Future for Query {
+    type Item = Vec<(Addr, Option<ServerInfo>)>;
+    type Error = io::Error;
+    fn poll(&mut self) -> Poll<Vec<(Addr, Option<ServerInfo>)>, io::Error> {
+        self.send()?;
+        self.receive()?;
+        if self.remaining == 0 {
+            return Ok(Async::Ready(self.finish()));
+        }
+        match self.delay.poll() {
+            Ok(Async::Ready(())) => Ok(Async::Ready(self.finish())),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use protocol::Addr;
+    use protocol::ClientInfo;
+    use protocol::IpAddr;
+    use protocol::ServerInfo;
+    use protocol::ServerInfoVersion;
+    use protocol::parse_info_request;
+    use std::cmp;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+    use super::Query;
+    use tokio::runtime::current_thread;
+
+    fn info(num_clients: usize) -> ServerInfo {
+        let clients: Vec<_> = (0..num_clients).map(|i| ClientInfo {
+            name: format!("long player {:03}", i).bytes().collect(),
+            clan: b"some clan".iter().cloned().collect(),
+            country: -1,
+            score: i as i32,
+            is_player: 1,
+        }).collect();
+        let mut info = ServerInfo {
+            info_version: ServerInfoVersion::V6Ex,
+            version: b"0.6.4, 16.0".iter().cloned().collect(),
+            name: b"test server".iter().cloned().collect(),
+            map: b"dm1".iter().cloned().collect(),
+            map_crc: Some(0x12345678),
+            map_size: Some(5805),
+            game_type: b"DM".iter().cloned().collect(),
+            num_players: clients.len() as i32,
+            max_players: 64,
+            num_clients: clients.len() as i32,
+            max_clients: 64,
+            clients: clients,
+            ..Default::default()
+        };
+        info.sort_clients();
+        info
+    }
+
+    /// Answers info requests like a DDNet server would, or only the vanilla
+    /// ones if `extended` is false.
+    fn serve(info: ServerInfo, extended: bool) -> Addr {
+        serve_token(info, extended, 0)
+    }
+
+    /// Like `serve`, but adds `token_offset` to the tokens of the replies.
+    fn serve_token(info: ServerInfo, extended: bool, token_offset: i32) -> Addr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 2048];
+            while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                let request = parse_info_request(&buf[..len]).unwrap();
+                let mut reply = info.clone();
+                reply.token = request.token + token_offset;
+                reply.info_version = request.info_version;
+                if request.info_version == ServerInfoVersion::V6 {
+                    // Capped like DDNet does.
+                    let num = cmp::min(reply.num_clients, 15);
+                    reply.num_clients = num;
+                    reply.num_players = num;
+                    reply.clients.truncate(num as usize);
+                    reply.max_clients = 16;
+                    reply.max_players = 16;
+                    reply.map_crc = None;
+                    reply.map_size = None;
+                } else if !extended {
+                    continue;
+                }
+                for packet in reply.encode().unwrap() {
+                    socket.send_to(&packet, from).unwrap();
+                    // Make the vanilla info arrive first.
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+        });
+        Addr {
+            ip_address: IpAddr::V4("127.0.0.1".parse().unwrap()),
+            port: addr.port(),
+        }
+    }
+
+    fn silent() -> (UdpSocket, Addr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        (socket, Addr { ip_address: IpAddr::V4("127.0.0.1".parse().unwrap()), port: port })
+    }
+
+    #[test]
+    fn extended_and_vanilla() {
+        let big = serve(info(60), true);
+        let small = serve(info(3), false);
+        let query = Query::new(&[big, small], Duration::from_secs(2)).unwrap();
+        let start = Instant::now();
+        let result = current_thread::block_on_all(query).unwrap();
+        // Everyone answered completely, no need to wait for the timeout.
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        assert_eq!(result.len(), 2);
+        assert!(result[0].0 == big);
+        let big_info = result[0].1.as_ref().unwrap();
+        assert_eq!(big_info.info_version, ServerInfoVersion::V6Ex);
+        assert_eq!(big_info.clients, info(60).clients);
+
+        assert!(result[1].0 == small);
+        let small_info = result[1].1.as_ref().unwrap();
+        assert_eq!(small_info.info_version, ServerInfoVersion::V6);
+        assert_eq!(small_info.clients, info(3).clients);
+    }
+
+    #[test]
+    fn truncated_and_missing() {
+        let truncated = serve(info(20), false);
+        let (_socket, missing) = silent();
+        let query = Query::new(&[truncated, missing, truncated], Duration::from_millis(300)).unwrap();
+        let result = current_thread::block_on_all(query).unwrap();
+
+        assert_eq!(result.len(), 3);
+        // Only the vanilla info was received, it's returned once the
+        // timeout expires.
+        let truncated_info = result[0].1.as_ref().unwrap();
+        assert_eq!(truncated_info.info_version, ServerInfoVersion::V6);
+        assert_eq!(truncated_info.clients.len(), 15);
+        assert!(result[1].1.is_none());
+        assert!(result[2].1 == result[0].1);
+    }
+
+    #[test]
+    fn wrong_token() {
+        let vanilla = serve_token(info(3), false, 1);
+        let extended = serve_token(info(60), true, 0x100);
+        let query = Query::new(&[vanilla, extended], Duration::from_millis(300)).unwrap();
+        let result = current_thread::block_on_all(query).unwrap();
+        assert!(result[0].1.is_none());
+        assert!(result[1].1.is_none());
+    }
+}
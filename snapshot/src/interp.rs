@@ -0,0 +1,421 @@
+use Snap;
+use gamenet::error::Error;
+use gamenet::msg::game::SvTuneParams;
+use gamenet::snap_obj::CHARACTER;
+use gamenet::snap_obj::Character as NetCharacter;
+use gamenet::snap_obj::CharacterCore;
+use gamenet::snap_obj::PlayerInput;
+use packer::ExcessData;
+use packer::IntUnpacker;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use warn::Warn;
+use world::Character;
+use world::CharacterId;
+use world::Collision;
+use world::OtherCharacters;
+use world::vec2;
+use world;
+
+/// Keeps the two most recent snapshots and the inputs sent since then.
+///
+/// Feed it every snapshot returned by `Manager` and every input sent to the
+/// server, it can then interpolate character positions between the two
+/// snapshots and predict the local character into the future.
+#[derive(Clone, Default)]
+pub struct Interpolator {
+    prev: Option<(i32, Snap)>,
+    cur: Option<(i32, Snap)>,
+    inputs: VecDeque<(i32, PlayerInput)>,
+}
+
+impl Interpolator {
+    pub fn new() -> Interpolator {
+        Default::default()
+    }
+    pub fn reset(&mut self) {
+        self.prev = None;
+        self.cur = None;
+        self.inputs.clear();
+    }
+    /// Adds a newly received snapshot.
+    ///
+    /// Inputs up to and including `tick` are dropped, as they're already
+    /// accounted for in the snapshot.
+    pub fn add_snap(&mut self, tick: i32, snap: &Snap) {
+        if let Some((cur_tick, _)) = self.cur {
+            if tick <= cur_tick {
+                return;
+            }
+        }
+        let mut cur = self.prev.take().unwrap_or_else(|| (0, Snap::empty()));
+        cur.0 = tick;
+        cur.1.clone_from(snap);
+        self.prev = self.cur.take();
+        self.cur = Some(cur);
+        while self.inputs.front().map(|&(t, _)| t <= tick).unwrap_or(false) {
+            self.inputs.pop_front();
+        }
+    }
+    /// Records an input sent to the server for the given tick.
+    pub fn add_input(&mut self, tick: i32, input: PlayerInput) {
+        if let Some(&(last_tick, _)) = self.inputs.back() {
+            if tick <= last_tick {
+                return;
+            }
+        }
+        if let Some((cur_tick, _)) = self.cur {
+            if tick <= cur_tick {
+                return;
+            }
+        }
+        self.inputs.push_back((tick, input));
+    }
+    pub fn prev_tick(&self) -> Option<i32> {
+        self.prev.as_ref().map(|&(t, _)| t)
+    }
+    pub fn cur_tick(&self) -> Option<i32> {
+        self.cur.as_ref().map(|&(t, _)| t)
+    }
+    pub fn prev_snap(&self) -> Option<&Snap> {
+        self.prev.as_ref().map(|&(_, ref s)| s)
+    }
+    pub fn cur_snap(&self) -> Option<&Snap> {
+        self.cur.as_ref().map(|&(_, ref s)| s)
+    }
+    /// Converts a fractional tick into a mixing factor between the previous
+    /// and the current snapshot.
+    ///
+    /// `0.0` corresponds to the previous snapshot and `1.0` to the current
+    /// one, the result is clamped to this range.
+    pub fn intra_tick(&self, tick: f64) -> f32 {
+        let (prev, cur) = match (self.prev_tick(), self.cur_tick()) {
+            (Some(p), Some(c)) => (p, c),
+            _ => return 1.0,
+        };
+        let amount = (tick - prev as f64) / (cur - prev) as f64;
+        world::clamp(amount as f32, 0.0, 1.0)
+    }
+    /// Returns the character core of the given client, interpolated between
+    /// the previous and the current snapshot.
+    ///
+    /// If the character doesn't exist in the previous snapshot, the current
+    /// one is returned unchanged.
+    pub fn character<W>(&self, warn: &mut W, id: u16, intra_tick: f32)
+        -> Result<Option<CharacterCore>, Error>
+        where W: Warn<ExcessData>,
+    {
+        let cur = match self.cur_snap() {
+            Some(s) => unwrap_or_return!(try!(decode_character(warn, s, id)), Ok(None)),
+            None => return Ok(None),
+        };
+        let prev = match self.prev_snap() {
+            Some(s) => try!(decode_character(warn, s, id)),
+            None => None,
+        };
+        Ok(Some(match prev {
+            Some(prev) => mix_character(&prev, &cur, intra_tick),
+            None => cur,
+        }))
+    }
+    /// Predicts the given character up to and including `tick`, starting from
+    /// the current snapshot and applying the recorded inputs.
+    ///
+    /// Ticks without a recorded input reuse the last known input. Other
+    /// characters are not taken into account.
+    pub fn predict<W, C>(&self,
+                         warn: &mut W,
+                         collision: &mut C,
+                         tuning: &SvTuneParams,
+                         id: u16,
+                         tick: i32)
+        -> Result<Option<CharacterCore>, Error>
+        where W: Warn<ExcessData>,
+              C: Collision,
+    {
+        let (cur_tick, cur) = match self.cur {
+            Some((t, ref s)) => (t, s),
+            None => return Ok(None),
+        };
+        let core = unwrap_or_return!(try!(decode_character(warn, cur, id)), Ok(None));
+        let mut character = Character::from_net(&core);
+        let mut input: PlayerInput = Default::default();
+        let mut inputs = self.inputs.iter().peekable();
+        for t in cur_tick + 1..tick + 1 {
+            while let Some(&&(input_tick, i)) = inputs.peek() {
+                if input_tick > t {
+                    break;
+                }
+                input = i;
+                inputs.next();
+            }
+            character.tick(collision, &mut NoOtherCharacters, input, tuning);
+            character.move_(collision, &mut NoOtherCharacters, tuning);
+            character.quantize();
+        }
+        let mut result = character.to_net();
+        result.tick = tick;
+        Ok(Some(result))
+    }
+}
+
+fn decode_character<W>(warn: &mut W, snap: &Snap, id: u16)
+    -> Result<Option<CharacterCore>, Error>
+    where W: Warn<ExcessData>,
+{
+    let data = unwrap_or_return!(snap.item(CHARACTER, id), Ok(None));
+    let character = try!(NetCharacter::decode(warn, &mut IntUnpacker::new(data)));
+    Ok(Some(character.character_core))
+}
+
+fn mix(a: i32, b: i32, amount: f32) -> i32 {
+    (a as f32 + (b - a) as f32 * amount).round() as i32
+}
+
+fn mix_angle(a: i32, b: i32, amount: f32) -> i32 {
+    // Angles are transmitted in 1/256 radians, take the shorter way around.
+    let a = a as f32 / 256.0;
+    let mut b = b as f32 / 256.0;
+    if b - a > PI {
+        b -= 2.0 * PI;
+    } else if a - b > PI {
+        b += 2.0 * PI;
+    }
+    ((a + (b - a) * amount) * 256.0).round() as i32
+}
+
+fn mix_character(prev: &CharacterCore, cur: &CharacterCore, amount: f32)
+    -> CharacterCore
+{
+    let pos = vec2::mix(
+        vec2::new(prev.x as f32, prev.y as f32),
+        vec2::new(cur.x as f32, cur.y as f32),
+        amount,
+    );
+    CharacterCore {
+        x: pos.x.round() as i32,
+        y: pos.y.round() as i32,
+        vel_x: mix(prev.vel_x, cur.vel_x, amount),
+        vel_y: mix(prev.vel_y, cur.vel_y, amount),
+        angle: mix_angle(prev.angle, cur.angle, amount),
+        hook_x: mix(prev.hook_x, cur.hook_x, amount),
+        hook_y: mix(prev.hook_y, cur.hook_y, amount),
+        ..*cur
+    }
+}
+
+struct NoOtherCharacters;
+
+impl OtherCharacters for NoOtherCharacters {
+    type Iter = ();
+    fn is_self(&self, _: CharacterId) -> bool {
+        false
+    }
+    fn get(&self, cid: CharacterId) -> Character {
+        panic!("no other characters, cid={}", cid.0);
+    }
+    fn modify<F: FnOnce(&mut Character)>(&self, cid: CharacterId, _: F) {
+        panic!("no other characters, cid={}", cid.0);
+    }
+    fn iter(&self) {
+    }
+    fn next(&self, _: &mut ()) -> Option<(CharacterId, Character)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gamenet::enums::Emote;
+    use gamenet::enums::Weapon;
+    use gamenet::msg::game::SV_TUNE_PARAMS_DEFAULT;
+    use gamenet::snap_obj::CHARACTER;
+    use gamenet::snap_obj::Character;
+    use gamenet::snap_obj::CharacterCore;
+    use gamenet::snap_obj::PlayerInput;
+    use gamenet::snap_obj::Tick;
+    use snap::Builder;
+    use snap::Snap;
+    use super::Interpolator;
+    use warn::Panic;
+    use world::Collision;
+    use world::CollisionType;
+    use world::vec2;
+
+    fn character(x: i32, y: i32, angle: i32) -> Character {
+        Character {
+            character_core: CharacterCore {
+                tick: 0,
+                x: x,
+                y: y,
+                vel_x: 0,
+                vel_y: 0,
+                angle: angle,
+                direction: 0,
+                jumped: 0,
+                hooked_player: -1,
+                hook_state: 0,
+                hook_tick: Tick(0),
+                hook_x: x,
+                hook_y: y,
+                hook_dx: 0,
+                hook_dy: 0,
+            },
+            player_flags: 0,
+            health: 10,
+            armor: 0,
+            ammo_count: 0,
+            weapon: Weapon::Hammer,
+            emote: Emote::Normal,
+            attack_tick: 0,
+        }
+    }
+
+    fn snap(characters: &[(u16, i32, i32, i32)]) -> Snap {
+        let mut builder = Builder::new();
+        for &(id, x, y, angle) in characters {
+            builder.add_item(CHARACTER, id, character(x, y, angle).encode()).unwrap();
+        }
+        builder.finish()
+    }
+
+    fn position(interp: &Interpolator, id: u16, intra_tick: f32) -> Option<(i32, i32)> {
+        interp.character(&mut Panic, id, intra_tick).unwrap().map(|c| (c.x, c.y))
+    }
+
+    #[test]
+    fn interpolate() {
+        let mut interp = Interpolator::new();
+        interp.add_snap(10, &snap(&[(0, 0, 0, 0)]));
+        interp.add_snap(12, &snap(&[(0, 100, -50, 0)]));
+        assert_eq!(interp.prev_tick(), Some(10));
+        assert_eq!(interp.cur_tick(), Some(12));
+
+        assert_eq!(interp.intra_tick(10.0), 0.0);
+        assert_eq!(interp.intra_tick(11.0), 0.5);
+        assert_eq!(interp.intra_tick(12.0), 1.0);
+        assert_eq!(interp.intra_tick(20.0), 1.0);
+        assert_eq!(interp.intra_tick(5.0), 0.0);
+
+        assert_eq!(position(&interp, 0, 0.0), Some((0, 0)));
+        assert_eq!(position(&interp, 0, 0.5), Some((50, -25)));
+        assert_eq!(position(&interp, 0, 1.0), Some((100, -50)));
+    }
+
+    #[test]
+    fn appear_disappear() {
+        let mut interp = Interpolator::new();
+        interp.add_snap(1, &snap(&[(0, 0, 0, 0), (1, 10, 10, 0)]));
+        interp.add_snap(2, &snap(&[(0, 32, 0, 0), (2, 64, 64, 0)]));
+        // Appeared: taken from the current snapshot unchanged.
+        assert_eq!(position(&interp, 2, 0.5), Some((64, 64)));
+        // Disappeared: not in the current snapshot.
+        assert_eq!(position(&interp, 1, 0.5), None);
+        // Never existed.
+        assert_eq!(position(&interp, 3, 0.5), None);
+        assert_eq!(position(&interp, 0, 0.25), Some((8, 0)));
+    }
+
+    #[test]
+    fn single_snap() {
+        let mut interp = Interpolator::new();
+        assert_eq!(position(&interp, 0, 0.5), None);
+        interp.add_snap(5, &snap(&[(0, 16, 16, 0)]));
+        assert_eq!(interp.intra_tick(3.0), 1.0);
+        assert_eq!(position(&interp, 0, 0.5), Some((16, 16)));
+    }
+
+    #[test]
+    fn old_snaps_ignored() {
+        let mut interp = Interpolator::new();
+        interp.add_snap(10, &snap(&[(0, 0, 0, 0)]));
+        interp.add_snap(12, &snap(&[(0, 100, 0, 0)]));
+        interp.add_snap(11, &snap(&[(0, 1000, 0, 0)]));
+        interp.add_snap(12, &snap(&[(0, 1000, 0, 0)]));
+        assert_eq!((interp.prev_tick(), interp.cur_tick()), (Some(10), Some(12)));
+        assert_eq!(position(&interp, 0, 1.0), Some((100, 0)));
+
+        interp.add_snap(14, &snap(&[(0, 200, 0, 0)]));
+        assert_eq!((interp.prev_tick(), interp.cur_tick()), (Some(12), Some(14)));
+        assert_eq!(position(&interp, 0, 0.5), Some((150, 0)));
+
+        interp.reset();
+        assert_eq!((interp.prev_tick(), interp.cur_tick()), (None, None));
+    }
+
+    #[test]
+    fn angle_short_way() {
+        use std::f32::consts::PI;
+        let almost_pi = (PI * 256.0) as i32 - 10;
+        let mut interp = Interpolator::new();
+        interp.add_snap(1, &snap(&[(0, 0, 0, almost_pi)]));
+        interp.add_snap(2, &snap(&[(0, 0, 0, -almost_pi)]));
+        let angle = interp.character(&mut Panic, 0, 0.5).unwrap().unwrap().angle;
+        // Halfway across the discontinuity at ±π, not through 0.
+        assert!(angle.abs() > almost_pi, "angle={}", angle);
+    }
+
+    /// Solid below the given height, empty above.
+    struct Floor(f32);
+
+    impl Collision for Floor {
+        fn check_point(&mut self, pos: vec2) -> Option<CollisionType> {
+            if pos.y >= self.0 { Some(CollisionType::Normal) } else { None }
+        }
+    }
+
+    fn predict(interp: &Interpolator, floor: f32, tick: i32) -> (i32, i32, i32) {
+        let c = interp.predict(&mut Panic, &mut Floor(floor), &SV_TUNE_PARAMS_DEFAULT, 0, tick)
+            .unwrap().unwrap();
+        assert_eq!(c.tick, tick);
+        (c.x, c.y, c.vel_y)
+    }
+
+    fn input(jump: bool) -> PlayerInput {
+        PlayerInput {
+            target_x: 1,
+            jump: jump as i32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn predict_fall() {
+        let mut interp = Interpolator::new();
+        interp.add_snap(10, &snap(&[(0, 0, 0, 0)]));
+        interp.add_input(11, input(false));
+        assert!(interp.predict(&mut Panic, &mut Floor(1000.0), &SV_TUNE_PARAMS_DEFAULT, 1, 11)
+            .unwrap().is_none());
+        // Gravity adds 0.5 to the velocity each tick, the position is
+        // rounded after every tick: 0.5, 1.5, 3.5, 6, 8.5.
+        assert_eq!(predict(&interp, 1000.0, 10), (0, 0, 0));
+        assert_eq!(predict(&interp, 1000.0, 11), (0, 1, 128));
+        assert_eq!(predict(&interp, 1000.0, 13), (0, 4, 384));
+        assert_eq!(predict(&interp, 1000.0, 15), (0, 9, 640));
+    }
+
+    #[test]
+    fn predict_jump() {
+        let mut interp = Interpolator::new();
+        // Standing on the floor: the ground check 19 units below the center
+        // hits it, the character box 14 units below only after moving by
+        // gravity.
+        interp.add_snap(10, &snap(&[(0, 0, 48, 0)]));
+        interp.add_input(11, input(true));
+        // The jump impulse of 13.2 replaces the velocity, it's quantized to
+        // -3379/256 after the first tick.
+        assert_eq!(predict(&interp, 62.5, 11), (0, 35, -3379));
+        // Holding jump doesn't jump again, gravity slows the character down.
+        assert_eq!(predict(&interp, 62.5, 12), (0, 22, -3251));
+
+        // Without the jump input, the character stays on the floor.
+        let mut standing = Interpolator::new();
+        standing.add_snap(10, &snap(&[(0, 0, 48, 0)]));
+        standing.add_input(11, input(false));
+        assert_eq!(predict(&standing, 62.5, 12), (0, 48, 0));
+
+        // Inputs already covered by a snapshot aren't applied again.
+        interp.add_snap(11, &snap(&[(0, 0, 48, 0)]));
+        assert_eq!(predict(&interp, 62.5, 12), (0, 48, 0));
+    }
+}
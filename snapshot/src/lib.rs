@@ -1,16 +1,20 @@
 extern crate buffer;
-extern crate common;
+#[macro_use] extern crate common;
 extern crate gamenet;
 extern crate packer;
 extern crate vec_map;
 extern crate warn;
+extern crate world;
 
 pub mod format;
+pub mod interp;
 pub mod manager;
 pub mod receiver;
 pub mod snap;
 pub mod storage;
+pub mod view;
 
+pub use interp::Interpolator;
 pub use manager::Manager;
 pub use receiver::DeltaReceiver;
 pub use receiver::ReceivedDelta;
@@ -18,6 +22,7 @@ pub use snap::Delta;
 pub use snap::DeltaReader;
 pub use snap::Snap;
 pub use storage::Storage;
+pub use view::View;
 
 use common::num::Cast;
 use std::ops;
@@ -39,6 +39,10 @@ pub enum Error {
     TooLongDiff,
     TooLongSnap,
     DeltaDifferingSizes,
+    OffsetsUnpacking,
+    InvalidOffset,
+    ItemsUnpacking,
+    DuplicateKey,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -183,6 +187,50 @@ impl Snap {
         }
         Ok(())
     }
+    /// Reads a full snapshot in the format written by `Snap::write`.
+    ///
+    /// `buf` is used as scratch space for the item offsets.
+    pub fn read<W>(&mut self, warn: &mut W, buf: &mut Vec<i32>, p: &mut Unpacker)
+        -> Result<(), Error>
+        where W: Warn<Warning>,
+    {
+        self.clear();
+
+        let data_size = try!(p.read_int(wrap(warn)));
+        let num_items = try!(p.read_int(wrap(warn)));
+        let data_size = try!(data_size.try_usize().ok_or(Error::NegativeSize));
+        let num_items = try!(num_items.try_usize().ok_or(Error::NegativeSize));
+        if data_size % mem::size_of::<i32>() != 0 {
+            return Err(Error::InvalidOffset);
+        }
+
+        let offsets = buf;
+        offsets.clear();
+        for _ in 0..num_items {
+            offsets.push(try!(p.read_int(wrap(warn)).map_err(|_| Error::OffsetsUnpacking)));
+        }
+        for i in 0..num_items {
+            let start = offsets[i];
+            let end = offsets.get(i + 1).cloned().unwrap_or(data_size.assert_i32());
+            let start = try!(start.try_usize().ok_or(Error::InvalidOffset));
+            let end = try!(end.try_usize().ok_or(Error::InvalidOffset));
+            if end <= start || start % mem::size_of::<i32>() != 0 {
+                return Err(Error::InvalidOffset);
+            }
+            // The key is included in the item size.
+            let size = (end - start) / mem::size_of::<i32>() - 1;
+
+            let key = try!(p.read_int(wrap(warn)).map_err(|_| Error::ItemsUnpacking));
+            if self.offsets.contains_key(&key) {
+                return Err(Error::DuplicateKey);
+            }
+            let out = try!(self.prepare_item(key_to_type_id(key), key_to_id(key), size));
+            for o in out {
+                *o = try!(p.read_int(wrap(warn)).map_err(|_| Error::ItemsUnpacking));
+            }
+        }
+        Ok(())
+    }
     pub fn write<'d, 's>(&self, buf: &mut Vec<i32>, mut p: Packer<'d, 's>)
         -> Result<&'d [u8], CapacityError>
     {
@@ -0,0 +1,251 @@
+use Snap;
+use gamenet::SnapObj;
+use gamenet::error::Error;
+use gamenet::snap_obj::CHARACTER;
+use gamenet::snap_obj::CLIENT_INFO;
+use gamenet::snap_obj::Character;
+use gamenet::snap_obj::ClientInfo;
+use gamenet::snap_obj::GAME_INFO;
+use gamenet::snap_obj::GameInfo;
+use gamenet::snap_obj::PLAYER_INFO;
+use gamenet::snap_obj::PlayerInfo;
+use packer::IntUnpacker;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::iter::Peekable;
+use warn::Warn;
+use warn;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    ExcessData(u16, u16),
+    Decode(u16, u16, Error),
+}
+
+/// A snapshot with all its items decoded into `SnapObj`s.
+///
+/// Items that fail to decode are skipped and reported as warnings.
+#[derive(Clone, Default)]
+pub struct View {
+    objs: BTreeMap<(u16, u16), SnapObj>,
+}
+
+impl View {
+    pub fn new() -> View {
+        Default::default()
+    }
+    pub fn decode<W: Warn<Warning>>(warn: &mut W, snap: &Snap) -> View {
+        let mut result = View::new();
+        result.decode_from(warn, snap);
+        result
+    }
+    /// Like `View::decode`, but reuses the allocation of `self`.
+    pub fn decode_from<W: Warn<Warning>>(&mut self, warn: &mut W, snap: &Snap) {
+        self.objs.clear();
+        for item in snap.items() {
+            let (type_id, id) = (item.type_id, item.id);
+            let res = SnapObj::decode_obj(
+                &mut warn::rev_map(warn, |_| Warning::ExcessData(type_id, id)),
+                type_id,
+                &mut IntUnpacker::new(item.data),
+            );
+            match res {
+                Ok(obj) => { self.objs.insert((type_id, id), obj); }
+                Err(e) => warn.warn(Warning::Decode(type_id, id, e)),
+            }
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.objs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.objs.is_empty()
+    }
+    pub fn get(&self, type_id: u16, id: u16) -> Option<&SnapObj> {
+        self.objs.get(&(type_id, id))
+    }
+    /// Iterates over all objects as `(type_id, id, obj)`, sorted by type ID
+    /// and ID.
+    pub fn objs(&self) -> Objs {
+        Objs(self.objs.iter())
+    }
+    fn of_type<T>(&self, type_id: u16, extract: fn(&SnapObj) -> Option<&T>)
+        -> OfType<T>
+    {
+        OfType {
+            iter: self.objs.range((type_id, 0)..=(type_id, u16::max_value())),
+            extract: extract,
+        }
+    }
+    pub fn characters(&self) -> OfType<Character> {
+        fn extract(obj: &SnapObj) -> Option<&Character> {
+            match *obj { SnapObj::Character(ref c) => Some(c), _ => None }
+        }
+        self.of_type(CHARACTER, extract)
+    }
+    pub fn player_infos(&self) -> OfType<PlayerInfo> {
+        fn extract(obj: &SnapObj) -> Option<&PlayerInfo> {
+            match *obj { SnapObj::PlayerInfo(ref p) => Some(p), _ => None }
+        }
+        self.of_type(PLAYER_INFO, extract)
+    }
+    pub fn client_infos(&self) -> OfType<ClientInfo> {
+        fn extract(obj: &SnapObj) -> Option<&ClientInfo> {
+            match *obj { SnapObj::ClientInfo(ref c) => Some(c), _ => None }
+        }
+        self.of_type(CLIENT_INFO, extract)
+    }
+    pub fn game_info(&self) -> Option<&GameInfo> {
+        fn extract(obj: &SnapObj) -> Option<&GameInfo> {
+            match *obj { SnapObj::GameInfo(ref g) => Some(g), _ => None }
+        }
+        self.of_type(GAME_INFO, extract).next().map(|(_, g)| g)
+    }
+    pub fn character(&self, id: u16) -> Option<&Character> {
+        match self.get(CHARACTER, id) {
+            Some(&SnapObj::Character(ref c)) => Some(c),
+            _ => None,
+        }
+    }
+    /// Returns the player info marked as local, i.e. the one belonging to
+    /// the receiving client.
+    pub fn local_player(&self) -> Option<(u16, &PlayerInfo)> {
+        self.player_infos().find(|&(_, p)| p.local != 0)
+    }
+    /// Compares two views, yielding the objects that were added, removed or
+    /// changed going from `self` to `other`.
+    pub fn diff<'a>(&'a self, other: &'a View) -> Diff<'a> {
+        Diff {
+            from: self.objs.iter().peekable(),
+            to: other.objs.iter().peekable(),
+        }
+    }
+}
+
+pub struct Objs<'a>(btree_map::Iter<'a, (u16, u16), SnapObj>);
+
+impl<'a> Iterator for Objs<'a> {
+    type Item = (u16, u16, &'a SnapObj);
+    fn next(&mut self) -> Option<(u16, u16, &'a SnapObj)> {
+        self.0.next().map(|(&(type_id, id), obj)| (type_id, id, obj))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Objs<'a> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+pub struct OfType<'a, T: 'a> {
+    iter: btree_map::Range<'a, (u16, u16), SnapObj>,
+    extract: fn(&SnapObj) -> Option<&T>,
+}
+
+impl<'a, T> Iterator for OfType<'a, T> {
+    type Item = (u16, &'a T);
+    fn next(&mut self) -> Option<(u16, &'a T)> {
+        while let Some((&(_, id), obj)) = self.iter.next() {
+            if let Some(o) = (self.extract)(obj) {
+                return Some((id, o));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Change<'a> {
+    Added(u16, u16, &'a SnapObj),
+    Removed(u16, u16, &'a SnapObj),
+    Changed(u16, u16, &'a SnapObj, &'a SnapObj),
+}
+
+pub struct Diff<'a> {
+    from: Peekable<btree_map::Iter<'a, (u16, u16), SnapObj>>,
+    to: Peekable<btree_map::Iter<'a, (u16, u16), SnapObj>>,
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = Change<'a>;
+    fn next(&mut self) -> Option<Change<'a>> {
+        loop {
+            let order = match (self.from.peek(), self.to.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(&(f, _)), Some(&(t, _))) => f.cmp(t),
+            };
+            match order {
+                Ordering::Less => {
+                    let (&(type_id, id), obj) = self.from.next().unwrap();
+                    return Some(Change::Removed(type_id, id, obj));
+                }
+                Ordering::Greater => {
+                    let (&(type_id, id), obj) = self.to.next().unwrap();
+                    return Some(Change::Added(type_id, id, obj));
+                }
+                Ordering::Equal => {
+                    let (&(type_id, id), from) = self.from.next().unwrap();
+                    let (_, to) = self.to.next().unwrap();
+                    if from.encode() != to.encode() {
+                        return Some(Change::Changed(type_id, id, from, to));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gamenet::enums::Team;
+    use gamenet::snap_obj::PLAYER_INFO;
+    use gamenet::snap_obj::PlayerInfo;
+    use snap::Builder;
+    use super::Change;
+    use super::View;
+    use warn::Panic;
+
+    fn player_info(local: bool, score: i32) -> PlayerInfo {
+        PlayerInfo {
+            local: local as i32,
+            client_id: 0,
+            team: Team::Red,
+            score: score,
+            latency: 0,
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let mut builder = Builder::new();
+        builder.add_item(PLAYER_INFO, 0, player_info(true, 0).encode()).unwrap();
+        builder.add_item(PLAYER_INFO, 1, player_info(false, 0).encode()).unwrap();
+        let from = View::decode(&mut Panic, &builder.finish());
+
+        let mut builder = Builder::new();
+        builder.add_item(PLAYER_INFO, 0, player_info(true, 1).encode()).unwrap();
+        builder.add_item(PLAYER_INFO, 2, player_info(false, 0).encode()).unwrap();
+        let to = View::decode(&mut Panic, &builder.finish());
+
+        assert_eq!(from.local_player().map(|(id, _)| id), Some(0));
+        assert_eq!(to.player_infos().count(), 2);
+
+        let changes: Vec<_> = from.diff(&to).map(|c| match c {
+            Change::Added(t, id, _) => ('+', t, id),
+            Change::Removed(t, id, _) => ('-', t, id),
+            Change::Changed(t, id, _, _) => ('~', t, id),
+        }).collect();
+        assert_eq!(changes, [
+            ('~', PLAYER_INFO, 0),
+            ('-', PLAYER_INFO, 1),
+            ('+', PLAYER_INFO, 2),
+        ]);
+        assert_eq!(to.diff(&to).count(), 0);
+    }
+}
@@ -55,3 +55,32 @@ fn simple() {
     println!("{:?}", snap);
     assert_eq!(snap.crc(), SECOND_CRC);
 }
+
+#[test]
+fn full_roundtrip() {
+    let mut buf = Vec::with_capacity(4096);
+    let mut reader = DeltaReader::new();
+    let mut delta = Delta::new();
+    let mut snap = Snap::default();
+
+    with_packer(&mut buf, |mut p| -> Result<_, CapacityError> {
+        for &d in &FIRST_DATA[..FIRST_DATA.len()] {
+            try!(p.write_int(d));
+        }
+        Ok(p.written())
+    }).unwrap();
+    reader.read(&mut Panic, &mut delta, obj_size, &mut Unpacker::new(&buf)).unwrap();
+    snap.read_with_delta(&mut Panic, &Snap::empty(), &delta).unwrap();
+
+    let mut keys = Vec::new();
+    let mut written = Vec::with_capacity(4096);
+    with_packer(&mut written, |p| snap.write(&mut keys, p)).unwrap();
+
+    let mut read = Snap::default();
+    read.read(&mut Panic, &mut keys, &mut Unpacker::new(&written)).unwrap();
+    assert_eq!(read.crc(), FIRST_CRC);
+    assert_eq!(read.items().len(), snap.items().len());
+    for item in snap.items() {
+        assert_eq!(read.item(item.type_id, item.id), Some(item.data));
+    }
+}
@@ -0,0 +1,157 @@
+// Compares `ServerInfo` with `CompactServerInfo` for 5000 servers with 60000
+// players. On x86_64, the compact representation needs about 16% less memory
+// (3.6 MB instead of 4.2 MB): player names are mostly distinct, so the gain
+// comes from not reserving the maximum length for each string and from
+// sharing maps, game types, versions and clans.
+
+#[macro_use] extern crate bencher;
+extern crate serverbrowse;
+extern crate stats_browser;
+
+use bencher::Bencher;
+use bencher::black_box;
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol::ServerInfoVersion;
+use stats_browser::compact::CompactServerInfo;
+use stats_browser::compact::StringPool;
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+const NUM_SERVERS: usize = 5000;
+const NUM_PLAYERS: usize = 60000;
+
+/// Allocator keeping track of the number of allocated bytes.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn bytes(string: &str) -> Vec<u8> {
+    string.as_bytes().to_vec()
+}
+
+/// Generates server infos resembling the ones found on the master servers:
+/// few distinct versions, game types, maps and clans, but many distinct
+/// server and player names.
+fn servers() -> Vec<ServerInfo> {
+    const MAPS: &'static [&'static str] = &[
+        "dm1", "dm2", "dm6", "dm7", "dm8", "dm9", "ctf1", "ctf2", "ctf3",
+        "ctf4", "ctf5", "ctf6", "ctf7", "Kobra 4", "Tutorial", "Sunny Side Up",
+    ];
+    const GAME_TYPES: &'static [&'static str] = &[
+        "DM", "TDM", "CTF", "DDraceNetwork", "Gores", "iDDRace", "zCatch",
+    ];
+    const VERSIONS: &'static [&'static str] = &["0.6.4", "0.6.5", "0.6.4, 12.8"];
+    const CLANS: &'static [&'static str] = &["", "", "", "Chillerdragon", "Team Cute", "|*KoG*|"];
+    let players_per_server = NUM_PLAYERS / NUM_SERVERS;
+    (0..NUM_SERVERS).map(|i| {
+        let clients: Vec<_> = (0..players_per_server).map(|j| {
+            let player = i * players_per_server + j;
+            ClientInfo {
+                name: bytes(&format!("player {}", player)).into_iter().collect(),
+                clan: bytes(CLANS[player % CLANS.len()]).into_iter().collect(),
+                country: -1,
+                score: (player % 50) as i32,
+                is_player: 1,
+            }
+        }).collect();
+        ServerInfo {
+            info_version: ServerInfoVersion::V6,
+            token: 0,
+            version: bytes(VERSIONS[i % VERSIONS.len()]).into_iter().collect(),
+            name: bytes(&format!("Server {} [{}]", i, GAME_TYPES[i % GAME_TYPES.len()])).into_iter().collect(),
+            hostname: None,
+            map: bytes(MAPS[i % MAPS.len()]).into_iter().collect(),
+            map_crc: None,
+            map_size: None,
+            game_type: bytes(GAME_TYPES[i % GAME_TYPES.len()]).into_iter().collect(),
+            flags: 0,
+            progression: None,
+            skill_level: None,
+            num_players: clients.len() as i32,
+            max_players: 16,
+            num_clients: clients.len() as i32,
+            max_clients: 16,
+            clients: clients,
+        }
+    }).collect()
+}
+
+fn compact(servers: &[ServerInfo]) -> (StringPool, Vec<CompactServerInfo>) {
+    let mut pool = StringPool::new();
+    let compact = servers.iter().map(|s| CompactServerInfo::new(&mut pool, s)).collect();
+    (pool, compact)
+}
+
+/// Prints the memory needed for the server infos in both representations.
+fn memory_usage() {
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    let servers = servers();
+    let full = ALLOCATED.load(Ordering::SeqCst) - before;
+
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    let (pool, compact) = compact(&servers);
+    let compact_size = ALLOCATED.load(Ordering::SeqCst) - before;
+
+    println!("memory for {} servers and {} players:", NUM_SERVERS, NUM_PLAYERS);
+    println!("  ServerInfo:        {:>9} bytes", full);
+    println!("  CompactServerInfo: {:>9} bytes ({} of them in the string pool with {} strings)",
+        compact_size, pool.memory_usage(), pool.len());
+    black_box((servers, pool, compact));
+}
+
+fn create_compact(bench: &mut Bencher) {
+    let servers = servers();
+    bench.iter(|| black_box(compact(&servers)));
+}
+
+fn clone_full(bench: &mut Bencher) {
+    let servers = servers();
+    bench.iter(|| black_box(servers.clone()));
+}
+
+fn restore_compact(bench: &mut Bencher) {
+    let (pool, compact) = compact(&servers());
+    bench.iter(|| {
+        black_box(compact.iter().map(|s| s.get(&pool)).collect::<Vec<_>>())
+    });
+}
+
+fn rebuild_pool(bench: &mut Bencher) {
+    let (pool, compact) = compact(&servers());
+    bench.iter(|| {
+        let mut new_pool = StringPool::new();
+        let moved: Vec<_> = compact.iter().map(|s| s.move_to(&pool, &mut new_pool)).collect();
+        black_box((new_pool, moved))
+    });
+}
+
+benchmark_group!(benches, create_compact, clone_full, restore_compact, rebuild_pool);
+
+fn main() {
+    memory_usage();
+    // Same as `benchmark_main!(benches)`, but after printing the memory
+    // usage.
+    let mut test_opts = bencher::TestOpts::default();
+    if let Some(arg) = std::env::args().skip(1).find(|arg| *arg != "--bench") {
+        test_opts.filter = Some(arg);
+    }
+    bencher::run_tests_console(&test_opts, benches()).unwrap();
+}
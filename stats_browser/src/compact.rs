@@ -0,0 +1,374 @@
+//! Compact storage of server infos.
+//!
+//! A `ServerInfo` reserves the maximum length for each of its strings and
+//! every `ClientInfo` those of the name and clan. Most strings are much
+//! shorter, and map names, game types, versions and clans are shared between
+//! many servers and players. `CompactServerInfo` instead refers to the
+//! strings in a `StringPool` that stores each distinct string once.
+//!
+//! The pool never forgets strings by itself; once it has grown too much, a
+//! new one is built from the strings that are still used, see
+//! `CompactServerInfo::move_to`.
+
+use common::num::Cast;
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol::ServerInfoVersion;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::iter::FromIterator;
+use std::mem;
+
+/// Reference to a string in a `StringPool`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct StrId(u32);
+
+const EMPTY: u32 = !0;
+
+/// The longest string a `StringPool` can store.
+///
+/// The strings of a `ServerInfo` are at most 64 bytes long, so
+/// `CompactServerInfo` can always store them.
+pub const MAX_STRING_LENGTH: usize = 255;
+
+/// Interns byte strings of up to `MAX_STRING_LENGTH` bytes.
+///
+/// The strings are stored one after another, each prefixed by its length,
+/// and found through an open addressing hash table of their offsets.
+#[derive(Clone)]
+pub struct StringPool {
+    data: Vec<u8>,
+    /// Offsets of the strings into `data`, `EMPTY` for unused slots. The
+    /// length is a power of two.
+    table: Vec<u32>,
+    len: usize,
+}
+
+fn string<T: FromIterator<u8>>(pool: &StringPool, id: StrId) -> T {
+    pool.get(id).iter().cloned().collect()
+}
+
+fn hash(string: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    string.hash(&mut hasher);
+    hasher.finish() as usize
+}
+
+impl Default for StringPool {
+    fn default() -> StringPool {
+        StringPool::new()
+    }
+}
+
+impl StringPool {
+    pub fn new() -> StringPool {
+        StringPool {
+            data: Vec::new(),
+            table: vec![EMPTY; 16],
+            len: 0,
+        }
+    }
+    /// Returns the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Returns whether the pool contains no strings.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Returns the approximate number of bytes allocated by the pool.
+    pub fn memory_usage(&self) -> usize {
+        self.data.capacity() + self.table.capacity() * mem::size_of::<u32>()
+    }
+    /// Returns the string referred to by `id`.
+    pub fn get(&self, id: StrId) -> &[u8] {
+        let StrId(offset) = id;
+        let offset = offset.usize();
+        let len = self.data[offset].usize();
+        &self.data[offset + 1..offset + 1 + len]
+    }
+    /// Returns the slot of `string` in the table, or the free slot where it
+    /// would be inserted.
+    fn slot(&self, string: &[u8]) -> usize {
+        let mask = self.table.len() - 1;
+        let mut i = hash(string) & mask;
+        loop {
+            let offset = self.table[i];
+            if offset == EMPTY || self.get(StrId(offset)) == string {
+                return i;
+            }
+            i = (i + 1) & mask;
+        }
+    }
+    fn grow(&mut self) {
+        let new = vec![EMPTY; self.table.len() * 2];
+        let old = mem::replace(&mut self.table, new);
+        for offset in old.into_iter().filter(|&o| o != EMPTY) {
+            let slot = self.slot(self.get(StrId(offset)));
+            self.table[slot] = offset;
+        }
+    }
+    /// Returns the ID of `string`, adding it to the pool if necessary.
+    ///
+    /// Panics if the string is longer than `MAX_STRING_LENGTH`, as its
+    /// length is stored in a single byte.
+    pub fn intern(&mut self, string: &[u8]) -> StrId {
+        assert!(string.len() <= MAX_STRING_LENGTH, "string too long for the pool");
+        let slot = self.slot(string);
+        if self.table[slot] != EMPTY {
+            return StrId(self.table[slot]);
+        }
+        let offset = self.data.len().assert_u32();
+        assert!(offset != EMPTY);
+        self.data.push(string.len().assert_u8());
+        self.data.extend_from_slice(string);
+        self.table[slot] = offset;
+        self.len += 1;
+        // Keep the load factor below 3/4.
+        if self.len * 4 >= self.table.len() * 3 {
+            self.grow();
+        }
+        StrId(offset)
+    }
+}
+
+/// Player as stored in a `CompactServerInfo`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CompactClient {
+    pub name: StrId,
+    pub clan: StrId,
+    pub score: i32,
+    pub country: i32,
+    pub is_player: i32,
+}
+
+impl CompactClient {
+    fn new(pool: &mut StringPool, info: &ClientInfo) -> CompactClient {
+        CompactClient {
+            name: pool.intern(&info.name),
+            clan: pool.intern(&info.clan),
+            score: info.score,
+            country: info.country,
+            is_player: info.is_player,
+        }
+    }
+    fn get(&self, pool: &StringPool) -> ClientInfo {
+        ClientInfo {
+            name: string(pool, self.name),
+            clan: string(pool, self.clan),
+            country: self.country,
+            score: self.score,
+            is_player: self.is_player,
+        }
+    }
+    fn move_to(&self, from: &StringPool, to: &mut StringPool) -> CompactClient {
+        CompactClient {
+            name: to.intern(from.get(self.name)),
+            clan: to.intern(from.get(self.clan)),
+            ..*self
+        }
+    }
+}
+
+/// Server info with its strings stored in a `StringPool`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompactServerInfo {
+    pub info_version: ServerInfoVersion,
+    pub token: i32,
+    pub version: StrId,
+    pub name: StrId,
+    pub hostname: Option<StrId>,
+    pub map: StrId,
+    pub map_crc: Option<u32>,
+    pub map_size: Option<u32>,
+    pub game_type: StrId,
+    pub flags: i32,
+    pub progression: Option<i32>,
+    pub skill_level: Option<i32>,
+    pub num_players: i32,
+    pub max_players: i32,
+    pub num_clients: i32,
+    pub max_clients: i32,
+    pub clients: Box<[CompactClient]>,
+}
+
+impl CompactServerInfo {
+    /// Stores `info`, adding its strings to `pool`.
+    pub fn new(pool: &mut StringPool, info: &ServerInfo) -> CompactServerInfo {
+        CompactServerInfo {
+            info_version: info.info_version,
+            token: info.token,
+            version: pool.intern(&info.version),
+            name: pool.intern(&info.name),
+            hostname: info.hostname.as_ref().map(|h| pool.intern(h)),
+            map: pool.intern(&info.map),
+            map_crc: info.map_crc,
+            map_size: info.map_size,
+            game_type: pool.intern(&info.game_type),
+            flags: info.flags,
+            progression: info.progression,
+            skill_level: info.skill_level,
+            num_players: info.num_players,
+            max_players: info.max_players,
+            num_clients: info.num_clients,
+            max_clients: info.max_clients,
+            clients: info.clients.iter().map(|c| CompactClient::new(pool, c)).collect::<Vec<_>>()
+                .into_boxed_slice(),
+        }
+    }
+    /// Restores the server info from the strings in `pool`.
+    pub fn get(&self, pool: &StringPool) -> ServerInfo {
+        ServerInfo {
+            info_version: self.info_version,
+            token: self.token,
+            version: string(pool, self.version),
+            name: string(pool, self.name),
+            hostname: self.hostname.map(|h| string(pool, h)),
+            map: string(pool, self.map),
+            map_crc: self.map_crc,
+            map_size: self.map_size,
+            game_type: string(pool, self.game_type),
+            flags: self.flags,
+            progression: self.progression,
+            skill_level: self.skill_level,
+            num_players: self.num_players,
+            max_players: self.max_players,
+            num_clients: self.num_clients,
+            max_clients: self.max_clients,
+            clients: self.clients.iter().map(|c| c.get(pool)).collect(),
+        }
+    }
+    /// Returns the same server info with its strings in the pool `to`
+    /// instead of `from`.
+    pub fn move_to(&self, from: &StringPool, to: &mut StringPool) -> CompactServerInfo {
+        let mut move_string = |id| to.intern(from.get(id));
+        CompactServerInfo {
+            version: move_string(self.version),
+            name: move_string(self.name),
+            hostname: self.hostname.map(&mut move_string),
+            map: move_string(self.map),
+            game_type: move_string(self.game_type),
+            clients: self.clients.iter().map(|c| c.move_to(from, to)).collect::<Vec<_>>()
+                .into_boxed_slice(),
+            ..*self
+        }
+    }
+    /// Returns the approximate number of bytes allocated by this server info,
+    /// not counting the strings in the pool.
+    pub fn memory_usage(&self) -> usize {
+        mem::size_of::<CompactServerInfo>() + mem::size_of_val(&*self.clients)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serverbrowse::protocol::ClientInfo;
+    use serverbrowse::protocol::ServerInfo;
+    use serverbrowse::protocol::ServerInfoVersion;
+
+    use super::CompactServerInfo;
+    use super::MAX_STRING_LENGTH;
+    use super::StringPool;
+
+    fn info(map: &str, clients: &[(&str, &str)]) -> ServerInfo {
+        ServerInfo {
+            info_version: ServerInfoVersion::V664,
+            token: 1,
+            version: "0.6.4".bytes().collect(),
+            name: "server".bytes().collect(),
+            hostname: Some("example.com".bytes().collect()),
+            map: map.bytes().collect(),
+            map_crc: Some(0x1234),
+            map_size: Some(5678),
+            game_type: "DM".bytes().collect(),
+            flags: 1,
+            progression: None,
+            skill_level: Some(2),
+            num_players: clients.len() as i32,
+            max_players: 16,
+            num_clients: clients.len() as i32,
+            max_clients: 16,
+            clients: clients.iter().map(|&(name, clan)| ClientInfo {
+                name: name.bytes().collect(),
+                clan: clan.bytes().collect(),
+                country: 276,
+                score: 3,
+                is_player: 1,
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn intern() {
+        let mut pool = StringPool::new();
+        assert!(pool.is_empty());
+        let a = pool.intern(b"a");
+        let empty = pool.intern(b"");
+        let long = pool.intern(&[b'x'; MAX_STRING_LENGTH]);
+        assert_eq!(pool.get(a), b"a");
+        assert_eq!(pool.get(empty), b"");
+        assert_eq!(pool.get(long), &[b'x'; MAX_STRING_LENGTH][..]);
+        assert_eq!(pool.len(), 3);
+    }
+
+    #[test]
+    fn dedup() {
+        let mut pool = StringPool::new();
+        let a = pool.intern(b"abc");
+        let b = pool.intern(b"abd");
+        assert!(a != b);
+        assert_eq!(pool.intern(b"abc"), a);
+        assert_eq!(pool.intern(b"abd"), b);
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn growth() {
+        let mut pool = StringPool::new();
+        let ids: Vec<_> = (0..1000).map(|i| pool.intern(i.to_string().as_bytes())).collect();
+        assert_eq!(pool.len(), 1000);
+        for (i, &id) in ids.iter().enumerate() {
+            assert_eq!(pool.get(id), i.to_string().as_bytes());
+            assert_eq!(pool.intern(i.to_string().as_bytes()), id);
+        }
+        assert_eq!(pool.len(), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "string too long")]
+    fn too_long() {
+        StringPool::new().intern(&[b'x'; MAX_STRING_LENGTH + 1]);
+    }
+
+    #[test]
+    fn get() {
+        let mut pool = StringPool::new();
+        let first = info("dm1", &[("a", "clan"), ("b", "clan")]);
+        let mut second = info("dm1", &[("a", "")]);
+        second.hostname = None;
+        let compact_first = CompactServerInfo::new(&mut pool, &first);
+        let compact_second = CompactServerInfo::new(&mut pool, &second);
+        assert!(compact_first.get(&pool) == first);
+        assert!(compact_second.get(&pool) == second);
+        // Shared strings are stored once.
+        assert_eq!(compact_first.map, compact_second.map);
+        assert_eq!(compact_first.clients[0].name, compact_second.clients[0].name);
+    }
+
+    #[test]
+    fn move_to() {
+        let mut pool = StringPool::new();
+        let old = CompactServerInfo::new(&mut pool, &info("dm1", &[("a", "clan")]));
+        let kept = info("dm2", &[("b", "clan")]);
+        let compact = CompactServerInfo::new(&mut pool, &kept);
+        drop(old);
+
+        let mut new_pool = StringPool::new();
+        let moved = compact.move_to(&pool, &mut new_pool);
+        assert!(moved.get(&new_pool) == kept);
+        // The strings only used by the dropped info are gone.
+        assert_eq!(new_pool.len(), pool.len() - 2);
+    }
+}
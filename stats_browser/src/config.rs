@@ -20,7 +20,13 @@ pub const INFO_REPEAT_MS:    Duration = Duration(  5_000);
 pub const LIST_EXPECT_MS:    Duration = Duration(  5_000);
 /// Time span after which a successful list request is repeated.
 pub const LIST_REPEAT_MS:    Duration = Duration( 30_000);
+/// Time span between checks whether a list fetch has finished.
+pub const FETCH_POLL_MS:     Duration = Duration(    100);
 /// Time span for re-resolving master servers.
 pub const RESOLVE_REPEAT_MS: Duration = Duration(120_000);
+/// Time span after which the state file is rewritten.
+pub const STATE_SAVE_MS:     Duration = Duration( 60_000);
+/// Size in bytes below which the string pool isn't rebuilt.
+pub const POOL_MIN_REBUILD_SIZE: usize = 1 << 20;
 /// Sleep time in the main loop.
 pub const SLEEP_MS:          Duration = Duration(      5);
@@ -1,9 +1,17 @@
+use serverbrowse::fetch::Fetch;
+use serverbrowse::fetch;
+use serverbrowse::json::Server;
 use serverbrowse::protocol::ServerInfo;
 
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
 
 use addr::Addr;
 use addr::ServerAddr;
+use compact::CompactServerInfo;
+use compact::StringPool;
 
 /// Describes a master server.
 #[derive(Clone)]
@@ -38,6 +46,37 @@ impl MasterServerEntry {
     }
 }
 
+/// The list fetched from a server list source.
+pub type FetchResult = Result<Vec<Server>, fetch::Error>;
+
+/// Describes a master server that publishes its list as JSON, e.g. over
+/// HTTP.
+pub struct HttpMasterEntry {
+    /// Source of the server list, shared with the thread fetching it.
+    pub fetch: Arc<Mutex<Box<Fetch>>>,
+    /// Description of the source, for logging.
+    pub source: String,
+    /// Receives the fetched list once the fetch running in the background is
+    /// done, `None` if no fetch is running.
+    pub pending: Option<Receiver<FetchResult>>,
+
+    /// Servers that the master server lists.
+    pub list: HashSet<ServerAddr>,
+}
+
+impl HttpMasterEntry {
+    /// Creates a new master server entry with an empty list from a source.
+    pub fn new(fetch: Box<Fetch>) -> HttpMasterEntry {
+        HttpMasterEntry {
+            source: fetch.source(),
+            fetch: Arc::new(Mutex::new(fetch)),
+            pending: None,
+
+            list: HashSet::new(),
+        }
+    }
+}
+
 /// Describes a server.
 #[derive(Clone)]
 pub struct ServerEntry {
@@ -70,15 +109,16 @@ impl ServerEntry {
 /// Describes a server info response.
 #[derive(Clone)]
 pub struct ServerResponse {
-    /// The server info received from the info request.
-    pub info: ServerInfo,
+    /// The server info received from the info request, with its strings in
+    /// the string pool of the browser.
+    pub info: CompactServerInfo,
 }
 
 impl ServerResponse {
     /// Creates a new server response from the received server info.
-    pub fn new(info: ServerInfo) -> ServerResponse {
+    pub fn new(pool: &mut StringPool, info: &ServerInfo) -> ServerResponse {
         ServerResponse {
-            info: info,
+            info: CompactServerInfo::new(pool, info),
         }
     }
 }
@@ -1,7 +1,7 @@
-#![cfg(not(test))]
-
 #[macro_use] extern crate log;
+extern crate map;
 extern crate rustc_serialize;
+#[cfg(feature = "sqlite")] #[macro_use] extern crate rusqlite;
 extern crate time as rust_time;
 
 #[macro_use] extern crate common;
@@ -12,13 +12,21 @@ pub use stats_browser::StatsBrowserCb;
 
 pub mod addr;
 pub mod base64;
+pub mod compact;
 pub mod config;
 pub mod entry;
 pub mod hashmap_ext;
 pub mod lookup;
+pub mod maps;
+pub mod sessions;
 pub mod socket;
+pub mod state;
 pub mod stats_browser;
 pub mod time;
+pub mod tracker;
 pub mod tracker_fstd;
+pub mod tracker_json;
+pub mod tracker_snapshot;
+#[cfg(feature = "sqlite")] pub mod tracker_sqlite;
 pub mod vec_map;
 pub mod work_queue;
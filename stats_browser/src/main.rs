@@ -1,25 +1,87 @@
 #![cfg(not(test))]
 
-extern crate clap;
+#[macro_use] extern crate clap;
 extern crate logger;
+extern crate serverbrowse;
 extern crate stats_browser;
 
 use clap::App;
 use clap::Arg;
+use serverbrowse::fetch::Fetch;
+use serverbrowse::fetch::FileFetch;
+use serverbrowse::fetch::HttpFetch;
 use stats_browser::StatsBrowser;
 use stats_browser::StatsBrowserCb;
+use stats_browser::maps::MapFetcher;
+use stats_browser::time::Duration;
 use stats_browser::tracker_fstd;
+use stats_browser::tracker_json;
+use stats_browser::tracker_snapshot;
+#[cfg(feature = "sqlite")] use stats_browser::tracker_sqlite;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
 
-fn run_browser<T: StatsBrowserCb>(tracker: &mut T) {
+struct Options {
+    http_masters: Vec<Box<Fetch>>,
+    state: Option<PathBuf>,
+    /// Map cache directory and downloader program, if maps are fetched.
+    maps: Option<(PathBuf, PathBuf)>,
+}
+
+fn run_browser_inner<T: StatsBrowserCb>(tracker: &mut T, http_masters: Vec<Box<Fetch>>, state: Option<PathBuf>) {
     let mut browser = match StatsBrowser::new(tracker) {
         Some(b) => b,
         None => {
             panic!("Failed to bind socket.");
         },
     };
+    for fetch in http_masters {
+        browser.add_http_master(fetch);
+    }
+    if let Some(state) = state {
+        browser.set_state_file(state.clone())
+            .unwrap_or_else(|e| panic!("Couldn't load state from {}, {}", state.display(), e));
+    }
     browser.run();
 }
 
+fn run_browser<T: StatsBrowserCb>(tracker: &mut T, options: Options) {
+    match options.maps {
+        Some((cache, downloader)) => {
+            let mut fetcher = MapFetcher::new(tracker, cache.clone(), downloader)
+                .unwrap_or_else(|e| panic!("Couldn't create {}, {}", cache.display(), e));
+            run_browser_inner(&mut fetcher, options.http_masters, options.state);
+        }
+        None => run_browser_inner(tracker, options.http_masters, options.state),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+const FORMATS: &'static [&'static str] = &["fstd", "json", "snapshot", "sqlite"];
+#[cfg(not(feature = "sqlite"))]
+const FORMATS: &'static [&'static str] = &["fstd", "json", "snapshot"];
+
+#[cfg(feature = "sqlite")]
+fn run_sqlite(output: Option<PathBuf>, options: Options) {
+    let output = output.unwrap_or_else(|| panic!("The sqlite format needs an output file."));
+    let mut tracker = tracker_sqlite::Tracker::open(&output)
+        .unwrap_or_else(|e| panic!("Couldn't open {}, {}", output.display(), e));
+    // Sessions left open by a previous run are continued if the servers are
+    // restored from the state file.
+    let result = match options.state {
+        Some(ref s) if s.exists() => tracker.resume(),
+        _ => tracker.start(),
+    };
+    result.unwrap_or_else(|e| panic!("Couldn't start tracking, {}", e));
+    run_browser(&mut tracker, options);
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn run_sqlite(_: Option<PathBuf>, _: Options) {
+    unreachable!();
+}
+
 fn main() {
     logger::init();
 
@@ -31,18 +93,96 @@ fn main() {
             .short("f")
             .long("format")
             .takes_value(true)
-            .value_name("FILE")
+            .value_name("FORMAT")
             .default_value("fstd")
-            .possible_value("fstd")
-            .help("Output format"))
+            .possible_values(FORMATS)
+            .help("Output format: fstd or json event logs, periodic snapshots \
+                   of all servers or a sqlite database of player sessions"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Output file, required for the snapshot and sqlite formats; \
+                   event logs are written to stdout by default"))
+        .arg(Arg::with_name("interval")
+            .long("interval")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .default_value("60")
+            .help("Time between two snapshots"))
+        .arg(Arg::with_name("http-master")
+            .long("http-master")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("URL_OR_FILE")
+            .help("Additionally tracks the servers of a JSON server list, \
+                   given as HTTP or HTTPS URL or as local file"))
+        .arg(Arg::with_name("map-cache")
+            .long("map-cache")
+            .takes_value(true)
+            .value_name("DIR")
+            .help("Downloads the maps of the tracked servers into this directory"))
+        .arg(Arg::with_name("downloader")
+            .long("downloader")
+            .takes_value(true)
+            .value_name("PROGRAM")
+            .default_value("downloader")
+            .help("Downloader program used to fetch maps"))
+        .arg(Arg::with_name("state")
+            .long("state")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Keeps the known servers in this file, so that a restart \
+                   only reports actual changes"))
         .get_matches();
 
+    let http_masters = matches.values_of("http-master").map(|v| v.map(|source| {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let fetch = HttpFetch::from_url(source)
+                .unwrap_or_else(|| panic!("Invalid URL {}", source));
+            Box::new(fetch) as Box<Fetch>
+        } else {
+            Box::new(FileFetch::new(source))
+        }
+    }).collect()).unwrap_or_default();
+
+    let maps = matches.value_of_os("map-cache").map(|cache| {
+        (PathBuf::from(cache), PathBuf::from(matches.value_of_os("downloader").unwrap()))
+    });
+    let options = Options {
+        http_masters: http_masters,
+        state: matches.value_of_os("state").map(PathBuf::from),
+        maps: maps,
+    };
+
+    let output = matches.value_of_os("output").map(PathBuf::from);
+    let interval = value_t!(matches, "interval", u32).unwrap_or_else(|e| e.exit());
+
     match matches.value_of("format").unwrap() {
         "fstd" => {
             let mut tracker = tracker_fstd::Tracker::new();
             tracker.start();
-            run_browser(&mut tracker);
+            run_browser(&mut tracker, options);
+        }
+        "json" => {
+            let output: Box<io::Write> = match output {
+                Some(o) => Box::new(File::create(&o)
+                    .unwrap_or_else(|e| panic!("Couldn't create {}, {}", o.display(), e))),
+                None => Box::new(io::stdout()),
+            };
+            let mut tracker = tracker_json::Tracker::new(output);
+            tracker.start();
+            run_browser(&mut tracker, options);
+        }
+        "snapshot" => {
+            let output = output.unwrap_or_else(|| panic!("The snapshot format needs an output file."));
+            let interval = Duration(i64::from(interval) * 1000);
+            let mut tracker = tracker_snapshot::Tracker::new(output, interval);
+            run_browser(&mut tracker, options);
         }
+        "sqlite" => run_sqlite(output, options),
         _ => unreachable!(),
     }
 }
@@ -0,0 +1,393 @@
+//! Archive of the maps played on the tracked servers.
+//!
+//! When a server runs a map that isn't in the cache directory yet, the
+//! `downloader` program is spawned with `--current-map` to fetch it, using the
+//! same connection logic as a game client. Maps are stored content-addressed
+//! by name and CRC as `maps/<name>_<crc>.map` in the cache directory, which is
+//! also the layout the downloader uses. Next to each map, a
+//! `maps/<name>_<crc>.json` file records its size and the map info: author,
+//! version, credits, license and server settings.
+//!
+//! Only servers replying with the extended info tell the CRC of their map.
+//! For the others, the map is downloaded if the cache contains no map of the
+//! same name.
+
+use map::info::MapInfo;
+use map;
+use rustc_serialize::json::Json;
+use rustc_serialize::json::Object;
+use serverbrowse::protocol::ServerInfo;
+
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::fs;
+use std::io::Write;
+use std::io;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+
+use addr::ServerAddr;
+
+use StatsBrowserCb;
+
+/// Maximum number of downloads running at the same time.
+pub const MAX_DOWNLOADS: usize = 4;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct MapId {
+    name: Vec<u8>,
+    crc: Option<u32>,
+}
+
+impl MapId {
+    fn from_info(info: &ServerInfo) -> Option<MapId> {
+        // Map names containing path separators are refused by the
+        // downloader.
+        if info.map.is_empty() || info.map.iter().any(|&b| b == b'/' || b == b'\\') {
+            return None;
+        }
+        Some(MapId { name: info.map.to_vec(), crc: info.map_crc })
+    }
+    /// Checks whether the file name of a cached map, as used by the
+    /// downloader, matches this map.
+    #[cfg(test)]
+    fn matches(&self, file_name: &str) -> bool {
+        split_file_name(file_name).map(|s| self.matches_stem(s)).unwrap_or(false)
+    }
+    /// Checks whether the stem of a cached map, `<name>_<crc>`, matches this
+    /// map.
+    fn matches_stem(&self, stem: &str) -> bool {
+        let name = String::from_utf8_lossy(&self.name);
+        match self.crc {
+            Some(crc) => stem == format!("{}_{:08x}", name, crc),
+            None => stem.len() == name.len() + 9 && stem.starts_with(&*name),
+        }
+    }
+}
+
+impl fmt::Display for MapId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = String::from_utf8_lossy(&self.name);
+        match self.crc {
+            Some(crc) => write!(f, "{}_{:08x}", name, crc),
+            None => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Returns the stem of `<name>_<crc>.map` file names, `None` for other files.
+fn split_file_name(file_name: &str) -> Option<&str> {
+    if !file_name.ends_with(".map") {
+        return None;
+    }
+    let stem = &file_name[..file_name.len() - 4];
+    let bytes = stem.as_bytes();
+    if bytes.len() < 9 || bytes[bytes.len() - 9] != b'_' {
+        return None;
+    }
+    if !bytes[bytes.len() - 8..].iter().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(stem)
+}
+
+struct Download {
+    map: MapId,
+    server: ServerAddr,
+    child: Child,
+}
+
+fn string(bytes: &[u8]) -> Json {
+    Json::String(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    Map(map::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<map::Error> for Error {
+    fn from(err: map::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+/// Wraps another tracker, downloading the maps of the servers it is told
+/// about.
+pub struct MapFetcher<T: StatsBrowserCb> {
+    inner: T,
+    cache: PathBuf,
+    downloader: PathBuf,
+    /// Maps that are in the cache or have already been tried in this run.
+    known: HashSet<MapId>,
+    /// Stems of the maps in the cache.
+    cached: HashSet<String>,
+    queue: VecDeque<(MapId, ServerAddr)>,
+    running: Vec<Download>,
+}
+
+impl<T: StatsBrowserCb> MapFetcher<T> {
+    /// Creates a map fetcher storing the maps in `cache`, running
+    /// `downloader` to fetch them.
+    pub fn new(inner: T, cache: PathBuf, downloader: PathBuf) -> io::Result<MapFetcher<T>> {
+        fs::create_dir_all(cache.join("maps"))?;
+        fs::create_dir_all(cache.join("downloading"))?;
+        let mut result = MapFetcher {
+            inner: inner,
+            cache: cache,
+            downloader: downloader,
+            known: HashSet::new(),
+            cached: HashSet::new(),
+            queue: VecDeque::new(),
+            running: Vec::new(),
+        };
+        result.update_cache();
+        Ok(result)
+    }
+    fn maps_dir(&self) -> PathBuf {
+        self.cache.join("maps")
+    }
+    /// Returns the stems of the maps in the cache.
+    fn cached_maps(&self) -> io::Result<HashSet<String>> {
+        let mut result = HashSet::new();
+        for entry in fs::read_dir(self.maps_dir())? {
+            let file_name = entry?.file_name();
+            if let Some(stem) = file_name.to_str().and_then(split_file_name) {
+                result.insert(stem.to_owned());
+            }
+        }
+        Ok(result)
+    }
+    fn is_cached(&self, map: &MapId) -> bool {
+        match map.crc {
+            Some(_) => self.cached.contains(&map.to_string()),
+            None => self.cached.iter().any(|s| map.matches_stem(s)),
+        }
+    }
+    fn check_map(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let map = match MapId::from_info(info) {
+            Some(m) => m,
+            None => return,
+        };
+        if !self.known.insert(map.clone()) || self.is_cached(&map) {
+            return;
+        }
+        self.queue.push_back((map, addr));
+    }
+    /// Rereads the list of cached maps and records the info of the ones
+    /// that don't have it yet.
+    fn update_cache(&mut self) {
+        let stems = match self.cached_maps() {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Couldn't list {}, {}", self.maps_dir().display(), e);
+                return;
+            },
+        };
+        for stem in &stems {
+            if self.cached.contains(stem) || self.maps_dir().join(format!("{}.json", stem)).exists() {
+                continue;
+            }
+            if let Err(e) = self.write_info(stem) {
+                error!("Couldn't record the info of map {}, {:?}", stem, e);
+            }
+        }
+        self.cached = stems;
+    }
+    fn write_info(&self, stem: &str) -> Result<(), Error> {
+        let path = self.maps_dir().join(format!("{}.map", stem));
+        let size = fs::metadata(&path)?.len();
+        let mut reader = map::Reader::open(&path)?;
+        let info = MapInfo::read(&mut reader)?;
+
+        // `split_file_name` guarantees the `_<crc>` suffix.
+        let (name, crc) = stem.split_at(stem.len() - 9);
+        let mut result = Object::new();
+        result.insert("name".to_owned(), Json::String(name.to_owned()));
+        result.insert("crc".to_owned(), Json::String(crc[1..].to_owned()));
+        result.insert("size".to_owned(), Json::U64(size));
+        let fields = [
+            ("author", &info.author),
+            ("version", &info.version),
+            ("credits", &info.credits),
+            ("license", &info.license),
+        ];
+        for &(name, value) in &fields {
+            if let Some(ref v) = *value {
+                result.insert(name.to_owned(), string(v));
+            }
+        }
+        let settings = info.settings.iter().map(|s| string(s)).collect();
+        result.insert("settings".to_owned(), Json::Array(settings));
+
+        let mut file = File::create(self.maps_dir().join(format!("{}.json", stem)))?;
+        writeln!(file, "{}", Json::Object(result))?;
+        Ok(())
+    }
+    fn start_downloads(&mut self) {
+        while self.running.len() < MAX_DOWNLOADS {
+            let (map, server) = match self.queue.pop_front() {
+                Some(x) => x,
+                None => return,
+            };
+            let child = Command::new(&self.downloader)
+                .arg("--current-map")
+                .arg(server.addr.to_string())
+                .current_dir(&self.cache)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            match child {
+                Ok(c) => {
+                    info!("Downloading map {} from {}", map, server.addr);
+                    self.running.push(Download { map: map, server: server, child: c });
+                },
+                Err(e) => error!("Couldn't run {}, {}", self.downloader.display(), e),
+            }
+        }
+    }
+    fn check_downloads(&mut self) {
+        let mut finished = Vec::new();
+        let mut i = 0;
+        while i < self.running.len() {
+            match self.running[i].child.try_wait() {
+                Ok(None) => {
+                    i += 1;
+                    continue;
+                },
+                Ok(Some(_)) => {},
+                Err(e) => error!("Couldn't wait for the downloader, {}", e),
+            }
+            finished.push(self.running.swap_remove(i));
+        }
+        if finished.is_empty() {
+            return;
+        }
+        // The downloader stores whatever map the server is on, which might
+        // not be the one that was asked for.
+        self.update_cache();
+        for download in finished {
+            if self.is_cached(&download.map) {
+                info!("Downloaded map {}", download.map);
+            } else {
+                // The map isn't retried in this run, the server might simply
+                // have changed the map in the meantime.
+                warn!("Couldn't download map {} from {}", download.map, download.server.addr);
+            }
+        }
+    }
+}
+
+impl<T: StatsBrowserCb> StatsBrowserCb for MapFetcher<T> {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.check_map(addr, info);
+        self.inner.on_server_new(addr, info);
+    }
+
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        if old.map != new.map || old.map_crc != new.map_crc {
+            self.check_map(addr, new);
+        }
+        self.inner.on_server_change(addr, old, new);
+    }
+
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        self.inner.on_server_remove(addr, last);
+    }
+
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.check_map(addr, info);
+        self.inner.on_server_restore(addr, info);
+    }
+
+    fn on_tick(&mut self) {
+        self.check_downloads();
+        self.start_downloads();
+        self.inner.on_tick();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serverbrowse::protocol::ServerInfo;
+
+    use super::MapId;
+    use super::split_file_name;
+
+    fn map(name: &str, crc: Option<u32>) -> MapId {
+        MapId { name: name.as_bytes().to_vec(), crc: crc }
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_file_name("dm1_f2159e6e.map"), Some("dm1_f2159e6e"));
+        assert_eq!(split_file_name("ctf_5_F2159E6E.map"), Some("ctf_5_F2159E6E"));
+        assert_eq!(split_file_name("_f2159e6e.map"), Some("_f2159e6e"));
+        assert_eq!(split_file_name("dm1_f2159e6e.json"), None);
+        assert_eq!(split_file_name("dm1.map"), None);
+        assert_eq!(split_file_name("dm1_f2159e6.map"), None);
+        assert_eq!(split_file_name("dm1_f2159e6g.map"), None);
+        assert_eq!(split_file_name("dm1-f2159e6e.map"), None);
+        assert_eq!(split_file_name("f2159e6e.map"), None);
+    }
+
+    #[test]
+    fn matches_crc() {
+        let dm1 = map("dm1", Some(0xf2159e6e));
+        assert!(dm1.matches("dm1_f2159e6e.map"));
+        assert!(!dm1.matches("dm1_f2159e6f.map"));
+        assert!(!dm1.matches("dm2_f2159e6e.map"));
+        assert!(!dm1.matches("dm1_f2159e6e.json"));
+        assert!(!dm1.matches("xdm1_f2159e6e.map"));
+
+        let ctf = map("ctf_5", Some(0x0000abcd));
+        assert!(ctf.matches("ctf_5_0000abcd.map"));
+        assert!(!ctf.matches("ctf_0000abcd.map"));
+        assert!(!ctf.matches("ctf_5_x_0000abcd.map"));
+    }
+
+    #[test]
+    fn matches_name() {
+        let ctf = map("ctf_5", None);
+        assert!(ctf.matches("ctf_5_0000abcd.map"));
+        assert!(ctf.matches("ctf_5_12345678.map"));
+        assert!(!ctf.matches("ctf_5.map"));
+        assert!(!ctf.matches("ctf_0000abcd.map"));
+        assert!(!ctf.matches("ctf_5_x_0000abcd.map"));
+        assert!(!ctf.matches("ctf_55_0000abcd.map"));
+
+        // The name of a map can look like a CRC suffix itself.
+        let tricky = map("dm1_f2159e6e", None);
+        assert!(tricky.matches("dm1_f2159e6e_0000abcd.map"));
+        assert!(!tricky.matches("dm1_f2159e6e.map"));
+        assert!(!map("dm1", None).matches("dm1_f2159e6e_0000abcd.map"));
+    }
+
+    #[test]
+    fn from_info() {
+        let mut info = ServerInfo {
+            map: "ctf_5".bytes().collect(),
+            map_crc: Some(1),
+            ..ServerInfo::default()
+        };
+        assert_eq!(MapId::from_info(&info), Some(map("ctf_5", Some(1))));
+        assert_eq!(map("ctf_5", Some(1)).to_string(), "ctf_5_00000001");
+        assert_eq!(map("ctf_5", None).to_string(), "ctf_5");
+        for name in &["", "../dm1", "maps/dm1", "dm1\\x"] {
+            info.map = name.bytes().collect();
+            assert_eq!(MapId::from_info(&info), None);
+        }
+    }
+}
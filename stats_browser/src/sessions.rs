@@ -0,0 +1,419 @@
+//! Player sessions and statistics derived from the server info updates.
+//!
+//! Servers are only polled every few seconds, so the exact time a player
+//! joined or left isn't known. A join is noticed in the first info response
+//! that contains the player, so it happened between the previous response
+//! and that one. The same holds for leaving. Sessions carry these bounds.
+//!
+//! Times are milliseconds since the Unix epoch.
+
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map;
+use std::ptr;
+
+use rust_time;
+
+use addr::ServerAddr;
+use tracker::PlayerDiff;
+use tracker::diff_players;
+use tracker::player_ignore;
+
+use StatsBrowserCb;
+
+/// Number of finished sessions kept by a `SessionTracker`, older ones only
+/// count towards the play times.
+pub const MAX_FINISHED_SESSIONS: usize = 1000;
+
+/// Returns the current time in milliseconds since the Unix epoch.
+pub fn now() -> i64 {
+    let time = rust_time::get_time();
+    time.sec * 1000 + i64::from(time.nsec / 1_000_000)
+}
+
+/// Time span in which an event happened.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Bounds {
+    /// Earliest time of the event, `None` if unknown, e.g. for players that
+    /// were already online when the server was first seen.
+    pub earliest: Option<i64>,
+    /// Latest time of the event.
+    pub latest: i64,
+}
+
+impl Bounds {
+    /// Returns the middle of the bounds, or the latest time if the earliest
+    /// one is unknown.
+    pub fn estimate(&self) -> i64 {
+        match self.earliest {
+            Some(e) => e + (self.latest - e) / 2,
+            None => self.latest,
+        }
+    }
+}
+
+/// Time a player spent on a server.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub name: Vec<u8>,
+    /// Last known clan of the player.
+    pub clan: Vec<u8>,
+    pub server: ServerAddr,
+    pub start: Bounds,
+    /// End of the session, `None` if the player is still online.
+    pub end: Option<Bounds>,
+}
+
+impl Session {
+    /// Returns the estimated duration of the session, assuming that open
+    /// sessions end at `now`.
+    pub fn duration(&self, now: i64) -> i64 {
+        let end = self.end.map(|e| e.estimate()).unwrap_or(now);
+        cmp::max(end - self.start.estimate(), 0)
+    }
+}
+
+/// Highest number of simultaneously online clients.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Peak {
+    pub clients: u32,
+    /// Time the peak was first reached.
+    pub time: i64,
+}
+
+struct Server {
+    last_update: i64,
+    game_type: Vec<u8>,
+    map: Vec<u8>,
+    num_clients: u32,
+}
+
+fn num_clients(info: &ServerInfo) -> u32 {
+    info.clients.iter().filter(|c| !player_ignore(c)).count() as u32
+}
+
+/// Tracks player sessions and aggregates statistics about them.
+///
+/// Can be used directly as a `StatsBrowserCb`, or fed with explicit times
+/// through the `server_*` methods.
+#[derive(Default)]
+pub struct SessionTracker {
+    servers: HashMap<ServerAddr, Server>,
+    /// Open sessions, by server and player name. Players with the same name
+    /// on one server each have their own session, in the order they appear
+    /// in the player list.
+    open: HashMap<(ServerAddr, Vec<u8>), Vec<Session>>,
+    /// The last `MAX_FINISHED_SESSIONS` finished sessions.
+    finished: VecDeque<Session>,
+    /// Accumulated duration of the finished sessions of each player.
+    finished_time: HashMap<Vec<u8>, i64>,
+
+    clients_by_game_type: HashMap<Vec<u8>, u32>,
+    peaks: HashMap<Vec<u8>, Peak>,
+    /// Accumulated time spent by clients on each map.
+    map_time: HashMap<Vec<u8>, i64>,
+}
+
+impl SessionTracker {
+    pub fn new() -> SessionTracker {
+        Default::default()
+    }
+    /// Sessions of the players that are currently online.
+    pub fn open_sessions(&self) -> Vec<&Session> {
+        self.open.values().flatten().collect()
+    }
+    /// The most recently ended sessions, at most `MAX_FINISHED_SESSIONS`, in
+    /// the order they ended.
+    pub fn finished_sessions(&self) -> &VecDeque<Session> {
+        &self.finished
+    }
+    /// Returns the total play time of each player, in milliseconds.
+    pub fn play_times(&self, now: i64) -> HashMap<Vec<u8>, i64> {
+        let mut result = self.finished_time.clone();
+        for s in self.open.values().flatten() {
+            *result.entry(s.name.clone()).or_insert(0) += s.duration(now);
+        }
+        result
+    }
+    /// Returns the total play time of a player, in milliseconds.
+    pub fn play_time(&self, name: &[u8], now: i64) -> i64 {
+        let open: i64 = self.open.values()
+            .flatten()
+            .filter(|s| s.name == name)
+            .map(|s| s.duration(now))
+            .sum();
+        self.finished_time.get(name).cloned().unwrap_or(0) + open
+    }
+    /// Returns the highest number of clients simultaneously online on
+    /// servers of each game type.
+    pub fn peaks(&self) -> &HashMap<Vec<u8>, Peak> {
+        &self.peaks
+    }
+    /// Returns the maps, sorted by the time clients spent on them, most
+    /// popular first. Times are in milliseconds.
+    pub fn map_popularity(&self) -> Vec<(&[u8], i64)> {
+        let mut result: Vec<_> = self.map_time.iter().map(|(m, &t)| (&m[..], t)).collect();
+        result.sort_by(|&(m1, t1), &(m2, t2)| t2.cmp(&t1).then(m1.cmp(m2)));
+        result
+    }
+
+    fn set_clients(&mut self, time: i64, game_type: &[u8], old: u32, new: u32) {
+        let count = self.clients_by_game_type.entry(game_type.to_vec()).or_insert(0);
+        *count = *count - old + new;
+        let peak = self.peaks.entry(game_type.to_vec()).or_insert(Peak::default());
+        if *count > peak.clients {
+            *peak = Peak { clients: *count, time: time };
+        }
+    }
+    fn player_new(&mut self, addr: ServerAddr, player: &ClientInfo, start: Bounds) {
+        let session = Session {
+            name: player.name.to_vec(),
+            clan: player.clan.to_vec(),
+            server: addr,
+            start: start,
+            end: None,
+        };
+        self.open.entry((addr, player.name.to_vec())).or_default().push(session);
+    }
+    fn player_remove(&mut self, addr: ServerAddr, player: &ClientInfo, end: Bounds) {
+        // Players with the same name that are still online come first in
+        // the player list, so the last session is the one that ended.
+        let key = (addr, player.name.to_vec());
+        let session = match self.open.entry(key) {
+            hash_map::Entry::Occupied(mut o) => {
+                let session = o.get_mut().pop();
+                if o.get().is_empty() {
+                    o.remove();
+                }
+                session
+            },
+            hash_map::Entry::Vacant(_) => None,
+        };
+        if let Some(mut session) = session {
+            session.end = Some(end);
+            *self.finished_time.entry(session.name.clone()).or_insert(0) += session.duration(end.latest);
+            if self.finished.len() == MAX_FINISHED_SESSIONS {
+                self.finished.pop_front();
+            }
+            self.finished.push_back(session);
+        }
+    }
+    /// Credits the time since the last update of a server to its map.
+    fn add_map_time(&mut self, map: &[u8], clients: u32, last_update: i64, time: i64) {
+        *self.map_time.entry(map.to_vec()).or_insert(0) += i64::from(clients) * (time - last_update);
+    }
+    fn diff_players(&mut self, addr: ServerAddr, old: &[ClientInfo], new: &[ClientInfo], bounds: Bounds) {
+        for diff in diff_players(old, new) {
+            match diff {
+                PlayerDiff::New(player) => self.player_new(addr, player, bounds),
+                PlayerDiff::Change(_, player) => {
+                    // Players with the same name are told apart by their
+                    // order in the player list.
+                    let index = new.iter()
+                        .take_while(|&c| !ptr::eq(c, player))
+                        .filter(|c| c.name == player.name)
+                        .count();
+                    let key = (addr, player.name.to_vec());
+                    if let Some(s) = self.open.get_mut(&key).and_then(|s| s.get_mut(index)) {
+                        s.clan = player.clan.to_vec();
+                    }
+                },
+                PlayerDiff::Remove(player) => self.player_remove(addr, player, bounds),
+            }
+        }
+    }
+
+    /// Records a newly seen server at `time`.
+    pub fn server_new(&mut self, time: i64, addr: ServerAddr, info: &ServerInfo) {
+        let clients = num_clients(info);
+        self.servers.insert(addr, Server {
+            last_update: time,
+            game_type: info.game_type.to_vec(),
+            map: info.map.to_vec(),
+            num_clients: clients,
+        });
+        self.set_clients(time, &info.game_type, 0, clients);
+        // The players might have joined long before.
+        let start = Bounds { earliest: None, latest: time };
+        self.diff_players(addr, &[], &info.clients, start);
+    }
+    /// Records a server info update received at `time`.
+    pub fn server_change(&mut self, time: i64, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        let (last_update, old_clients) = match self.servers.get(&addr) {
+            Some(s) => (s.last_update, s.num_clients),
+            None => {
+                self.server_new(time, addr, new);
+                return;
+            },
+        };
+        let new_clients = num_clients(new);
+        self.add_map_time(&old.map, old_clients, last_update, time);
+        {
+            let server = self.servers.get_mut(&addr).unwrap();
+            server.last_update = time;
+            server.game_type = new.game_type.to_vec();
+            server.map = new.map.to_vec();
+            server.num_clients = new_clients;
+        }
+        self.set_clients(time, &old.game_type, old_clients, 0);
+        self.set_clients(time, &new.game_type, 0, new_clients);
+        let bounds = Bounds { earliest: Some(last_update), latest: time };
+        self.diff_players(addr, &old.clients, &new.clients, bounds);
+    }
+    /// Records that the server stopped responding, noticed at `time`.
+    pub fn server_remove(&mut self, time: i64, addr: ServerAddr, last: &ServerInfo) {
+        let server = match self.servers.remove(&addr) {
+            Some(s) => s,
+            None => return,
+        };
+        self.add_map_time(&server.map, server.num_clients, server.last_update, time);
+        self.set_clients(time, &server.game_type, server.num_clients, 0);
+        let end = Bounds { earliest: Some(server.last_update), latest: time };
+        self.diff_players(addr, &last.clients, &[], end);
+    }
+}
+
+impl StatsBrowserCb for SessionTracker {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.server_new(now(), addr, info);
+    }
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        self.server_change(now(), addr, old, new);
+    }
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        self.server_remove(now(), addr, last);
+    }
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.server_new(now(), addr, info);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serverbrowse::protocol::ClientInfo;
+    use serverbrowse::protocol::ServerInfo;
+
+    use addr::Addr;
+    use addr::ProtocolVersion;
+    use addr::ServerAddr;
+
+    use super::Bounds;
+    use super::MAX_FINISHED_SESSIONS;
+    use super::Peak;
+    use super::SessionTracker;
+
+    fn addr(port: u16) -> ServerAddr {
+        let addr = format!("192.0.2.1:{}", port).parse().unwrap();
+        ServerAddr::new(ProtocolVersion::V6, Addr::from_socket_addr(addr))
+    }
+
+    fn info(game_type: &str, map: &str, players: &[&str]) -> ServerInfo {
+        ServerInfo {
+            game_type: game_type.bytes().collect(),
+            map: map.bytes().collect(),
+            clients: players.iter().map(|name| ClientInfo {
+                name: name.bytes().collect(),
+                ..ClientInfo::default()
+            }).collect(),
+            ..ServerInfo::default()
+        }
+    }
+
+    #[test]
+    fn sessions() {
+        let mut tracker = SessionTracker::new();
+        let first = info("DM", "dm1", &["a", "b"]);
+        let second = info("DM", "dm1", &["b", "c"]);
+        tracker.server_new(0, addr(8303), &first);
+        tracker.server_change(4000, addr(8303), &first, &second);
+
+        let finished = tracker.finished_sessions();
+        assert_eq!(finished.len(), 1);
+        assert_eq!(finished[0].name, b"a");
+        assert_eq!(finished[0].start, Bounds { earliest: None, latest: 0 });
+        assert_eq!(finished[0].end, Some(Bounds { earliest: Some(0), latest: 4000 }));
+        let c = tracker.open_sessions().into_iter().find(|s| s.name == b"c").unwrap();
+        assert_eq!(c.start, Bounds { earliest: Some(0), latest: 4000 });
+
+        assert_eq!(tracker.play_time(b"a", 10000), 2000);
+        assert_eq!(tracker.play_time(b"b", 10000), 10000);
+        assert_eq!(tracker.play_time(b"c", 10000), 8000);
+        assert_eq!(tracker.play_time(b"d", 10000), 0);
+
+        tracker.server_remove(6000, addr(8303), &second);
+        assert!(tracker.open_sessions().is_empty());
+        let times = tracker.play_times(10000);
+        assert_eq!(times.len(), 3);
+        assert_eq!(times[&b"a"[..]], 2000);
+        assert_eq!(times[&b"b"[..]], 5000);
+        assert_eq!(times[&b"c"[..]], 3000);
+    }
+
+    #[test]
+    fn map_popularity() {
+        let mut tracker = SessionTracker::new();
+        let dm1 = info("DM", "dm1", &["a", "b"]);
+        let dm2 = info("DM", "dm2", &["a", "b"]);
+        let ctf1 = info("CTF", "ctf1", &["c"]);
+        tracker.server_new(0, addr(8303), &dm1);
+        tracker.server_new(0, addr(8304), &ctf1);
+        tracker.server_change(1000, addr(8303), &dm1, &dm2);
+        tracker.server_change(1000, addr(8304), &ctf1, &ctf1);
+        // The time until a server is removed also counts.
+        tracker.server_remove(3000, addr(8303), &dm2);
+        let expected: &[(&[u8], i64)] = &[(b"dm2", 4000), (b"dm1", 2000), (b"ctf1", 1000)];
+        assert_eq!(tracker.map_popularity(), expected);
+    }
+
+    #[test]
+    fn peaks() {
+        let mut tracker = SessionTracker::new();
+        let one = info("DM", "dm1", &["a"]);
+        let two = info("DM", "dm1", &["a", "(connecting)", "b"]);
+        tracker.server_new(0, addr(8303), &one);
+        tracker.server_new(1000, addr(8304), &two);
+        tracker.server_change(2000, addr(8304), &two, &one);
+        tracker.server_new(3000, addr(8305), &one);
+        tracker.server_remove(4000, addr(8303), &one);
+        assert_eq!(tracker.peaks()[&b"DM"[..]], Peak { clients: 3, time: 1000 });
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut tracker = SessionTracker::new();
+        let one = info("DM", "dm1", &["a"]);
+        let two = info("DM", "dm1", &["a", "a"]);
+        tracker.server_new(0, addr(8303), &one);
+        tracker.server_change(1000, addr(8303), &one, &two);
+        assert_eq!(tracker.open_sessions().len(), 2);
+        assert_eq!(tracker.play_time(b"a", 2000), 2000 + 1500);
+
+        tracker.server_change(2000, addr(8303), &two, &one);
+        assert_eq!(tracker.open_sessions().len(), 1);
+        assert_eq!(tracker.finished_sessions().len(), 1);
+        assert_eq!(tracker.finished_sessions()[0].start, Bounds { earliest: Some(0), latest: 1000 });
+
+        tracker.server_remove(3000, addr(8303), &one);
+        assert!(tracker.open_sessions().is_empty());
+        assert_eq!(tracker.finished_sessions().len(), 2);
+    }
+
+    #[test]
+    fn finished_bounded() {
+        let mut tracker = SessionTracker::new();
+        let empty = info("DM", "dm1", &[]);
+        let full = info("DM", "dm1", &["a"]);
+        tracker.server_new(0, addr(8303), &empty);
+        let num = MAX_FINISHED_SESSIONS + 10;
+        for i in 0..num as i64 {
+            tracker.server_change(i * 20 + 10, addr(8303), &empty, &full);
+            tracker.server_change(i * 20 + 20, addr(8303), &full, &empty);
+        }
+        assert_eq!(tracker.finished_sessions().len(), MAX_FINISHED_SESSIONS);
+        assert_eq!(tracker.play_time(b"a", 0), num as i64 * 10);
+    }
+}
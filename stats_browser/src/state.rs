@@ -0,0 +1,300 @@
+//! State of the browser that survives restarts.
+//!
+//! The state file is a JSON object listing the known servers along with the
+//! last info they replied with, encoded as the Base64 of the info response
+//! packets:
+//!
+//! ```json
+//! {"servers": [
+//!     {"server": "192.0.2.1:8303", "version": "V6", "info": ["//////////9pbmYz..."]},
+//!     {"server": "192.0.2.2:8303", "version": "V5", "info": null}
+//! ]}
+//! ```
+//!
+//! Older state files store a single packet as a string instead of an array.
+//!
+//! It is replaced atomically so that a crash never leaves a partially written
+//! file behind.
+
+use rustc_serialize::base64::FromBase64;
+use rustc_serialize::base64::FromBase64Error;
+use rustc_serialize::json::Json;
+use rustc_serialize::json::Object;
+use rustc_serialize::json::ParserError;
+use serverbrowse::protocol::PartialServerInfo;
+use serverbrowse::protocol::Response;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol;
+
+use std::fmt;
+use std::fs::File;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use addr::Addr;
+use addr::ProtocolVersion;
+use addr::ServerAddr;
+use base64::B64;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(ParserError),
+    Base64(FromBase64Error),
+    /// The JSON doesn't describe a saved state. Contains a description of
+    /// the missing or malformed field.
+    Invalid(&'static str),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<ParserError> for Error {
+    fn from(err: ParserError) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<FromBase64Error> for Error {
+    fn from(err: FromBase64Error) -> Error {
+        Error::Base64(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Json(ref e) => write!(f, "invalid JSON: {}", e),
+            Error::Base64(ref e) => write!(f, "invalid Base64: {}", e),
+            Error::Invalid(field) => write!(f, "invalid state: {}", field),
+        }
+    }
+}
+
+/// Known server, along with its last info if it replied.
+pub type Server = (ServerAddr, Option<ServerInfo>);
+
+/// Encodes the info as an array of response packets, `None` if it can't be
+/// encoded.
+fn encode_info(info: &ServerInfo) -> Option<Json> {
+    match info.encode() {
+        Ok(packets) => Some(Json::Array(packets.iter().map(|p| Json::String(B64(p).to_string())).collect())),
+        Err(e) => {
+            warn!("Couldn't encode server info, {:?}", e);
+            None
+        },
+    }
+}
+
+/// Decodes the response packets of an info, merging the ones of the
+/// versions that are split across several packets.
+fn decode_info(packets: &[&str]) -> Result<ServerInfo, Error> {
+    let mut partial: Option<PartialServerInfo> = None;
+    for packet in packets {
+        let packet = packet.from_base64()?;
+        let part = match protocol::parse_response(&packet) {
+            Some(Response::Info5(i)) if packets.len() == 1 => return i.parse().ok_or(Error::Invalid("info")),
+            Some(Response::Info6(i)) if packets.len() == 1 => return i.parse().ok_or(Error::Invalid("info")),
+            Some(Response::Info664(i)) => i.parse(),
+            Some(Response::Info6Ex(i)) => i.parse(),
+            Some(Response::Info6ExMore(i)) => i.parse(),
+            _ => None,
+        };
+        let part = part.ok_or(Error::Invalid("info"))?;
+        match partial {
+            Some(ref mut p) => p.merge(part).map_err(|_| Error::Invalid("info"))?,
+            None => partial = Some(part),
+        }
+    }
+    let mut partial = partial.ok_or(Error::Invalid("info"))?;
+    if let Some(info) = partial.get_info() {
+        return Ok(info.clone());
+    }
+    // Infos with more clients than the version can carry are saved
+    // truncated.
+    let mut info = partial.partial_info().clone();
+    info.sort_clients();
+    Ok(info)
+}
+
+fn decode_server(server: &Json) -> Result<Server, Error> {
+    let addr: SocketAddr = server.find("server").and_then(Json::as_string)
+        .and_then(|a| a.parse().ok())
+        .ok_or(Error::Invalid("server"))?;
+    let version = match server.find("version").and_then(Json::as_string) {
+        Some("V5") => ProtocolVersion::V5,
+        Some("V6") => ProtocolVersion::V6,
+        _ => return Err(Error::Invalid("version")),
+    };
+    let info = match server.find("info") {
+        Some(Json::Null) => None,
+        Some(Json::String(i)) => Some(decode_info(&[i])?),
+        Some(Json::Array(packets)) => {
+            let packets: Option<Vec<_>> = packets.iter().map(Json::as_string).collect();
+            Some(decode_info(&packets.ok_or(Error::Invalid("info"))?)?)
+        },
+        _ => return Err(Error::Invalid("info")),
+    };
+    Ok((ServerAddr::new(version, Addr::from_socket_addr(addr)), info))
+}
+
+/// Reads the servers saved in the state file at `path`.
+pub fn load(path: &Path) -> Result<Vec<Server>, Error> {
+    let mut string = String::new();
+    File::open(path)?.read_to_string(&mut string)?;
+    let json = Json::from_str(&string)?;
+    let servers = json.find("servers").and_then(Json::as_array)
+        .ok_or(Error::Invalid("servers"))?;
+    servers.iter().map(decode_server).collect()
+}
+
+/// Replaces the state file at `path` with the given servers.
+pub fn save<I>(path: &Path, servers: I) -> io::Result<()>
+    where I: Iterator<Item=(ServerAddr, Option<ServerInfo>)>,
+{
+    let servers = servers.map(|(addr, info)| {
+        let mut server = Object::new();
+        server.insert("server".to_owned(), Json::String(addr.addr.to_string()));
+        server.insert("version".to_owned(), Json::String(addr.version.to_string()));
+        // Servers whose info can't be stored are saved without it, their
+        // info is requested again after loading.
+        server.insert("info".to_owned(), info.as_ref().and_then(encode_info).unwrap_or(Json::Null));
+        Json::Object(server)
+    }).collect();
+    let mut state = Object::new();
+    state.insert("servers".to_owned(), Json::Array(servers));
+
+    let mut tmp_path = path.to_owned().into_os_string();
+    tmp_path.push(".tmp");
+    {
+        let mut file = File::create(&tmp_path)?;
+        writeln!(file, "{}", Json::Object(state))?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod test {
+    use serverbrowse::protocol::ClientInfo;
+    use serverbrowse::protocol::ServerInfo;
+    use serverbrowse::protocol::ServerInfoVersion;
+
+    use std::cmp;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use addr::Addr;
+    use addr::ProtocolVersion;
+    use addr::ServerAddr;
+    use base64::B64;
+
+    use super::load;
+    use super::save;
+
+    fn addr(version: ProtocolVersion, addr: &str) -> ServerAddr {
+        ServerAddr::new(version, Addr::from_socket_addr(addr.parse().unwrap()))
+    }
+
+    fn info(version: ServerInfoVersion, clients: &[(&str, &str, i32)]) -> ServerInfo {
+        let extended = version != ServerInfoVersion::V5;
+        let clients: Vec<_> = clients.iter().map(|&(name, clan, score)| ClientInfo {
+            name: name.bytes().collect(),
+            clan: clan.bytes().collect(),
+            country: if extended { 276 } else { -1 },
+            score: score,
+            is_player: 1,
+        }).collect();
+        let num = clients.len() as i32;
+        ServerInfo {
+            info_version: version,
+            token: 0,
+            version: "0.6.4".bytes().collect(),
+            name: "server".bytes().collect(),
+            map: "dm1".bytes().collect(),
+            game_type: "DM".bytes().collect(),
+            flags: 1,
+            hostname: if version.has_hostname() { Some("example.org".bytes().collect()) } else { None },
+            map_crc: if version.has_extended_map_info() { Some(0xf2159e6e) } else { None },
+            map_size: if version.has_extended_map_info() { Some(5805) } else { None },
+            progression: if version.has_progression() { Some(50) } else { None },
+            skill_level: if version.has_skill_level() { Some(2) } else { None },
+            num_players: num,
+            max_players: cmp::max(num, 16),
+            num_clients: num,
+            max_clients: cmp::max(num, 16),
+            clients: clients,
+        }
+    }
+
+    /// Info with more clients than fit into a single packet.
+    fn crowded(version: ServerInfoVersion) -> ServerInfo {
+        let names: Vec<_> = (0..64).map(|i| format!("player name {:02}", i)).collect();
+        let clients: Vec<_> = names.iter().map(|n| (&n[..], "clan name", 1)).collect();
+        info(version, &clients)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let path = env::temp_dir().join(format!("stats-browser-state-{}.json", process::id()));
+        let mut invalid = info(ServerInfoVersion::V6, &[]);
+        invalid.name.push(0);
+        let servers = vec![
+            (addr(ProtocolVersion::V5, "192.0.2.1:8303"), Some(info(ServerInfoVersion::V5, &[("a", "", 3)]))),
+            (addr(ProtocolVersion::V6, "192.0.2.2:8303"), Some(info(ServerInfoVersion::V6, &[("a", "", 1), ("b", "clan", 2)]))),
+            (addr(ProtocolVersion::V6, "[2001:db8::1]:8303"), None),
+            (addr(ProtocolVersion::V6, "192.0.2.3:8303"), Some(info(ServerInfoVersion::V7, &[("a", "clan", 1)]))),
+            (addr(ProtocolVersion::V6, "192.0.2.4:8303"), Some(info(ServerInfoVersion::V664, &[]))),
+            (addr(ProtocolVersion::V6, "192.0.2.5:8303"), Some(crowded(ServerInfoVersion::V664))),
+            (addr(ProtocolVersion::V6, "192.0.2.6:8303"), Some(crowded(ServerInfoVersion::V6Ex))),
+            (addr(ProtocolVersion::V6, "192.0.2.7:8303"), Some(invalid)),
+        ];
+        for (_, i) in &servers[5..7] {
+            assert!(i.as_ref().unwrap().encode().unwrap().len() > 1);
+        }
+        save(&path, servers.clone().into_iter()).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), servers.len());
+        assert!(loaded[..7] == servers[..7]);
+        // Infos that can't be encoded are dropped.
+        assert!(loaded[7] == (servers[7].0, None));
+    }
+
+    #[test]
+    fn single_packet() {
+        let path = env::temp_dir().join(format!("stats-browser-state-single-{}.json", process::id()));
+        let info = info(ServerInfoVersion::V6, &[("a", "", 1)]);
+        let packet = B64(&info.encode().unwrap()[0]).to_string();
+        let contents = format!(r#"{{"servers": [{{"server": "192.0.2.1:8303", "version": "V6", "info": "{}"}}]}}"#, packet);
+        fs::write(&path, contents).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(loaded == [(addr(ProtocolVersion::V6, "192.0.2.1:8303"), Some(info))]);
+    }
+
+    #[test]
+    fn invalid() {
+        let path = env::temp_dir().join(format!("stats-browser-state-invalid-{}.json", process::id()));
+        for contents in &["", "{}", r#"{"servers": [{"server": "x", "version": "V6", "info": null}]}"#,
+                          r#"{"servers": [{"server": "192.0.2.1:8303", "version": "V7", "info": null}]}"#,
+                          r#"{"servers": [{"server": "192.0.2.1:8303", "version": "V6", "info": "AAAA"}]}"#,
+                          r#"{"servers": [{"server": "192.0.2.1:8303", "version": "V6", "info": []}]}"#,
+                          r#"{"servers": [{"server": "192.0.2.1:8303", "version": "V6", "info": [1]}]}"#]
+        {
+            fs::write(&path, contents).unwrap();
+            assert!(load(&path).is_err(), "{}", contents);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,6 @@
 use common::pretty::Bytes;
+use serverbrowse::fetch::Fetch;
+use serverbrowse::json;
 use serverbrowse::protocol::CountResponse;
 use serverbrowse::protocol::Info5Response;
 use serverbrowse::protocol::Info6Response;
@@ -13,17 +15,23 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::default::Default;
 use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::TryRecvError;
+use std::sync::mpsc;
 use std::thread;
 
 use addr::Addr;
 use addr::ProtocolVersion;
 use addr::ServerAddr;
+use compact::StringPool;
 use config;
+use entry::HttpMasterEntry;
 use entry::MasterServerEntry;
 use entry::ServerEntry;
 use entry::ServerResponse;
 use hashmap_ext::HashMapEntryIntoInner;
 use lookup::lookup_host;
+use state;
 use socket::NonBlockExt;
 use socket::UdpSocket;
 use socket::WouldBlock;
@@ -36,6 +44,32 @@ pub trait StatsBrowserCb {
     fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo);
     fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo);
     fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo);
+    /// Called for servers whose last info was loaded from the state file of
+    /// a previous run. Nothing changed about them, so no event should be
+    /// emitted; the next info is reported through `on_server_change`.
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let _ = (addr, info);
+    }
+    /// Called regularly from the main loop, for periodic work.
+    fn on_tick(&mut self) { }
+}
+
+impl<T: StatsBrowserCb> StatsBrowserCb for &mut T {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        (**self).on_server_new(addr, info)
+    }
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        (**self).on_server_change(addr, old, new)
+    }
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        (**self).on_server_remove(addr, last)
+    }
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        (**self).on_server_restore(addr, info)
+    }
+    fn on_tick(&mut self) {
+        (**self).on_tick()
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd, RustcEncodable)]
@@ -46,23 +80,41 @@ impl vec_map::Index for MasterId {
     fn from_usize(val: usize) -> MasterId { MasterId(val) }
 }
 
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+struct HttpMasterId(usize);
+
+impl vec_map::Index for HttpMasterId {
+    fn to_usize(self) -> usize { let HttpMasterId(val) = self; val }
+    fn from_usize(val: usize) -> HttpMasterId { HttpMasterId(val) }
+}
+
 enum Work {
     Resolve(MasterId),
+    FetchList(HttpMasterId),
+    ReceiveList(HttpMasterId),
     RequestList(MasterId),
     ExpectList(MasterId),
     RequestInfo(ServerAddr),
     ExpectInfo(ServerAddr),
+    SaveState,
 }
 
 pub struct StatsBrowser<'a> {
     master_servers: VecMap<MasterId, MasterServerEntry>,
+    http_master_servers: VecMap<HttpMasterId, HttpMasterEntry>,
     servers: HashMap<ServerAddr,ServerEntry>,
 
     list_limit: Limit,
     info_limit: Limit,
 
+    /// Strings of the stored server infos.
+    pool: StringPool,
+    /// Size of the string pool after it was last rebuilt.
+    pool_rebuild_size: usize,
+
     work_queue: TimedWorkQueue<Work>,
     socket: UdpSocket,
+    state_path: Option<PathBuf>,
     cb: &'a mut (StatsBrowserCb+'a),
 }
 
@@ -91,15 +143,21 @@ impl<'a> StatsBrowser<'a> {
         work_queue.add_duration(config::LIST_EXPECT_MS);
         work_queue.add_duration(config::INFO_REPEAT_MS);
         work_queue.add_duration(config::INFO_EXPECT_MS);
+        work_queue.add_duration(config::STATE_SAVE_MS);
         Some(StatsBrowser {
             master_servers: Default::default(),
+            http_master_servers: Default::default(),
             servers: Default::default(),
 
             list_limit: Limit::new(config::MAX_LISTS, config::MAX_LISTS_MS),
             info_limit: Limit::new(config::MAX_INFOS, config::MAX_INFOS_MS),
 
+            pool: StringPool::new(),
+            pool_rebuild_size: 0,
+
             work_queue: work_queue,
             socket: socket,
+            state_path: None,
             cb: cb,
         })
     }
@@ -107,6 +165,111 @@ impl<'a> StatsBrowser<'a> {
         let master_id = self.master_servers.push(MasterServerEntry::new(domain));
         self.work_queue.push_now(Work::Resolve(master_id));
     }
+    /// Adds a master server that publishes its server list as JSON.
+    ///
+    /// Only the 0.6 addresses of the listed servers are tracked.
+    pub fn add_http_master(&mut self, fetch: Box<Fetch>) {
+        let master_id = self.http_master_servers.push(HttpMasterEntry::new(fetch));
+        self.work_queue.push_now(Work::FetchList(master_id));
+    }
+    /// Keeps the known servers in the state file at `path`, so that a
+    /// restarted browser continues where it stopped instead of reporting all
+    /// servers and players as new.
+    ///
+    /// Loads the servers from the file if it exists, the trackers are told
+    /// about them through `on_server_restore`.
+    pub fn set_state_file(&mut self, path: PathBuf) -> Result<(), state::Error> {
+        if path.exists() {
+            let servers = state::load(&path)?;
+            info!("Restoring {} servers from {}", servers.len(), path.display());
+            for (addr, info) in servers {
+                if let Some(ref i) = info {
+                    self.cb.on_server_restore(addr, i);
+                }
+                let mut entry = ServerEntry::new();
+                entry.resp = info.map(|i| ServerResponse::new(&mut self.pool, &i));
+                if self.servers.insert(addr, entry).is_none() {
+                    self.work_queue.push_now(Work::RequestInfo(addr));
+                }
+            }
+        }
+        self.state_path = Some(path);
+        self.work_queue.push(config::STATE_SAVE_MS, Work::SaveState);
+        Ok(())
+    }
+    fn do_save_state(&mut self) -> Result<(),()> {
+        self.work_queue.push(config::STATE_SAVE_MS, Work::SaveState);
+        let path = self.state_path.as_ref().unwrap();
+        let pool = &self.pool;
+        let servers = self.servers.iter().map(|(&addr, s)| (addr, s.resp.as_ref().map(|r| r.info.get(pool))));
+        match state::save(path, servers) {
+            Ok(()) => debug!("Saved state to {}", path.display()),
+            Err(e) => error!("Couldn't save state to {}, {}", path.display(), e),
+        }
+        Ok(())
+    }
+    fn do_fetch_list(&mut self, master_id: HttpMasterId) -> Result<(),()> {
+        let master = &mut self.http_master_servers[master_id];
+        self.work_queue.push(config::LIST_REPEAT_MS, Work::FetchList(master_id));
+
+        if master.pending.is_some() {
+            warn!("Still fetching list from {}", master.source);
+            return Ok(());
+        }
+        debug!("Fetching list from {}", master.source);
+        let fetch = master.fetch.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The lock is poisoned if an earlier fetch panicked, the source
+            // can still be used for another try.
+            let mut fetch = fetch.lock().unwrap_or_else(|e| e.into_inner());
+            let servers = fetch.fetch_servers();
+            // The receiver is only gone if the browser stopped.
+            let _ = sender.send(servers);
+        });
+        master.pending = Some(receiver);
+        self.work_queue.push(config::FETCH_POLL_MS, Work::ReceiveList(master_id));
+        Ok(())
+    }
+    fn do_receive_list(&mut self, master_id: HttpMasterId) -> Result<(),()> {
+        let master = &mut self.http_master_servers[master_id];
+        let result = match master.pending.as_ref().unwrap().try_recv() {
+            Ok(r) => r,
+            Err(TryRecvError::Empty) => {
+                self.work_queue.push(config::FETCH_POLL_MS, Work::ReceiveList(master_id));
+                return Ok(());
+            },
+            Err(TryRecvError::Disconnected) => {
+                // The list is fetched again with the next `FetchList`.
+                error!("Fetching list from {} panicked", master.source);
+                master.pending = None;
+                return Ok(());
+            },
+        };
+        master.pending = None;
+        let servers = match result {
+            Ok(s) => s,
+            Err(x) => {
+                warn!("Error while fetching list from {}, {}", master.source, x);
+                return Ok(());
+            },
+        };
+        let list: HashSet<_> = servers.iter()
+            .flat_map(|s| s.addresses.iter())
+            .filter(|a| a.protocol == json::Protocol::V6)
+            .map(|a| ServerAddr::new(ProtocolVersion::V6, Addr::from_srvbrowse_addr(a.addr)))
+            .collect();
+        debug!("Fetched list from {}, length {}", master.source, list.len());
+
+        for &s in &list {
+            if let Some(v) = self.servers.entry(s).into_vacant() {
+                v.insert(ServerEntry::new());
+                self.work_queue.push_now(Work::RequestInfo(s));
+            }
+        }
+        master.list = list;
+        Ok(())
+    }
     fn do_resolve(&mut self, master_id: MasterId) -> Result<(),()> {
         let master = &mut self.master_servers[master_id];
         match lookup_host(&master.domain, MASTERSERVER_PORT) {
@@ -165,7 +328,7 @@ impl<'a> StatsBrowser<'a> {
                 info!("Missing responses from {}, removing", server_addr);
                 // Throw the server out after ten missing replies.
                 match server.remove().resp {
-                    Some(ref y) => self.cb.on_server_remove(server_addr, &y.info),
+                    Some(ref y) => self.cb.on_server_remove(server_addr, &y.info.get(&self.pool)),
                     None => {},
                 }
             } else {
@@ -280,13 +443,30 @@ impl<'a> StatsBrowser<'a> {
                 server.num_missing_resp = 0;
                 debug!("Received server info from {}, {:?}", from, x);
                 match server.resp {
-                    Some(ref y) => self.cb.on_server_change(from, &y.info, &x),
+                    Some(ref y) => self.cb.on_server_change(from, &y.info.get(&self.pool), &x),
                     None => self.cb.on_server_new(from, &x)
                 }
-                server.resp = Some(ServerResponse::new(x));
+                server.resp = Some(ServerResponse::new(&mut self.pool, &x));
             },
         }
     }
+    /// Rebuilds the string pool from the stored server infos once it has
+    /// grown enough, dropping the strings that aren't used anymore.
+    fn check_pool(&mut self) {
+        let size = self.pool.memory_usage();
+        if size < config::POOL_MIN_REBUILD_SIZE || size < 2 * self.pool_rebuild_size {
+            return;
+        }
+        let mut pool = StringPool::new();
+        for server in self.servers.values_mut() {
+            if let Some(ref mut resp) = server.resp {
+                resp.info = resp.info.move_to(&self.pool, &mut pool);
+            }
+        }
+        debug!("Rebuilt string pool, {} to {} bytes", size, pool.memory_usage());
+        self.pool = pool;
+        self.pool_rebuild_size = self.pool.memory_usage();
+    }
     fn process_packet(&mut self, from: Addr, data: &[u8]) {
         match protocol::parse_response(data) {
             Some(Response::Count(CountResponse(count))) => {
@@ -357,16 +537,21 @@ impl<'a> StatsBrowser<'a> {
             while let Some(work) = self.work_queue.pop() {
                 let result = match work {
                     Work::Resolve(id)       => self.do_resolve(id),
+                    Work::FetchList(id)     => self.do_fetch_list(id),
+                    Work::ReceiveList(id)   => self.do_receive_list(id),
                     Work::RequestList(id)   => self.do_request_list(id),
                     Work::ExpectList(id)    => self.do_expect_list(id),
                     Work::RequestInfo(addr) => self.do_request_info(addr),
                     Work::ExpectInfo(addr)  => self.do_expect_info(addr),
+                    Work::SaveState         => self.do_save_state(),
                 };
                 if !result.is_ok() {
                     self.work_queue.push_now_front(work);
                     break;
                 }
             }
+            self.check_pool();
+            self.cb.on_tick();
             thread::sleep(config::SLEEP_MS.to_std());
         }
     }
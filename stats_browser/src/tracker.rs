@@ -0,0 +1,209 @@
+//! Helpers shared by the `StatsBrowserCb` implementations.
+
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol::ServerInfoVersion;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::slice;
+
+use addr::Addr;
+use addr::ServerAddr;
+
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct LogVersion(pub ServerInfoVersion);
+
+/// Server address as logged, with the version of the info the server sent.
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub struct LogAddr {
+    pub addr: Addr,
+    pub version: LogVersion,
+}
+
+impl LogAddr {
+    pub fn new(addr: ServerAddr, info: &ServerInfo) -> LogAddr {
+        LogAddr {
+            addr: addr.addr,
+            version: LogVersion(info.info_version),
+        }
+    }
+}
+
+/// Returns whether the player should be left out of the logs.
+///
+/// Clients that are still connecting don't have a name yet.
+pub fn player_ignore(info: &ClientInfo) -> bool {
+    &*info.name == "(connecting)".as_bytes()
+}
+
+/// Returns whether the server changed in a way worth logging.
+pub fn server_changed(old: &ServerInfo, new: &ServerInfo) -> bool {
+    old.flags != new.flags
+        || old.version != new.version
+        || old.game_type != new.game_type
+        || old.map != new.map
+        || old.name != new.name
+}
+
+/// A difference between two player lists.
+#[derive(Clone, Copy)]
+pub enum PlayerDiff<'a> {
+    New(&'a ClientInfo),
+    /// The player's clan, country or team changed. Score changes are not
+    /// reported.
+    Change(&'a ClientInfo, &'a ClientInfo),
+    Remove(&'a ClientInfo),
+}
+
+/// Iterator over the differences between two player lists, created by
+/// `diff_players`.
+pub struct DiffPlayers<'a> {
+    iter_old: slice::Iter<'a, ClientInfo>,
+    iter_new: slice::Iter<'a, ClientInfo>,
+    maybe_old: Option<&'a ClientInfo>,
+    maybe_new: Option<&'a ClientInfo>,
+}
+
+/// Compares two player lists sorted by name.
+///
+/// Ignored players (see `player_ignore`) are skipped.
+pub fn diff_players<'a>(old: &'a [ClientInfo], new: &'a [ClientInfo]) -> DiffPlayers<'a> {
+    let mut iter_old = old.iter();
+    let mut iter_new = new.iter();
+    DiffPlayers {
+        maybe_old: iter_old.next(),
+        maybe_new: iter_new.next(),
+        iter_old: iter_old,
+        iter_new: iter_new,
+    }
+}
+
+impl<'a> DiffPlayers<'a> {
+    fn next_old(&mut self) -> &'a ClientInfo {
+        let old = self.maybe_old.unwrap();
+        self.maybe_old = self.iter_old.next();
+        old
+    }
+    fn next_new(&mut self) -> &'a ClientInfo {
+        let new = self.maybe_new.unwrap();
+        self.maybe_new = self.iter_new.next();
+        new
+    }
+    fn next_diff(&mut self) -> Option<PlayerDiff<'a>> {
+        let order = match (self.maybe_old, self.maybe_new) {
+            (None, None) => return None,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(old), Some(new)) => Ord::cmp(&*old.name, &*new.name),
+        };
+        Some(match order {
+            Ordering::Less => PlayerDiff::Remove(self.next_old()),
+            Ordering::Equal => PlayerDiff::Change(self.next_old(), self.next_new()),
+            Ordering::Greater => PlayerDiff::New(self.next_new()),
+        })
+    }
+}
+
+impl<'a> Iterator for DiffPlayers<'a> {
+    type Item = PlayerDiff<'a>;
+    fn next(&mut self) -> Option<PlayerDiff<'a>> {
+        while let Some(diff) = self.next_diff() {
+            let relevant = match diff {
+                PlayerDiff::New(new) => !player_ignore(new),
+                PlayerDiff::Remove(old) => !player_ignore(old),
+                PlayerDiff::Change(old, new) => {
+                    !player_ignore(old) && !player_ignore(new)
+                        && (old.clan != new.clan
+                            || old.is_player != new.is_player
+                            || old.country != new.country)
+                },
+            };
+            if relevant {
+                return Some(diff);
+            }
+        }
+        None
+    }
+}
+
+// ---------------------------------------
+// Boilerplate trait implementations below
+// ---------------------------------------
+
+impl fmt::Display for LogVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let LogVersion(inner) = *self;
+        fmt::Display::fmt(inner.name(), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serverbrowse::protocol::ClientInfo;
+    use super::PlayerDiff;
+    use super::diff_players;
+
+    fn client(name: &str, clan: &str, score: i32) -> ClientInfo {
+        ClientInfo {
+            name: name.bytes().collect(),
+            clan: clan.bytes().collect(),
+            country: -1,
+            score: score,
+            is_player: 1,
+        }
+    }
+
+    fn diff(old: &[ClientInfo], new: &[ClientInfo]) -> Vec<(char, String)> {
+        diff_players(old, new).map(|d| match d {
+            PlayerDiff::New(n) => ('+', String::from_utf8(n.name.to_vec()).unwrap()),
+            PlayerDiff::Change(o, n) => {
+                assert_eq!(o.name, n.name);
+                ('~', String::from_utf8(n.name.to_vec()).unwrap())
+            },
+            PlayerDiff::Remove(o) => ('-', String::from_utf8(o.name.to_vec()).unwrap()),
+        }).collect()
+    }
+
+    fn d(kind: char, name: &str) -> (char, String) {
+        (kind, name.to_owned())
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(diff(&[], &[]), vec![]);
+        let players = [client("a", "", 0), client("b", "", 0)];
+        assert_eq!(diff(&[], &players), vec![d('+', "a"), d('+', "b")]);
+        assert_eq!(diff(&players, &[]), vec![d('-', "a"), d('-', "b")]);
+        assert_eq!(diff(&players, &players), vec![]);
+    }
+
+    #[test]
+    fn changes() {
+        let old = [client("a", "", 0), client("b", "", 0), client("c", "", 0), client("e", "", 0)];
+        let mut team = client("e", "", 0);
+        team.is_player = 0;
+        let mut country = client("c", "", 0);
+        country.country = 276;
+        let new = [client("b", "clan", 0), country, client("d", "", 0), team];
+        assert_eq!(diff(&old, &new), vec![
+            d('-', "a"), d('~', "b"), d('~', "c"), d('+', "d"), d('~', "e"),
+        ]);
+    }
+
+    #[test]
+    fn score_ignored() {
+        let old = [client("a", "", 0)];
+        let new = [client("a", "", 10)];
+        assert_eq!(diff(&old, &new), vec![]);
+    }
+
+    #[test]
+    fn connecting_ignored() {
+        let old = [client("(connecting)", "", 0), client("a", "", 0)];
+        let new = [client("(connecting)", "clan", 0), client("b", "", 0)];
+        assert_eq!(diff(&old, &new), vec![d('-', "a"), d('+', "b")]);
+        assert_eq!(diff(&[], &old[..1]), vec![]);
+        assert_eq!(diff(&old[..1], &[]), vec![]);
+    }
+}
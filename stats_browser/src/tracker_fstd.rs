@@ -1,15 +1,16 @@
 use serverbrowse::protocol::ClientInfo;
 use serverbrowse::protocol::ServerInfo;
-use serverbrowse::protocol::ServerInfoVersion;
 
-use std::cmp::Ordering;
 use std::fmt;
 
 use rust_time;
 
-use addr::Addr;
 use addr::ServerAddr;
 use base64::B64;
+use tracker::LogAddr;
+use tracker::PlayerDiff;
+use tracker::diff_players;
+use tracker::server_changed;
 
 use StatsBrowserCb;
 
@@ -19,38 +20,6 @@ pub struct Tracker {
     server_count: u32,
 }
 
-#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
-struct LogVersion(ServerInfoVersion);
-
-#[derive(Clone, Copy)]
-struct LogAddr {
-    addr: Addr,
-    version: LogVersion,
-}
-
-impl LogAddr {
-    fn new(addr: ServerAddr, info: &ServerInfo) -> LogAddr {
-        LogAddr {
-            addr: addr.addr,
-            version: LogVersion(info.info_version),
-        }
-    }
-}
-
-impl fmt::Display for LogVersion {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let LogVersion(inner) = *self;
-        let output = match inner {
-            ServerInfoVersion::V5 => "5",
-            ServerInfoVersion::V6 => "6",
-            ServerInfoVersion::V664 => "6_64",
-            ServerInfoVersion::V6Ex => "6_ex",
-            ServerInfoVersion::V7 => "7",
-        };
-        fmt::Display::fmt(&output, f)
-    }
-}
-
 impl Tracker {
     pub fn new() -> Tracker {
         Tracker {
@@ -65,61 +34,18 @@ impl Tracker {
         let _ = addr;
         false
     }
-    fn on_player_new(&mut self, addr: LogAddr, info: &ClientInfo) {
-        if player_ignore(addr, info) { return; }
-        print_player_new(addr, info);
-        self.player_count += 1;
-    }
-
-    fn on_player_change(&mut self, addr: LogAddr, old: &ClientInfo, new: &ClientInfo) {
-        if player_ignore(addr, old) || player_ignore(addr, new) { return; }
-        if old.clan != new.clan
-            || old.is_player != new.is_player
-            || old.country != new.country
-        {
-            print_player_change(addr, old, new);
-        }
-    }
-
-    fn on_player_remove(&mut self, addr: LogAddr, last: &ClientInfo) {
-        if player_ignore(addr, last) { return; }
-        print_player_remove(addr, last);
-        self.player_count -= 1;
-    }
-
     fn diff_players(&mut self, addr: LogAddr, slice_old: &[ClientInfo], slice_new: &[ClientInfo]) {
-        let mut iter_old = slice_old.iter();
-        let mut iter_new = slice_new.iter();
-        let mut maybe_old: Option<&ClientInfo> = iter_old.next();
-        let mut maybe_new: Option<&ClientInfo> = iter_new.next();
-        loop {
-            match (maybe_old, maybe_new) {
-                (None, None) => break,
-                (None, Some(new)) => {
-                    self.on_player_new(addr, new);
-                    maybe_new = iter_new.next();
-                }
-                (Some(old), None) => {
-                    self.on_player_remove(addr, old);
-                    maybe_old = iter_old.next();
-                }
-                (Some(old), Some(new)) => {
-                    match Ord::cmp(&*old.name, &*new.name) {
-                        Ordering::Less => {
-                            self.on_player_remove(addr, old);
-                            maybe_old = iter_old.next();
-                        }
-                        Ordering::Equal => {
-                            self.on_player_change(addr, old, new);
-                            maybe_old = iter_old.next();
-                            maybe_new = iter_new.next();
-                        }
-                        Ordering::Greater => {
-                            self.on_player_new(addr, new);
-                            maybe_new = iter_new.next();
-                        }
-                    }
-                }
+        for diff in diff_players(slice_old, slice_new) {
+            match diff {
+                PlayerDiff::New(new) => {
+                    print_player_new(addr, new);
+                    self.player_count += 1;
+                },
+                PlayerDiff::Change(old, new) => print_player_change(addr, old, new),
+                PlayerDiff::Remove(old) => {
+                    print_player_remove(addr, old);
+                    self.player_count -= 1;
+                },
             }
         }
     }
@@ -143,12 +69,7 @@ impl StatsBrowserCb for Tracker {
         }
         let addr = LogAddr::new(addr, old);
         if Tracker::server_ignore(addr) { return; }
-        if old.flags != new.flags
-            || old.version != new.version
-            || old.game_type != new.game_type
-            || old.map != new.map
-            || old.name != new.name
-        {
+        if server_changed(old, new) {
             print_server_change(addr, old, new);
         }
         self.diff_players(addr, &old.clients, &new.clients);
@@ -161,6 +82,13 @@ impl StatsBrowserCb for Tracker {
         print_server_remove(addr, last);
         self.server_count -= 1;
     }
+
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let addr = LogAddr::new(addr, info);
+        if Tracker::server_ignore(addr) { return; }
+        self.player_count += diff_players(&[], &info.clients).count() as u32;
+        self.server_count += 1;
+    }
 }
 
 fn print_iter<'a,I:Iterator<Item=&'a (fmt::Display+'a)>>(command: &str, args: I) {
@@ -228,8 +156,3 @@ fn print_server_change(addr: LogAddr, old: &ServerInfo, new: &ServerInfo) {
     let _ = old;
     print_server_change_impl(addr, false, new);
 }
-
-fn player_ignore(addr: LogAddr, info: &ClientInfo) -> bool {
-    let _ = addr;
-    &*info.name == "(connecting)".as_bytes()
-}
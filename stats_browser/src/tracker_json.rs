@@ -0,0 +1,142 @@
+//! Tracker writing an event log with one JSON object per line.
+//!
+//! Every event has a `time` (seconds since the Unix epoch) and an `event`
+//! field. Server and player events additionally carry the `server` address
+//! and the `info_version` the server replied with. Names and other strings
+//! are converted to UTF-8 lossily, use the fstd format if the exact bytes
+//! are needed.
+
+use rustc_serialize::json::Json;
+use rustc_serialize::json::Object;
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+
+use std::io::Write;
+
+use rust_time;
+
+use addr::ServerAddr;
+use tracker::LogAddr;
+use tracker::PlayerDiff;
+use tracker::diff_players;
+use tracker::server_changed;
+
+use StatsBrowserCb;
+
+fn string(bytes: &[u8]) -> Json {
+    Json::String(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Returns the JSON representation of a player.
+pub fn client_json(info: &ClientInfo) -> Json {
+    let mut result = Object::new();
+    result.insert("name".to_owned(), string(&info.name));
+    result.insert("clan".to_owned(), string(&info.clan));
+    result.insert("country".to_owned(), Json::I64(info.country.into()));
+    result.insert("score".to_owned(), Json::I64(info.score.into()));
+    result.insert("is_player".to_owned(), Json::Boolean(info.is_player != 0));
+    Json::Object(result)
+}
+
+/// Returns the JSON representation of a server info, without the players.
+pub fn server_json(info: &ServerInfo) -> Object {
+    let mut result = Object::new();
+    result.insert("flags".to_owned(), Json::I64(info.flags.into()));
+    result.insert("version".to_owned(), string(&info.version));
+    result.insert("game_type".to_owned(), string(&info.game_type));
+    result.insert("map".to_owned(), string(&info.map));
+    result.insert("name".to_owned(), string(&info.name));
+    result.insert("num_players".to_owned(), Json::I64(info.num_players.into()));
+    result.insert("max_players".to_owned(), Json::I64(info.max_players.into()));
+    result.insert("num_clients".to_owned(), Json::I64(info.num_clients.into()));
+    result.insert("max_clients".to_owned(), Json::I64(info.max_clients.into()));
+    result
+}
+
+/// Adds the `server` and `info_version` fields.
+pub fn add_addr(object: &mut Object, addr: LogAddr) {
+    object.insert("server".to_owned(), Json::String(addr.addr.to_string()));
+    object.insert("info_version".to_owned(), Json::String(addr.version.to_string()));
+}
+
+pub struct Tracker<W: Write> {
+    output: W,
+}
+
+impl<W: Write> Tracker<W> {
+    pub fn new(output: W) -> Tracker<W> {
+        Tracker {
+            output: output,
+        }
+    }
+    pub fn start(&mut self) {
+        let mut fields = Object::new();
+        fields.insert("program".to_owned(), Json::String("libtw2_statsbrowser".to_owned()));
+        fields.insert("program_version".to_owned(), Json::String("0.0.1".to_owned()));
+        self.event("start", None, fields);
+    }
+    fn event(&mut self, event: &str, addr: Option<LogAddr>, mut fields: Object) {
+        fields.insert("time".to_owned(), Json::I64(rust_time::get_time().sec));
+        fields.insert("event".to_owned(), Json::String(event.to_owned()));
+        if let Some(a) = addr {
+            add_addr(&mut fields, a);
+        }
+        writeln!(self.output, "{}", Json::Object(fields))
+            .and_then(|()| self.output.flush())
+            .expect("failed to write event");
+    }
+    fn server_event(&mut self, event: &str, addr: LogAddr, info: &ServerInfo) {
+        let mut fields = Object::new();
+        fields.insert("info".to_owned(), Json::Object(server_json(info)));
+        self.event(event, Some(addr), fields);
+    }
+    fn diff_players(&mut self, addr: LogAddr, old: &[ClientInfo], new: &[ClientInfo]) {
+        for diff in diff_players(old, new) {
+            let mut fields = Object::new();
+            let event = match diff {
+                PlayerDiff::New(new) => {
+                    fields.insert("player".to_owned(), client_json(new));
+                    "player_new"
+                },
+                PlayerDiff::Change(old, new) => {
+                    fields.insert("old".to_owned(), client_json(old));
+                    fields.insert("player".to_owned(), client_json(new));
+                    "player_change"
+                },
+                PlayerDiff::Remove(old) => {
+                    fields.insert("player".to_owned(), client_json(old));
+                    "player_remove"
+                },
+            };
+            self.event(event, Some(addr), fields);
+        }
+    }
+}
+
+impl<W: Write> StatsBrowserCb for Tracker<W> {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let addr = LogAddr::new(addr, info);
+        self.server_event("server_new", addr, info);
+        self.diff_players(addr, &[], &info.clients);
+    }
+
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        // If the info version changed, treat the server as a new one.
+        if old.info_version != new.info_version {
+            self.on_server_remove(addr, old);
+            self.on_server_new(addr, new);
+            return;
+        }
+        let addr = LogAddr::new(addr, new);
+        if server_changed(old, new) {
+            self.server_event("server_change", addr, new);
+        }
+        self.diff_players(addr, &old.clients, &new.clients);
+    }
+
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        let addr = LogAddr::new(addr, last);
+        self.diff_players(addr, &last.clients, &[]);
+        self.event("server_remove", Some(addr), Object::new());
+    }
+}
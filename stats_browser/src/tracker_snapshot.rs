@@ -0,0 +1,105 @@
+//! Tracker periodically dumping all known servers and their players.
+//!
+//! The dump is a single JSON object with a `time` and a `servers` array,
+//! replaced atomically so readers never see a partially written file.
+
+use rustc_serialize::json::Json;
+use rustc_serialize::json::Object;
+use serverbrowse::protocol::ServerInfo;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs;
+use std::io::Write;
+use std::io;
+use std::path::PathBuf;
+
+use rust_time;
+
+use addr::ServerAddr;
+use time::Duration;
+use time::Time;
+use tracker::LogAddr;
+use tracker_json::add_addr;
+use tracker_json::client_json;
+use tracker_json::server_json;
+
+use StatsBrowserCb;
+
+pub struct Tracker {
+    path: PathBuf,
+    interval: Duration,
+    next_dump: Time,
+    servers: HashMap<ServerAddr, ServerInfo>,
+}
+
+impl Tracker {
+    /// Creates a tracker writing to `path` every `interval`.
+    pub fn new(path: PathBuf, interval: Duration) -> Tracker {
+        Tracker {
+            path: path,
+            interval: interval,
+            next_dump: Time::now() + interval,
+            servers: HashMap::new(),
+        }
+    }
+    fn snapshot(&self) -> Json {
+        let mut servers: Vec<_> = self.servers.iter().map(|(&addr, info)| {
+            let mut server = Object::new();
+            add_addr(&mut server, LogAddr::new(addr, info));
+            server.insert("info".to_owned(), Json::Object(server_json(info)));
+            let clients = info.clients.iter().map(client_json).collect();
+            server.insert("clients".to_owned(), Json::Array(clients));
+            Json::Object(server)
+        }).collect();
+        // Sort the servers so that consecutive dumps are easy to compare.
+        servers.sort_by_key(|s| s["server"].to_string());
+
+        let mut result = Object::new();
+        result.insert("time".to_owned(), Json::I64(rust_time::get_time().sec));
+        result.insert("servers".to_owned(), Json::Array(servers));
+        Json::Object(result)
+    }
+    /// Writes the current list of servers.
+    pub fn dump(&self) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            writeln!(file, "{}", self.snapshot())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+impl StatsBrowserCb for Tracker {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.servers.insert(addr, info.clone());
+    }
+
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        let _ = old;
+        self.servers.insert(addr, new.clone());
+    }
+
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        let _ = last;
+        self.servers.remove(&addr);
+    }
+
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        self.servers.insert(addr, info.clone());
+    }
+
+    fn on_tick(&mut self) {
+        let now = Time::now();
+        if now < self.next_dump {
+            return;
+        }
+        self.next_dump = now + self.interval;
+        if let Err(e) = self.dump() {
+            error!("Couldn't write snapshot to {}, {}", self.path.display(), e);
+        }
+    }
+}
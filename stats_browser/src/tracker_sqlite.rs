@@ -0,0 +1,516 @@
+//! Tracker recording servers, players and their sessions in a SQLite
+//! database.
+//!
+//! A session is the time span a player spends on one server. Times are
+//! seconds since the Unix epoch, open sessions have no `end_time`. Strings
+//! are stored as blobs with their original bytes.
+
+use rusqlite::Connection;
+use rusqlite::NO_PARAMS;
+use rusqlite;
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::path::Path;
+use std::ptr;
+
+use rust_time;
+
+use addr::ServerAddr;
+use tracker::LogAddr;
+use tracker::PlayerDiff;
+use tracker::diff_players;
+use tracker::player_ignore;
+use tracker::server_changed;
+
+use StatsBrowserCb;
+
+const SCHEMA: &'static str = "
+    CREATE TABLE IF NOT EXISTS servers (
+        id INTEGER PRIMARY KEY,
+        addr TEXT NOT NULL,
+        info_version TEXT NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        online INTEGER NOT NULL,
+        flags INTEGER,
+        version BLOB,
+        game_type BLOB,
+        map BLOB,
+        name BLOB,
+        UNIQUE (addr, info_version)
+    );
+    CREATE TABLE IF NOT EXISTS players (
+        id INTEGER PRIMARY KEY,
+        name BLOB NOT NULL UNIQUE,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        player_id INTEGER NOT NULL REFERENCES players (id),
+        server_id INTEGER NOT NULL REFERENCES servers (id),
+        clan BLOB NOT NULL,
+        country INTEGER NOT NULL,
+        is_player INTEGER NOT NULL,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS sessions_player_id ON sessions (player_id);
+    CREATE INDEX IF NOT EXISTS sessions_server_id ON sessions (server_id);
+";
+
+fn now() -> i64 {
+    rust_time::get_time().sec
+}
+
+pub struct Tracker {
+    db: Connection,
+    /// Database IDs of the online servers.
+    servers: HashMap<LogAddr, i64>,
+    /// Database IDs of the open sessions, by server ID and player name.
+    /// Players with the same name on one server each have their own
+    /// session, in the order they appear in the player list.
+    sessions: HashMap<(i64, Vec<u8>), Vec<i64>>,
+    /// Sessions left open by a previous run that haven't been continued by
+    /// a restored server yet.
+    resumable: HashMap<(i64, Vec<u8>), Vec<i64>>,
+}
+
+impl Tracker {
+    /// Opens or creates the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Tracker> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+        Ok(Tracker {
+            db: db,
+            servers: HashMap::new(),
+            sessions: HashMap::new(),
+            resumable: HashMap::new(),
+        })
+    }
+    /// Closes the sessions left open by a previous run.
+    pub fn start(&mut self) -> rusqlite::Result<()> {
+        self.db.execute("UPDATE sessions SET end_time = ? WHERE end_time IS NULL", params![now()])?;
+        self.db.execute("UPDATE servers SET online = 0", NO_PARAMS)?;
+        Ok(())
+    }
+    /// Continues the sessions left open by a previous run, for use with the
+    /// state file of the browser.
+    ///
+    /// The sessions of the players on restored servers are continued, the
+    /// others are closed on the first tick. Like with `start`, all servers
+    /// are offline until they are restored.
+    pub fn resume(&mut self) -> rusqlite::Result<()> {
+        self.db.execute("UPDATE servers SET online = 0", NO_PARAMS)?;
+        let mut statement = self.db.prepare(
+            "SELECT sessions.id, sessions.server_id, players.name FROM sessions \
+             JOIN players ON players.id = sessions.player_id WHERE sessions.end_time IS NULL",
+        )?;
+        let rows = statement.query_map(NO_PARAMS, |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Vec<u8>>(2)?))
+        })?;
+        for row in rows {
+            let (id, server_id, name) = row?;
+            self.resumable.entry((server_id, name)).or_default().push(id);
+        }
+        Ok(())
+    }
+    fn close_resumable(&mut self) -> rusqlite::Result<()> {
+        let time = now();
+        for (_, ids) in self.resumable.drain() {
+            for id in ids {
+                self.db.execute("UPDATE sessions SET end_time = ? WHERE id = ?", params![time, id])?;
+            }
+        }
+        Ok(())
+    }
+    fn server_update(&mut self, addr: LogAddr, info: &ServerInfo) -> rusqlite::Result<i64> {
+        let time = now();
+        let addr_string = addr.addr.to_string();
+        let version_string = addr.version.to_string();
+        let id = match self.servers.get(&addr) {
+            Some(&id) => id,
+            None => {
+                self.db.execute(
+                    "INSERT OR IGNORE INTO servers (addr, info_version, first_seen, last_seen, online) \
+                     VALUES (?, ?, ?, ?, 0)",
+                    params![addr_string, version_string, time, time],
+                )?;
+                self.db.query_row(
+                    "SELECT id FROM servers WHERE addr = ? AND info_version = ?",
+                    params![addr_string, version_string],
+                    |row| row.get(0),
+                )?
+            },
+        };
+        self.db.execute(
+            "UPDATE servers SET last_seen = ?, online = 1, flags = ?, version = ?, \
+             game_type = ?, map = ?, name = ? WHERE id = ?",
+            params![time, info.flags, &info.version[..], &info.game_type[..],
+                    &info.map[..], &info.name[..], id],
+        )?;
+        self.servers.insert(addr, id);
+        Ok(id)
+    }
+    fn server_remove(&mut self, addr: LogAddr) -> rusqlite::Result<()> {
+        if let Some(id) = self.servers.remove(&addr) {
+            self.db.execute(
+                "UPDATE servers SET last_seen = ?, online = 0 WHERE id = ?",
+                params![now(), id],
+            )?;
+        }
+        Ok(())
+    }
+    fn player_new(&mut self, server_id: i64, info: &ClientInfo) -> rusqlite::Result<()> {
+        let time = now();
+        self.db.execute(
+            "INSERT OR IGNORE INTO players (name, first_seen, last_seen) VALUES (?, ?, ?)",
+            params![&info.name[..], time, time],
+        )?;
+        self.db.execute("UPDATE players SET last_seen = ? WHERE name = ?", params![time, &info.name[..]])?;
+        let player_id: i64 = self.db.query_row(
+            "SELECT id FROM players WHERE name = ?",
+            params![&info.name[..]],
+            |row| row.get(0),
+        )?;
+        self.db.execute(
+            "INSERT INTO sessions (player_id, server_id, clan, country, is_player, start_time) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+            params![player_id, server_id, &info.clan[..], info.country, info.is_player, time],
+        )?;
+        let id = self.db.last_insert_rowid();
+        self.sessions.entry((server_id, info.name.to_vec())).or_default().push(id);
+        Ok(())
+    }
+    /// Updates the session of the `index`-th player called like `info`.
+    fn player_change(&mut self, server_id: i64, index: usize, info: &ClientInfo)
+        -> rusqlite::Result<()>
+    {
+        let id = self.sessions.get(&(server_id, info.name.to_vec())).and_then(|s| s.get(index).cloned());
+        if let Some(id) = id {
+            self.db.execute(
+                "UPDATE sessions SET clan = ?, country = ?, is_player = ? WHERE id = ?",
+                params![&info.clan[..], info.country, info.is_player, id],
+            )?;
+        }
+        Ok(())
+    }
+    fn player_remove(&mut self, server_id: i64, info: &ClientInfo) -> rusqlite::Result<()> {
+        let time = now();
+        // Players with the same name that are still online come first in
+        // the player list, so the last session is the one that ended.
+        let key = (server_id, info.name.to_vec());
+        let id = match self.sessions.entry(key) {
+            hash_map::Entry::Occupied(mut o) => {
+                let id = o.get_mut().pop();
+                if o.get().is_empty() {
+                    o.remove();
+                }
+                id
+            },
+            hash_map::Entry::Vacant(_) => None,
+        };
+        if let Some(id) = id {
+            self.db.execute("UPDATE sessions SET end_time = ? WHERE id = ?", params![time, id])?;
+        }
+        self.db.execute("UPDATE players SET last_seen = ? WHERE name = ?", params![time, &info.name[..]])?;
+        Ok(())
+    }
+    fn diff_players(&mut self, server_id: i64, old: &[ClientInfo], new: &[ClientInfo])
+        -> rusqlite::Result<()>
+    {
+        for diff in diff_players(old, new) {
+            match diff {
+                PlayerDiff::New(player) => self.player_new(server_id, player)?,
+                PlayerDiff::Change(_, player) => {
+                    // Players with the same name are told apart by their
+                    // order in the player list.
+                    let index = new.iter()
+                        .take_while(|&c| !ptr::eq(c, player))
+                        .filter(|c| c.name == player.name)
+                        .count();
+                    self.player_change(server_id, index, player)?;
+                },
+                PlayerDiff::Remove(player) => self.player_remove(server_id, player)?,
+            }
+        }
+        Ok(())
+    }
+    fn on_server_new_impl(&mut self, addr: LogAddr, info: &ServerInfo) -> rusqlite::Result<()> {
+        let id = self.server_update(addr, info)?;
+        self.diff_players(id, &[], &info.clients)
+    }
+    fn on_server_change_impl(&mut self, addr: LogAddr, old: &ServerInfo, new: &ServerInfo)
+        -> rusqlite::Result<()>
+    {
+        let id = match self.servers.get(&addr) {
+            Some(&id) if !server_changed(old, new) => id,
+            _ => self.server_update(addr, new)?,
+        };
+        self.diff_players(id, &old.clients, &new.clients)
+    }
+    fn on_server_restore_impl(&mut self, addr: LogAddr, info: &ServerInfo) -> rusqlite::Result<()> {
+        let id = self.server_update(addr, info)?;
+        for player in info.clients.iter().filter(|c| !player_ignore(c)) {
+            let key = (id, player.name.to_vec());
+            let session_id = match self.resumable.get_mut(&key) {
+                Some(ids) if !ids.is_empty() => Some(ids.remove(0)),
+                _ => None,
+            };
+            match session_id {
+                Some(session_id) => self.sessions.entry(key).or_default().push(session_id),
+                None => self.player_new(id, player)?,
+            }
+        }
+        Ok(())
+    }
+    fn on_server_remove_impl(&mut self, addr: LogAddr, last: &ServerInfo) -> rusqlite::Result<()> {
+        if let Some(&id) = self.servers.get(&addr) {
+            self.diff_players(id, &last.clients, &[])?;
+        }
+        self.server_remove(addr)
+    }
+    /// Runs `f` in a single transaction, so that the many small updates of
+    /// one event are written at once. Rolls back the database and the IDs
+    /// kept in memory if `f` fails.
+    fn transaction<F>(&mut self, f: F) -> rusqlite::Result<()>
+        where F: FnOnce(&mut Tracker) -> rusqlite::Result<()>
+    {
+        let servers = self.servers.clone();
+        let sessions = self.sessions.clone();
+        let resumable = self.resumable.clone();
+        self.db.execute_batch("BEGIN")?;
+        let result = match f(self) {
+            Ok(()) => self.db.execute_batch("COMMIT"),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            if let Err(e) = self.db.execute_batch("ROLLBACK") {
+                error!("SQLite error while rolling back, {}", e);
+            }
+            self.servers = servers;
+            self.sessions = sessions;
+            self.resumable = resumable;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+fn log_error(result: rusqlite::Result<()>) {
+    if let Err(e) = result {
+        error!("SQLite error, {}", e);
+    }
+}
+
+impl StatsBrowserCb for Tracker {
+    fn on_server_new(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let result = self.transaction(|t| t.on_server_new_impl(LogAddr::new(addr, info), info));
+        log_error(result);
+    }
+
+    fn on_server_change(&mut self, addr: ServerAddr, old: &ServerInfo, new: &ServerInfo) {
+        let result = self.transaction(|t| {
+            // If the info version changed, treat the server as a new one.
+            if old.info_version != new.info_version {
+                t.on_server_remove_impl(LogAddr::new(addr, old), old)?;
+                return t.on_server_new_impl(LogAddr::new(addr, new), new);
+            }
+            t.on_server_change_impl(LogAddr::new(addr, new), old, new)
+        });
+        log_error(result);
+    }
+
+    fn on_server_remove(&mut self, addr: ServerAddr, last: &ServerInfo) {
+        let result = self.transaction(|t| t.on_server_remove_impl(LogAddr::new(addr, last), last));
+        log_error(result);
+    }
+
+    fn on_server_restore(&mut self, addr: ServerAddr, info: &ServerInfo) {
+        let result = self.transaction(|t| t.on_server_restore_impl(LogAddr::new(addr, info), info));
+        log_error(result);
+    }
+
+    fn on_tick(&mut self) {
+        if !self.resumable.is_empty() {
+            let result = self.transaction(|t| t.close_resumable());
+            log_error(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite::NO_PARAMS;
+    use rusqlite;
+    use serverbrowse::protocol::ClientInfo;
+    use serverbrowse::protocol::ServerInfo;
+    use serverbrowse::protocol::ServerInfoVersion;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use addr::Addr;
+    use addr::ProtocolVersion;
+    use addr::ServerAddr;
+
+    use StatsBrowserCb;
+    use super::Tracker;
+    use tracker::LogAddr;
+
+    fn addr() -> ServerAddr {
+        addr_port(8303)
+    }
+
+    fn addr_port(port: u16) -> ServerAddr {
+        ServerAddr::new(ProtocolVersion::V6, Addr::from_socket_addr(([192, 0, 2, 1], port).into()))
+    }
+
+    fn info(players: &[(&str, &str)]) -> ServerInfo {
+        ServerInfo {
+            info_version: ServerInfoVersion::V6,
+            map: "dm1".bytes().collect(),
+            clients: players.iter().map(|&(name, clan)| ClientInfo {
+                name: name.bytes().collect(),
+                clan: clan.bytes().collect(),
+                country: -1,
+                score: 0,
+                is_player: 1,
+            }).collect(),
+            ..ServerInfo::default()
+        }
+    }
+
+    /// Returns the name, clan and whether it's open of each session.
+    fn sessions(tracker: &Tracker) -> Vec<(String, String, bool)> {
+        let mut statement = tracker.db.prepare(
+            "SELECT players.name, sessions.clan, sessions.end_time IS NULL FROM sessions \
+             JOIN players ON players.id = sessions.player_id ORDER BY sessions.id",
+        ).unwrap();
+        let rows = statement.query_map(NO_PARAMS, |row| {
+            Ok((
+                String::from_utf8(row.get(0)?).unwrap(),
+                String::from_utf8(row.get(1)?).unwrap(),
+                row.get(2)?,
+            ))
+        }).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn online(tracker: &Tracker) -> i64 {
+        tracker.db.query_row("SELECT online FROM servers", NO_PARAMS, |row| row.get(0)).unwrap()
+    }
+
+    /// Returns whether each server is online, in the order they were added.
+    fn online_all(tracker: &Tracker) -> Vec<i64> {
+        let mut statement = tracker.db.prepare("SELECT online FROM servers ORDER BY id").unwrap();
+        let rows = statement.query_map(NO_PARAMS, |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn s(name: &str, clan: &str, open: bool) -> (String, String, bool) {
+        (name.to_owned(), clan.to_owned(), open)
+    }
+
+    #[test]
+    fn diff_players() {
+        let mut tracker = Tracker::open(":memory:").unwrap();
+        tracker.start().unwrap();
+        let first = info(&[("a", ""), ("b", "")]);
+        tracker.on_server_new(addr(), &first);
+        assert_eq!(sessions(&tracker), vec![s("a", "", true), s("b", "", true)]);
+        assert_eq!(online(&tracker), 1);
+
+        let second = info(&[("b", "clan"), ("c", "")]);
+        tracker.on_server_change(addr(), &first, &second);
+        assert_eq!(sessions(&tracker), vec![
+            s("a", "", false), s("b", "clan", true), s("c", "", true),
+        ]);
+
+        tracker.on_server_remove(addr(), &second);
+        assert_eq!(sessions(&tracker), vec![
+            s("a", "", false), s("b", "clan", false), s("c", "", false),
+        ]);
+        assert_eq!(online(&tracker), 0);
+    }
+
+    #[test]
+    fn info_version_change() {
+        let mut tracker = Tracker::open(":memory:").unwrap();
+        tracker.start().unwrap();
+        let old = info(&[("a", "")]);
+        let mut new = old.clone();
+        new.info_version = ServerInfoVersion::V664;
+        tracker.on_server_new(addr(), &old);
+        tracker.on_server_change(addr(), &old, &new);
+        // The server is tracked as a new one, with new sessions.
+        assert_eq!(sessions(&tracker), vec![s("a", "", false), s("a", "", true)]);
+        let servers: i64 = tracker.db.query_row("SELECT COUNT(*) FROM servers", NO_PARAMS, |row| row.get(0)).unwrap();
+        assert_eq!(servers, 2);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut tracker = Tracker::open(":memory:").unwrap();
+        tracker.start().unwrap();
+        let one = info(&[("a", "x")]);
+        let two = info(&[("a", "x"), ("a", "y")]);
+        let two_changed = info(&[("a", "x"), ("a", "z")]);
+        tracker.on_server_new(addr(), &one);
+        tracker.on_server_change(addr(), &one, &two);
+        tracker.on_server_change(addr(), &two, &two_changed);
+        assert_eq!(sessions(&tracker), vec![s("a", "x", true), s("a", "z", true)]);
+        tracker.on_server_change(addr(), &two_changed, &one);
+        assert_eq!(sessions(&tracker), vec![s("a", "x", true), s("a", "z", false)]);
+        tracker.on_server_remove(addr(), &one);
+        assert_eq!(sessions(&tracker), vec![s("a", "x", false), s("a", "z", false)]);
+    }
+
+    #[test]
+    fn rollback() {
+        let mut tracker = Tracker::open(":memory:").unwrap();
+        tracker.start().unwrap();
+        let first = info(&[("a", "")]);
+        let result = tracker.transaction(|t| {
+            t.on_server_new_impl(LogAddr::new(addr(), &first), &first)?;
+            Err(rusqlite::Error::QueryReturnedNoRows)
+        });
+        assert!(result.is_err());
+        assert!(tracker.servers.is_empty());
+        assert!(tracker.sessions.is_empty());
+        assert_eq!(sessions(&tracker), vec![]);
+
+        // The server is still recognized as new afterwards.
+        tracker.on_server_new(addr(), &first);
+        let second = info(&[]);
+        tracker.on_server_change(addr(), &first, &second);
+        assert_eq!(sessions(&tracker), vec![s("a", "", false)]);
+        assert_eq!(online(&tracker), 1);
+    }
+
+    #[test]
+    fn resume() {
+        let path = env::temp_dir().join(format!("stats-browser-tracker-{}.sqlite", process::id()));
+        {
+            let mut tracker = Tracker::open(&path).unwrap();
+            tracker.start().unwrap();
+            tracker.on_server_new(addr_port(8303), &info(&[("a", "")]));
+            tracker.on_server_new(addr_port(8304), &info(&[("b", "")]));
+        }
+        let mut tracker = Tracker::open(&path).unwrap();
+        tracker.resume().unwrap();
+        assert_eq!(online_all(&tracker), vec![0, 0]);
+
+        tracker.on_server_restore(addr_port(8303), &info(&[("a", "")]));
+        tracker.on_tick();
+        assert_eq!(online_all(&tracker), vec![1, 0]);
+        // The session on the restored server is continued.
+        assert_eq!(sessions(&tracker), vec![s("a", "", true), s("b", "", false)]);
+        drop(tracker);
+        fs::remove_file(&path).unwrap();
+    }
+}
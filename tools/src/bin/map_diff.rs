@@ -0,0 +1,45 @@
+extern crate clap;
+extern crate logger;
+extern crate map;
+
+use std::path::Path;
+use std::process;
+
+fn process(old: &Path, new: &Path) -> Result<Vec<map::diff::Change>, map::Error> {
+    let mut old = map::Reader::open(old)?;
+    let mut new = map::Reader::open(new)?;
+    map::diff::diff(&mut old, &mut new)
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Map diff")
+        .about("Lists the groups, layers, tiles, images, envelopes and \
+                settings that changed between two map files.")
+        .arg(Arg::with_name("OLD")
+            .help("Sets the original map file")
+            .required(true)
+        )
+        .arg(Arg::with_name("NEW")
+            .help("Sets the changed map file")
+            .required(true)
+        )
+        .get_matches();
+
+    let old = Path::new(matches.value_of_os("OLD").unwrap());
+    let new = Path::new(matches.value_of_os("NEW").unwrap());
+
+    match process(old, new) {
+        Ok(changes) => for c in changes {
+            println!("{}", c);
+        },
+        Err(err) => {
+            eprintln!("{} {}: {:?}", old.display(), new.display(), err);
+            process::exit(1);
+        }
+    }
+}
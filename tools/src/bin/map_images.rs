@@ -0,0 +1,178 @@
+extern crate clap;
+extern crate datafile;
+extern crate image;
+extern crate logger;
+extern crate map;
+extern crate zlib_minimal as zlib;
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::Write;
+use std::io;
+use std::path::Path;
+use std::process;
+
+#[derive(Debug)]
+enum Error {
+    Map(map::Error),
+    Df(datafile::Error),
+    Image(image::ImageError),
+    Io(io::Error),
+}
+
+impl From<map::Error> for Error {
+    fn from(err: map::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+impl From<datafile::Error> for Error {
+    fn from(err: datafile::Error) -> Error {
+        Error::Df(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Error {
+        Error::Image(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+fn list(path: &Path) -> Result<(), Error> {
+    let mut map = map::Reader::open(path)?;
+    for (i, image) in map::images::list(&mut map)?.into_iter().enumerate() {
+        println!("#{} {} {}x{} {}",
+            i,
+            String::from_utf8_lossy(&image.name),
+            image.width,
+            image.height,
+            if image.external { "external" } else { "embedded" },
+        );
+    }
+    Ok(())
+}
+
+fn export(path: &Path, out_dir: &Path) -> Result<(), Error> {
+    let mut map = map::Reader::open(path)?;
+    let images = map::images::list(&mut map)?;
+    let file_names = map::images::file_names(&images);
+    for (image, file_name) in images.iter().zip(file_names) {
+        match map::images::data(&mut map, image.index)? {
+            Some(data) => {
+                let out_path = out_dir.join(format!("{}.png", file_name));
+                image::save_buffer(&out_path, &data, image.width, image.height, image::RGBA(8))?;
+                println!("{}", out_path.display());
+            },
+            None => println!("{}: external, skipped", String::from_utf8_lossy(&image.name)),
+        }
+    }
+    Ok(())
+}
+
+fn import(path: &Path, out_path: &Path, pngs: &[&Path]) -> Result<(), Error> {
+    let mut map = map::Reader::open(path)?;
+    let images = map::images::list(&mut map)?;
+    let mut replacements = HashMap::new();
+    for &png in pngs {
+        let name = png.file_stem().and_then(OsStr::to_str).unwrap_or("");
+        let target = images.iter().find(|i| i.name == name.as_bytes());
+        let target = match target {
+            Some(t) => t,
+            None => {
+                eprintln!("{}: no image named {:?}", path.display(), name);
+                continue;
+            },
+        };
+        let rgba = image::open(png)?.to_rgba();
+        let (width, height) = rgba.dimensions();
+        replacements.insert(target.index, map::images::Replacement {
+            width: width,
+            height: height,
+            data: rgba.into_raw(),
+        });
+        println!("{}: replaced {}", path.display(), name);
+    }
+    let buffer = map::images::replace(&mut map, &replacements)?;
+    let mut result = Vec::new();
+    datafile::writer::write(&mut result, &buffer, zlib::BEST_COMPRESSION)?;
+    File::create(out_path)?.write_all(&result)?;
+    Ok(())
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+    use clap::SubCommand;
+
+    logger::init();
+
+    let matches = App::new("Map images")
+        .about("Lists, exports and replaces the images of a map.")
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists embedded and external images")
+            .arg(Arg::with_name("MAP")
+                .help("Sets the map file to read")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("export")
+            .about("Exports all embedded images as PNG files named after the images")
+            .arg(Arg::with_name("MAP")
+                .help("Sets the map file to read")
+                .required(true)
+            )
+            .arg(Arg::with_name("DIR")
+                .help("Sets the directory to write the PNG files to")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("import")
+            .about("Replaces or embeds the images named like the given PNG files")
+            .arg(Arg::with_name("MAP")
+                .help("Sets the map file to read")
+                .required(true)
+            )
+            .arg(Arg::with_name("OUTPUT")
+                .help("Sets the file to write the new map to")
+                .required(true)
+            )
+            .arg(Arg::with_name("PNG")
+                .help("Sets the PNG files to import, e.g. grass_main.png")
+                .multiple(true)
+                .required(true)
+            )
+        )
+        .get_matches();
+
+    let (path, result) = match matches.subcommand() {
+        ("list", Some(m)) => {
+            let path = Path::new(m.value_of_os("MAP").unwrap());
+            (path, list(path))
+        },
+        ("export", Some(m)) => {
+            let path = Path::new(m.value_of_os("MAP").unwrap());
+            (path, export(path, Path::new(m.value_of_os("DIR").unwrap())))
+        },
+        ("import", Some(m)) => {
+            let path = Path::new(m.value_of_os("MAP").unwrap());
+            let out_path = Path::new(m.value_of_os("OUTPUT").unwrap());
+            let pngs: Vec<_> = m.values_of_os("PNG").unwrap().map(Path::new).collect();
+            (path, import(path, out_path, &pngs))
+        },
+        _ => {
+            eprintln!("{}", matches.usage());
+            process::exit(1);
+        },
+    };
+    if let Err(err) = result {
+        eprintln!("{}: {:?}", path.display(), err);
+        process::exit(1);
+    }
+}
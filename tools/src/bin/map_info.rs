@@ -0,0 +1,195 @@
+extern crate clap;
+extern crate common;
+extern crate datafile;
+extern crate logger;
+extern crate map;
+extern crate zlib_minimal as zlib;
+
+use common::pretty;
+use map::info::MapInfo;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+#[derive(Debug)]
+enum Error {
+    Map(map::Error),
+    Df(datafile::Error),
+}
+
+impl From<map::Error> for Error {
+    fn from(err: map::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+impl From<datafile::Error> for Error {
+    fn from(err: datafile::Error) -> Error {
+        Error::Df(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Df(datafile::Error::Io(err))
+    }
+}
+
+struct Edit<'a> {
+    author: Option<&'a str>,
+    version: Option<&'a str>,
+    credits: Option<&'a str>,
+    license: Option<&'a str>,
+    clear_settings: bool,
+    remove_settings: Vec<&'a str>,
+    set_settings: Vec<&'a str>,
+}
+
+impl<'a> Edit<'a> {
+    fn apply(&self, info: &mut MapInfo) {
+        fn set(field: &mut Option<Vec<u8>>, value: Option<&str>) {
+            if let Some(v) = value {
+                // An empty value removes the field.
+                *field = if v.is_empty() { None } else { Some(v.as_bytes().to_vec()) };
+            }
+        }
+        set(&mut info.author, self.author);
+        set(&mut info.version, self.version);
+        set(&mut info.credits, self.credits);
+        set(&mut info.license, self.license);
+        if self.clear_settings {
+            info.settings.clear();
+        }
+        for s in &self.remove_settings {
+            info.remove_setting(s.as_bytes());
+        }
+        for s in &self.set_settings {
+            info.set_setting(s.as_bytes());
+        }
+    }
+}
+
+fn print(info: &MapInfo) {
+    let fields = [
+        ("author", &info.author),
+        ("version", &info.version),
+        ("credits", &info.credits),
+        ("license", &info.license),
+    ];
+    for &(name, value) in &fields {
+        if let Some(ref v) = *value {
+            println!("{}: {:?}", name, pretty::AlmostString::new(v));
+        }
+    }
+    for s in &info.settings {
+        println!("setting: {:?}", pretty::AlmostString::new(s));
+    }
+}
+
+fn process(path: &Path, output: Option<&Path>, edit: &Edit) -> Result<(), Error> {
+    let mut map = map::Reader::open(path)?;
+    let mut info = MapInfo::read(&mut map)?;
+    let output = match output {
+        Some(o) => o,
+        None => {
+            print(&info);
+            return Ok(());
+        },
+    };
+    edit.apply(&mut info);
+    let buffer = map::info::write(&mut map, &info)?;
+    let mut result = Vec::new();
+    datafile::writer::write(&mut result, &buffer, zlib::BEST_COMPRESSION)?;
+    File::create(output)?.write_all(&result)?;
+    Ok(())
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Map info")
+        .about("Prints or changes the author, version, credits, license and \
+                server settings of a map.")
+        .arg(Arg::with_name("MAP")
+            .help("Sets the map file to read")
+            .required(true)
+        )
+        .arg(Arg::with_name("OUTPUT")
+            .help("Sets the file to write the changed map to; without it, \
+                   the map info is printed")
+        )
+        .arg(Arg::with_name("author")
+            .long("author")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .value_name("AUTHOR")
+            .help("Sets the author, an empty value removes it")
+        )
+        .arg(Arg::with_name("map-version")
+            .long("map-version")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .value_name("VERSION")
+            .help("Sets the map version, an empty value removes it")
+        )
+        .arg(Arg::with_name("credits")
+            .long("credits")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .value_name("CREDITS")
+            .help("Sets the credits, an empty value removes them")
+        )
+        .arg(Arg::with_name("license")
+            .long("license")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .value_name("LICENSE")
+            .help("Sets the license, an empty value removes it")
+        )
+        .arg(Arg::with_name("clear-settings")
+            .long("clear-settings")
+            .requires("OUTPUT")
+            .help("Removes all server settings")
+        )
+        .arg(Arg::with_name("remove-setting")
+            .long("remove-setting")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("COMMAND")
+            .help("Removes the server settings with the given command, e.g. sv_deepfly")
+        )
+        .arg(Arg::with_name("setting")
+            .long("setting")
+            .requires("OUTPUT")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("SETTING")
+            .help("Adds a server setting, replacing the ones with the same command, \
+                   e.g. \"sv_deepfly 0\"")
+        )
+        .get_matches();
+
+    let path = Path::new(matches.value_of_os("MAP").unwrap());
+    let output = matches.value_of_os("OUTPUT").map(Path::new);
+    let edit = Edit {
+        author: matches.value_of("author"),
+        version: matches.value_of("map-version"),
+        credits: matches.value_of("credits"),
+        license: matches.value_of("license"),
+        clear_settings: matches.is_present("clear-settings"),
+        remove_settings: matches.values_of("remove-setting").map(|v| v.collect()).unwrap_or_default(),
+        set_settings: matches.values_of("setting").map(|v| v.collect()).unwrap_or_default(),
+    };
+
+    if let Err(err) = process(path, output, &edit) {
+        eprintln!("{}: {:?}", path.display(), err);
+        process::exit(1);
+    }
+}
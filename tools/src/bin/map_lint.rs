@@ -0,0 +1,107 @@
+extern crate clap;
+extern crate logger;
+extern crate map;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use map::lint::Diagnostic;
+use map::lint::Severity;
+use std::io;
+use std::path::Path;
+use std::process;
+
+#[derive(Serialize)]
+struct JsonDiagnostic {
+    check: &'static str,
+    severity: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct JsonMap<'a> {
+    map: &'a str,
+    error: Option<String>,
+    diagnostics: Vec<JsonDiagnostic>,
+}
+
+fn print_json(path: &Path, result: &Result<Vec<Diagnostic>, map::Error>) {
+    let (error, diagnostics) = match *result {
+        Ok(ref d) => (None, &d[..]),
+        Err(ref e) => (Some(format!("{:?}", e)), &[][..]),
+    };
+    let map = path.to_string_lossy();
+    let stdout = io::stdout();
+    serde_json::to_writer(stdout.lock(), &JsonMap {
+        map: &map,
+        error: error,
+        diagnostics: diagnostics.iter().map(|d| JsonDiagnostic {
+            check: d.check().name(),
+            severity: d.severity().name(),
+            message: d.to_string(),
+        }).collect(),
+    }).unwrap();
+    println!();
+}
+
+fn print_human(path: &Path, result: &Result<Vec<Diagnostic>, map::Error>) {
+    match *result {
+        Ok(ref diagnostics) => for d in diagnostics {
+            println!("{}: {}: {} [{}]",
+                path.display(), d.severity().name(), d, d.check().name());
+        },
+        Err(ref e) => eprintln!("{}: {:?}", path.display(), e),
+    }
+}
+
+fn process(path: &Path) -> Result<Vec<Diagnostic>, map::Error> {
+    let mut map = map::Reader::open(path)?;
+    map::lint::lint(&mut map)
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Map linter")
+        .about("Checks map files for inconsistencies, e.g. unpaired \
+                teleporters, unused images or invalid envelope references.")
+        .arg(Arg::with_name("MAP")
+            .help("Sets the map files to check")
+            .multiple(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Prints one JSON object per map instead of human-readable output")
+        )
+        .arg(Arg::with_name("warnings-as-errors")
+            .long("warnings-as-errors")
+            .help("Exits with an error code if there are warnings")
+        )
+        .get_matches();
+
+    let json = matches.is_present("json");
+    let strict = matches.is_present("warnings-as-errors");
+
+    let mut failed = false;
+    for map in matches.values_of_os("MAP").unwrap() {
+        let path = Path::new(map);
+        let result = process(path);
+        failed |= match result {
+            Ok(ref diagnostics) => diagnostics.iter()
+                .any(|d| strict || d.severity() == Severity::Error),
+            Err(_) => true,
+        };
+        if json {
+            print_json(path, &result);
+        } else {
+            print_human(path, &result);
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
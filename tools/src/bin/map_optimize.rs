@@ -0,0 +1,81 @@
+extern crate clap;
+extern crate datafile;
+extern crate logger;
+extern crate map;
+extern crate zlib_minimal as zlib;
+
+use std::fs::File;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+
+#[derive(Debug)]
+enum Error {
+    Map(map::Error),
+    Df(datafile::Error),
+}
+
+impl From<map::Error> for Error {
+    fn from(err: map::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+impl From<datafile::Error> for Error {
+    fn from(err: datafile::Error) -> Error {
+        Error::Df(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Df(datafile::Error::Io(err))
+    }
+}
+
+fn process(input: &Path, output: &Path) -> Result<(u64, u64), Error> {
+    let old_size = fs::metadata(input)?.len();
+    let mut map = map::Reader::open(input)?;
+    let buffer = map::optimize::optimize(&mut map)?;
+    let mut result = Vec::new();
+    datafile::writer::write(&mut result, &buffer, zlib::BEST_COMPRESSION)?;
+    File::create(output)?.write_all(&result)?;
+    Ok((old_size, result.len() as u64))
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Map optimizer")
+        .about("Removes unused images, envelopes, sounds and empty layers \
+                from a map and recompresses it.")
+        .arg(Arg::with_name("INPUT")
+            .help("Sets the map file to optimize")
+            .required(true)
+        )
+        .arg(Arg::with_name("OUTPUT")
+            .help("Sets the file to write the optimized map to")
+            .required(true)
+        )
+        .get_matches();
+
+    let input = Path::new(matches.value_of_os("INPUT").unwrap());
+    let output = Path::new(matches.value_of_os("OUTPUT").unwrap());
+
+    match process(input, output) {
+        Ok((old, new)) => {
+            let saved = old as i64 - new as i64;
+            let percent = if old != 0 { saved as f64 * 100.0 / old as f64 } else { 0.0 };
+            println!("{}: {} -> {} bytes, saved {} bytes ({:.1}%)",
+                input.display(), old, new, saved, percent);
+        },
+        Err(err) => {
+            eprintln!("{}: {:?}", input.display(), err);
+            process::exit(1);
+        }
+    }
+}
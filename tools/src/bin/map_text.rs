@@ -0,0 +1,128 @@
+extern crate clap;
+extern crate datafile;
+extern crate logger;
+extern crate map;
+extern crate serde_json;
+extern crate zlib_minimal as zlib;
+
+use std::fs::File;
+use std::fs;
+use std::io::Write;
+use std::io;
+use std::path::Path;
+use std::process;
+
+// Name of the JSON file in the export directory.
+const MAP_JSON: &'static str = "map.json";
+
+#[derive(Debug)]
+enum Error {
+    Map(map::Error),
+    Text(map::text::Error),
+    Df(datafile::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+}
+
+impl From<map::Error> for Error {
+    fn from(err: map::Error) -> Error {
+        Error::Map(err)
+    }
+}
+
+impl From<map::text::Error> for Error {
+    fn from(err: map::text::Error) -> Error {
+        Error::Text(err)
+    }
+}
+
+impl From<datafile::Error> for Error {
+    fn from(err: datafile::Error) -> Error {
+        Error::Df(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+fn export(path: &Path, dir: &Path) -> Result<(), Error> {
+    let mut map = map::Reader::open(path)?;
+    fs::create_dir_all(dir)?;
+    let text = map::text::export(&mut map, dir)?;
+    let mut file = File::create(dir.join(MAP_JSON))?;
+    serde_json::to_writer_pretty(&mut file, &text)?;
+    file.write_all(b"\n")?;
+    Ok(())
+}
+
+fn import(dir: &Path, out_path: &Path) -> Result<(), Error> {
+    let text: map::text::TextMap = serde_json::from_reader(File::open(dir.join(MAP_JSON))?)?;
+    let buffer = map::text::import(&text, dir)?;
+    let mut result = Vec::new();
+    datafile::writer::write(&mut result, &buffer, zlib::BEST_COMPRESSION)?;
+    File::create(out_path)?.write_all(&result)?;
+    Ok(())
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+    use clap::SubCommand;
+
+    logger::init();
+
+    let matches = App::new("Map text")
+        .about("Converts maps to a JSON representation suitable for version \
+                control and back.")
+        .subcommand(SubCommand::with_name("export")
+            .about("Writes the map as map.json, images and sounds into a directory")
+            .arg(Arg::with_name("MAP")
+                .help("Sets the map file to read")
+                .required(true)
+            )
+            .arg(Arg::with_name("DIR")
+                .help("Sets the directory to write to")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("import")
+            .about("Creates a map from a directory written by export")
+            .arg(Arg::with_name("DIR")
+                .help("Sets the directory to read")
+                .required(true)
+            )
+            .arg(Arg::with_name("OUTPUT")
+                .help("Sets the file to write the map to")
+                .required(true)
+            )
+        )
+        .get_matches();
+
+    let (path, result) = match matches.subcommand() {
+        ("export", Some(m)) => {
+            let path = Path::new(m.value_of_os("MAP").unwrap());
+            (path, export(path, Path::new(m.value_of_os("DIR").unwrap())))
+        },
+        ("import", Some(m)) => {
+            let path = Path::new(m.value_of_os("DIR").unwrap());
+            (path, import(path, Path::new(m.value_of_os("OUTPUT").unwrap())))
+        },
+        _ => {
+            eprintln!("{}", matches.usage());
+            process::exit(1);
+        },
+    };
+    if let Err(err) = result {
+        eprintln!("{}: {:?}", path.display(), err);
+        process::exit(1);
+    }
+}
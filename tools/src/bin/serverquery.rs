@@ -0,0 +1,273 @@
+#![cfg(not(test))]
+
+extern crate clap;
+#[macro_use]
+extern crate log;
+extern crate logger;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serverbrowse;
+
+use serverbrowse::browser::Browser;
+use serverbrowse::browser::Filter;
+use serverbrowse::protocol::ClientInfo;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol;
+use std::cmp;
+use std::io;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::process;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 8303;
+const MASTER_MIN: u32 = 1;
+const MASTER_MAX: u32 = 4;
+/// Server flag signalling a password.
+const FLAG_PASSWORD: i32 = 1;
+
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + duration.subsec_millis() as u64
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[derive(Serialize)]
+struct JsonMap {
+    name: String,
+    crc: Option<u32>,
+    size: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct JsonClient {
+    name: String,
+    clan: String,
+    country: i32,
+    score: i32,
+    is_player: bool,
+}
+
+#[derive(Serialize)]
+struct JsonServer {
+    address: String,
+    ping_ms: Option<u64>,
+    info_version: &'static str,
+    version: String,
+    name: String,
+    game_type: String,
+    map: JsonMap,
+    passworded: bool,
+    num_players: i32,
+    max_players: i32,
+    num_clients: i32,
+    max_clients: i32,
+    clients: Vec<JsonClient>,
+}
+
+fn print_json(addr: SocketAddr, ping: Option<Duration>, info: &ServerInfo) {
+    let stdout = io::stdout();
+    serde_json::to_writer(stdout.lock(), &JsonServer {
+        address: addr.to_string(),
+        ping_ms: ping.map(duration_ms),
+        info_version: info.info_version.name(),
+        version: string(&info.version),
+        name: string(&info.name),
+        game_type: string(&info.game_type),
+        map: JsonMap {
+            name: string(&info.map),
+            crc: info.map_crc,
+            size: info.map_size,
+        },
+        passworded: info.flags & FLAG_PASSWORD != 0,
+        num_players: info.num_players,
+        max_players: info.max_players,
+        num_clients: info.num_clients,
+        max_clients: info.max_clients,
+        clients: info.clients.iter().map(|c| JsonClient {
+            name: string(&c.name),
+            clan: string(&c.clan),
+            country: c.country,
+            score: c.score,
+            is_player: c.is_player != 0,
+        }).collect(),
+    }).unwrap();
+    println!();
+}
+
+fn print_clients(clients: &[ClientInfo]) {
+    let mut clients: Vec<_> = clients.iter().collect();
+    clients.sort_by_key(|c| (c.is_player == 0, -c.score));
+    for c in clients {
+        println!("    {:>5}  {:<15}  {:<11}{}",
+            c.score,
+            string(&c.name),
+            string(&c.clan),
+            if c.is_player == 0 { "  (spectator)" } else { "" },
+        );
+    }
+}
+
+fn print_table(servers: &[(SocketAddr, Option<Duration>, ServerInfo)], clients: bool) {
+    let rows: Vec<_> = servers.iter().map(|&(addr, ping, ref info)| [
+        addr.to_string(),
+        ping.map(|p| duration_ms(p).to_string()).unwrap_or_else(|| "-".to_owned()),
+        format!("{}/{}", info.num_clients, info.max_clients),
+        string(&info.game_type),
+        string(&info.map),
+        string(&info.name),
+    ]).collect();
+    let header = ["ADDRESS", "PING", "PLAYERS", "GAME TYPE", "MAP", "NAME"];
+    let mut widths: Vec<_> = header.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (w, c) in widths.iter_mut().zip(row.iter()) {
+            *w = cmp::max(*w, c.chars().count());
+        }
+    }
+    let print_row = |row: &[&str]| {
+        println!("{:<w0$}  {:>w1$}  {:>w2$}  {:<w3$}  {:<w4$}  {}",
+            row[0], row[1], row[2], row[3], row[4], row[5],
+            w0=widths[0], w1=widths[1], w2=widths[2], w3=widths[3], w4=widths[4]);
+    };
+    print_row(&header);
+    for (row, &(_, _, ref info)) in rows.iter().zip(servers) {
+        print_row(&row.iter().map(|c| &c[..]).collect::<Vec<_>>());
+        if clients {
+            print_clients(&info.clients);
+        }
+    }
+}
+
+fn resolve(addr: &str, default_port: u16) -> Option<SocketAddr> {
+    let result = addr.to_socket_addrs()
+        .or_else(|_| (addr, default_port).to_socket_addrs())
+        .map(|mut addrs| addrs.next());
+    match result {
+        Ok(Some(a)) => Some(a),
+        Ok(None) => {
+            error!("{} didn't resolve to any address", addr);
+            None
+        },
+        Err(e) => {
+            error!("couldn't resolve {}: {}", addr, e);
+            None
+        },
+    }
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Server query")
+        .about("Queries the master servers for their server lists and all \
+                listed servers for their info. The extended info request is \
+                tried first, falling back to the vanilla 0.6 requests.")
+        .arg(Arg::with_name("SERVER")
+            .help("Queries these servers instead of the ones listed by the master servers")
+            .multiple(true)
+        )
+        .arg(Arg::with_name("master")
+            .long("master")
+            .value_name("HOST")
+            .help("Queries this master server instead of master1.teeworlds.com to \
+                   master4.teeworlds.com, can be given multiple times")
+            .multiple(true)
+            .number_of_values(1)
+        )
+        .arg(Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("MS")
+            .help("Sets the time to wait for replies to a request, in milliseconds")
+            .default_value("2000")
+        )
+        .arg(Arg::with_name("gametype")
+            .long("gametype")
+            .value_name("STRING")
+            .help("Only shows servers whose game type contains STRING, ignoring case")
+        )
+        .arg(Arg::with_name("map")
+            .long("map")
+            .value_name("STRING")
+            .help("Only shows servers whose map name contains STRING, ignoring case")
+        )
+        .arg(Arg::with_name("player")
+            .long("player")
+            .value_name("STRING")
+            .help("Only shows servers with a client whose name contains STRING, ignoring case")
+        )
+        .arg(Arg::with_name("non-empty")
+            .long("non-empty")
+            .help("Only shows servers with at least one client")
+        )
+        .arg(Arg::with_name("clients")
+            .long("clients")
+            .help("Lists the clients of each server below it")
+        )
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Prints one JSON object per server instead of a table")
+            .conflicts_with("clients")
+        )
+        .get_matches();
+
+    let timeout = match matches.value_of("timeout").unwrap().parse() {
+        Ok(t) => Duration::from_millis(t),
+        Err(e) => {
+            eprintln!("invalid timeout: {}", e);
+            process::exit(1);
+        },
+    };
+    let filter = Filter {
+        game_type: matches.value_of("gametype").map(str::to_owned),
+        map: matches.value_of("map").map(str::to_owned),
+        player: matches.value_of("player").map(str::to_owned),
+        non_empty: matches.is_present("non-empty"),
+    };
+
+    let mut browser = Browser::new(timeout);
+    if let Some(servers) = matches.values_of("SERVER") {
+        for server in servers {
+            if let Some(addr) = resolve(server, DEFAULT_PORT) {
+                browser.add_server(addr);
+            }
+        }
+    } else {
+        let masters: Vec<_> = match matches.values_of("master") {
+            Some(m) => m.map(str::to_owned).collect(),
+            None => (MASTER_MIN..MASTER_MAX+1)
+                .map(|i| format!("master{}.teeworlds.com", i))
+                .collect(),
+        };
+        for master in masters {
+            if let Some(addr) = resolve(&master, protocol::MASTERSERVER_PORT) {
+                browser.add_master(addr);
+            }
+        }
+    }
+    browser.run();
+
+    let num_servers = browser.servers().len();
+    let mut servers: Vec<_> = browser.servers().iter()
+        .filter_map(|s| s.best_info().map(|i| (s.addr(), s.ping(), i)))
+        .collect();
+    let num_replied = servers.len();
+    servers.retain(|&(_, _, ref info)| filter.matches(info));
+    servers.sort_by(|&(a_addr, _, ref a), &(b_addr, _, ref b)| {
+        b.num_clients.cmp(&a.num_clients).then(a_addr.cmp(&b_addr))
+    });
+
+    if matches.is_present("json") {
+        for &(addr, ping, ref info) in &servers {
+            print_json(addr, ping, info);
+        }
+    } else {
+        print_table(&servers, matches.is_present("clients"));
+    }
+    eprintln!("{} servers, {} replied, {} shown", num_servers, num_replied, servers.len());
+}
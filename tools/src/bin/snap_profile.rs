@@ -0,0 +1,363 @@
+extern crate clap;
+extern crate demo;
+extern crate gamenet;
+extern crate logger;
+extern crate packer;
+extern crate snapshot;
+extern crate teehistorian;
+extern crate tools;
+
+use gamenet::enums::MAX_SNAPSHOT_PACKSIZE;
+use gamenet::snap_obj::obj_size;
+use gamenet::snap_obj;
+use packer::Unpacker;
+use packer::with_packer;
+use snapshot::snap::Delta;
+use snapshot::snap::DeltaReader;
+use snapshot::snap::MAX_SNAPSHOT_SIZE;
+use snapshot::snap::Snap;
+use snapshot::snap;
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::process;
+use tools::teehistorian_snaps::Snaps;
+use tools::warn_stdout::Stdout;
+
+#[derive(Debug)]
+enum Error {
+    Demo(demo::format::Error),
+    Teehistorian(teehistorian::format::Error),
+    Io(io::Error),
+    Snap(snap::Error),
+}
+
+impl From<demo::Error> for Error {
+    fn from(e: demo::Error) -> Error {
+        match e {
+            demo::Error::Demo(e) => Error::Demo(e),
+            demo::Error::Io(e) => Error::Io(e),
+        }
+    }
+}
+
+impl From<teehistorian::Error> for Error {
+    fn from(e: teehistorian::Error) -> Error {
+        match e {
+            teehistorian::Error::Teehistorian(e) => Error::Teehistorian(e),
+            teehistorian::Error::Io(e) => Error::Io(e),
+        }
+    }
+}
+
+impl From<snap::Error> for Error {
+    fn from(e: snap::Error) -> Error {
+        Error::Snap(e)
+    }
+}
+
+fn type_name(type_id: u16) -> Option<&'static str> {
+    Some(match type_id {
+        snap_obj::PLAYER_INPUT => "player_input",
+        snap_obj::PROJECTILE => "projectile",
+        snap_obj::LASER => "laser",
+        snap_obj::PICKUP => "pickup",
+        snap_obj::FLAG => "flag",
+        snap_obj::GAME_INFO => "game_info",
+        snap_obj::GAME_DATA => "game_data",
+        snap_obj::CHARACTER_CORE => "character_core",
+        snap_obj::CHARACTER => "character",
+        snap_obj::PLAYER_INFO => "player_info",
+        snap_obj::CLIENT_INFO => "client_info",
+        snap_obj::SPECTATOR_INFO => "spectator_info",
+        snap_obj::COMMON => "common",
+        snap_obj::EXPLOSION => "explosion",
+        snap_obj::SPAWN => "spawn",
+        snap_obj::HAMMER_HIT => "hammer_hit",
+        snap_obj::DEATH => "death",
+        snap_obj::SOUND_GLOBAL => "sound_global",
+        snap_obj::SOUND_WORLD => "sound_world",
+        snap_obj::DAMAGE_IND => "damage_ind",
+        _ => return None,
+    })
+}
+
+/// Number of bytes `packer::Packer::write_int` uses for the given integer.
+fn int_size(int: i32) -> u64 {
+    let sign = if int < 0 { 1 } else { 0 };
+    let mut int = ((int ^ -sign) as u32) >> 6;
+    let mut size = 1;
+    while int != 0 {
+        int >>= 7;
+        size += 1;
+    }
+    size
+}
+
+fn num_parts(size: usize) -> usize {
+    (size + MAX_SNAPSHOT_PACKSIZE - 1) / MAX_SNAPSHOT_PACKSIZE
+}
+
+#[derive(Default)]
+struct TypeStats {
+    /// Number of items of this type, summed over all snapshots.
+    items: u64,
+    /// Bytes in full snapshots, not counting the offset table.
+    bytes: u64,
+    /// Bytes in deltas, including deletions.
+    delta_bytes: u64,
+    /// Items that were also present in the previous snapshot.
+    kept: u64,
+    /// Items that were unchanged compared to the previous snapshot.
+    unchanged: u64,
+    added: u64,
+    removed: u64,
+}
+
+#[derive(Default)]
+struct Stats {
+    types: BTreeMap<u16, TypeStats>,
+    snaps: u64,
+    deltas: u64,
+    bytes: u64,
+    delta_bytes: u64,
+    max_bytes: usize,
+    max_delta_bytes: usize,
+    max_added: u64,
+    max_removed: u64,
+    parts: BTreeMap<usize, u64>,
+    delta_parts: BTreeMap<usize, u64>,
+    print_ticks: bool,
+
+    delta: Delta,
+    keys: Vec<i32>,
+    buf: Vec<u8>,
+}
+
+impl Stats {
+    fn add(&mut self, tick: Option<i32>, prev: Option<&Snap>, snap: &Snap) {
+        self.buf.clear();
+        self.buf.reserve(MAX_SNAPSHOT_SIZE * 2);
+        let keys = &mut self.keys;
+        let size = with_packer(&mut self.buf, |p| snap.write(keys, p))
+            .unwrap().len();
+        self.snaps += 1;
+        self.bytes += size as u64;
+        self.max_bytes = self.max_bytes.max(size);
+        *self.parts.entry(num_parts(size)).or_insert(0) += 1;
+
+        for item in snap.items() {
+            let t = self.types.entry(item.type_id).or_insert_with(Default::default);
+            t.items += 1;
+            t.bytes += int_size(item.key()) +
+                item.data.iter().map(|&d| int_size(d)).sum::<u64>();
+        }
+
+        let prev = match prev {
+            Some(p) => p,
+            None => {
+                if self.print_ticks {
+                    println!("tick={:?} size={}", tick, size);
+                }
+                return;
+            }
+        };
+
+        self.delta.create(prev, snap);
+        self.buf.clear();
+        self.buf.reserve(MAX_SNAPSHOT_SIZE * 2);
+        let delta = &self.delta;
+        let delta_size = with_packer(&mut self.buf, |p| delta.write(obj_size, p))
+            .unwrap().len();
+        self.deltas += 1;
+        self.delta_bytes += delta_size as u64;
+        self.max_delta_bytes = self.max_delta_bytes.max(delta_size);
+        *self.delta_parts.entry(num_parts(delta_size)).or_insert(0) += 1;
+
+        let mut added = 0;
+        let mut removed = 0;
+        for item in prev.items() {
+            if snap.item(item.type_id, item.id).is_none() {
+                let t = self.types.entry(item.type_id).or_insert_with(Default::default);
+                t.removed += 1;
+                t.delta_bytes += int_size(item.key());
+                removed += 1;
+            }
+        }
+        for item in snap.items() {
+            let t = self.types.entry(item.type_id).or_insert_with(Default::default);
+            t.delta_bytes += int_size(item.type_id as i32) + int_size(item.id as i32);
+            if obj_size(item.type_id).is_none() {
+                t.delta_bytes += int_size(item.data.len() as i32);
+            }
+            match prev.item(item.type_id, item.id) {
+                Some(from) => {
+                    t.kept += 1;
+                    if from == item.data {
+                        t.unchanged += 1;
+                    }
+                    t.delta_bytes += from.iter().zip(item.data)
+                        .map(|(&f, &d)| int_size(d.wrapping_sub(f))).sum::<u64>();
+                },
+                None => {
+                    t.added += 1;
+                    t.delta_bytes += item.data.iter().map(|&d| int_size(d)).sum::<u64>();
+                    added += 1;
+                },
+            }
+        }
+        self.max_added = self.max_added.max(added);
+        self.max_removed = self.max_removed.max(removed);
+        if self.print_ticks {
+            println!("tick={:?} size={} delta_size={} added={} removed={}",
+                tick, size, delta_size, added, removed);
+        }
+    }
+    fn print(&self) {
+        fn percent(a: u64, b: u64) -> f64 {
+            if b != 0 { 100.0 * a as f64 / b as f64 } else { 0.0 }
+        }
+        fn average(a: u64, b: u64) -> f64 {
+            if b != 0 { a as f64 / b as f64 } else { 0.0 }
+        }
+        println!("snaps: {}, avg size: {:.1}, max size: {}",
+            self.snaps, average(self.bytes, self.snaps), self.max_bytes);
+        println!("deltas: {}, avg size: {:.1}, max size: {}",
+            self.deltas, average(self.delta_bytes, self.deltas), self.max_delta_bytes);
+        let total_added: u64 = self.types.values().map(|t| t.added).sum();
+        let total_removed: u64 = self.types.values().map(|t| t.removed).sum();
+        println!("items added per tick: avg {:.2}, max {}",
+            average(total_added, self.deltas), self.max_added);
+        println!("items removed per tick: avg {:.2}, max {}",
+            average(total_removed, self.deltas), self.max_removed);
+        println!();
+        println!("{:>16} {:>10} {:>6} {:>12} {:>6} {:>12} {:>6} {:>9} {:>9} {:>9}",
+            "type", "items", "size", "bytes", "%", "delta_bytes", "%",
+            "unchanged", "added", "removed");
+        for (&type_id, t) in &self.types {
+            let name = match type_name(type_id) {
+                Some(n) => n.to_owned(),
+                None => type_id.to_string(),
+            };
+            println!("{:>16} {:>10} {:>6.1} {:>12} {:>5.1}% {:>12} {:>5.1}% {:>8.1}% {:>9} {:>9}",
+                name,
+                t.items,
+                average(t.bytes, t.items),
+                t.bytes,
+                percent(t.bytes, self.bytes),
+                t.delta_bytes,
+                percent(t.delta_bytes, self.delta_bytes),
+                percent(t.unchanged, t.kept),
+                t.added,
+                t.removed);
+        }
+        println!();
+        println!("parts of {} bytes: full / delta", MAX_SNAPSHOT_PACKSIZE);
+        let max_parts = self.parts.keys().chain(self.delta_parts.keys())
+            .cloned().max().unwrap_or(0);
+        for parts in 0..max_parts + 1 {
+            let full = self.parts.get(&parts).cloned().unwrap_or(0);
+            let delta = self.delta_parts.get(&parts).cloned().unwrap_or(0);
+            if full == 0 && delta == 0 {
+                continue;
+            }
+            println!("{:>4}: {:>10} ({:>5.1}%) / {:>10} ({:>5.1}%)",
+                parts,
+                full, percent(full, self.snaps),
+                delta, percent(delta, self.deltas));
+        }
+    }
+}
+
+fn process_demo(path: &Path, stats: &mut Stats) -> Result<(), Error> {
+    let mut reader = demo::Reader::open(&mut Stdout, path)?;
+    let mut delta_reader = DeltaReader::new();
+    let mut delta = Delta::new();
+    let mut keys = Vec::new();
+    let mut prev = Snap::empty();
+    let mut snap = Snap::empty();
+    let mut have_prev = false;
+    let mut tick = None;
+    while let Some(chunk) = reader.read_chunk(&mut Stdout)? {
+        match chunk {
+            demo::Chunk::Tick(_, demo::Tick(t)) => {
+                tick = Some(t);
+                continue;
+            },
+            demo::Chunk::Snapshot(data) => {
+                snap.read(&mut Stdout, &mut keys, &mut Unpacker::new(data))?;
+            },
+            demo::Chunk::SnapshotDelta(data) => {
+                delta_reader.read(&mut Stdout, &mut delta, obj_size, &mut Unpacker::new(data))?;
+                snap.read_with_delta(&mut Stdout, &prev, &delta)?;
+            },
+            demo::Chunk::Message(_) => continue,
+        }
+        stats.add(tick, if have_prev { Some(&prev) } else { None }, &snap);
+        mem::swap(&mut prev, &mut snap);
+        have_prev = true;
+    }
+    Ok(())
+}
+
+fn process_teehistorian(path: &Path, stats: &mut Stats) -> Result<(), Error> {
+    let mut snaps = Snaps::open(path)?;
+    while let Some(tick) = snaps.next_tick()? {
+        stats.add(Some(tick), snaps.prev_snap(), snaps.snap().unwrap());
+    }
+    Ok(())
+}
+
+fn main() {
+    use clap::App;
+    use clap::Arg;
+
+    logger::init();
+
+    let matches = App::new("Snapshot size profiler")
+        .about("Reports which snapshot items contribute how much to the \
+                snapshot and delta sizes of demos or teehistorian files.")
+        .arg(Arg::with_name("FILE")
+            .help("Sets the demo or teehistorian file to analyse")
+            .multiple(true)
+            .required(true)
+        )
+        .arg(Arg::with_name("teehistorian")
+            .long("teehistorian")
+            .short("t")
+            .help("Treats the files as teehistorian files instead of demos")
+        )
+        .arg(Arg::with_name("ticks")
+            .long("ticks")
+            .help("Prints sizes and added/removed items for each tick")
+        )
+        .get_matches();
+
+    let files = matches.values_of_os("FILE").unwrap();
+    let teehistorian = matches.is_present("teehistorian");
+
+    let mut stats = Stats::default();
+    stats.print_ticks = matches.is_present("ticks");
+
+    let mut error = false;
+    for file in files {
+        let file = Path::new(file);
+        let result = if teehistorian {
+            process_teehistorian(file, &mut stats)
+        } else {
+            process_demo(file, &mut stats)
+        };
+        match result {
+            Ok(()) => {},
+            Err(err) => {
+                eprintln!("{}: {:?}", file.display(), err);
+                error = true;
+            }
+        }
+    }
+    stats.print();
+    if error {
+        process::exit(1);
+    }
+}
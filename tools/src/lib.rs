@@ -1,9 +1,18 @@
+extern crate arrayvec;
+extern crate common;
 extern crate datafile as df;
+extern crate gamenet;
 extern crate logger;
 extern crate map;
+extern crate packer;
+extern crate snapshot;
+extern crate teehistorian;
+extern crate vec_map;
 extern crate warn;
+extern crate world;
 
 pub mod client;
 pub mod map_stats;
+pub mod teehistorian_snaps;
 pub mod unhexdump;
 pub mod warn_stdout;
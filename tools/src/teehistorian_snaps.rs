@@ -0,0 +1,250 @@
+use arrayvec::ArrayVec;
+use common::num::Cast;
+use gamenet::enums::Emote;
+use gamenet::enums::Team;
+use gamenet::enums::Weapon;
+use gamenet::msg::Game;
+use gamenet::msg::game;
+use gamenet::snap_obj::PLAYER_INPUT_EMPTY;
+use gamenet::snap_obj::PlayerInput;
+use gamenet::snap_obj;
+use packer::IntUnpacker;
+use packer::Unpacker;
+use packer::string_to_ints3;
+use packer::string_to_ints4;
+use packer::string_to_ints6;
+use snapshot::Snap;
+use snapshot::snap;
+use std::ops;
+use std::path::Path;
+use teehistorian::Buffer;
+use teehistorian::Error;
+use teehistorian::Item;
+use teehistorian::Pos;
+use teehistorian::Reader;
+use vec_map::VecMap;
+use warn::Ignore;
+use world::vec2;
+
+struct Info {
+    name: ArrayVec<[u8; 4*4-1]>,
+    clan: ArrayVec<[u8; 3*4-1]>,
+    country: i32,
+    skin: ArrayVec<[u8; 6*4-1]>,
+    use_custom_color: bool,
+    color_body: i32,
+    color_feet: i32,
+}
+
+impl<'a> From<game::ClChangeInfo<'a>> for Info {
+    fn from(m: game::ClChangeInfo) -> Info {
+        Info {
+            name: m.name.iter().cloned().collect(),
+            clan: m.clan.iter().cloned().collect(),
+            country: m.country,
+            skin: m.skin.iter().cloned().collect(),
+            use_custom_color: m.use_custom_color,
+            color_body: m.color_body,
+            color_feet: m.color_feet,
+        }
+    }
+}
+
+impl<'a> From<game::ClStartInfo<'a>> for Info {
+    fn from(m: game::ClStartInfo) -> Info {
+        Info {
+            name: m.name.iter().cloned().collect(),
+            clan: m.clan.iter().cloned().collect(),
+            country: m.country,
+            skin: m.skin.iter().cloned().collect(),
+            use_custom_color: m.use_custom_color,
+            color_body: m.color_body,
+            color_feet: m.color_feet,
+        }
+    }
+}
+
+/// Reconstructs snapshots from the player positions and inputs recorded in a
+/// teehistorian file, one per tick.
+pub struct Snaps {
+    buffer: Buffer,
+    th: Reader,
+    map_name: String,
+    map_crc: u32,
+    ticks: ops::Range<i32>,
+    last_tick: i32,
+    supplied_infos: VecMap<Info>,
+    inputs: VecMap<PlayerInput>,
+    prev_pos: VecMap<Pos>,
+    snap: Option<Snap>,
+    prev_snap: Option<Snap>,
+}
+
+impl Snaps {
+    pub fn open(path: &Path) -> Result<Snaps, Error> {
+        let mut buffer = Buffer::new();
+        let th;
+        let map_name;
+        let map_crc;
+        {
+            let (header, teehistorian) = Reader::open(path, &mut buffer)?;
+            th = teehistorian;
+            map_name = header.map_name.into_owned();
+            map_crc = header.map_crc;
+        }
+        Ok(Snaps {
+            buffer: buffer,
+            th: th,
+            map_name: map_name,
+            map_crc: map_crc,
+            ticks: 0..0,
+            last_tick: 0,
+            supplied_infos: VecMap::new(),
+            inputs: VecMap::new(),
+            prev_pos: VecMap::new(),
+            snap: None,
+            prev_snap: None,
+        })
+    }
+    pub fn map_name(&self) -> &str {
+        &self.map_name
+    }
+    pub fn map_crc(&self) -> u32 {
+        self.map_crc
+    }
+    /// The snapshot of the tick last returned by `next_tick`.
+    pub fn snap(&self) -> Option<&Snap> {
+        self.snap.as_ref()
+    }
+    /// The snapshot of the tick before that.
+    pub fn prev_snap(&self) -> Option<&Snap> {
+        self.prev_snap.as_ref()
+    }
+    /// Advances to the next tick, returning `None` at the end of the file.
+    pub fn next_tick(&mut self) -> Result<Option<i32>, Error> {
+        loop {
+            if let Some(tick) = self.ticks.next() {
+                self.build(tick);
+                return Ok(Some(tick));
+            }
+            let item = match self.th.read(&mut self.buffer)? {
+                Some(i) => i,
+                None => return Ok(None),
+            };
+            match item {
+                Item::TickStart(tick) => {
+                    self.ticks = self.last_tick+1..tick;
+                },
+                Item::TickEnd(tick) => {
+                    self.last_tick = tick;
+                    self.ticks = tick..tick+1;
+                },
+                Item::Input(input) => {
+                    if let Ok(pi) = PlayerInput::decode(&mut Ignore, &mut IntUnpacker::new(&input.input)) {
+                        self.inputs.insert(input.cid.assert_usize(), pi);
+                    }
+                },
+                Item::Message(msg) => {
+                    let mut p = Unpacker::new(msg.msg);
+                    if let Ok(m) = Game::decode(&mut Ignore, &mut p) {
+                        match m {
+                            Game::ClStartInfo(i) => {
+                                self.supplied_infos.insert(msg.cid.assert_usize(), i.into());
+                            },
+                            Game::ClChangeInfo(i) => {
+                                self.supplied_infos.insert(msg.cid.assert_usize(), i.into());
+                            },
+                            _ => {},
+                        }
+                    }
+                }
+                _ => {},
+            }
+        }
+    }
+    fn build(&mut self, tick: i32) {
+        let mut builder = match self.prev_snap.take() {
+            Some(s) => s.recycle(),
+            None => snap::Builder::new(),
+        };
+        for cid in self.th.cids() {
+            let maybe_pos = self.th.player_pos(cid);
+            if let Some(pos) = maybe_pos {
+                let ppos = self.prev_pos.get(cid.assert_usize()).cloned().unwrap_or(pos);
+                let input = self.inputs.get(cid.assert_usize()).unwrap_or(&PLAYER_INPUT_EMPTY);
+                let info = &self.supplied_infos[cid.assert_usize()];
+                let name: &[u8] = if !info.name.is_empty() {
+                    &info.name
+                } else {
+                    // Theoretically we have to track all the names. We
+                    // don't do that, so just pretend we care and do the
+                    // common case.
+                    b"(1)"
+                };
+                let client_info = snap_obj::ClientInfo {
+                    name: string_to_ints4(name),
+                    clan: string_to_ints3(&info.clan),
+                    country: info.country,
+                    skin: string_to_ints6(&info.skin),
+                    use_custom_color: info.use_custom_color as i32,
+                    color_body: info.color_body,
+                    color_feet: info.color_feet,
+                };
+                let player_info = snap_obj::PlayerInfo {
+                    local: 0,
+                    client_id: cid,
+                    team: Team::Red,
+                    score: -9999,
+                    latency: 0,
+                };
+                let target = vec2::new(input.target_x as f32, input.target_y as f32);
+                let character = snap_obj::Character {
+                    character_core: snap_obj::CharacterCore {
+                        tick: tick,
+                        x: pos.x,
+                        y: pos.y,
+                        vel_x: pos.x - ppos.x,
+                        vel_y: pos.y - ppos.y,
+                        angle: target.angle().to_net(),
+                        direction: input.direction,
+                        jumped: (input.jump != 0) as i32,
+                        hooked_player: 0,
+                        hook_state: -1,
+                        hook_tick: snap_obj::Tick(0),
+                        hook_x: 0,
+                        hook_y: 0,
+                        hook_dx: 0,
+                        hook_dy: 0,
+                    },
+                    player_flags: snap_obj::PLAYERFLAG_PLAYING,
+                    health: 10,
+                    armor: 10,
+                    ammo_count: 0,
+                    weapon: Weapon::Hammer,
+                    emote: Emote::Normal,
+                    attack_tick: 0,
+                };
+                builder.add_item(snap_obj::CLIENT_INFO, cid.assert_u16(), client_info.encode()).unwrap();
+                builder.add_item(snap_obj::PLAYER_INFO, cid.assert_u16(), player_info.encode()).unwrap();
+                builder.add_item(snap_obj::CHARACTER, cid.assert_u16(), character.encode()).unwrap();
+
+                self.prev_pos.insert(cid.assert_usize(), pos);
+            } else {
+                self.prev_pos.remove(cid.assert_usize());
+            }
+        }
+        let game_info = snap_obj::GameInfo {
+            game_flags: 0,
+            game_state_flags: 0,
+            round_start_tick: snap_obj::Tick(0),
+            warmup_timer: 0,
+            score_limit: 0,
+            time_limit: 0,
+            round_num: 0,
+            round_current: 1,
+        };
+        builder.add_item(snap_obj::GAME_INFO, 0, game_info.encode()).unwrap();
+        self.prev_snap = self.snap.take();
+        self.snap = Some(builder.finish());
+    }
+}
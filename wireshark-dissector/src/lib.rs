@@ -1,22 +1,38 @@
 extern crate arrayvec;
 #[macro_use]
 extern crate common;
+extern crate gamenet;
 extern crate huffman;
 extern crate net;
+extern crate packer;
+extern crate serverbrowse;
+extern crate snapshot;
 extern crate warn;
 extern crate wireshark_dissector_sys as sys;
 
 mod format;
+mod snap;
 
 use arrayvec::ArrayVec;
 use common::num::Cast;
 use common::pretty;
 use format::Bitfield;
 use format::CommaSeparated;
+use gamenet::fields::Fields;
+use gamenet::fields;
+use gamenet::msg::Connless;
+use gamenet::msg::SystemOrGame;
 use huffman::instances::TEEWORLDS as HUFFMAN;
 use net::protocol;
+use packer::Unpacker;
+use serverbrowse::protocol::Response;
+use serverbrowse::protocol::ServerInfo;
+use serverbrowse::protocol::parse_response;
+use snapshot::View;
+use std::cmp;
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::fmt;
 use std::io::Write;
 use std::os::raw::c_char;
 use std::os::raw::c_int;
@@ -32,6 +48,10 @@ static mut ETT_PACKET: c_int = -1;
 static mut ETT_PACKET_FLAGS: c_int = -1;
 static mut ETT_CHUNK: c_int = -1;
 static mut ETT_CHUNK_FLAGS: c_int = -1;
+static mut ETT_MSG: c_int = -1;
+static mut ETT_MSG_FIELD: c_int = -1;
+static mut ETT_SNAP: c_int = -1;
+static mut ETT_SNAP_ITEM: c_int = -1;
 
 static mut HF_PACKET_FLAGS: c_int = -1;
 static mut HF_PACKET_CONTROL: c_int = -1;
@@ -47,6 +67,15 @@ static mut HF_CHUNK_RESEND: c_int = -1;
 static mut HF_CHUNK_VITAL: c_int = -1;
 static mut HF_CHUNK_SIZE: c_int = -1;
 static mut HF_CHUNK_SEQ: c_int = -1;
+static mut HF_MSG: c_int = -1;
+static mut HF_MSG_ID: c_int = -1;
+static mut HF_MSG_FIELD: c_int = -1;
+static mut HF_CONNLESS_ID: c_int = -1;
+static mut HF_SNAP: c_int = -1;
+static mut HF_SNAP_ITEM: c_int = -1;
+
+/// Tree item texts longer than this are truncated.
+const MAX_TEXT_LEN: usize = 240;
 
 #[allow(non_upper_case_globals)]
 #[no_mangle]
@@ -67,6 +96,210 @@ fn unpack_header(data: &[u8]) -> Option<protocol::PacketHeader> {
     Some(raw_header.unpack_warn(&mut Ignore))
 }
 
+fn cstring(s: &str) -> CString {
+    CString::new(s.replace('\0', "\\0")).unwrap()
+}
+
+unsafe fn add_text(
+    tree: *mut sys::proto_tree,
+    hf: c_int,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    value: &str,
+    text: &str,
+) -> *mut sys::proto_item {
+    let mut end = cmp::min(text.len(), MAX_TEXT_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let truncated;
+    let text = if end != text.len() {
+        truncated = format!("{}...", &text[..end]);
+        &truncated
+    } else {
+        text
+    };
+    let value = cstring(value);
+    let text = cstring(text);
+    sys::proto_tree_add_string_format(tree, hf, tvb, start.assert_i32(), len.assert_i32(), value.as_ptr(), c("%s\0"), text.as_ptr())
+}
+
+unsafe fn add_field(
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    text: &str,
+) -> *mut sys::proto_item {
+    add_text(tree, HF_MSG_FIELD, tvb, start, len, text, text)
+}
+
+/// Adds a message item and returns the subtree for its fields.
+unsafe fn add_message(
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    name: &str,
+    text: &str,
+) -> *mut sys::proto_tree {
+    let item = add_text(tree, HF_MSG, tvb, start, len, name, text);
+    sys::proto_item_add_subtree(item, ETT_MSG)
+}
+
+/// Adds the fields of a message or snapshot object to a tree, nested
+/// structures become subtrees.
+struct FieldAdder {
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+}
+
+impl FieldAdder {
+    unsafe fn add(tree: *mut sys::proto_tree, tvb: *mut sys::tvbuff_t, start: usize, len: usize, value: &Fields) {
+        value.visit_fields(&mut FieldAdder {
+            tree: tree,
+            tvb: tvb,
+            start: start,
+            len: len,
+        });
+    }
+}
+
+impl fields::Visitor for FieldAdder {
+    fn field(&mut self, name: &'static str, value: &fmt::Debug) {
+        unsafe {
+            add_field(self.tree, self.tvb, self.start, self.len, &format!("{}: {:?}", name, value));
+        }
+    }
+    fn nested(&mut self, name: &'static str, value: &Fields) {
+        unsafe {
+            let item = add_field(self.tree, self.tvb, self.start, self.len,
+                &format!("{}: {}", name, value.type_name()));
+            let tree = sys::proto_item_add_subtree(item, ETT_MSG_FIELD);
+            FieldAdder::add(tree, self.tvb, self.start, self.len, value);
+        }
+    }
+}
+
+unsafe fn add_server_info(
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    info: &ServerInfo,
+) {
+    let fields = [
+        format!("Info version: {:?}", info.info_version),
+        format!("Token: {}", info.token),
+        format!("Version: {:?}", pretty::AlmostString::new(&info.version)),
+        format!("Name: {:?}", pretty::AlmostString::new(&info.name)),
+        format!("Hostname: {:?}", info.hostname.as_ref().map(|h| pretty::AlmostString::new(h))),
+        format!("Map: {:?}", pretty::AlmostString::new(&info.map)),
+        format!("Map CRC: {:?}", info.map_crc),
+        format!("Map size: {:?}", info.map_size),
+        format!("Game type: {:?}", pretty::AlmostString::new(&info.game_type)),
+        format!("Flags: {}", info.flags),
+        format!("Progression: {:?}", info.progression),
+        format!("Skill level: {:?}", info.skill_level),
+        format!("Players: {}/{}", info.num_players, info.max_players),
+        format!("Clients: {}/{}", info.num_clients, info.max_clients),
+    ];
+    for f in &fields {
+        add_field(tree, tvb, start, len, f);
+    }
+    let item = add_field(tree, tvb, start, len, &format!("Client list: {} entries", info.clients.len()));
+    let client_tree = sys::proto_item_add_subtree(item, ETT_MSG_FIELD);
+    for client in &info.clients {
+        add_field(client_tree, tvb, start, len, &format!("{:?}", client));
+    }
+}
+
+unsafe fn add_snapshot(
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    completed: &snap::Completed,
+) {
+    let &(tick, ref snap) = match *completed {
+        Ok(ref s) => s,
+        Err(ref e) => {
+            let text = format!("Snapshot error: {:?}", e);
+            add_text(tree, HF_SNAP, tvb, start, len, &text, &text);
+            return;
+        },
+    };
+    let view = View::decode(&mut Ignore, snap);
+    let text = format!("Snapshot: tick {}, {} items", tick, view.len());
+    let item = add_text(tree, HF_SNAP, tvb, start, len, &text, &text);
+    let snap_tree = sys::proto_item_add_subtree(item, ETT_SNAP);
+    for (type_id, id, obj) in view.objs() {
+        let name = obj.type_name();
+        let item = add_text(snap_tree, HF_SNAP_ITEM, tvb, start, len, name,
+            &format!("{} (type {}, ID {})", name, type_id, id));
+        let item_tree = sys::proto_item_add_subtree(item, ETT_SNAP_ITEM);
+        FieldAdder::add(item_tree, tvb, start, len, obj);
+    }
+}
+
+fn response_name(response: &Response) -> &'static str {
+    match *response {
+        Response::List5(..) => "List (0.5)",
+        Response::List6(..) => "List",
+        Response::Count(..) => "Count",
+        Response::Info5(..) => "Info (0.5)",
+        Response::Info6(..) => "Info",
+        Response::Info664(..) => "Info (64 clients)",
+        Response::Info6Ex(..) => "Extended info",
+        Response::Info6ExMore(..) => "Extended info (continued)",
+        Response::Pong(..) => "Pong",
+    }
+}
+
+unsafe fn add_response_fields(
+    tree: *mut sys::proto_tree,
+    tvb: *mut sys::tvbuff_t,
+    start: usize,
+    len: usize,
+    response: Response,
+) {
+    let info = match response {
+        Response::List5(r) => {
+            for addr in r.0 {
+                add_field(tree, tvb, start, len, &format!("Server: {}", addr.unpack()));
+            }
+            return;
+        },
+        Response::List6(r) => {
+            for addr in r.0 {
+                add_field(tree, tvb, start, len, &format!("Server: {}", addr.unpack()));
+            }
+            return;
+        },
+        Response::Count(r) => {
+            add_field(tree, tvb, start, len, &format!("Count: {}", r.0));
+            return;
+        },
+        Response::Pong(r) => {
+            add_field(tree, tvb, start, len, &format!("Token: {}", r.0));
+            return;
+        },
+        Response::Info5(r) => r.parse(),
+        Response::Info6(r) => r.parse(),
+        Response::Info664(r) => r.parse().map(|p| p.partial_info().clone()),
+        Response::Info6Ex(r) => r.parse().map(|p| p.partial_info().clone()),
+        Response::Info6ExMore(r) => r.parse().map(|p| p.partial_info().clone()),
+    };
+    if let Some(info) = info {
+        add_server_info(tree, tvb, start, len, &info);
+    } else {
+        add_field(tree, tvb, start, len, "Invalid server info");
+    }
+}
+
 unsafe extern "C" fn dissect_tw(
     tvb: *mut sys::tvbuff_t,
     pinfo: *mut sys::packet_info,
@@ -76,6 +309,10 @@ unsafe extern "C" fn dissect_tw(
     sys::col_set_str((*pinfo).cinfo, sys::COL_PROTOCOL as c_int, c("TW\0"));
     sys::col_clear((*pinfo).cinfo, sys::COL_INFO as c_int);
 
+    let frame = (*pinfo).num;
+    let first_visit = snap::visit(frame);
+    let conversation = sys::find_or_create_conversation(pinfo) as usize;
+
     let ti = sys::proto_tree_add_item(ttree, PROTO_TW_PACKET, tvb, 0, -1, sys::ENC_NA);
     let tree = sys::proto_item_add_subtree(ti, ETT_PACKET);
 
@@ -87,6 +324,7 @@ unsafe extern "C" fn dissect_tw(
     original_buffer.set_len(len);
     sys::tvb_memcpy(tvb, original_buffer.as_mut_ptr() as *mut c_void, 0, len);
     let mut data: &[u8] = &original_buffer;
+    let mut summary: Vec<String> = Vec::new();
 
     macro_rules! field {
         ($type:expr, $tree:expr, $hf:expr, $from:expr, $to: expr, $value:expr, $fmt:expr, $($args:tt)*) => {{
@@ -236,6 +474,7 @@ unsafe extern "C" fn dissect_tw(
                 Accept => "Acknowledge connection acceptance",
                 Close(_) => "Disconnect",
             };
+            summary.push(ctrl_str.to_owned());
             field_uint!(tree, HF_PACKET_CTRL, 3, 1, ctrl_raw,
                 "Control message: {} ({})",
                 ctrl_str,
@@ -255,7 +494,7 @@ unsafe extern "C" fn dissect_tw(
             type_: protocol::ConnectedPacketType::Chunks(_, num_chunks, chunks_data),
         }) => {
             let mut iter = protocol::ChunksIter::new(chunks_data, num_chunks);
-            while let (offset, Some(_)) = (iter.pos(), iter.next_warn(&mut Ignore)) {
+            while let (offset, Some(chunk)) = (iter.pos(), iter.next_warn(&mut Ignore)) {
                 let (header, sequence, _) = if let Some(s) =
                     protocol::read_chunk_header(&mut Ignore, &chunks_data[offset..])
                 {
@@ -303,12 +542,99 @@ unsafe extern "C" fn dissect_tw(
                         Bitfield::new(&data[offset+1..offset+3], 0b1100_0000_1111_1111),
                     );
                 }
+
+                let start = offset + if sequence.is_some() { 3 } else { 2 };
+                let len = chunk.data.len();
+                match SystemOrGame::decode(&mut Ignore, &mut Unpacker::new(chunk.data)) {
+                    Ok(msg) => {
+                        if let (true, SystemOrGame::System(s)) = (first_visit, msg) {
+                            snap::process(conversation, frame, s);
+                        }
+                        let (kind, id, fields): (_, _, &Fields) = match msg {
+                            SystemOrGame::System(ref s) => ("System", s.msg_id(), s),
+                            SystemOrGame::Game(ref g) => ("Game", g.msg_id(), g),
+                        };
+                        let name = fields.type_name();
+                        let msg_tree = add_message(tree, tvb, start, len, name,
+                            &format!("{} message: {}", kind, name));
+                        field_uint!(msg_tree, HF_MSG_ID, start.assert_i32(), len.assert_i32(), id,
+                            "ID: {}",
+                            id,
+                        );
+                        FieldAdder::add(msg_tree, tvb, start, len, fields);
+                        summary.push(name.to_owned());
+                    },
+                    Err(e) => {
+                        add_message(tree, tvb, start, len, "",
+                            &format!("Invalid message: {:?}", e));
+                        summary.push("Invalid".to_owned());
+                    },
+                }
             }
         }
         protocol::Packet::Connless(message) => {
+            let start = data.len() - message.len();
+            let len = message.len();
+            // Server browser responses come first, they have nicer
+            // decoding for the server info variants.
+            if let Some(response) = parse_response(data) {
+                let name = response_name(&response);
+                let id = pretty::AlmostString::new(&message[4..8]);
+                let id_value = cstring(&format!("{:?}", id));
+                let msg_tree = add_message(tree, tvb, start, len, name,
+                    &format!("Connless message: {}", name));
+                field_string!(msg_tree, HF_CONNLESS_ID, start.assert_i32() + 4, 4,
+                    id_value.as_ptr(),
+                    "ID: {:?}",
+                    id,
+                );
+                add_response_fields(msg_tree, tvb, start, len, response);
+                summary.push(name.to_owned());
+            } else {
+                let mut id = [0; 8];
+                let result = if message.len() >= id.len() {
+                    id.copy_from_slice(&message[..8]);
+                    Connless::decode_connless(&mut Ignore, id, &mut Unpacker::new(&message[8..]))
+                } else {
+                    Err(gamenet::Error::UnknownId)
+                };
+                match result {
+                    Ok(msg) => {
+                        let name = msg.type_name();
+                        let id = pretty::AlmostString::new(&id[4..]);
+                        let id_value = cstring(&format!("{:?}", id));
+                        let msg_tree = add_message(tree, tvb, start, len, name,
+                            &format!("Connless message: {}", name));
+                        field_string!(msg_tree, HF_CONNLESS_ID, start.assert_i32() + 4, 4,
+                            id_value.as_ptr(),
+                            "ID: {:?}",
+                            id,
+                        );
+                        FieldAdder::add(msg_tree, tvb, start, len, &msg);
+                        summary.push(name.to_owned());
+                    },
+                    Err(e) => {
+                        add_message(tree, tvb, start, len, "",
+                            &format!("Invalid connless message: {:?}", e));
+                        summary.push("Invalid".to_owned());
+                    },
+                }
+            }
         },
     }
 
+    let packet_len = sys::tvb_captured_length(original_tvb).usize();
+    snap::with_completed(frame, |completed| {
+        for c in completed {
+            add_snapshot(tree, original_tvb, 0, packet_len, c);
+        }
+    });
+
+    if !summary.is_empty() {
+        let summary = cstring(&summary.join(", "));
+        sys::col_add_str((*pinfo).cinfo, sys::COL_INFO as c_int, summary.as_ptr());
+    }
+
     sys::tvb_captured_length(original_tvb) as c_int
 }
 
@@ -468,11 +794,81 @@ unsafe extern "C" fn proto_register_teeworlds() {
         },
     ]};
 
-    static mut ETT: [*mut c_int; 4] = unsafe {[
+    static mut MSG_HF: [sys::hf_register_info; 4] = unsafe {[
+        sys::hf_register_info {
+            p_id: &HF_MSG as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Message\0" as *const _ as *const c_char,
+                abbrev: b"tw.msg\0" as *const _ as *const c_char,
+                type_: sys::FT_STRING,
+                display: sys::STR_ASCII as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+        sys::hf_register_info {
+            p_id: &HF_MSG_ID as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Message ID\0" as *const _ as *const c_char,
+                abbrev: b"tw.msg.id\0" as *const _ as *const c_char,
+                type_: sys::FT_UINT32,
+                display: sys::BASE_DEC as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+        sys::hf_register_info {
+            p_id: &HF_MSG_FIELD as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Message field\0" as *const _ as *const c_char,
+                abbrev: b"tw.msg.field\0" as *const _ as *const c_char,
+                type_: sys::FT_STRING,
+                display: sys::STR_ASCII as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+        sys::hf_register_info {
+            p_id: &HF_CONNLESS_ID as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Connless message ID\0" as *const _ as *const c_char,
+                abbrev: b"tw.connless.id\0" as *const _ as *const c_char,
+                type_: sys::FT_STRING,
+                display: sys::STR_ASCII as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+    ]};
+
+    static mut SNAP_HF: [sys::hf_register_info; 2] = unsafe {[
+        sys::hf_register_info {
+            p_id: &HF_SNAP as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Snapshot\0" as *const _ as *const c_char,
+                abbrev: b"tw.snap\0" as *const _ as *const c_char,
+                type_: sys::FT_STRING,
+                display: sys::STR_ASCII as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+        sys::hf_register_info {
+            p_id: &HF_SNAP_ITEM as *const _ as *mut _,
+            hfinfo: sys::_header_field_info {
+                name: b"Snapshot item\0" as *const _ as *const c_char,
+                abbrev: b"tw.snap.item\0" as *const _ as *const c_char,
+                type_: sys::FT_STRING,
+                display: sys::STR_ASCII as c_int,
+                ..HFRI_DEFAULT
+            },
+        },
+    ]};
+
+    static mut ETT: [*mut c_int; 8] = unsafe {[
         &ETT_PACKET as *const _ as *mut _,
         &ETT_PACKET_FLAGS as *const _ as *mut _,
         &ETT_CHUNK as *const _ as *mut _,
         &ETT_CHUNK_FLAGS as *const _ as *mut _,
+        &ETT_MSG as *const _ as *mut _,
+        &ETT_MSG_FIELD as *const _ as *mut _,
+        &ETT_SNAP as *const _ as *mut _,
+        &ETT_SNAP_ITEM as *const _ as *mut _,
     ]};
 
     PROTO_TW_PACKET = sys::proto_register_protocol(
@@ -487,7 +883,14 @@ unsafe extern "C" fn proto_register_teeworlds() {
     );
     sys::proto_register_field_array(PROTO_TW_PACKET, PACKET_HF.as_mut_ptr(), PACKET_HF.len().assert_i32());
     sys::proto_register_field_array(PROTO_TW_CHUNK, CHUNK_HF.as_mut_ptr(), CHUNK_HF.len().assert_i32());
+    sys::proto_register_field_array(PROTO_TW_PACKET, MSG_HF.as_mut_ptr(), MSG_HF.len().assert_i32());
+    sys::proto_register_field_array(PROTO_TW_PACKET, SNAP_HF.as_mut_ptr(), SNAP_HF.len().assert_i32());
     sys::proto_register_subtree_array(ETT.as_ptr(), ETT.len().assert_i32());
+    sys::register_init_routine(Some(init_teeworlds));
+}
+
+unsafe extern "C" fn init_teeworlds() {
+    snap::reset();
 }
 
 unsafe extern "C" fn proto_reg_handoff_teeworlds() {
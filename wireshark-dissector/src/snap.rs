@@ -0,0 +1,195 @@
+//! Snapshot reassembly across the packets of a capture.
+//!
+//! Wireshark dissects every packet once in order when loading a capture and
+//! again whenever a packet is selected. The snapshot managers are therefore
+//! only fed on the first visit of a frame, the snapshots completed by each
+//! frame are remembered for later visits. Only their raw items are kept,
+//! they're decoded when the frame is displayed.
+
+use gamenet::msg::System;
+use gamenet::snap_obj::obj_size;
+use snapshot::Manager;
+use snapshot::Snap;
+use snapshot::manager;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use warn::Ignore;
+
+/// A snapshot completed by a frame together with its tick, or the error
+/// that occurred while applying the delta.
+pub type Completed = Result<(i32, Snap), manager::Error>;
+
+#[derive(Default)]
+struct State {
+    conversations: HashMap<usize, Manager>,
+    visited: HashSet<u32>,
+    completed: HashMap<u32, Vec<Completed>>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// Forgets everything, called before a capture is dissected.
+pub fn reset() {
+    STATE.with(|s| *s.borrow_mut() = State::default());
+}
+
+/// Marks a frame as visited, returns `true` if it wasn't visited before.
+pub fn visit(frame: u32) -> bool {
+    STATE.with(|s| s.borrow_mut().visited.insert(frame))
+}
+
+/// Feeds a system message of the given conversation to its snapshot
+/// manager.
+///
+/// Must only be called on the first visit of `frame`.
+pub fn process(conversation: usize, frame: u32, msg: System) {
+    STATE.with(|s| {
+        let s = &mut *s.borrow_mut();
+        let manager = s.conversations.entry(conversation).or_insert_with(Manager::new);
+        let res = match msg {
+            System::MapChange(..) => {
+                manager.reset();
+                return;
+            },
+            System::Snap(snap) => manager.snap(&mut Ignore, obj_size, snap),
+            System::SnapEmpty(snap) => manager.snap_empty(&mut Ignore, obj_size, snap),
+            System::SnapSingle(snap) => manager.snap_single(&mut Ignore, obj_size, snap),
+            _ => return,
+        };
+        let completed = match res {
+            Ok(Some(snap)) => Ok(snap.clone()),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let completed = completed.map(|snap| (manager.ack_tick().unwrap(), snap));
+        s.completed.entry(frame).or_insert_with(Vec::new).push(completed);
+    });
+}
+
+/// Calls `f` with the snapshots completed by `frame`.
+pub fn with_completed<F: FnOnce(&[Completed])>(frame: u32, f: F) {
+    STATE.with(|s| {
+        let s = s.borrow();
+        f(s.completed.get(&frame).map(|c| &c[..]).unwrap_or(&[]))
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use gamenet::msg::System;
+    use gamenet::msg::system::MapChange;
+    use gamenet::msg::system::SnapEmpty;
+    use gamenet::snap_obj::LASER;
+    use gamenet::snap_obj::Laser;
+    use gamenet::snap_obj::Tick;
+    use gamenet::snap_obj::obj_size;
+    use packer::with_packer;
+    use snapshot::Delta;
+    use snapshot::Snap;
+    use snapshot::snap::Builder;
+    use snapshot::snap::delta_chunks;
+    use super::Completed;
+    use super::process;
+    use super::reset;
+    use super::with_completed;
+
+    fn laser(id: u16) -> Laser {
+        let x = 1000 * id as i32;
+        Laser {
+            x: x,
+            y: 2000,
+            from_x: x + 100,
+            from_y: 2000,
+            start_tick: Tick(50),
+        }
+    }
+
+    /// Returns the delta from the empty snapshot to a snapshot with `num`
+    /// lasers, and its CRC.
+    fn delta(num: u16) -> (Vec<u8>, i32) {
+        let mut builder = Builder::new();
+        for id in 0..num {
+            builder.add_item(LASER, id, laser(id).encode()).unwrap();
+        }
+        let snap = builder.finish();
+        let mut delta = Delta::new();
+        delta.create(&Snap::empty(), &snap);
+        let mut buf = Vec::with_capacity(64 * 1024);
+        with_packer(&mut buf, |p| delta.write(obj_size, p)).unwrap();
+        (buf, snap.crc())
+    }
+
+    fn completed(frame: u32) -> Vec<Completed> {
+        let mut result = Vec::new();
+        with_completed(frame, |c| result.extend(c.iter().cloned()));
+        result
+    }
+
+    fn assert_lasers(completed: &Completed, tick: i32, num: u16) {
+        let &(t, ref snap) = completed.as_ref().unwrap();
+        assert_eq!(t, tick);
+        assert_eq!(snap.items().count(), num as usize);
+        for id in 0..num {
+            assert_eq!(snap.item(LASER, id), Some(laser(id).encode()));
+        }
+    }
+
+    #[test]
+    fn reassembly() {
+        reset();
+        let (data, crc) = delta(128);
+        let parts: Vec<System> = delta_chunks(100, -1, &data, crc).map(|m| m.into()).collect();
+        assert_eq!(parts.len(), 2);
+
+        process(0, 1, parts[0]);
+        assert!(completed(1).is_empty());
+        process(0, 2, parts[1]);
+        assert!(completed(1).is_empty());
+        let snaps = completed(2);
+        assert_eq!(snaps.len(), 1);
+        assert_lasers(&snaps[0], 100, 128);
+
+        // A snapshot split across frames of another conversation doesn't
+        // interfere.
+        process(1, 3, parts[1]);
+        assert!(completed(3).is_empty());
+
+        // Single snapshots complete in their own frame and are based on the
+        // previous ones.
+        let (data, crc) = delta(1);
+        for m in delta_chunks(101, -1, &data, crc) {
+            process(1, 4, m.into());
+        }
+        let empty = System::SnapEmpty(SnapEmpty { tick: 102, delta_tick: 1 });
+        process(1, 4, empty);
+        let snaps = completed(4);
+        assert_eq!(snaps.len(), 2);
+        assert_lasers(&snaps[0], 101, 1);
+        assert_lasers(&snaps[1], 102, 1);
+    }
+
+    #[test]
+    fn errors() {
+        reset();
+        // Delta against a snapshot that was never received.
+        process(0, 1, System::SnapEmpty(SnapEmpty { tick: 50, delta_tick: 1 }));
+        let snaps = completed(1);
+        assert_eq!(snaps.len(), 1);
+        assert!(snaps[0].is_err());
+
+        // A map change forgets the previous snapshots.
+        let (data, crc) = delta(1);
+        for m in delta_chunks(101, -1, &data, crc) {
+            process(0, 2, m.into());
+        }
+        assert_lasers(&completed(2)[0], 101, 1);
+        process(0, 3, System::MapChange(MapChange { name: b"dm1", crc: 0, size: 0 }));
+        process(0, 3, System::SnapEmpty(SnapEmpty { tick: 102, delta_tick: 1 }));
+        let snaps = completed(3);
+        assert_eq!(snaps.len(), 1);
+        assert!(snaps[0].is_err());
+    }
+}
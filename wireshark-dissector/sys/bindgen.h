@@ -1,4 +1,5 @@
 #include <config.h>
+#include <epan/conversation.h>
 #include <epan/packet.h>
 #include <epan/proto.h>
 #include <string.h>
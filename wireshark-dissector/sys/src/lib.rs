@@ -1986,6 +1986,14 @@ extern "C" {
     #[doc = " @param str the string to set"]
     pub fn col_set_str(cinfo: *mut column_info, col: gint, str: *const gchar);
 }
+extern "C" {
+    #[doc = " Add (replace) the text of a column element, the text will be copied."]
+    #[doc = ""]
+    #[doc = " @param cinfo the current packet row"]
+    #[doc = " @param col the column to use, e.g. COL_INFO"]
+    #[doc = " @param str the string to add"]
+    pub fn col_add_str(cinfo: *mut column_info, col: gint, str: *const gchar);
+}
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct dissector_handle {
@@ -2021,3 +2029,25 @@ extern "C" {
         name: *const ::std::os::raw::c_char,
     );
 }
+extern "C" {
+    #[doc = " Allows protocols to register \"init\" routines, which are called before"]
+    #[doc = " starting a dissection of a capture file."]
+    pub fn register_init_routine(func: ::std::option::Option<unsafe extern "C" fn()>);
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct conversation {
+    _unused: [u8; 0],
+}
+pub type conversation_t = conversation;
+extern "C" {
+    #[doc = " A helper function that calls find_conversation() and, if a conversation is"]
+    #[doc = " not found, calls conversation_new()."]
+    #[doc = " The frame number and addresses are taken from pinfo."]
+    #[doc = " No options are used, though we could extend this API to include an options"]
+    #[doc = " parameter."]
+    #[doc = ""]
+    #[doc = " @param pinfo Packet info."]
+    #[doc = " @return The existing or new conversation."]
+    pub fn find_or_create_conversation(pinfo: *mut packet_info) -> *mut conversation_t;
+}
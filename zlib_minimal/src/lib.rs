@@ -1,14 +1,20 @@
 //! A minimal zlib wrapper
 //!
-//! This wrapper only exposes the `uncompress` method of zlib, both without
-//! indirection and as idiomatic Rust function.
+//! This wrapper only exposes the `uncompress` and `compress` methods of zlib,
+//! both without indirection and as idiomatic Rust function.
 
 extern crate libc;
 extern crate libz_sys as raw;
 
 use std::fmt;
+use libc::c_int;
 use libc::c_ulong;
 
+/// The compression level used by `compress`.
+pub const DEFAULT_COMPRESSION: i32 = raw::Z_DEFAULT_COMPRESSION;
+/// The compression level yielding the smallest output.
+pub const BEST_COMPRESSION: i32 = raw::Z_BEST_COMPRESSION;
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub struct Error {
     inner: i32,
@@ -78,6 +84,18 @@ pub fn compress(dest: &mut [u8], src: &[u8]) -> Result<usize, Error> {
     }).map(|()| output_size as usize)
 }
 
+/// The wrapper for zlib's `compress2` function.
+///
+/// Like `compress`, but allows to specify the compression `level`, ranging
+/// from 0 (no compression) to 9 (`BEST_COMPRESSION`).
+pub fn compress2(dest: &mut [u8], src: &[u8], level: i32) -> Result<usize, Error> {
+    let mut output_size = dest.len() as c_ulong;
+    Error::from_raw(unsafe {
+        raw::compress2(dest.as_mut_ptr(), &mut output_size,
+                       src.as_ptr(), src.len() as c_ulong, level as c_int)
+    }).map(|()| output_size as usize)
+}
+
 /// The wrapper for zlib's `compressBound` function.
 ///
 /// Returns an upper bound on the compressed size for `compress()`.
@@ -86,13 +104,17 @@ pub fn compress_bound(source_len: usize) -> usize {
 }
 
 pub fn compress_vec(source: &[u8]) -> Result<Vec<u8>, Error> {
+    compress_vec_level(source, DEFAULT_COMPRESSION)
+}
+
+pub fn compress_vec_level(source: &[u8], level: i32) -> Result<Vec<u8>, Error> {
     let upper_bound = compress_bound(source.len());
     let mut dest = Vec::with_capacity(upper_bound);
 
     // u8 has no destructor, this is safe
     unsafe { dest.set_len(upper_bound); }
 
-    let output_length = try!(compress(&mut dest, source));
+    let output_length = try!(compress2(&mut dest, source, level));
     unsafe { dest.set_len(output_length); }
 
     Ok(dest)